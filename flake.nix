@@ -54,6 +54,12 @@
 
       checks = forAllSystems (system: {
         build = self.packages.${system}.default;
+
+        edge =
+          let
+            pkgs = nixpkgsFor.${system};
+            rust-utils = import ./nix/rust.nix { inherit pkgs config crane; inherit (pkgs) lib; src = self; };
+          in rust-utils.edgeTest;
         
         format-check = 
           let pkgs = nixpkgsFor.${system};
@@ -7,8 +7,16 @@ let
   
   craneLib = (crane.mkLib pkgs).overrideToolchain (_: stableToolchain);
   
+  # Cargo sources plus the static assets embedded with include_bytes!
+  cargoSourcesAndAssets = path: type:
+    (builtins.match ".*\\.html$" path != null) || (craneLib.filterCargoSources path type);
+
   commonArgs = {
-    src = craneLib.cleanCargoSource src;
+    src = lib.cleanSourceWith {
+      inherit src;
+      filter = cargoSourcesAndAssets;
+      name = "source";
+    };
     strictDeps = true;
     
     nativeBuildInputs = with pkgs; [
@@ -31,6 +39,19 @@ let
       };
     });
 
+  # Size and startup budgets of the edge build (tests/edge), which a plain
+  # `cargo test` ignores
+  edgeArgs = commonArgs // {
+    CARGO_PROFILE = "edge";
+    cargoExtraArgs = "--locked --no-default-features";
+  };
+
+  edgeTest =
+    craneLib.cargoTest (edgeArgs // {
+      cargoArtifacts = craneLib.buildDepsOnly edgeArgs;
+      cargoTestExtraArgs = "--test edge -- --ignored";
+    });
+
 in {
-  inherit craneLib buildPackage;
+  inherit craneLib buildPackage edgeTest;
 }
\ No newline at end of file
@@ -0,0 +1,507 @@
+use crate::config::ProviderConfig;
+use crate::logging::log_level;
+use crate::store::ConfigSnapshot;
+use crate::{AppState, ErrorResponse};
+use axum::{
+    Extension, Router,
+    extract::{ConnectInfo, Path, Request, State},
+    http::StatusCode,
+    middleware::{self, Next},
+    response::{IntoResponse, Json, Response},
+    routing::{get, post},
+};
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tracing::{error, info, warn};
+use utoipa::ToSchema;
+
+/// Authentication method guarding the admin API
+#[derive(Debug, Clone)]
+pub enum AdminAuth {
+    /// No admin principals configured - every admin request is rejected
+    Disabled,
+    /// Authenticate callers by the Tailscale identity behind their source address
+    TailscaleIdentity {
+        users: Vec<String>,
+        tags: Vec<String>,
+    },
+}
+
+impl AdminAuth {
+    pub fn from_config(config: &ProviderConfig) -> Self {
+        let users = config.admin_users.clone().unwrap_or_default();
+        let tags = config.admin_tags.clone().unwrap_or_default();
+
+        if users.is_empty() && tags.is_empty() {
+            AdminAuth::Disabled
+        } else {
+            AdminAuth::TailscaleIdentity { users, tags }
+        }
+    }
+}
+
+/// Tailscale identity of an authenticated admin caller
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct AdminIdentity {
+    pub login_name: String,
+    pub node_name: String,
+    pub tags: Vec<String>,
+}
+
+/// Version of the configuration published by a forced refresh
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct RefreshResponse {
+    pub version: String,
+    pub hash: String,
+    pub generation: u64,
+    /// False when the regenerated configuration equals the one already published
+    pub changed: bool,
+}
+
+/// Peers and services taken out of the configuration through the admin API
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct DisabledEntries {
+    /// Hostnames of disabled peers
+    pub peers: Vec<String>,
+    /// Names (or globs) of disabled services
+    pub services: Vec<String>,
+    /// Version of the configuration published after the change (None if regenerating failed)
+    pub version: Option<String>,
+}
+
+/// Log filter directives, e.g. "debug" or "info,traefik_tailscale_provider=trace"
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct LogLevelBody {
+    pub level: String,
+}
+
+pub fn router(state: AppState) -> Router<AppState> {
+    Router::new()
+        .route("/admin/whoami", get(whoami))
+        .route("/refresh", post(refresh))
+        .route("/admin/disabled", get(get_disabled))
+        .route("/admin/peers/{hostname}/disable", post(disable_peer))
+        .route("/admin/peers/{hostname}/enable", post(enable_peer))
+        .route("/admin/services/{name}/disable", post(disable_service))
+        .route("/admin/services/{name}/enable", post(enable_service))
+        .route("/admin/loglevel", get(get_log_level).put(set_log_level))
+        .route_layer(middleware::from_fn_with_state(state, require_admin))
+}
+
+/// Reject requests whose caller is not an authorized admin
+pub async fn require_admin(
+    State(state): State<AppState>,
+    ConnectInfo(remote_addr): ConnectInfo<SocketAddr>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    match authenticate(&state, remote_addr).await {
+        Ok(identity) => {
+            request.extensions_mut().insert(identity);
+            next.run(request).await
+        }
+        Err(message) => {
+            warn!("Rejected admin request from {}: {}", remote_addr, message);
+            let error_response = ErrorResponse {
+                error: message.to_string(),
+            };
+            (StatusCode::FORBIDDEN, Json(error_response)).into_response()
+        }
+    }
+}
+
+async fn authenticate(
+    state: &AppState,
+    remote_addr: SocketAddr,
+) -> Result<AdminIdentity, &'static str> {
+    let AdminAuth::TailscaleIdentity { users, tags } = state.admin_auth.as_ref() else {
+        return Err("Admin API is disabled");
+    };
+
+    let whois = state
+        .provider
+        .whois(&remote_addr.ip().to_string())
+        .await
+        .map_err(|_| "Caller is not a known Tailscale node")?;
+
+    let node_tags: Vec<String> = whois
+        .node
+        .tags
+        .unwrap_or_default()
+        .iter()
+        .map(|tag| tag.strip_prefix("tag:").unwrap_or(tag).to_string())
+        .collect();
+
+    let user_allowed = users.contains(&whois.user_profile.login_name);
+    let tag_allowed = tags.iter().any(|tag| {
+        let clean_tag = tag.strip_prefix("tag:").unwrap_or(tag);
+        node_tags.iter().any(|node_tag| node_tag == clean_tag)
+    });
+
+    if !user_allowed && !tag_allowed {
+        return Err("Caller is not an authorized admin");
+    }
+
+    Ok(AdminIdentity {
+        login_name: whois.user_profile.login_name,
+        node_name: whois.node.name,
+        tags: node_tags,
+    })
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/whoami",
+    tag = "Admin",
+    summary = "Get admin identity",
+    description = "Returns the Tailscale identity the caller was authenticated as",
+    responses(
+        (status = 200, description = "Caller is an authorized admin", body = AdminIdentity),
+        (status = 403, description = "Caller is not an authorized admin", body = ErrorResponse)
+    )
+)]
+pub async fn whoami(Extension(identity): Extension<AdminIdentity>) -> Json<AdminIdentity> {
+    Json(identity)
+}
+
+#[utoipa::path(
+    post,
+    path = "/refresh",
+    tag = "Admin",
+    summary = "Regenerate the configuration",
+    description = "Regenerates the configuration from the current Tailscale status right away instead of at the next update interval, and publishes it",
+    responses(
+        (status = 200, description = "Configuration regenerated", body = RefreshResponse),
+        (status = 403, description = "Caller is not an authorized admin", body = ErrorResponse),
+        (status = 409, description = "This replica mirrors the configuration of another replica, the leader", body = ErrorResponse),
+        (status = 503, description = "Failed to generate configuration", body = ErrorResponse)
+    )
+)]
+pub async fn refresh(
+    State(state): State<AppState>,
+    Extension(identity): Extension<AdminIdentity>,
+) -> Response {
+    if let Some(response) = reject_on_follower(&state).await {
+        return response;
+    }
+    let previous = state
+        .store
+        .current()
+        .await
+        .map(|snapshot| snapshot.generation);
+
+    let Some(snapshot) = regenerate(&state, &identity).await else {
+        let error_response = ErrorResponse {
+            error: "Failed to generate configuration from Tailscale".to_string(),
+        };
+        return (StatusCode::SERVICE_UNAVAILABLE, Json(error_response)).into_response();
+    };
+
+    let response = RefreshResponse {
+        version: snapshot.version.clone(),
+        hash: snapshot.hash.clone(),
+        generation: snapshot.generation,
+        changed: previous != Some(snapshot.generation),
+    };
+    (StatusCode::OK, Json(response)).into_response()
+}
+
+/// Refuse a change on a replica mirroring the leader (LEADER_ELECTION), where it would
+/// never show in the configuration, naming the replica to send it to instead
+pub async fn reject_on_follower(state: &AppState) -> Option<Response> {
+    let (leader_id, url) = state.provider.leader().await?;
+    let error_response = ErrorResponse {
+        error: format!(
+            "This replica mirrors the configuration of leader {}, send changes to {}",
+            leader_id, url
+        ),
+    };
+    Some((StatusCode::CONFLICT, Json(error_response)).into_response())
+}
+
+/// Generate and publish the configuration on behalf of an admin
+pub async fn regenerate(state: &AppState, identity: &AdminIdentity) -> Option<Arc<ConfigSnapshot>> {
+    match state.provider.generate_config().await {
+        Ok(config) => {
+            let snapshot = state.store.publish(config).await;
+            info!(
+                "Regenerated Traefik configuration for {} ({})",
+                identity.login_name, snapshot.version
+            );
+            Some(snapshot)
+        }
+        Err(e) => {
+            error!("Regenerating for {} failed: {}", identity.login_name, e);
+            None
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/disabled",
+    tag = "Admin",
+    summary = "List disabled peers and services",
+    description = "Returns the peers and services disabled through the admin API. Disabling is kept in memory only and ends with a restart.",
+    responses(
+        (status = 200, description = "Disabled peers and services", body = DisabledEntries),
+        (status = 403, description = "Caller is not an authorized admin", body = ErrorResponse)
+    )
+)]
+pub async fn get_disabled(State(state): State<AppState>) -> Json<DisabledEntries> {
+    let (peers, services) = state.provider.disabled();
+    let version = state.store.current().await.map(|s| s.version.clone());
+
+    Json(DisabledEntries {
+        peers,
+        services,
+        version,
+    })
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/peers/{hostname}/disable",
+    tag = "Admin",
+    summary = "Disable a peer",
+    description = "Leaves all services of the peer out of the configuration, e.g. to drain it for maintenance, and regenerates the configuration",
+    params(("hostname" = String, Path, description = "Tailscale hostname of the peer")),
+    responses(
+        (status = 200, description = "Peer disabled", body = DisabledEntries),
+        (status = 403, description = "Caller is not an authorized admin", body = ErrorResponse),
+        (status = 409, description = "This replica mirrors the configuration of another replica, the leader", body = ErrorResponse)
+    )
+)]
+pub async fn disable_peer(
+    State(state): State<AppState>,
+    Extension(identity): Extension<AdminIdentity>,
+    Path(hostname): Path<String>,
+) -> Response {
+    if let Some(response) = reject_on_follower(&state).await {
+        return response;
+    }
+    if state.provider.set_peer_disabled(&hostname, true) {
+        info!("Peer {} disabled by {}", hostname, identity.login_name);
+    }
+    disabled_entries(&state, &identity).await.into_response()
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/peers/{hostname}/enable",
+    tag = "Admin",
+    summary = "Enable a peer",
+    description = "Takes a disabled peer back into the configuration and regenerates it",
+    params(("hostname" = String, Path, description = "Tailscale hostname of the peer")),
+    responses(
+        (status = 200, description = "Peer enabled", body = DisabledEntries),
+        (status = 403, description = "Caller is not an authorized admin", body = ErrorResponse),
+        (status = 409, description = "This replica mirrors the configuration of another replica, the leader", body = ErrorResponse)
+    )
+)]
+pub async fn enable_peer(
+    State(state): State<AppState>,
+    Extension(identity): Extension<AdminIdentity>,
+    Path(hostname): Path<String>,
+) -> Response {
+    if let Some(response) = reject_on_follower(&state).await {
+        return response;
+    }
+    if state.provider.set_peer_disabled(&hostname, false) {
+        info!("Peer {} enabled by {}", hostname, identity.login_name);
+    }
+    disabled_entries(&state, &identity).await.into_response()
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/services/{name}/disable",
+    tag = "Admin",
+    summary = "Disable a service",
+    description = "Leaves the service and the routers pointing at it out of the configuration and regenerates it. The name may be a glob like EXCLUDE_SERVICES entries.",
+    params(("name" = String, Path, description = "Published service name")),
+    responses(
+        (status = 200, description = "Service disabled", body = DisabledEntries),
+        (status = 403, description = "Caller is not an authorized admin", body = ErrorResponse),
+        (status = 409, description = "This replica mirrors the configuration of another replica, the leader", body = ErrorResponse)
+    )
+)]
+pub async fn disable_service(
+    State(state): State<AppState>,
+    Extension(identity): Extension<AdminIdentity>,
+    Path(name): Path<String>,
+) -> Response {
+    if let Some(response) = reject_on_follower(&state).await {
+        return response;
+    }
+    if state.provider.set_service_disabled(&name, true) {
+        info!("Service {} disabled by {}", name, identity.login_name);
+    }
+    disabled_entries(&state, &identity).await.into_response()
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/services/{name}/enable",
+    tag = "Admin",
+    summary = "Enable a service",
+    description = "Takes a disabled service back into the configuration and regenerates it",
+    params(("name" = String, Path, description = "Published service name")),
+    responses(
+        (status = 200, description = "Service enabled", body = DisabledEntries),
+        (status = 403, description = "Caller is not an authorized admin", body = ErrorResponse),
+        (status = 409, description = "This replica mirrors the configuration of another replica, the leader", body = ErrorResponse)
+    )
+)]
+pub async fn enable_service(
+    State(state): State<AppState>,
+    Extension(identity): Extension<AdminIdentity>,
+    Path(name): Path<String>,
+) -> Response {
+    if let Some(response) = reject_on_follower(&state).await {
+        return response;
+    }
+    if state.provider.set_service_disabled(&name, false) {
+        info!("Service {} enabled by {}", name, identity.login_name);
+    }
+    disabled_entries(&state, &identity).await.into_response()
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/loglevel",
+    tag = "Admin",
+    summary = "Get the log level",
+    description = "Returns the log filter directives in effect",
+    responses(
+        (status = 200, description = "Current log level", body = LogLevelBody),
+        (status = 403, description = "Caller is not an authorized admin", body = ErrorResponse)
+    )
+)]
+pub async fn get_log_level() -> Json<LogLevelBody> {
+    Json(LogLevelBody {
+        level: log_level().map(|level| level.get()).unwrap_or_default(),
+    })
+}
+
+#[utoipa::path(
+    put,
+    path = "/admin/loglevel",
+    tag = "Admin",
+    summary = "Change the log level",
+    description = "Replaces the log filter directives (as in LOG_LEVEL) until the next restart",
+    request_body = LogLevelBody,
+    responses(
+        (status = 200, description = "Log level changed", body = LogLevelBody),
+        (status = 400, description = "Invalid filter directives", body = ErrorResponse),
+        (status = 403, description = "Caller is not an authorized admin", body = ErrorResponse)
+    )
+)]
+pub async fn set_log_level(
+    Extension(identity): Extension<AdminIdentity>,
+    Json(body): Json<LogLevelBody>,
+) -> Response {
+    let result = match log_level() {
+        Some(level) => level.set(&body.level),
+        None => Err("Logging is not initialized".to_string()),
+    };
+
+    match result {
+        Ok(()) => {
+            info!("Log level set to {} by {}", body.level, identity.login_name);
+            Json(body).into_response()
+        }
+        Err(e) => {
+            let error_response = ErrorResponse {
+                error: format!("Invalid log level: {}", e),
+            };
+            (StatusCode::BAD_REQUEST, Json(error_response)).into_response()
+        }
+    }
+}
+
+/// Regenerate after a change and report what is disabled now
+async fn disabled_entries(state: &AppState, identity: &AdminIdentity) -> Json<DisabledEntries> {
+    let version = regenerate(state, identity)
+        .await
+        .map(|snapshot| snapshot.version.clone());
+    let (peers, services) = state.provider.disabled();
+
+    Json(DisabledEntries {
+        peers,
+        services,
+        version,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::test_state;
+
+    fn admin() -> Extension<AdminIdentity> {
+        Extension(AdminIdentity {
+            login_name: "alice@example.com".to_string(),
+            node_name: "laptop".to_string(),
+            tags: Vec::new(),
+        })
+    }
+
+    /// A replica of LEADER_ELECTION whose leader, "replica-a", listens on a local port
+    async fn follower() -> (AppState, String) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let leader = Router::new().route(
+            "/cluster/member",
+            get(|| async { Json(serde_json::json!({"replica_id": "replica-a"})) }),
+        );
+        tokio::spawn(async move { axum::serve(listener, leader).await });
+
+        let state = test_state(ProviderConfig {
+            replica_id: "replica-b".to_string(),
+            cluster_members: Some(vec![url.clone()]),
+            leader_election: true,
+            deterministic_output: true,
+            ..Default::default()
+        });
+        (state, url)
+    }
+
+    #[tokio::test]
+    async fn follower_refuses_changes() {
+        let (state, url) = follower().await;
+
+        let responses = [
+            disable_peer(State(state.clone()), admin(), Path("nas".to_string())).await,
+            enable_peer(State(state.clone()), admin(), Path("nas".to_string())).await,
+            disable_service(State(state.clone()), admin(), Path("web".to_string())).await,
+            enable_service(State(state.clone()), admin(), Path("web".to_string())).await,
+            refresh(State(state.clone()), admin()).await,
+        ];
+        for response in responses {
+            assert_eq!(response.status(), StatusCode::CONFLICT);
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            let error: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            assert!(error["error"].as_str().unwrap().contains(&url));
+        }
+        assert_eq!(state.provider.disabled(), (Vec::new(), Vec::new()));
+    }
+
+    #[tokio::test]
+    async fn leader_applies_changes() {
+        let state = test_state(ProviderConfig {
+            replica_id: "replica-a".to_string(),
+            cluster_members: Some(vec!["http://127.0.0.1:9".to_string()]),
+            leader_election: true,
+            deterministic_output: true,
+            ..Default::default()
+        });
+
+        let response = disable_peer(State(state.clone()), admin(), Path("nas".to_string())).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(state.provider.disabled().0, ["nas"]);
+    }
+}
@@ -0,0 +1,71 @@
+use crate::ErrorResponse;
+use crate::config::{ProviderConfig, Secret};
+use axum::{
+    extract::{Request, State},
+    http::{StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Json, Response},
+};
+use std::sync::Arc;
+use tracing::warn;
+
+/// Bearer token protecting the provider API (API_TOKEN)
+#[derive(Debug)]
+pub struct TokenAuth {
+    token: Secret,
+    public_health: bool,
+}
+
+impl TokenAuth {
+    pub fn from_config(config: &ProviderConfig) -> Option<Self> {
+        config.api_token.clone().map(|token| Self {
+            token,
+            public_health: config.api_token_public_health,
+        })
+    }
+
+    fn accepts(&self, request: &Request) -> bool {
+        request
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .is_some_and(|token| constant_time_eq(token.trim(), self.token.expose()))
+    }
+}
+
+/// Reject requests without the API token, except the health checks when they are public
+pub async fn require_token(
+    State(auth): State<Arc<TokenAuth>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if (auth.public_health && matches!(request.uri().path(), "/" | "/readyz"))
+        || auth.accepts(&request)
+    {
+        return next.run(request).await;
+    }
+
+    warn!(
+        "Rejected unauthenticated request for {}",
+        request.uri().path()
+    );
+    let error_response = ErrorResponse {
+        error: "Missing or invalid API token".to_string(),
+    };
+    (
+        StatusCode::UNAUTHORIZED,
+        [(header::WWW_AUTHENTICATE, "Bearer")],
+        Json(error_response),
+    )
+        .into_response()
+}
+
+/// Compare without exiting early, so the response time does not reveal the matching prefix
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0u8, |diff, (x, y)| diff | (x ^ y))
+            == 0
+}
@@ -0,0 +1,184 @@
+use crate::config::Secret;
+use crate::{AppState, ErrorResponse};
+use axum::{
+    Router,
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Json, Response},
+    routing::get,
+};
+use chrono::{DateTime, Utc};
+use http_body_util::{BodyExt, Full};
+use hyper::body::Bytes;
+use hyper_util::client::legacy::{Client, connect::HttpConnector};
+use hyper_util::rt::TokioExecutor;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tracing::warn;
+use utoipa::ToSchema;
+
+/// How long to wait for another replica before reporting it unreachable
+const MEMBER_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Published configuration of a single provider replica
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ClusterMember {
+    pub replica_id: String,
+    /// Base URL the member was queried at (None for the local replica)
+    pub url: Option<String>,
+    pub reachable: bool,
+    pub version: Option<String>,
+    pub hash: Option<String>,
+    /// When the member's current configuration generation was first published
+    pub changed_at: Option<DateTime<Utc>>,
+    /// When the member last published a configuration
+    pub last_published_at: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Configuration agreement across all provider replicas
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ClusterStatus {
+    pub replica_id: String,
+    pub members: Vec<ClusterMember>,
+    /// True when every member is reachable and serves the same configuration hash
+    pub in_agreement: bool,
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/cluster", get(get_cluster_status))
+        .route("/cluster/member", get(get_cluster_member))
+}
+
+#[utoipa::path(
+    get,
+    path = "/cluster",
+    tag = "Cluster",
+    summary = "Get cluster status",
+    description = "Returns the configuration published by this replica and by every replica in CLUSTER_MEMBERS, and whether they agree",
+    responses(
+        (status = 200, description = "Cluster status", body = ClusterStatus)
+    )
+)]
+pub async fn get_cluster_status(State(state): State<AppState>) -> Json<ClusterStatus> {
+    let mut members = vec![local_member(&state).await];
+
+    let client = Client::builder(TokioExecutor::new()).build(HttpConnector::new());
+    for url in state.cluster_members.iter() {
+        members.push(remote_member(&client, url, state.api_token.as_ref()).await);
+    }
+
+    let in_agreement = members.iter().all(|member| member.reachable)
+        && members
+            .iter()
+            .all(|member| member.hash.is_some() && member.hash == members[0].hash);
+
+    Json(ClusterStatus {
+        replica_id: state.replica_id.to_string(),
+        members,
+        in_agreement,
+    })
+}
+
+#[utoipa::path(
+    get,
+    path = "/cluster/member",
+    tag = "Cluster",
+    summary = "Get this replica's cluster member state",
+    description = "Returns the configuration version published by this replica, as queried by other replicas",
+    responses(
+        (status = 200, description = "Member state", body = ClusterMember),
+        (status = 503, description = "No configuration published yet", body = ErrorResponse)
+    )
+)]
+pub async fn get_cluster_member(State(state): State<AppState>) -> Response {
+    let member = local_member(&state).await;
+    if member.hash.is_none() {
+        let error_response = ErrorResponse {
+            error: "No configuration published yet".to_string(),
+        };
+        return (StatusCode::SERVICE_UNAVAILABLE, Json(error_response)).into_response();
+    }
+
+    (StatusCode::OK, Json(member)).into_response()
+}
+
+async fn local_member(state: &AppState) -> ClusterMember {
+    let snapshot = state.store.current().await;
+
+    ClusterMember {
+        replica_id: state.replica_id.to_string(),
+        url: None,
+        reachable: true,
+        version: snapshot.as_ref().map(|s| s.version.clone()),
+        hash: snapshot.as_ref().map(|s| s.hash.clone()),
+        changed_at: snapshot.as_ref().map(|s| s.created_at),
+        last_published_at: state.store.last_published_at().await,
+        error: None,
+    }
+}
+
+async fn remote_member(
+    client: &Client<HttpConnector, Full<Bytes>>,
+    url: &str,
+    token: Option<&Secret>,
+) -> ClusterMember {
+    let result = tokio::time::timeout(MEMBER_TIMEOUT, fetch_member(client, url, token))
+        .await
+        .unwrap_or_else(|_| Err("timed out".to_string()));
+
+    match result {
+        Ok(member) => ClusterMember {
+            url: Some(url.to_string()),
+            ..member
+        },
+        Err(e) => {
+            warn!("Cluster member {} is unreachable: {}", url, e);
+            ClusterMember {
+                replica_id: url.to_string(),
+                url: Some(url.to_string()),
+                reachable: false,
+                version: None,
+                hash: None,
+                changed_at: None,
+                last_published_at: None,
+                error: Some(e),
+            }
+        }
+    }
+}
+
+async fn fetch_member(
+    client: &Client<HttpConnector, Full<Bytes>>,
+    url: &str,
+    token: Option<&Secret>,
+) -> Result<ClusterMember, String> {
+    let uri: hyper::Uri = format!("{}/cluster/member", url.trim_end_matches('/'))
+        .parse()
+        .map_err(|e| format!("invalid URL: {}", e))?;
+
+    let mut request = hyper::Request::get(uri);
+    if let Some(token) = token {
+        request = request.header(
+            hyper::header::AUTHORIZATION,
+            format!("Bearer {}", token.expose()),
+        );
+    }
+    let request = request
+        .body(Full::new(Bytes::new()))
+        .map_err(|e| e.to_string())?;
+    let response = client.request(request).await.map_err(|e| e.to_string())?;
+    if !response.status().is_success() {
+        return Err(format!("HTTP {}", response.status()));
+    }
+
+    let body = response
+        .into_body()
+        .collect()
+        .await
+        .map_err(|e| e.to_string())?
+        .to_bytes();
+    serde_json::from_slice(&body).map_err(|e| e.to_string())
+}
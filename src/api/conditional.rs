@@ -0,0 +1,217 @@
+use crate::CONFIG_VERSION_HEADER;
+use crate::store::ConfigStore;
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::{HeaderMap, HeaderValue, Method, StatusCode, header},
+    middleware::Next,
+    response::Response,
+};
+use chrono::{DateTime, Utc};
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+
+/// Format of Last-Modified and If-Modified-Since (IMF-fixdate)
+const HTTP_DATE: &str = "%a, %d %b %Y %H:%M:%S GMT";
+
+/// Query parameters of /config that change the body served for a version
+const FILTER_PARAMS: [&str; 3] = ["protocol", "tag", "hostname"];
+
+/// Give /config responses an ETag of the configuration they carry (its content hash,
+/// version and filters, so the body is never buffered) and the time it last changed as
+/// Last-Modified, and answer 304 to requests that already have them: If-None-Match is
+/// checked first, If-Modified-Since only without it
+pub async fn conditional_get(
+    State(store): State<Arc<ConfigStore>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if request.method() != Method::GET && request.method() != Method::HEAD {
+        return next.run(request).await;
+    }
+    let conditions = request.headers().clone();
+    let filters = request.uri().query().map(filters).unwrap_or_default();
+
+    let mut response = next.run(request).await;
+    if response.status() != StatusCode::OK {
+        return response;
+    }
+    let Some(version) = response
+        .headers()
+        .get(CONFIG_VERSION_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+    else {
+        return response;
+    };
+
+    let snapshot = store.find(&version).await;
+    let mut digest = Sha256::new();
+    if let Some(snapshot) = &snapshot {
+        digest.update(&snapshot.hash);
+    }
+    digest.update(&version);
+    for filter in &filters {
+        digest.update(filter);
+    }
+    let etag = format!("\"{}\"", &hex::encode(digest.finalize())[..16]);
+    let headers = response.headers_mut();
+    headers.insert(header::ETAG, HeaderValue::from_str(&etag).unwrap());
+    let last_modified = snapshot.map(|snapshot| snapshot.created_at);
+    if let Some(last_modified) = last_modified {
+        let value = last_modified.format(HTTP_DATE).to_string();
+        headers.insert(
+            header::LAST_MODIFIED,
+            HeaderValue::from_str(&value).unwrap(),
+        );
+    }
+
+    if not_modified(&conditions, &etag, last_modified) {
+        let (mut parts, _) = response.into_parts();
+        parts.status = StatusCode::NOT_MODIFIED;
+        parts.headers.remove(header::CONTENT_TYPE);
+        parts.headers.remove(header::CONTENT_LENGTH);
+        return Response::from_parts(parts, Body::empty());
+    }
+    response
+}
+
+/// The filter parameters of a query, sorted so their order doesn't change the ETag
+fn filters(query: &str) -> Vec<String> {
+    let mut filters: Vec<String> = query
+        .split('&')
+        .filter(|pair| {
+            let name = pair.split('=').next().unwrap_or_default();
+            FILTER_PARAMS.contains(&name)
+        })
+        .map(|pair| format!("{}\0", pair))
+        .collect();
+    filters.sort();
+    filters
+}
+
+fn not_modified(conditions: &HeaderMap, etag: &str, last_modified: Option<DateTime<Utc>>) -> bool {
+    if let Some(if_none_match) = conditions.get(header::IF_NONE_MATCH) {
+        return if_none_match.to_str().is_ok_and(|value| {
+            value.split(',').any(|tag| {
+                let tag = tag.trim();
+                tag == "*" || tag.trim_start_matches("W/") == etag
+            })
+        });
+    }
+
+    let Some(last_modified) = last_modified else {
+        return false;
+    };
+    conditions
+        .get(header::IF_MODIFIED_SINCE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| DateTime::parse_from_rfc2822(value).ok())
+        // Last-Modified has whole seconds
+        .is_some_and(|since| last_modified.timestamp() <= since.timestamp())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::{get, serve};
+    use axum::{Json, Router, middleware, response::IntoResponse, routing};
+    use serde_json::json;
+
+    async fn server(store: Arc<ConfigStore>) -> String {
+        let handler = {
+            let store = store.clone();
+            move || async move {
+                let snapshot = store.current().await.expect("published configuration");
+                (
+                    [(CONFIG_VERSION_HEADER, snapshot.version.clone())],
+                    Json(snapshot.config.clone()),
+                )
+                    .into_response()
+            }
+        };
+        let app = Router::new()
+            .route("/config", routing::get(handler))
+            .layer(middleware::from_fn_with_state(store, conditional_get));
+        serve(app).await
+    }
+
+    async fn publish(store: &ConfigStore, url: &str) {
+        let config = serde_json::from_value(json!({
+            "http": {"services": {"web": {"loadBalancer": {"servers": [{"url": url}]}}}}
+        }))
+        .expect("configuration");
+        store.publish(config).await;
+    }
+
+    fn etag(headers: &HeaderMap) -> String {
+        headers[header::ETAG].to_str().unwrap().to_string()
+    }
+
+    #[tokio::test]
+    async fn etag_follows_the_configuration_and_filters() {
+        let store = Arc::new(ConfigStore::new(false, None, 10));
+        publish(&store, "http://100.64.0.1:80").await;
+        let url = server(store.clone()).await;
+
+        let (status, headers, body) = get(&format!("{}/config", url), &[]).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.contains("100.64.0.1"));
+        let first = etag(&headers);
+        assert!(headers.contains_key(header::LAST_MODIFIED));
+
+        let (status, _, body) = get(
+            &format!("{}/config", url),
+            &[("if-none-match", first.as_str())],
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_MODIFIED);
+        assert!(body.is_empty());
+
+        // Filters change the body, their order and other parameters don't
+        let (_, headers, _) = get(&format!("{}/config?protocol=http&tag=web", url), &[]).await;
+        let filtered = etag(&headers);
+        assert_ne!(filtered, first);
+        let (_, headers, _) = get(
+            &format!("{}/config?wait=1s&tag=web&protocol=http", url),
+            &[],
+        )
+        .await;
+        assert_eq!(etag(&headers), filtered);
+
+        publish(&store, "http://100.64.0.2:80").await;
+        let (status, headers, _) = get(
+            &format!("{}/config", url),
+            &[("if-none-match", first.as_str())],
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_ne!(etag(&headers), first);
+    }
+
+    #[tokio::test]
+    async fn if_modified_since_applies_without_if_none_match() {
+        let store = Arc::new(ConfigStore::new(false, None, 10));
+        publish(&store, "http://100.64.0.1:80").await;
+        let url = server(store).await;
+
+        let (_, headers, _) = get(&format!("{}/config", url), &[]).await;
+        let last_modified = headers[header::LAST_MODIFIED].to_str().unwrap().to_string();
+        let (status, _, _) = get(
+            &format!("{}/config", url),
+            &[("if-modified-since", last_modified.as_str())],
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_MODIFIED);
+
+        let (status, _, _) = get(
+            &format!("{}/config", url),
+            &[
+                ("if-modified-since", last_modified.as_str()),
+                ("if-none-match", "\"stale\""),
+            ],
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+    }
+}
@@ -0,0 +1,86 @@
+use crate::AppState;
+use axum::{
+    Router,
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    response::{IntoResponse, Response},
+    routing::get,
+};
+use hyper::body::Bytes;
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use utoipa::openapi::OpenApi;
+
+/// Scalar page loading the spec from /openapi.json, so the page itself is static
+const DOCS_HTML: &[u8] = include_bytes!("docs.html");
+
+/// Assets only change with the binary; clients revalidate with their ETag afterwards
+const CACHE_CONTROL: &str = "public, max-age=3600";
+
+/// A response body rendered once, served with an ETag and cache headers
+struct CachedAsset {
+    body: Bytes,
+    etag: String,
+    content_type: &'static str,
+}
+
+impl CachedAsset {
+    fn new(body: impl Into<Bytes>, content_type: &'static str) -> Self {
+        let body = body.into();
+        let etag = format!("\"{}\"", &hex::encode(Sha256::digest(&body))[..16]);
+
+        Self {
+            body,
+            etag,
+            content_type,
+        }
+    }
+
+    fn respond(&self, headers: &HeaderMap) -> Response {
+        let not_modified = headers
+            .get(header::IF_NONE_MATCH)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| {
+                value
+                    .split(',')
+                    .any(|tag| tag.trim() == self.etag || tag.trim() == "*")
+            });
+
+        let cache_headers = [
+            (header::ETAG, HeaderValue::from_str(&self.etag).unwrap()),
+            (
+                header::CACHE_CONTROL,
+                HeaderValue::from_static(CACHE_CONTROL),
+            ),
+        ];
+
+        if not_modified {
+            return (StatusCode::NOT_MODIFIED, cache_headers).into_response();
+        }
+
+        (
+            cache_headers,
+            [(header::CONTENT_TYPE, self.content_type)],
+            self.body.clone(),
+        )
+            .into_response()
+    }
+}
+
+/// API documentation UI at /docs and the OpenAPI document at /openapi.json
+pub fn router(openapi: OpenApi) -> Router<AppState> {
+    let page = Arc::new(CachedAsset::new(DOCS_HTML, "text/html; charset=utf-8"));
+    let spec = Arc::new(CachedAsset::new(
+        openapi.to_json().expect("OpenAPI document serializes"),
+        "application/json",
+    ));
+
+    Router::new()
+        .route(
+            "/docs",
+            get(move |headers: HeaderMap| async move { page.respond(&headers) }),
+        )
+        .route(
+            "/openapi.json",
+            get(move |headers: HeaderMap| async move { spec.respond(&headers) }),
+        )
+}
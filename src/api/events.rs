@@ -0,0 +1,40 @@
+use crate::AppState;
+use crate::events::{ProviderEvent, events};
+use axum::{
+    Router,
+    response::sse::{Event, KeepAlive, Sse},
+    routing::get,
+};
+use std::convert::Infallible;
+use tokio_stream::{Stream, StreamExt, wrappers::BroadcastStream};
+use tracing::warn;
+
+pub fn router() -> Router<AppState> {
+    Router::new().route("/events", get(stream_events))
+}
+
+#[utoipa::path(
+    get,
+    path = "/events",
+    tag = "Status",
+    summary = "Stream peer and service changes",
+    description = "Server-sent events for peers joining or leaving the tailnet, going online or offline, and services added to or removed from the configuration. Each event is named after the type field of its JSON data.",
+    responses(
+        (status = 200, description = "Event stream", body = ProviderEvent, content_type = "text/event-stream")
+    )
+)]
+pub async fn stream_events() -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let stream = BroadcastStream::new(events().subscribe()).filter_map(|event| match event {
+        Ok(event) => Event::default()
+            .event(event.kind())
+            .json_data(&event)
+            .ok()
+            .map(Ok),
+        Err(lagged) => {
+            warn!("Event subscriber fell behind: {}", lagged);
+            None
+        }
+    });
+
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
@@ -0,0 +1,143 @@
+use crate::{AppState, CONFIG_VERSION_HEADER, ErrorResponse};
+use axum::{
+    Router,
+    extract::State,
+    http::{StatusCode, header},
+    response::{IntoResponse, Json, Response},
+    routing::get,
+};
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/config/caddy", get(get_caddy_config))
+        .route("/config/nginx", get(get_nginx_config))
+        .route("/config/haproxy", get(get_haproxy_config))
+        .route("/config/dns", get(get_dns_config))
+}
+
+#[utoipa::path(
+    get,
+    path = "/config/caddy",
+    tag = "Configuration",
+    summary = "Get the configuration for Caddy",
+    description = "Renders the HTTP routers of the current configuration as a Caddy JSON config, loadable with POST /load of Caddy's admin API. Each router becomes a reverse_proxy route of the CADDY_SERVER_NAME server; routers whose rule uses anything but Host, Path and PathPrefix are left out, as are TCP and UDP.",
+    responses(
+        (status = 200, description = "Caddy JSON config", body = Object,
+            headers(("X-Config-Version" = String, description = "Provider generation the configuration came from"))),
+        (status = 503, description = "No configuration published yet", body = ErrorResponse)
+    )
+)]
+pub async fn get_caddy_config(State(state): State<AppState>) -> Response {
+    let Some(snapshot) = state.store.current().await else {
+        return not_published();
+    };
+    (
+        [(CONFIG_VERSION_HEADER, snapshot.version.clone())],
+        Json(state.caddy.render(&snapshot.config)),
+    )
+        .into_response()
+}
+
+#[utoipa::path(
+    get,
+    path = "/config/nginx",
+    tag = "Configuration",
+    summary = "Get the configuration for nginx",
+    description = "Renders the HTTP routers of the current configuration as nginx upstream and server blocks, to include from the http block. Each service becomes an upstream and each set of hosts a server with a location per path; routers whose rule uses anything but Host, Path and PathPrefix are left out, as are TCP and UDP.",
+    responses(
+        (status = 200, description = "nginx configuration", body = String, content_type = "text/plain",
+            headers(("X-Config-Version" = String, description = "Provider generation the configuration came from"))),
+        (status = 503, description = "No configuration published yet", body = ErrorResponse)
+    )
+)]
+pub async fn get_nginx_config(State(state): State<AppState>) -> Response {
+    let Some(snapshot) = state.store.current().await else {
+        return not_published();
+    };
+    (
+        [
+            (
+                header::CONTENT_TYPE,
+                "text/plain; charset=utf-8".to_string(),
+            ),
+            (
+                CONFIG_VERSION_HEADER.parse().unwrap(),
+                snapshot.version.clone(),
+            ),
+        ],
+        state.nginx.render(&snapshot.config),
+    )
+        .into_response()
+}
+
+#[utoipa::path(
+    get,
+    path = "/config/haproxy",
+    tag = "Configuration",
+    summary = "Get the configuration for HAProxy",
+    description = "Renders the HTTP routers of the current configuration as an HAProxy frontend with a backend per service. Requests are routed by the first router matching them in Traefik's priority order; routers whose rule uses anything but Host, Path and PathPrefix are left out, as are TCP and UDP.",
+    responses(
+        (status = 200, description = "HAProxy configuration", body = String, content_type = "text/plain",
+            headers(("X-Config-Version" = String, description = "Provider generation the configuration came from"))),
+        (status = 503, description = "No configuration published yet", body = ErrorResponse)
+    )
+)]
+pub async fn get_haproxy_config(State(state): State<AppState>) -> Response {
+    let Some(snapshot) = state.store.current().await else {
+        return not_published();
+    };
+    (
+        [
+            (
+                header::CONTENT_TYPE,
+                "text/plain; charset=utf-8".to_string(),
+            ),
+            (
+                CONFIG_VERSION_HEADER.parse().unwrap(),
+                snapshot.version.clone(),
+            ),
+        ],
+        state.haproxy.render(&snapshot.config),
+    )
+        .into_response()
+}
+
+#[utoipa::path(
+    get,
+    path = "/config/dns",
+    tag = "Configuration",
+    summary = "Get the DNS records of the current configuration",
+    description = "Renders the hosts of the HTTP routers' Host rules, and with DNS_ZONE the service names in that zone, with the addresses of the peers behind them (or DNS_TARGETS). The format is DNS_FORMAT: a hosts file for CoreDNS's hosts plugin, or a zone file versioned by the configuration's publication time.",
+    responses(
+        (status = 200, description = "Hosts or zone file", body = String, content_type = "text/plain",
+            headers(("X-Config-Version" = String, description = "Provider generation the configuration came from"))),
+        (status = 503, description = "No configuration published yet", body = ErrorResponse)
+    )
+)]
+pub async fn get_dns_config(State(state): State<AppState>) -> Response {
+    let Some(snapshot) = state.store.current().await else {
+        return not_published();
+    };
+    let serial = u32::try_from(snapshot.created_at.timestamp()).unwrap_or(u32::MAX);
+    (
+        [
+            (
+                header::CONTENT_TYPE,
+                "text/plain; charset=utf-8".to_string(),
+            ),
+            (
+                CONFIG_VERSION_HEADER.parse().unwrap(),
+                snapshot.version.clone(),
+            ),
+        ],
+        state.dns.render(&snapshot.config, serial),
+    )
+        .into_response()
+}
+
+fn not_published() -> Response {
+    let error_response = ErrorResponse {
+        error: "No configuration published yet".to_string(),
+    };
+    (StatusCode::SERVICE_UNAVAILABLE, Json(error_response)).into_response()
+}
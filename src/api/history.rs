@@ -0,0 +1,89 @@
+use crate::store::ConfigSnapshot;
+use crate::store::diff::ConfigDiff;
+use crate::{AppState, ErrorResponse};
+use axum::{
+    Router,
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Json, Response},
+    routing::get,
+};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+/// Metadata of a configuration kept in the history
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ConfigHistoryEntry {
+    pub generation: u64,
+    pub version: String,
+    pub hash: String,
+    /// When this configuration was first published
+    pub created_at: DateTime<Utc>,
+    /// True for the configuration currently served at /config
+    pub current: bool,
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/config/diff", get(get_config_diff))
+        .route("/config/history", get(get_config_history))
+}
+
+#[utoipa::path(
+    get,
+    path = "/config/history",
+    tag = "Configuration",
+    summary = "Get the configuration history",
+    description = "Lists the most recent configurations (up to CONFIG_HISTORY_SIZE), newest first. Each can be fetched with GET /config?version=<version or hash>.",
+    responses(
+        (status = 200, description = "Past configurations", body = Vec<ConfigHistoryEntry>)
+    )
+)]
+pub async fn get_config_history(State(state): State<AppState>) -> Json<Vec<ConfigHistoryEntry>> {
+    let current = state.store.current().await;
+    let is_current = |snapshot: &ConfigSnapshot| {
+        current
+            .as_ref()
+            .is_some_and(|current| current.hash == snapshot.hash)
+    };
+
+    let entries = state
+        .store
+        .history()
+        .await
+        .iter()
+        .map(|snapshot| ConfigHistoryEntry {
+            generation: snapshot.generation,
+            version: snapshot.version.clone(),
+            hash: snapshot.hash.clone(),
+            created_at: snapshot.created_at,
+            current: is_current(snapshot),
+        })
+        .collect();
+
+    Json(entries)
+}
+
+#[utoipa::path(
+    get,
+    path = "/config/diff",
+    tag = "Configuration",
+    summary = "Get the last configuration change",
+    description = "Returns the routers and services added, removed or modified by the most recent generation that changed the configuration",
+    responses(
+        (status = 200, description = "Changes since the previous configuration", body = ConfigDiff),
+        (status = 503, description = "No configuration published yet", body = ErrorResponse)
+    )
+)]
+pub async fn get_config_diff(State(state): State<AppState>) -> Response {
+    match state.store.last_diff().await {
+        Some(diff) => (StatusCode::OK, Json(diff.as_ref().clone())).into_response(),
+        None => {
+            let error_response = ErrorResponse {
+                error: "No configuration published yet".to_string(),
+            };
+            (StatusCode::SERVICE_UNAVAILABLE, Json(error_response)).into_response()
+        }
+    }
+}
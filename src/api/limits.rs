@@ -0,0 +1,353 @@
+use crate::ErrorResponse;
+use crate::api::longpoll;
+use crate::config::{EndpointLimit, ProviderConfig};
+use axum::{
+    body::Body,
+    extract::{MatchedPath, Request, State},
+    http::{StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Json, Response},
+    serve::Listener,
+};
+use http_body_util::Limited;
+use std::collections::HashMap;
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tracing::warn;
+
+/// Request limits of the provider API, with per-endpoint overrides
+#[derive(Debug, Clone)]
+pub struct RequestLimits {
+    timeout: Duration,
+    max_body_bytes: usize,
+    max_header_bytes: usize,
+    endpoints: HashMap<String, EndpointLimit>,
+}
+
+impl RequestLimits {
+    pub fn from_config(config: &ProviderConfig) -> Self {
+        Self {
+            timeout: Duration::from_secs(config.request_timeout_seconds),
+            max_body_bytes: config.max_request_body_bytes,
+            max_header_bytes: config.max_request_header_bytes,
+            endpoints: config.endpoint_limits.clone().unwrap_or_default(),
+        }
+    }
+
+    /// Override for a route - exact route path first, then the longest "/prefix/*" match
+    fn endpoint(&self, route: &str) -> Option<&EndpointLimit> {
+        self.endpoints.get(route).or_else(|| {
+            self.endpoints
+                .iter()
+                .filter_map(|(pattern, limit)| {
+                    let prefix = pattern.strip_suffix('*')?;
+                    route.starts_with(prefix).then_some((prefix.len(), limit))
+                })
+                .max_by_key(|(len, _)| *len)
+                .map(|(_, limit)| limit)
+        })
+    }
+}
+
+/// Enforce header size, body size and timeout limits for the matched route
+pub async fn enforce_limits(
+    State(limits): State<Arc<RequestLimits>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| request.uri().path().to_string());
+    let endpoint = limits.endpoint(&route);
+    let timeout = endpoint
+        .and_then(|limit| limit.timeout_seconds)
+        .map(Duration::from_secs)
+        .unwrap_or(limits.timeout);
+    // Time a long poll spends waiting for a change isn't held against it
+    let timeout = timeout + longpoll::requested_wait(request.uri()).unwrap_or_default();
+    let max_body_bytes = endpoint
+        .and_then(|limit| limit.max_body_bytes)
+        .unwrap_or(limits.max_body_bytes);
+
+    let header_bytes: usize = request
+        .headers()
+        .iter()
+        .map(|(name, value)| name.as_str().len() + value.len())
+        .sum();
+    if header_bytes > limits.max_header_bytes {
+        return reject(
+            StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE,
+            "Request headers too large",
+        );
+    }
+
+    let content_length = request
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<usize>().ok());
+    if content_length.is_some_and(|length| length > max_body_bytes) {
+        return reject(StatusCode::PAYLOAD_TOO_LARGE, "Request body too large");
+    }
+
+    // Bodies without (or with a lying) Content-Length are cut off while streaming
+    let request = request.map(|body| Body::new(Limited::new(body, max_body_bytes)));
+
+    match tokio::time::timeout(timeout, next.run(request)).await {
+        Ok(response) => response,
+        Err(_) => {
+            warn!("Request to {} timed out after {:?}", route, timeout);
+            reject(StatusCode::REQUEST_TIMEOUT, "Request timed out")
+        }
+    }
+}
+
+fn reject(status: StatusCode, message: &str) -> Response {
+    let error_response = ErrorResponse {
+        error: message.to_string(),
+    };
+    (status, Json(error_response)).into_response()
+}
+
+/// TCP listeners, e.g. on the address of each tailnet, accepting at most a fixed number
+/// of simultaneous connections between them. Further clients wait in the kernel backlog
+/// until a connection closes.
+pub struct LimitedListener {
+    listeners: Vec<TcpListener>,
+    permits: Arc<Semaphore>,
+}
+
+impl LimitedListener {
+    pub fn new(listeners: Vec<TcpListener>, max_connections: usize) -> Self {
+        Self {
+            listeners,
+            permits: Arc::new(Semaphore::new(max_connections)),
+        }
+    }
+
+    /// The next connection of any of the listeners
+    async fn accept_any(&self) -> (TcpStream, SocketAddr) {
+        loop {
+            let accepted = std::future::poll_fn(|cx| {
+                self.listeners
+                    .iter()
+                    .find_map(|listener| match listener.poll_accept(cx) {
+                        Poll::Ready(accepted) => Some(Poll::Ready(accepted)),
+                        Poll::Pending => None,
+                    })
+                    .unwrap_or(Poll::Pending)
+            })
+            .await;
+            match accepted {
+                Ok(accepted) => return accepted,
+                // Clients giving up before being accepted
+                Err(e)
+                    if matches!(
+                        e.kind(),
+                        io::ErrorKind::ConnectionAborted | io::ErrorKind::ConnectionReset
+                    ) => {}
+                // e.g. out of file descriptors, which takes connections closing to recover
+                Err(e) => {
+                    warn!("Failed to accept a connection: {}", e);
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                }
+            }
+        }
+    }
+}
+
+impl Listener for LimitedListener {
+    type Io = LimitedStream;
+    type Addr = SocketAddr;
+
+    async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+        let permit = self
+            .permits
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("connection semaphore is never closed");
+        let (stream, addr) = self.accept_any().await;
+        (
+            LimitedStream {
+                stream,
+                _permit: permit,
+            },
+            addr,
+        )
+    }
+
+    /// Address of the first listener
+    fn local_addr(&self) -> io::Result<Self::Addr> {
+        match self.listeners.first() {
+            Some(listener) => listener.local_addr(),
+            None => Err(io::Error::new(io::ErrorKind::NotFound, "no listener")),
+        }
+    }
+}
+
+/// Accepted connection holding its slot until dropped
+pub struct LimitedStream {
+    stream: TcpStream,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl AsyncRead for LimitedStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for LimitedStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.stream).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::{get, serve};
+    use axum::{Router, middleware, routing};
+
+    fn limits(endpoints: &[(&str, EndpointLimit)]) -> RequestLimits {
+        RequestLimits::from_config(&ProviderConfig {
+            request_timeout_seconds: 1,
+            max_request_header_bytes: 1024,
+            endpoint_limits: Some(
+                endpoints
+                    .iter()
+                    .map(|(route, limit)| (route.to_string(), limit.clone()))
+                    .collect(),
+            ),
+            ..Default::default()
+        })
+    }
+
+    fn timeout(seconds: u64) -> EndpointLimit {
+        EndpointLimit {
+            timeout_seconds: Some(seconds),
+            max_body_bytes: None,
+        }
+    }
+
+    #[test]
+    fn endpoint_overrides_prefer_exact_then_longest_prefix() {
+        let limits = limits(&[
+            ("/config", timeout(1)),
+            ("/config/*", timeout(2)),
+            ("/config/history/*", timeout(3)),
+            ("/*", timeout(4)),
+        ]);
+        let seconds = |route| {
+            limits
+                .endpoint(route)
+                .and_then(|limit| limit.timeout_seconds)
+        };
+
+        assert_eq!(seconds("/config"), Some(1));
+        assert_eq!(seconds("/config/diff"), Some(2));
+        assert_eq!(seconds("/config/history/{version}"), Some(3));
+        assert_eq!(seconds("/status"), Some(4));
+        assert_eq!(
+            limits.endpoint("/status").map(|limit| limit.max_body_bytes),
+            Some(None)
+        );
+    }
+
+    #[tokio::test]
+    async fn long_polls_get_their_wait_on_top_of_the_timeout() {
+        let slow = || async {
+            tokio::time::sleep(Duration::from_millis(1500)).await;
+            "done"
+        };
+        let app = Router::new().route("/config", routing::get(slow)).layer(
+            middleware::from_fn_with_state(Arc::new(limits(&[])), enforce_limits),
+        );
+        let url = serve(app).await;
+
+        let (status, _, _) = get(&format!("{}/config", url), &[]).await;
+        assert_eq!(status, StatusCode::REQUEST_TIMEOUT);
+        let (status, _, body) = get(&format!("{}/config?hash=abc&wait=2s", url), &[]).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, "done");
+    }
+
+    #[tokio::test]
+    async fn oversized_headers_are_rejected() {
+        let app = Router::new()
+            .route("/status", routing::get(|| async { "ok" }))
+            .layer(middleware::from_fn_with_state(
+                Arc::new(limits(&[])),
+                enforce_limits,
+            ));
+        let url = serve(app).await;
+
+        let (status, _, _) = get(&format!("{}/status", url), &[("x-small", "a")]).await;
+        assert_eq!(status, StatusCode::OK);
+        let large = "a".repeat(2048);
+        let (status, _, _) = get(&format!("{}/status", url), &[("x-large", &large)]).await;
+        assert_eq!(status, StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE);
+    }
+
+    /// Send a keep-alive GET / and read the response head
+    async fn request(stream: &mut TcpStream) -> String {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        stream
+            .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = vec![0; 1024];
+        let read = stream.read(&mut response).await.unwrap();
+        String::from_utf8_lossy(&response[..read]).into_owned()
+    }
+
+    #[tokio::test]
+    async fn connections_share_one_limit_across_listeners() {
+        let first = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let second = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addrs = [first.local_addr().unwrap(), second.local_addr().unwrap()];
+        let listener = LimitedListener::new(vec![first, second], 1);
+        assert_eq!(listener.local_addr().unwrap(), addrs[0]);
+        let app = Router::new().route("/", routing::get(|| async { "ok" }));
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let mut held = TcpStream::connect(addrs[0]).await.unwrap();
+        assert!(request(&mut held).await.starts_with("HTTP/1.1 200"));
+
+        // The second listener's client waits for the slot of the first connection
+        let mut waiting = TcpStream::connect(addrs[1]).await.unwrap();
+        let response = tokio::spawn(async move { request(&mut waiting).await });
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert!(!response.is_finished());
+        drop(held);
+        let response = tokio::time::timeout(Duration::from_secs(5), response).await;
+        assert!(response.unwrap().unwrap().starts_with("HTTP/1.1 200"));
+    }
+}
@@ -0,0 +1,127 @@
+use crate::store::{ConfigSnapshot, ConfigStore};
+use axum::{extract::Query, http::Uri};
+use serde::Deserialize;
+use std::time::Duration;
+
+/// Longest a request may be held waiting for a change
+pub const MAX_WAIT: Duration = Duration::from_secs(300);
+
+#[derive(Deserialize)]
+struct WaitQuery {
+    wait: Option<String>,
+}
+
+/// A long poll wait such as "30s", "2m", "500ms" or plain seconds, capped at MAX_WAIT
+pub fn parse_wait(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    let (number, unit) = value
+        .find(|c: char| !c.is_ascii_digit())
+        .map(|index| value.split_at(index))
+        .unwrap_or((value, "s"));
+    let number: u64 = number.parse().ok()?;
+    let wait = match unit {
+        "ms" => Duration::from_millis(number),
+        "s" => Duration::from_secs(number),
+        "m" => Duration::from_secs(number.checked_mul(60)?),
+        _ => return None,
+    };
+    Some(wait.min(MAX_WAIT))
+}
+
+/// Wait asked for with ?wait=, which the request may spend on top of its timeout
+pub fn requested_wait(uri: &Uri) -> Option<Duration> {
+    let Query(query) = Query::<WaitQuery>::try_from_uri(uri).ok()?;
+    parse_wait(&query.wait?)
+}
+
+/// Wait until the current configuration is no longer `known` (its version or content
+/// hash), at most `wait`. True when it changed, including when it was already
+/// different.
+pub async fn wait_for_change(store: &ConfigStore, known: &str, wait: Duration) -> bool {
+    let differs = |snapshot: Option<std::sync::Arc<ConfigSnapshot>>| {
+        snapshot.is_some_and(|snapshot| snapshot.version != known && snapshot.hash != known)
+    };
+
+    // Subscribed before looking, so a change in between isn't missed
+    let mut generations = store.subscribe();
+    if differs(store.current().await) {
+        return true;
+    }
+    tokio::time::timeout(wait, async {
+        while generations.changed().await.is_ok() {
+            if differs(store.current().await) {
+                return true;
+            }
+        }
+        false
+    })
+    .await
+    .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::traefik::DynamicConfig;
+    use serde_json::json;
+    use std::sync::Arc;
+
+    fn config(url: &str) -> DynamicConfig {
+        serde_json::from_value(json!({
+            "http": {"services": {"web": {"loadBalancer": {"servers": [{"url": url}]}}}}
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn waits_parse_with_units_and_are_capped() {
+        assert_eq!(parse_wait("30s"), Some(Duration::from_secs(30)));
+        assert_eq!(parse_wait(" 45 "), Some(Duration::from_secs(45)));
+        assert_eq!(parse_wait("2m"), Some(Duration::from_secs(120)));
+        assert_eq!(parse_wait("500ms"), Some(Duration::from_millis(500)));
+        assert_eq!(parse_wait("10m"), Some(MAX_WAIT));
+        assert_eq!(parse_wait(&format!("{}m", u64::MAX)), None);
+        for invalid in ["", "s", "1h", "-5s", "1.5s", "thirty"] {
+            assert_eq!(parse_wait(invalid), None, "{}", invalid);
+        }
+
+        let uri: Uri = "/config?hash=abc&wait=2m".parse().unwrap();
+        assert_eq!(requested_wait(&uri), Some(Duration::from_secs(120)));
+        let uri: Uri = "/config?hash=abc".parse().unwrap();
+        assert_eq!(requested_wait(&uri), None);
+    }
+
+    #[tokio::test]
+    async fn answers_at_once_when_the_configuration_already_differs() {
+        let store = ConfigStore::new(false, None, 10);
+        let snapshot = store.publish(config("http://100.64.0.1:80")).await;
+
+        let wait = Duration::from_secs(60);
+        assert!(wait_for_change(&store, "gen-000000-000000", wait).await);
+        // Neither the version nor the content hash of the current configuration
+        // counts as a change
+        let unchanged = Duration::from_millis(50);
+        assert!(!wait_for_change(&store, &snapshot.version, unchanged).await);
+        assert!(!wait_for_change(&store, &snapshot.hash, unchanged).await);
+    }
+
+    #[tokio::test]
+    async fn wakes_up_on_a_change() {
+        let store = Arc::new(ConfigStore::new(false, None, 10));
+        let known = store.publish(config("http://100.64.0.1:80")).await;
+
+        let publisher = store.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            // Republishing the same content is no change
+            publisher.publish(config("http://100.64.0.1:80")).await;
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            publisher.publish(config("http://100.64.0.2:80")).await;
+        });
+
+        let started = std::time::Instant::now();
+        assert!(wait_for_change(&store, &known.version, Duration::from_secs(10)).await);
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert_ne!(store.current().await.unwrap().hash, known.hash);
+    }
+}
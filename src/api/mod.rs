@@ -0,0 +1,92 @@
+pub mod admin;
+pub mod auth;
+pub mod cluster;
+pub mod conditional;
+#[cfg(feature = "docs")]
+pub mod docs;
+pub mod events;
+pub mod formats;
+pub mod history;
+pub mod limits;
+pub mod longpoll;
+pub mod peers;
+pub mod services;
+pub mod staleness;
+pub mod state;
+#[cfg(feature = "https")]
+pub mod tls;
+pub mod verify;
+pub mod websocket;
+
+/// State of the API around a provider whose tailscaled is unreachable, so that
+/// regenerating fails fast, for testing the handlers
+#[cfg(test)]
+pub fn test_state(config: crate::config::ProviderConfig) -> crate::AppState {
+    use crate::output::{caddy::CaddyServer, dns::DnsRenderer};
+    use crate::output::{haproxy::HaproxyRenderer, nginx::NginxRenderer};
+    use std::sync::Arc;
+
+    let config = crate::config::ProviderConfig {
+        tailscale_socket_path: Some("tcp://127.0.0.1:9".to_string()),
+        tailscale_retry_attempts: 1,
+        ..config
+    };
+    crate::AppState {
+        provider: Arc::new(crate::traefik::TraefikProvider::new(config.clone()).expect("provider")),
+        store: Arc::new(crate::store::ConfigStore::new(true, None, 10)),
+        admin_auth: Arc::new(admin::AdminAuth::from_config(&config)),
+        replica_id: config.replica_id.as_str().into(),
+        cluster_members: config.cluster_members.clone().unwrap_or_default().into(),
+        api_token: None,
+        caddy: Arc::new(CaddyServer::from_config(&config)),
+        nginx: Arc::new(NginxRenderer::from_config(&config)),
+        haproxy: Arc::new(HaproxyRenderer::from_config(&config)),
+        dns: Arc::new(DnsRenderer::from_config(&config)),
+        max_config_staleness_seconds: 0,
+        traefik_api_url: None,
+    }
+}
+
+/// Serve a router on a free local port for testing, returning its base URL
+#[cfg(test)]
+pub async fn serve(app: axum::Router) -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        axum::serve(
+            listener,
+            app.into_make_service_with_connect_info::<std::net::SocketAddr>(),
+        )
+        .await
+    });
+    format!("http://{}", addr)
+}
+
+/// GET a URL with extra request headers for testing, returning the status, headers
+/// and body
+#[cfg(test)]
+pub async fn get(
+    url: &str,
+    headers: &[(&str, &str)],
+) -> (axum::http::StatusCode, axum::http::HeaderMap, String) {
+    use http_body_util::{BodyExt, Empty};
+    use hyper_util::client::legacy::{Client, connect::HttpConnector};
+    use hyper_util::rt::TokioExecutor;
+
+    let client = Client::builder(TokioExecutor::new()).build(HttpConnector::new());
+    let mut request = axum::http::Request::get(url);
+    for (name, value) in headers {
+        request = request.header(*name, *value);
+    }
+    let response = client
+        .request(request.body(Empty::<axum::body::Bytes>::new()).unwrap())
+        .await
+        .expect("response");
+    let (parts, body) = response.into_parts();
+    let body = body.collect().await.expect("body").to_bytes();
+    (
+        parts.status,
+        parts.headers,
+        String::from_utf8_lossy(&body).into_owned(),
+    )
+}
@@ -0,0 +1,275 @@
+use crate::tailscale::PeerStatus;
+use crate::traefik::provider::{FilterCheck, TagCheck};
+use crate::{AppState, ErrorResponse};
+use axum::{
+    Router,
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Json, Response},
+    routing::get,
+};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+/// Slimmed view of a peer, with the outcome of the peer filters
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct PeerView {
+    pub id: String,
+    pub hostname: String,
+    pub dns_name: String,
+    pub tailscale_ips: Vec<String>,
+    pub tags: Vec<String>,
+    pub os: String,
+    pub online: bool,
+    /// Whether the peer passes the peer filters
+    pub included: bool,
+    /// Which filter excludes the peer
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exclusion_reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct PeersQuery {
+    /// Only peers that are (or are not) online
+    online: Option<bool>,
+    /// Only peers with a tag containing this text, matched like INCLUDE_TAGS ("web" or "tag:web")
+    tag: Option<String>,
+    /// Only peers running this OS, e.g. linux (case-insensitive)
+    os: Option<String>,
+    /// Only peers that pass (or fail) the peer filters
+    included: Option<bool>,
+}
+
+/// Why a peer is or is not part of the configuration
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct PeerExplanation {
+    pub id: String,
+    pub hostname: String,
+    /// Whether the peer passes every filter
+    pub included: bool,
+    /// Peer filters in the order they are applied
+    pub checks: Vec<FilterCheck>,
+    /// How each tag is parsed into services
+    pub tags: Vec<TagCheck>,
+    /// Further remarks, e.g. about the default service of untagged peers
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub notes: Vec<String>,
+}
+
+/// A node whose key expires within the warning window (or already expired)
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ExpiringPeer {
+    pub id: String,
+    pub hostname: String,
+    pub dns_name: String,
+    pub key_expiry: DateTime<Utc>,
+    /// Negative once the key has expired
+    pub expires_in_seconds: i64,
+    pub expired: bool,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct ExpiringQuery {
+    /// Warning window in days (default: KEY_EXPIRY_WARNING_DAYS)
+    within_days: Option<i64>,
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/peers", get(get_peers))
+        .route("/peers/expiring", get(get_expiring_peers))
+        .route("/explain/{hostname}", get(explain_peer))
+}
+
+#[utoipa::path(
+    get,
+    path = "/peers",
+    tag = "Status",
+    summary = "Get peers",
+    description = "Lists the peers of the tailnet with their addresses, tags and whether the peer filters include them (and if not, why), sorted by hostname",
+    params(PeersQuery),
+    responses(
+        (status = 200, description = "Matching peers", body = Vec<PeerView>),
+        (status = 503, description = "Service unavailable - cannot connect to Tailscale daemon", body = ErrorResponse)
+    )
+)]
+pub async fn get_peers(State(state): State<AppState>, Query(query): Query<PeersQuery>) -> Response {
+    let status = match state.provider.tailscale_client.get_status().await {
+        Ok(status) => status,
+        Err(_) => return tailscale_unavailable(),
+    };
+
+    let tag = query
+        .tag
+        .as_deref()
+        .map(|tag| tag.strip_prefix("tag:").unwrap_or(tag));
+    let mut peers: Vec<PeerView> = status
+        .peers
+        .iter()
+        .flat_map(|peers| peers.values().flatten())
+        .map(|peer| {
+            let exclusion_reason = state.provider.exclusion_reason(peer);
+            PeerView {
+                id: peer.id.0.clone(),
+                hostname: peer.hostname.clone(),
+                dns_name: peer.dns_name.trim_end_matches('.').to_string(),
+                tailscale_ips: peer.tailscale_ips.clone(),
+                tags: peer.tags.clone().unwrap_or_default(),
+                os: peer.os.clone(),
+                online: peer.online.unwrap_or(false),
+                included: exclusion_reason.is_none(),
+                exclusion_reason,
+            }
+        })
+        .filter(|peer| query.online.is_none_or(|online| peer.online == online))
+        .filter(|peer| {
+            query
+                .included
+                .is_none_or(|included| peer.included == included)
+        })
+        .filter(|peer| {
+            query
+                .os
+                .as_deref()
+                .is_none_or(|os| peer.os.eq_ignore_ascii_case(os))
+        })
+        .filter(|peer| {
+            tag.is_none_or(|tag| {
+                peer.tags.iter().any(|peer_tag| {
+                    peer_tag
+                        .strip_prefix("tag:")
+                        .unwrap_or(peer_tag)
+                        .contains(tag)
+                })
+            })
+        })
+        .collect();
+    peers.sort_by(|a, b| (&a.hostname, &a.id).cmp(&(&b.hostname, &b.id)));
+    Json(peers).into_response()
+}
+
+#[utoipa::path(
+    get,
+    path = "/peers/expiring",
+    tag = "Status",
+    summary = "Get peers with expiring keys",
+    description = "Lists the nodes (peers and this node) whose key expires within the warning window or has expired, soonest first. Nodes with key expiry disabled are never listed.",
+    params(ExpiringQuery),
+    responses(
+        (status = 200, description = "Nodes with expiring keys", body = Vec<ExpiringPeer>),
+        (status = 503, description = "Service unavailable - cannot connect to Tailscale daemon", body = ErrorResponse)
+    )
+)]
+pub async fn get_expiring_peers(
+    State(state): State<AppState>,
+    Query(query): Query<ExpiringQuery>,
+) -> Response {
+    let status = match state.provider.tailscale_client.get_status().await {
+        Ok(status) => status,
+        Err(_) => return tailscale_unavailable(),
+    };
+
+    let within_days = query
+        .within_days
+        .unwrap_or(state.provider.key_expiry_warning_days() as i64);
+    let now = Utc::now();
+    let deadline = now + Duration::days(within_days);
+
+    let mut expiring: Vec<ExpiringPeer> = status
+        .peers
+        .iter()
+        .flat_map(|peers| peers.values().flatten())
+        .chain(status.self_peer.as_ref())
+        .filter_map(|peer| expiring_peer(peer, now, deadline))
+        .collect();
+    expiring.sort_by(|a, b| (a.key_expiry, &a.hostname).cmp(&(b.key_expiry, &b.hostname)));
+    Json(expiring).into_response()
+}
+
+#[utoipa::path(
+    get,
+    path = "/explain/{hostname}",
+    tag = "Status",
+    summary = "Explain a peer",
+    description = "Walks through every peer filter and every tag of the peer, with the outcome and reason of each, to find out why a node is or is not routed",
+    params(("hostname" = String, Path, description = "Tailscale hostname of the peer (case-insensitive)")),
+    responses(
+        (status = 200, description = "Filter decisions for the peer", body = PeerExplanation),
+        (status = 404, description = "No peer with this hostname", body = ErrorResponse),
+        (status = 503, description = "Service unavailable - cannot connect to Tailscale daemon", body = ErrorResponse)
+    )
+)]
+pub async fn explain_peer(State(state): State<AppState>, Path(hostname): Path<String>) -> Response {
+    let status = match state.provider.tailscale_client.get_status().await {
+        Ok(status) => status,
+        Err(_) => return tailscale_unavailable(),
+    };
+
+    let is_self = status
+        .self_peer
+        .as_ref()
+        .is_some_and(|node| node.hostname.eq_ignore_ascii_case(&hostname));
+    let Some(peer) = status
+        .peers
+        .iter()
+        .flat_map(|peers| peers.values().flatten())
+        .chain(status.self_peer.as_ref())
+        .find(|peer| peer.hostname.eq_ignore_ascii_case(&hostname))
+    else {
+        let error_response = ErrorResponse {
+            error: format!("No peer with hostname {}", hostname),
+        };
+        return (StatusCode::NOT_FOUND, Json(error_response)).into_response();
+    };
+
+    let checks = state.provider.inclusion_checks(peer);
+    let tags = state.provider.tag_checks(peer);
+    let mut notes = Vec::new();
+    if is_self {
+        notes.push(
+            "This is the node running tailscaled, only published with INCLUDE_SELF".to_string(),
+        );
+    }
+    if peer.tags.is_none() {
+        notes.push(
+            "Untagged peers get a default service unless INCLUDE_TAGS or EXCLUDE_PEERS_WITHOUT_SERVICES is set"
+                .to_string(),
+        );
+    } else if !tags.iter().any(|tag| tag.passed) {
+        notes.push("No tag declares a service, so the peer publishes nothing".to_string());
+    }
+
+    Json(PeerExplanation {
+        id: peer.id.0.clone(),
+        hostname: peer.hostname.clone(),
+        included: checks.iter().all(|check| check.passed),
+        checks,
+        tags,
+        notes,
+    })
+    .into_response()
+}
+
+fn tailscale_unavailable() -> Response {
+    let error_response = ErrorResponse {
+        error: "Failed to connect to Tailscale daemon".to_string(),
+    };
+    (StatusCode::SERVICE_UNAVAILABLE, Json(error_response)).into_response()
+}
+
+fn expiring_peer(
+    peer: &PeerStatus,
+    now: DateTime<Utc>,
+    deadline: DateTime<Utc>,
+) -> Option<ExpiringPeer> {
+    let key_expiry = peer.key_expiry.filter(|expiry| *expiry <= deadline)?;
+    Some(ExpiringPeer {
+        id: peer.id.0.clone(),
+        hostname: peer.hostname.clone(),
+        dns_name: peer.dns_name.trim_end_matches('.').to_string(),
+        key_expiry,
+        expires_in_seconds: (key_expiry - now).num_seconds(),
+        expired: peer.expired.unwrap_or(false) || key_expiry <= now,
+    })
+}
@@ -0,0 +1,202 @@
+use crate::store::ConfigSnapshot;
+use crate::traefik::DynamicConfig;
+use crate::{AppState, ErrorResponse};
+use axum::{
+    Router,
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Json, Response},
+    routing::get,
+};
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap};
+use utoipa::ToSchema;
+
+/// A generated service with the servers and peers behind it
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ServiceSummary {
+    pub name: String,
+    /// http, tcp or udp
+    pub protocol: &'static str,
+    /// Server URLs (http) or addresses (tcp/udp)
+    pub servers: Vec<String>,
+    /// Hostnames of the peers owning the server addresses
+    pub peers: Vec<String>,
+    pub ports: Vec<u16>,
+}
+
+/// A generated router and the service it forwards to
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct RouterSummary {
+    pub name: String,
+    /// http, tcp or udp
+    pub protocol: &'static str,
+    /// Matching rule (udp routers have none)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rule: Option<String>,
+    pub service: String,
+    pub entry_points: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub middlewares: Vec<String>,
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/services", get(get_services))
+        .route("/routers", get(get_routers))
+}
+
+#[utoipa::path(
+    get,
+    path = "/services",
+    tag = "Configuration",
+    summary = "Get the generated services",
+    description = "Lists the services of the current configuration with their servers, ports and the peers behind them, sorted by protocol and name. Peers are resolved from the Tailscale addresses and left empty when the daemon is unreachable.",
+    responses(
+        (status = 200, description = "Services of the current configuration", body = Vec<ServiceSummary>),
+        (status = 503, description = "No configuration published yet", body = ErrorResponse)
+    )
+)]
+pub async fn get_services(State(state): State<AppState>) -> Response {
+    let Some(snapshot) = state.store.current().await else {
+        return not_published();
+    };
+
+    // Map the Tailscale addresses back to hostnames, best effort
+    let mut hostnames: HashMap<String, String> = HashMap::new();
+    if let Ok(status) = state.provider.tailscale_client.get_status().await {
+        for peer in status
+            .peers
+            .iter()
+            .flat_map(|peers| peers.values().flatten())
+            .chain(status.self_peer.as_ref())
+        {
+            for ip in &peer.tailscale_ips {
+                hostnames.insert(ip.clone(), peer.hostname.clone());
+            }
+        }
+    }
+
+    Json(service_summaries(&snapshot, &hostnames)).into_response()
+}
+
+#[utoipa::path(
+    get,
+    path = "/routers",
+    tag = "Configuration",
+    summary = "Get the generated routers",
+    description = "Lists the routers of the current configuration with their rule, entry points and service, sorted by protocol and name",
+    responses(
+        (status = 200, description = "Routers of the current configuration", body = Vec<RouterSummary>),
+        (status = 503, description = "No configuration published yet", body = ErrorResponse)
+    )
+)]
+pub async fn get_routers(State(state): State<AppState>) -> Response {
+    let Some(snapshot) = state.store.current().await else {
+        return not_published();
+    };
+    Json(router_summaries(&snapshot.config)).into_response()
+}
+
+fn not_published() -> Response {
+    let error_response = ErrorResponse {
+        error: "No configuration published yet".to_string(),
+    };
+    (StatusCode::SERVICE_UNAVAILABLE, Json(error_response)).into_response()
+}
+
+fn service_summaries(
+    snapshot: &ConfigSnapshot,
+    hostnames: &HashMap<String, String>,
+) -> Vec<ServiceSummary> {
+    let summary = |name: &String, protocol, servers: Vec<String>| {
+        let mut peers = BTreeSet::new();
+        let mut ports = BTreeSet::new();
+        for (host, port) in servers.iter().filter_map(|server| split_server(server)) {
+            if let Some(hostname) = hostnames.get(host) {
+                peers.insert(hostname.clone());
+            }
+            ports.extend(port);
+        }
+        ServiceSummary {
+            name: name.clone(),
+            protocol,
+            servers,
+            peers: peers.into_iter().collect(),
+            ports: ports.into_iter().collect(),
+        }
+    };
+
+    let config = &snapshot.config;
+    let mut summaries = Vec::new();
+    if let Some(http) = &config.http {
+        summaries.extend(http.services.iter().map(|(name, service)| {
+            let servers = service.load_balancer.servers.iter();
+            summary(name, "http", servers.map(|s| s.url.clone()).collect())
+        }));
+    }
+    if let Some(tcp) = &config.tcp {
+        summaries.extend(tcp.services.iter().map(|(name, service)| {
+            let servers = service.load_balancer.servers.iter();
+            summary(name, "tcp", servers.map(|s| s.address.clone()).collect())
+        }));
+    }
+    if let Some(udp) = &config.udp {
+        summaries.extend(udp.services.iter().map(|(name, service)| {
+            let servers = service.load_balancer.servers.iter();
+            summary(name, "udp", servers.map(|s| s.address.clone()).collect())
+        }));
+    }
+    summaries
+}
+
+fn router_summaries(config: &DynamicConfig) -> Vec<RouterSummary> {
+    let mut summaries = Vec::new();
+    if let Some(http) = &config.http {
+        summaries.extend(http.routers.iter().map(|(name, router)| RouterSummary {
+            name: name.clone(),
+            protocol: "http",
+            rule: Some(router.rule.clone()),
+            service: router.service.clone(),
+            entry_points: router.entry_points.clone().unwrap_or_default(),
+            middlewares: router.middlewares.clone().unwrap_or_default(),
+        }));
+    }
+    if let Some(tcp) = &config.tcp {
+        summaries.extend(tcp.routers.iter().map(|(name, router)| RouterSummary {
+            name: name.clone(),
+            protocol: "tcp",
+            rule: Some(router.rule.clone()),
+            service: router.service.clone(),
+            entry_points: router.entry_points.clone().unwrap_or_default(),
+            middlewares: Vec::new(),
+        }));
+    }
+    if let Some(udp) = &config.udp {
+        summaries.extend(udp.routers.iter().map(|(name, router)| RouterSummary {
+            name: name.clone(),
+            protocol: "udp",
+            rule: None,
+            service: router.service.clone(),
+            entry_points: router.entry_points.clone().unwrap_or_default(),
+            middlewares: Vec::new(),
+        }));
+    }
+    summaries
+}
+
+/// Split a server URL or address into its host and port, e.g. "http://[fd7a::1]:80/x"
+/// into ("fd7a::1", Some(80))
+fn split_server(server: &str) -> Option<(&str, Option<u16>)> {
+    let authority = server.split_once("://").map_or(server, |(_, rest)| rest);
+    let authority = authority.split('/').next()?;
+    if let Some(rest) = authority.strip_prefix('[') {
+        let (host, rest) = rest.split_once(']')?;
+        let port = rest.strip_prefix(':').and_then(|port| port.parse().ok());
+        return Some((host, port));
+    }
+    match authority.rsplit_once(':') {
+        Some((host, port)) => Some((host, port.parse().ok())),
+        None => Some((authority, None)),
+    }
+}
@@ -0,0 +1,34 @@
+use crate::CONFIG_VERSION_HEADER;
+use crate::store::ConfigStore;
+use axum::{
+    extract::{Request, State},
+    http::HeaderValue,
+    middleware::Next,
+    response::Response,
+};
+use std::sync::Arc;
+
+/// Set on configurations served while generating fresh ones keeps failing
+pub const CONFIG_STALE_HEADER: &str = "X-Config-Stale";
+/// Seconds since a stale configuration was generated
+pub const CONFIG_AGE_HEADER: &str = "X-Config-Age-Seconds";
+
+/// Flag responses carrying a configuration (those with a version header) as stale
+/// while the generations since it have failed, so consumers can tell it may be
+/// arbitrarily old
+pub async fn mark_stale(
+    State(store): State<Arc<ConfigStore>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let mut response = next.run(request).await;
+    if !response.headers().contains_key(CONFIG_VERSION_HEADER) {
+        return response;
+    }
+    if let Some(age) = store.stale_for().await {
+        let headers = response.headers_mut();
+        headers.insert(CONFIG_STALE_HEADER, HeaderValue::from_static("true"));
+        headers.insert(CONFIG_AGE_HEADER, HeaderValue::from(age));
+    }
+    response
+}
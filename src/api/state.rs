@@ -0,0 +1,344 @@
+use crate::api::admin::{AdminIdentity, regenerate, reject_on_follower, require_admin};
+use crate::store::ConfigSnapshot;
+use crate::traefik::DynamicConfig;
+use crate::traefik::overrides::ServiceOverride;
+use crate::{AppState, ErrorResponse};
+use axum::{
+    Extension, Router,
+    extract::State,
+    http::StatusCode,
+    middleware,
+    response::{IntoResponse, Json, Response},
+    routing::{get, post},
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use tracing::{error, info, warn};
+use utoipa::ToSchema;
+
+/// Format of the state document, raised on incompatible changes
+const STATE_FORMAT: u32 = 1;
+
+/// Runtime state of the provider as a single document, for backups and for moving the
+/// provider to another host. Sections left out of an import are kept as they are.
+/// Services are registered through tags and notifications subscribed to through the
+/// environment, so there are no registrations, annotations or subscriptions to carry.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ProviderState {
+    pub format: u32,
+    #[serde(default)]
+    pub exported_at: Option<DateTime<Utc>>,
+    /// Peers and services disabled through the admin API
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub disabled: Option<DisabledState>,
+    /// Service overrides, as in OVERRIDES_FILE
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<Object>)]
+    pub overrides: Option<BTreeMap<String, ServiceOverride>>,
+    /// Past configurations, oldest first
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub history: Option<Vec<HistorySnapshot>>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct DisabledState {
+    /// Hostnames of disabled peers
+    #[serde(default)]
+    pub peers: Vec<String>,
+    /// Names (or globs) of disabled services
+    #[serde(default)]
+    pub services: Vec<String>,
+}
+
+/// A configuration of the history with its version metadata
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct HistorySnapshot {
+    pub generation: u64,
+    pub version: String,
+    pub hash: String,
+    pub created_at: DateTime<Utc>,
+    pub config: DynamicConfig,
+}
+
+/// What an import changed
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ImportSummary {
+    pub disabled_peers: usize,
+    pub disabled_services: usize,
+    pub overrides: usize,
+    /// Imported history entries kept (up to CONFIG_HISTORY_SIZE, known versions skipped)
+    pub history_entries: usize,
+    /// Version of the configuration published after the import (None if regenerating failed)
+    pub version: Option<String>,
+}
+
+pub fn router(state: AppState) -> Router<AppState> {
+    Router::new()
+        .route("/state/export", get(export_state))
+        .route("/state/import", post(import_state))
+        .route_layer(middleware::from_fn_with_state(state, require_admin))
+}
+
+#[utoipa::path(
+    get,
+    path = "/state/export",
+    tag = "Admin",
+    summary = "Export the provider state",
+    description = "Returns the disabled peers and services, the service overrides and the configuration history as one JSON document, to back them up or import them on another host with POST /state/import",
+    responses(
+        (status = 200, description = "Provider state", body = ProviderState),
+        (status = 403, description = "Caller is not an authorized admin", body = ErrorResponse)
+    )
+)]
+pub async fn export_state(
+    State(state): State<AppState>,
+    Extension(identity): Extension<AdminIdentity>,
+) -> Json<ProviderState> {
+    let (peers, services) = state.provider.disabled();
+    let mut history: Vec<HistorySnapshot> = state
+        .store
+        .history()
+        .await
+        .iter()
+        .map(|snapshot| HistorySnapshot {
+            generation: snapshot.generation,
+            version: snapshot.version.clone(),
+            hash: snapshot.hash.clone(),
+            created_at: snapshot.created_at,
+            config: snapshot.config.clone(),
+        })
+        .collect();
+    history.reverse();
+    info!("Provider state exported by {}", identity.login_name);
+
+    Json(ProviderState {
+        format: STATE_FORMAT,
+        exported_at: Some(Utc::now()),
+        disabled: Some(DisabledState { peers, services }),
+        overrides: Some(state.provider.overrides().into_iter().collect()),
+        history: Some(history),
+    })
+}
+
+#[utoipa::path(
+    post,
+    path = "/state/import",
+    tag = "Admin",
+    summary = "Import the provider state",
+    description = "Replaces the disabled peers and services and the service overrides with those of an exported document, adds its configurations to the history and regenerates the configuration. History entries are checked against their content hash, and nothing is imported if one doesn't match. Imported overrides are written to OVERRIDES_FILE when it is set; disabled entries are kept in memory like those of the admin API.",
+    request_body = ProviderState,
+    responses(
+        (status = 200, description = "State imported", body = ImportSummary),
+        (status = 400, description = "Unsupported state format, or a history entry whose hash or version doesn't match its configuration", body = ErrorResponse),
+        (status = 403, description = "Caller is not an authorized admin", body = ErrorResponse),
+        (status = 409, description = "This replica mirrors the configuration of another replica, the leader", body = ErrorResponse),
+        (status = 500, description = "The overrides could not be written", body = ErrorResponse)
+    )
+)]
+pub async fn import_state(
+    State(state): State<AppState>,
+    Extension(identity): Extension<AdminIdentity>,
+    Json(document): Json<ProviderState>,
+) -> Response {
+    if let Some(response) = reject_on_follower(&state).await {
+        return response;
+    }
+    if document.format != STATE_FORMAT {
+        let error_response = ErrorResponse {
+            error: format!(
+                "Unsupported state format {}, expected {}",
+                document.format, STATE_FORMAT
+            ),
+        };
+        return (StatusCode::BAD_REQUEST, Json(error_response)).into_response();
+    }
+
+    let mut summary = ImportSummary {
+        disabled_peers: 0,
+        disabled_services: 0,
+        overrides: 0,
+        history_entries: 0,
+        version: None,
+    };
+    // First, as the only section a crafted or damaged document can be rejected for
+    if let Some(history) = document.history {
+        let snapshots = history
+            .into_iter()
+            .map(|entry| ConfigSnapshot {
+                config: entry.config,
+                generation: entry.generation,
+                hash: entry.hash,
+                version: entry.version,
+                created_at: entry.created_at,
+            })
+            .collect();
+        summary.history_entries = match state.store.import_history(snapshots).await {
+            Ok(imported) => imported,
+            Err(e) => {
+                warn!("Rejected state import by {}: {}", identity.login_name, e);
+                let error_response = ErrorResponse {
+                    error: format!("Invalid history: {}", e),
+                };
+                return (StatusCode::BAD_REQUEST, Json(error_response)).into_response();
+            }
+        };
+    }
+    if let Some(overrides) = document.overrides {
+        summary.overrides = overrides.len();
+        let overrides: HashMap<String, ServiceOverride> = overrides.into_iter().collect();
+        if let Err(e) = state.provider.set_overrides(overrides) {
+            error!(
+                "Importing overrides for {} failed: {}",
+                identity.login_name, e
+            );
+            let error_response = ErrorResponse {
+                error: format!("Failed to import the overrides: {}", e),
+            };
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json(error_response)).into_response();
+        }
+    }
+    if let Some(disabled) = document.disabled {
+        summary.disabled_peers = disabled.peers.len();
+        summary.disabled_services = disabled.services.len();
+        state
+            .provider
+            .set_disabled(disabled.peers, disabled.services);
+    }
+    info!(
+        "Provider state imported by {}: {} disabled peers, {} disabled services, {} overrides, {} history entries",
+        identity.login_name,
+        summary.disabled_peers,
+        summary.disabled_services,
+        summary.overrides,
+        summary.history_entries
+    );
+
+    summary.version = regenerate(&state, &identity)
+        .await
+        .map(|snapshot| snapshot.version.clone());
+    (StatusCode::OK, Json(summary)).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::test_state;
+    use crate::config::ProviderConfig;
+    use serde_json::json;
+
+    fn app_state() -> AppState {
+        test_state(ProviderConfig::default())
+    }
+
+    fn admin() -> Extension<AdminIdentity> {
+        Extension(AdminIdentity {
+            login_name: "alice@example.com".to_string(),
+            node_name: "laptop".to_string(),
+            tags: Vec::new(),
+        })
+    }
+
+    async fn export(state: &AppState) -> ProviderState {
+        export_state(State(state.clone()), admin()).await.0
+    }
+
+    async fn import(state: &AppState, document: ProviderState) -> (StatusCode, serde_json::Value) {
+        let response = import_state(State(state.clone()), admin(), Json(document)).await;
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .expect("body");
+        (status, serde_json::from_slice(&body).expect("JSON body"))
+    }
+
+    /// A source provider with something in every section of the state
+    async fn populated() -> AppState {
+        let state = app_state();
+        state
+            .provider
+            .set_disabled(vec!["nas".to_string()], vec!["web*".to_string()]);
+        let overrides = serde_json::from_value(json!({
+            "api": {"router": {"priority": 50}}
+        }))
+        .expect("overrides");
+        state.provider.set_overrides(overrides).expect("overrides");
+        for url in ["http://100.64.0.1:80", "http://100.64.0.2:80"] {
+            let config = serde_json::from_value(json!({
+                "http": {
+                    "routers": {},
+                    "services": {"web": {"loadBalancer": {"servers": [{"url": url}]}}}
+                },
+                "tcp": null,
+                "udp": null
+            }))
+            .expect("configuration");
+            state.store.publish(config).await;
+        }
+        state
+    }
+
+    fn versions(document: &ProviderState) -> Vec<String> {
+        let history = document.history.as_deref().unwrap_or_default();
+        history.iter().map(|entry| entry.version.clone()).collect()
+    }
+
+    #[tokio::test]
+    async fn export_import_round_trip() {
+        let exported = export(&populated().await).await;
+        assert_eq!(exported.format, STATE_FORMAT);
+        // Through JSON, like a backup file
+        let document = serde_json::from_value(serde_json::to_value(&exported).unwrap()).unwrap();
+
+        let state = app_state();
+        let (status, summary) = import(&state, document).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(summary["disabled_peers"], 1);
+        assert_eq!(summary["disabled_services"], 1);
+        assert_eq!(summary["overrides"], 1);
+        assert_eq!(summary["history_entries"], 2);
+
+        let reexported = export(&state).await;
+        let disabled = reexported.disabled.as_ref().expect("disabled");
+        assert_eq!(disabled.peers, ["nas"]);
+        assert_eq!(disabled.services, ["web*"]);
+        let overrides = reexported.overrides.as_ref().expect("overrides");
+        assert_eq!(overrides["api"].router.priority, Some(50));
+        assert_eq!(versions(&reexported), versions(&exported));
+        for entry in exported.history.as_deref().unwrap_or_default() {
+            let found = state.store.find(&entry.version).await.expect("imported");
+            assert_eq!(found.hash, entry.hash);
+        }
+    }
+
+    #[tokio::test]
+    async fn import_rejects_unknown_format() {
+        let mut document = export(&populated().await).await;
+        document.format = STATE_FORMAT + 1;
+
+        let state = app_state();
+        let (status, body) = import(&state, document).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body["error"].as_str().unwrap().contains("format"));
+        let untouched = export(&state).await;
+        assert!(untouched.disabled.unwrap().peers.is_empty());
+        assert!(untouched.overrides.unwrap().is_empty());
+        assert!(untouched.history.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn import_rejects_history_not_matching_its_hash() {
+        let mut document = export(&populated().await).await;
+        let history = document.history.as_mut().expect("history");
+        history[1].config = history[0].config.clone();
+
+        let state = app_state();
+        let (status, body) = import(&state, document).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body["error"].as_str().unwrap().contains("Hash"));
+        let untouched = export(&state).await;
+        assert!(untouched.disabled.unwrap().peers.is_empty());
+        assert!(untouched.overrides.unwrap().is_empty());
+        assert!(untouched.history.unwrap().is_empty());
+    }
+}
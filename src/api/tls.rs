@@ -0,0 +1,166 @@
+use crate::api::limits::{LimitedListener, LimitedStream};
+use crate::tailscale::TailscaleClient;
+use axum::serve::Listener;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio_rustls::TlsAcceptor;
+use tokio_rustls::rustls::ServerConfig;
+use tokio_rustls::rustls::crypto::ring::{default_provider, sign::any_supported_type};
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use tokio_rustls::rustls::server::{ClientHello, ResolvesServerCert};
+use tokio_rustls::rustls::sign::CertifiedKey;
+use tokio_rustls::server::TlsStream;
+use tracing::{debug, info, warn};
+
+/// How long a client gets to complete the TLS handshake
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// tailscaled renews certificates 30 days before expiry, so fetching daily is plenty
+const RENEW_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Retry delay while no certificate could be fetched
+const RETRY_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Serves the certificate last fetched from tailscaled
+#[derive(Debug, Default)]
+pub struct TailscaleCertResolver {
+    current: RwLock<Option<Arc<CertifiedKey>>>,
+}
+
+impl ResolvesServerCert for TailscaleCertResolver {
+    fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        self.current.read().unwrap().clone()
+    }
+}
+
+impl TailscaleCertResolver {
+    /// Fetch the certificate of `domain` (default: this node's MagicDNS name) now and
+    /// keep it renewed in the background
+    pub fn spawn(client: Arc<TailscaleClient>, domain: Option<String>) -> Arc<Self> {
+        let resolver = Arc::new(Self::default());
+        let renewing = resolver.clone();
+        tokio::spawn(async move {
+            loop {
+                let delay = match renewing.renew(&client, domain.as_deref()).await {
+                    Ok(domain) => {
+                        info!("Loaded the Tailscale certificate of {}", domain);
+                        RENEW_INTERVAL
+                    }
+                    Err(e) => {
+                        warn!("Failed to load the Tailscale certificate: {}", e);
+                        RETRY_INTERVAL
+                    }
+                };
+                tokio::time::sleep(delay).await;
+            }
+        });
+        resolver
+    }
+
+    async fn renew(
+        &self,
+        client: &TailscaleClient,
+        domain: Option<&str>,
+    ) -> Result<String, String> {
+        let domain = match domain {
+            Some(domain) => domain.to_string(),
+            None => {
+                let status = client
+                    .get_status_without_peers()
+                    .await
+                    .map_err(|e| e.to_string())?;
+                let dns_name = status
+                    .self_peer
+                    .map(|peer| peer.dns_name.trim_end_matches('.').to_string())
+                    .unwrap_or_default();
+                if dns_name.is_empty() {
+                    return Err("this node has no MagicDNS name".to_string());
+                }
+                dns_name
+            }
+        };
+
+        let pem = client
+            .get_cert_pair(&domain)
+            .await
+            .map_err(|e| e.to_string())?;
+        let certs = CertificateDer::pem_slice_iter(&pem)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("invalid certificate: {:?}", e))?;
+        if certs.is_empty() {
+            return Err("tailscaled returned no certificate".to_string());
+        }
+        let key = PrivateKeyDer::from_pem_slice(&pem)
+            .map_err(|e| format!("invalid private key: {:?}", e))?;
+        let key = any_supported_type(&key).map_err(|e| e.to_string())?;
+
+        *self.current.write().unwrap() = Some(Arc::new(CertifiedKey::new(certs, key)));
+        Ok(domain)
+    }
+}
+
+/// Listener completing TLS handshakes in the background, so a slow client does not
+/// hold up the others
+pub struct TlsListener {
+    handshaken: mpsc::Receiver<(TlsStream<LimitedStream>, SocketAddr)>,
+    local_addr: SocketAddr,
+}
+
+impl TlsListener {
+    pub fn new(
+        mut listener: LimitedListener,
+        resolver: Arc<TailscaleCertResolver>,
+    ) -> io::Result<Self> {
+        let local_addr = listener.local_addr()?;
+        let mut config = ServerConfig::builder_with_provider(Arc::new(default_provider()))
+            .with_safe_default_protocol_versions()
+            .map_err(io::Error::other)?
+            .with_no_client_auth()
+            .with_cert_resolver(resolver);
+        config.alpn_protocols = vec![b"http/1.1".to_vec()];
+        let acceptor = TlsAcceptor::from(Arc::new(config));
+
+        let (sender, handshaken) = mpsc::channel(64);
+        tokio::spawn(async move {
+            loop {
+                let (stream, addr) = listener.accept().await;
+                let acceptor = acceptor.clone();
+                let sender = sender.clone();
+                tokio::spawn(async move {
+                    match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                        Ok(Ok(stream)) => {
+                            let _ = sender.send((stream, addr)).await;
+                        }
+                        Ok(Err(e)) => debug!("TLS handshake with {} failed: {}", addr, e),
+                        Err(_) => debug!("TLS handshake with {} timed out", addr),
+                    }
+                });
+            }
+        });
+
+        Ok(Self {
+            handshaken,
+            local_addr,
+        })
+    }
+}
+
+impl Listener for TlsListener {
+    type Io = TlsStream<LimitedStream>;
+    type Addr = SocketAddr;
+
+    async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+        self.handshaken
+            .recv()
+            .await
+            .expect("the accept task runs as long as the listener")
+    }
+
+    fn local_addr(&self) -> io::Result<Self::Addr> {
+        Ok(self.local_addr)
+    }
+}
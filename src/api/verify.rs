@@ -0,0 +1,372 @@
+use crate::traefik::DynamicConfig;
+use crate::{AppState, ErrorResponse};
+use axum::{
+    Router,
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Json, Response},
+    routing::get,
+};
+use http_body_util::{BodyExt, Full};
+use hyper::body::Bytes;
+use hyper_util::client::legacy::{Client, connect::HttpConnector};
+use hyper_util::rt::TokioExecutor;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::Duration;
+use tracing::warn;
+use utoipa::ToSchema;
+
+/// How long Traefik gets to list all of its routers and services
+const TRAEFIK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Entries requested per page of the Traefik API
+const PAGE_SIZE: usize = 100;
+
+/// Header of Traefik API responses naming the next page (1 on the last one)
+const NEXT_PAGE_HEADER: &str = "X-Next-Page";
+
+/// Generated entries of one kind, by whether Traefik has them. Entries are named
+/// "<protocol>.<name>" like in /config/diff.
+#[derive(Debug, Clone, Default, Serialize, ToSchema)]
+pub struct IngestionStatus {
+    /// Loaded by Traefik and enabled
+    pub loaded: Vec<String>,
+    /// Not known to Traefik
+    pub missing: Vec<String>,
+    /// Loaded but disabled or with warnings, with the errors Traefik reports
+    pub errors: BTreeMap<String, Vec<String>>,
+}
+
+/// Generated configuration compared with what Traefik has loaded
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct VerifyReport {
+    /// Version of the configuration checked
+    pub version: String,
+    pub traefik_api_url: String,
+    /// True when Traefik has loaded every generated router and service without errors
+    pub in_sync: bool,
+    pub routers: IngestionStatus,
+    pub services: IngestionStatus,
+}
+
+/// Router or service as listed by the Traefik API
+#[derive(Deserialize)]
+struct TraefikEntry {
+    /// "<name>@<provider>"
+    name: String,
+    #[serde(default)]
+    status: Option<String>,
+    #[serde(default)]
+    error: Vec<String>,
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new().route("/verify", get(verify_ingestion))
+}
+
+#[utoipa::path(
+    get,
+    path = "/verify",
+    tag = "Configuration",
+    summary = "Verify that Traefik loaded the configuration",
+    description = "Lists the routers and services of TRAEFIK_API_URL and reports which generated ones Traefik has loaded, which are missing and which it disabled with errors. Entries are matched by name whatever provider Traefik loaded them from. Traefik polling /config with filters, or not having polled the latest version yet, also shows up as missing entries.",
+    responses(
+        (status = 200, description = "Ingestion report", body = VerifyReport),
+        (status = 404, description = "TRAEFIK_API_URL is not configured", body = ErrorResponse),
+        (status = 502, description = "The Traefik API could not be queried", body = ErrorResponse),
+        (status = 503, description = "No configuration published yet", body = ErrorResponse)
+    )
+)]
+pub async fn verify_ingestion(State(state): State<AppState>) -> Response {
+    let Some(url) = state.traefik_api_url.as_deref() else {
+        let error_response = ErrorResponse {
+            error: "TRAEFIK_API_URL is not configured".to_string(),
+        };
+        return (StatusCode::NOT_FOUND, Json(error_response)).into_response();
+    };
+    let Some(snapshot) = state.store.current().await else {
+        let error_response = ErrorResponse {
+            error: "No configuration published yet".to_string(),
+        };
+        return (StatusCode::SERVICE_UNAVAILABLE, Json(error_response)).into_response();
+    };
+
+    let (generated_routers, generated_services) = generated_entries(&snapshot.config);
+    let client = Client::builder(TokioExecutor::new()).build(HttpConnector::new());
+    let loaded = tokio::time::timeout(
+        TRAEFIK_TIMEOUT,
+        fetch_loaded(&client, url, &generated_routers, &generated_services),
+    )
+    .await
+    .unwrap_or_else(|_| Err("timed out".to_string()));
+    let (loaded_routers, loaded_services) = match loaded {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            warn!("Failed to query the Traefik API {}: {}", url, e);
+            let error_response = ErrorResponse {
+                error: format!("Failed to query the Traefik API: {}", e),
+            };
+            return (StatusCode::BAD_GATEWAY, Json(error_response)).into_response();
+        }
+    };
+
+    let routers = ingestion_status(&generated_routers, &loaded_routers);
+    let services = ingestion_status(&generated_services, &loaded_services);
+    let in_sync = [&routers, &services]
+        .iter()
+        .all(|status| status.missing.is_empty() && status.errors.is_empty());
+
+    Json(VerifyReport {
+        version: snapshot.version.clone(),
+        traefik_api_url: url.to_string(),
+        in_sync,
+        routers,
+        services,
+    })
+    .into_response()
+}
+
+/// Names of the generated routers and services, as "<protocol>.<name>"
+fn generated_entries(config: &DynamicConfig) -> (BTreeSet<String>, BTreeSet<String>) {
+    let mut routers = BTreeSet::new();
+    let mut services = BTreeSet::new();
+    let named = |protocol: &str, name: &String| format!("{}.{}", protocol, name);
+    if let Some(http) = &config.http {
+        routers.extend(http.routers.keys().map(|name| named("http", name)));
+        services.extend(http.services.keys().map(|name| named("http", name)));
+    }
+    if let Some(tcp) = &config.tcp {
+        routers.extend(tcp.routers.keys().map(|name| named("tcp", name)));
+        services.extend(tcp.services.keys().map(|name| named("tcp", name)));
+    }
+    if let Some(udp) = &config.udp {
+        routers.extend(udp.routers.keys().map(|name| named("udp", name)));
+        services.extend(udp.services.keys().map(|name| named("udp", name)));
+    }
+    (routers, services)
+}
+
+type LoadedEntries = BTreeMap<String, Vec<String>>;
+
+/// Routers and services Traefik has loaded, by "<protocol>.<name>", with their errors.
+/// Only the protocols the generated configuration has are queried.
+async fn fetch_loaded(
+    client: &Client<HttpConnector, Full<Bytes>>,
+    url: &str,
+    generated_routers: &BTreeSet<String>,
+    generated_services: &BTreeSet<String>,
+) -> Result<(LoadedEntries, LoadedEntries), String> {
+    let mut routers = LoadedEntries::new();
+    let mut services = LoadedEntries::new();
+    for protocol in ["http", "tcp", "udp"] {
+        let prefix = format!("{}.", protocol);
+        let has = |names: &BTreeSet<String>| names.iter().any(|name| name.starts_with(&prefix));
+        if has(generated_routers) {
+            let entries = fetch_entries(client, url, &format!("{}/routers", protocol)).await?;
+            routers.extend(loaded_entries(protocol, entries));
+        }
+        if has(generated_services) {
+            let entries = fetch_entries(client, url, &format!("{}/services", protocol)).await?;
+            services.extend(loaded_entries(protocol, entries));
+        }
+    }
+    Ok((routers, services))
+}
+
+fn loaded_entries(
+    protocol: &str,
+    entries: Vec<TraefikEntry>,
+) -> impl Iterator<Item = (String, Vec<String>)> {
+    entries.into_iter().map(move |entry| {
+        let name = entry
+            .name
+            .rsplit_once('@')
+            .map_or(entry.name.as_str(), |(name, _)| name);
+        let mut errors = entry.error;
+        if errors.is_empty() && entry.status.as_deref().is_some_and(|s| s != "enabled") {
+            errors.push(format!("status {}", entry.status.unwrap_or_default()));
+        }
+        (format!("{}.{}", protocol, name), errors)
+    })
+}
+
+/// Every entry of a Traefik API list such as "http/routers", following its pages
+async fn fetch_entries(
+    client: &Client<HttpConnector, Full<Bytes>>,
+    url: &str,
+    list: &str,
+) -> Result<Vec<TraefikEntry>, String> {
+    let mut entries = Vec::new();
+    let mut page = 1;
+    loop {
+        let uri: hyper::Uri = format!(
+            "{}/api/{}?page={}&per_page={}",
+            url.trim_end_matches('/'),
+            list,
+            page,
+            PAGE_SIZE
+        )
+        .parse()
+        .map_err(|e| format!("invalid URL: {}", e))?;
+
+        let response = client.get(uri).await.map_err(|e| e.to_string())?;
+        if !response.status().is_success() {
+            return Err(format!("HTTP {} for /api/{}", response.status(), list));
+        }
+        let next_page = response
+            .headers()
+            .get(NEXT_PAGE_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<usize>().ok());
+
+        let body = response
+            .into_body()
+            .collect()
+            .await
+            .map_err(|e| e.to_string())?
+            .to_bytes();
+        let page_entries: Vec<TraefikEntry> =
+            serde_json::from_slice(&body).map_err(|e| format!("/api/{}: {}", list, e))?;
+        entries.extend(page_entries);
+
+        match next_page {
+            Some(next) if next > page => page = next,
+            _ => return Ok(entries),
+        }
+    }
+}
+
+fn ingestion_status(generated: &BTreeSet<String>, loaded: &LoadedEntries) -> IngestionStatus {
+    let mut status = IngestionStatus::default();
+    for name in generated {
+        match loaded.get(name) {
+            None => status.missing.push(name.clone()),
+            Some(errors) if !errors.is_empty() => {
+                status.errors.insert(name.clone(), errors.clone());
+            }
+            Some(_) => status.loaded.push(name.clone()),
+        }
+    }
+    status
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::{serve, test_state};
+    use crate::config::ProviderConfig;
+    use axum::extract::{Path, Query};
+    use axum::http::HeaderMap;
+    use serde_json::{Value, json};
+    use std::collections::HashMap;
+
+    /// Traefik API listing two routers per page and the services at once; TCP and UDP
+    /// lists fail, as the generated configuration has none
+    async fn traefik_api(
+        Path((protocol, kind)): Path<(String, String)>,
+        Query(query): Query<HashMap<String, String>>,
+    ) -> Response {
+        if protocol != "http" {
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+        let entries = match kind.as_str() {
+            "routers" => vec![
+                json!({"name": "web-router@http", "status": "enabled"}),
+                json!({"name": "api-router@file", "status": "disabled", "error": ["middleware auth@http does not exist"]}),
+                json!({"name": "admin-router@http", "status": "warning"}),
+                json!({"name": "other-router@docker", "status": "enabled"}),
+            ],
+            _ => vec![
+                json!({"name": "web@http", "status": "enabled"}),
+                json!({"name": "api@http", "status": "enabled"}),
+            ],
+        };
+        let page: usize = query["page"].parse().unwrap();
+        let per_page = if kind == "routers" { 2 } else { entries.len() };
+        let last = entries.len().div_ceil(per_page);
+        let mut headers = HeaderMap::new();
+        let next = if page < last { page + 1 } else { 1 };
+        headers.insert(NEXT_PAGE_HEADER, next.into());
+        let page: Vec<Value> = entries
+            .into_iter()
+            .skip((page - 1) * per_page)
+            .take(per_page)
+            .collect();
+        (headers, Json(page)).into_response()
+    }
+
+    fn config() -> DynamicConfig {
+        let router = |service: &str| json!({ "rule": "Host(`example.com`)", "service": service });
+        let service = json!({ "loadBalancer": { "servers": [] } });
+        serde_json::from_value(json!({
+            "http": {
+                "routers": {
+                    "web-router": router("web"),
+                    "api-router": router("api"),
+                    "admin-router": router("admin"),
+                    "db-router": router("db")
+                },
+                "services": {"web": service, "api": service, "admin": service}
+            }
+        }))
+        .unwrap()
+    }
+
+    async fn body(response: Response) -> Value {
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn reports_loaded_missing_and_failing_entries() {
+        let traefik = serve(Router::new().route("/api/{protocol}/{kind}", get(traefik_api))).await;
+        let mut state = test_state(ProviderConfig::default());
+        state.traefik_api_url = Some(traefik.as_str().into());
+        let snapshot = state.store.publish(config()).await;
+
+        let response = verify_ingestion(State(state)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let report = body(response).await;
+        assert_eq!(report["version"], snapshot.version.as_str());
+        assert_eq!(report["in_sync"], false);
+        assert_eq!(
+            report["routers"],
+            json!({
+                "loaded": ["http.web-router"],
+                "missing": ["http.db-router"],
+                "errors": {
+                    "http.admin-router": ["status warning"],
+                    "http.api-router": ["middleware auth@http does not exist"]
+                }
+            })
+        );
+        assert_eq!(
+            report["services"]["loaded"],
+            json!(["http.api", "http.web"])
+        );
+        assert_eq!(report["services"]["missing"], json!(["http.admin"]));
+    }
+
+    #[tokio::test]
+    async fn reports_why_nothing_can_be_verified() {
+        let state = test_state(ProviderConfig::default());
+        let response = verify_ingestion(State(state.clone())).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let mut state = state;
+        state.traefik_api_url = Some("http://127.0.0.1:9".into());
+        let response = verify_ingestion(State(state.clone())).await;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        state.store.publish(config()).await;
+        let response = verify_ingestion(State(state)).await;
+        assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+        let error = body(response).await["error"].as_str().unwrap().to_string();
+        assert!(
+            error.starts_with("Failed to query the Traefik API"),
+            "{}",
+            error
+        );
+    }
+}
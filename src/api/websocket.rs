@@ -0,0 +1,222 @@
+use crate::AppState;
+use crate::store::ConfigSnapshot;
+use crate::store::diff::ConfigDiff;
+use crate::traefik::DynamicConfig;
+use axum::{
+    Router,
+    extract::{
+        Query, State,
+        ws::{Message, WebSocket, WebSocketUpgrade},
+    },
+    response::Response,
+    routing::get,
+};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tokio::time::{MissedTickBehavior, interval};
+use tracing::debug;
+use utoipa::ToSchema;
+
+/// Pings keep idle connections from being dropped by proxies in between
+const PING_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Message pushed to /config/ws subscribers, tagged by "type"
+#[derive(Serialize, ToSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ConfigMessage {
+    /// The whole configuration, sent first and on every change without ?diff
+    Config {
+        version: String,
+        config: DynamicConfig,
+    },
+    /// The routers and services a change touched, relative to the previous message
+    Diff { version: String, diff: ConfigDiff },
+}
+
+#[derive(Deserialize)]
+pub struct WebSocketQuery {
+    /// Push diffs instead of the whole configuration after the first message
+    #[serde(default)]
+    diff: bool,
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new().route("/config/ws", get(config_socket))
+}
+
+#[utoipa::path(
+    get,
+    path = "/config/ws",
+    tag = "Configuration",
+    summary = "Push configuration changes over a WebSocket",
+    description = "Upgrades to a WebSocket that receives the current configuration as a JSON text message, then a new message whenever it changes: the whole configuration, or with diff=true the routers and services that changed (the same as /config/diff). A diff that doesn't follow the last message, e.g. after the client fell behind, is sent as the whole configuration instead.",
+    params(
+        ("diff" = Option<bool>, Query, description = "Push diffs after the first message")
+    ),
+    responses(
+        (status = 101, description = "Switching to the WebSocket protocol; messages are ConfigMessage", body = ConfigMessage)
+    )
+)]
+pub async fn config_socket(
+    State(state): State<AppState>,
+    Query(query): Query<WebSocketQuery>,
+    upgrade: WebSocketUpgrade,
+) -> Response {
+    upgrade.on_upgrade(move |socket| push_changes(state, socket, query.diff))
+}
+
+async fn push_changes(state: AppState, mut socket: WebSocket, diffs: bool) {
+    // Subscribed before the first message, so a change in between isn't missed
+    let mut generations = state.store.subscribe();
+    let mut sent: Option<String> = None;
+    let mut ping = interval(PING_INTERVAL);
+    ping.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    ping.tick().await;
+
+    if let Some(snapshot) = state.store.current().await {
+        if send(&mut socket, full(&snapshot)).await.is_err() {
+            return;
+        }
+        sent = Some(snapshot.version.clone());
+    }
+
+    loop {
+        tokio::select! {
+            changed = generations.changed() => {
+                if changed.is_err() {
+                    break;
+                }
+                let Some(snapshot) = state.store.current().await else {
+                    continue;
+                };
+                if sent.as_ref() == Some(&snapshot.version) {
+                    continue;
+                }
+                let diff = match diffs {
+                    true => state.store.last_diff().await.filter(|diff| {
+                        diff.to_version == snapshot.version && diff.from_version == sent
+                    }),
+                    false => None,
+                };
+                let message = match diff {
+                    Some(diff) => ConfigMessage::Diff {
+                        version: snapshot.version.clone(),
+                        diff: (*diff).clone(),
+                    },
+                    None => full(&snapshot),
+                };
+                if send(&mut socket, message).await.is_err() {
+                    break;
+                }
+                sent = Some(snapshot.version.clone());
+            }
+            received = socket.recv() => match received {
+                // Pings are answered by the socket itself, anything else is ignored
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => {}
+            },
+            _ = ping.tick() => {
+                if socket.send(Message::Ping(Default::default())).await.is_err() {
+                    break;
+                }
+            }
+        }
+    }
+    debug!("Configuration WebSocket closed");
+}
+
+fn full(snapshot: &ConfigSnapshot) -> ConfigMessage {
+    ConfigMessage::Config {
+        version: snapshot.version.clone(),
+        config: snapshot.config.clone(),
+    }
+}
+
+async fn send(socket: &mut WebSocket, message: ConfigMessage) -> Result<(), axum::Error> {
+    let text = serde_json::to_string(&message).expect("configuration serializes");
+    socket.send(Message::Text(text.into())).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::{serve, test_state};
+    use crate::config::ProviderConfig;
+    use futures_util::StreamExt;
+    use serde_json::{Value, json};
+    use tokio_tungstenite::{MaybeTlsStream, WebSocketStream, connect_async};
+
+    type Client = WebSocketStream<MaybeTlsStream<tokio::net::TcpStream>>;
+
+    fn config(services: &[&str]) -> DynamicConfig {
+        let services: serde_json::Map<String, Value> = services
+            .iter()
+            .map(|name| {
+                let service =
+                    json!({ "loadBalancer": { "servers": [{ "url": "http://100.64.0.1:80" }] } });
+                (name.to_string(), service)
+            })
+            .collect();
+        serde_json::from_value(json!({ "http": { "services": services } })).unwrap()
+    }
+
+    async fn connect(state: &AppState, query: &str) -> Client {
+        let url = serve(router().with_state(state.clone())).await;
+        let url = format!("{}/config/ws{}", url.replace("http://", "ws://"), query);
+        connect_async(url).await.expect("WebSocket handshake").0
+    }
+
+    /// The next configuration message, skipping pings
+    async fn next(client: &mut Client) -> Value {
+        loop {
+            let message = tokio::time::timeout(Duration::from_secs(5), client.next())
+                .await
+                .expect("a message in time")
+                .expect("an open socket")
+                .expect("a valid message");
+            if let tokio_tungstenite::tungstenite::Message::Text(text) = message {
+                return serde_json::from_str(&text).unwrap();
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn pushes_the_whole_configuration_on_every_change() {
+        let state = test_state(ProviderConfig::default());
+        let first = state.store.publish(config(&["web"])).await;
+        let mut client = connect(&state, "").await;
+
+        let message = next(&mut client).await;
+        assert_eq!(message["type"], "config");
+        assert_eq!(message["version"], first.version.as_str());
+        assert!(message["config"]["http"]["services"]["web"].is_object());
+
+        // Republishing the same content is no change and sends nothing
+        state.store.publish(config(&["web"])).await;
+        let second = state.store.publish(config(&["web", "db"])).await;
+        let message = next(&mut client).await;
+        assert_eq!(message["type"], "config");
+        assert_eq!(message["version"], second.version.as_str());
+        assert!(message["config"]["http"]["services"]["db"].is_object());
+    }
+
+    #[tokio::test]
+    async fn pushes_diffs_after_the_first_message() {
+        let state = test_state(ProviderConfig::default());
+        let mut client = connect(&state, "?diff=true").await;
+
+        // Nothing is published yet, so the first message waits for a configuration
+        let first = state.store.publish(config(&["web"])).await;
+        let message = next(&mut client).await;
+        assert_eq!(message["type"], "config");
+        assert_eq!(message["version"], first.version.as_str());
+
+        let second = state.store.publish(config(&["db"])).await;
+        let message = next(&mut client).await;
+        assert_eq!(message["type"], "diff");
+        assert_eq!(message["version"], second.version.as_str());
+        assert_eq!(message["diff"]["from_version"], first.version.as_str());
+        assert_eq!(message["diff"]["services"]["added"], json!(["http.db"]));
+        assert_eq!(message["diff"]["services"]["removed"], json!(["http.web"]));
+    }
+}
@@ -0,0 +1,65 @@
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use std::error::Error;
+use std::fmt;
+use std::path::Path;
+
+#[derive(Debug)]
+pub enum ConfigFileError {
+    Io(String, std::io::Error),
+    Json(String, serde_json::Error),
+    Yaml(String, serde_yaml::Error),
+    Write(String, std::io::Error),
+    Serialize(String, String),
+}
+
+impl fmt::Display for ConfigFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigFileError::Io(path, err) => write!(f, "Failed to read {}: {}", path, err),
+            ConfigFileError::Json(path, err) => write!(f, "Invalid JSON in {}: {}", path, err),
+            ConfigFileError::Yaml(path, err) => write!(f, "Invalid YAML in {}: {}", path, err),
+            ConfigFileError::Write(path, err) => write!(f, "Failed to write {}: {}", path, err),
+            ConfigFileError::Serialize(path, err) => {
+                write!(f, "Failed to serialize {}: {}", path, err)
+            }
+        }
+    }
+}
+
+impl Error for ConfigFileError {}
+
+/// Load a JSON or YAML file, picking the format from the file extension
+pub fn load_file<T: DeserializeOwned>(path: &str) -> Result<T, ConfigFileError> {
+    let content =
+        std::fs::read_to_string(path).map_err(|e| ConfigFileError::Io(path.to_string(), e))?;
+
+    if is_yaml(path) {
+        serde_yaml::from_str(&content).map_err(|e| ConfigFileError::Yaml(path.to_string(), e))
+    } else {
+        serde_json::from_str(&content).map_err(|e| ConfigFileError::Json(path.to_string(), e))
+    }
+}
+
+/// Write a JSON or YAML file, picking the format from the file extension. The file is
+/// replaced through a temporary file, so a crash never leaves a truncated one behind.
+pub fn save_file<T: Serialize>(path: &str, value: &T) -> Result<(), ConfigFileError> {
+    let content = if is_yaml(path) {
+        serde_yaml::to_string(value).map_err(|e| e.to_string())
+    } else {
+        serde_json::to_string_pretty(value).map_err(|e| e.to_string())
+    }
+    .map_err(|e| ConfigFileError::Serialize(path.to_string(), e))?;
+
+    let temp_path = format!("{}.tmp", path);
+    std::fs::write(&temp_path, content)
+        .and_then(|()| std::fs::rename(&temp_path, path))
+        .map_err(|e| ConfigFileError::Write(path.to_string(), e))
+}
+
+fn is_yaml(path: &str) -> bool {
+    Path::new(path)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("yaml") || ext.eq_ignore_ascii_case("yml"))
+}
@@ -1,5 +1,9 @@
+pub mod file;
+
+use crate::traefik::RateLimitMiddleware;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::net::IpAddr;
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub enum Protocol {
@@ -9,9 +13,9 @@ pub enum Protocol {
 }
 
 impl Protocol {
-    pub fn from_str(s: &str) -> Self {
+    pub fn parse(s: &str) -> Self {
         match s.to_lowercase().as_str() {
-            "tcp" => Protocol::Tcp,
+            "tcp" | "tls" => Protocol::Tcp,
             "udp" => Protocol::Udp,
             "http" | "https" => Protocol::Http,
             _ => Protocol::Http,
@@ -19,12 +23,221 @@ impl Protocol {
     }
 }
 
+/// Which of a peer's Tailscale addresses back its services
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum AddressFamily {
+    /// IPv4 address, falling back to IPv6 for IPv6-only peers
+    Ipv4,
+    /// IPv6 address, falling back to IPv4 for IPv4-only peers
+    Ipv6,
+    /// One server per address
+    Both,
+}
+
+impl AddressFamily {
+    pub fn parse(s: &str) -> Self {
+        match s.to_lowercase().as_str() {
+            "ipv6" | "v6" => AddressFamily::Ipv6,
+            "both" | "all" => AddressFamily::Both,
+            _ => AddressFamily::Ipv4,
+        }
+    }
+}
+
+/// How peers whose generated names clash with another peer's are handled
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum NameCollisionStrategy {
+    /// Append a short form of the peer's stable node ID to its names
+    NodeId,
+    /// Keep the peer that sorts first by hostname and drop the others
+    Skip,
+}
+
+impl NameCollisionStrategy {
+    pub fn parse(s: &str) -> Self {
+        match s.to_lowercase().as_str() {
+            "skip" | "drop" => NameCollisionStrategy::Skip,
+            _ => NameCollisionStrategy::NodeId,
+        }
+    }
+}
+
+/// File format of the DNS output
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum DnsFormat {
+    /// "address name..." lines, for CoreDNS's hosts plugin or /etc/hosts
+    Hosts,
+    /// RFC 1035 zone file of DNS_ZONE, for CoreDNS's file plugin and other servers
+    Zone,
+}
+
+impl DnsFormat {
+    pub fn parse(s: &str) -> Self {
+        match s.to_lowercase().as_str() {
+            "zone" | "zonefile" => DnsFormat::Zone,
+            _ => DnsFormat::Hosts,
+        }
+    }
+}
+
+/// Chat service a notification channel posts to
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum ChatKind {
+    Ntfy,
+    Slack,
+    Discord,
+}
+
+/// Chat notification target and the kinds of changes it is told about
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatChannel {
+    pub kind: ChatKind,
+    /// Webhook or topic URL, which for these services is the credential
+    pub url: Secret,
+    /// Services added to or removed from the configuration
+    pub services: bool,
+    /// Peers that went offline or left the tailnet
+    pub peers: bool,
+}
+
+/// Where the provider learns about the nodes of the tailnet
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum DataSource {
+    /// The LocalAPI of a tailscaled
+    LocalApi,
+    /// The Tailscale admin API, for hosts without tailscaled
+    Api,
+}
+
+impl DataSource {
+    pub fn parse(s: &str) -> Self {
+        match s.to_lowercase().as_str() {
+            "api" => DataSource::Api,
+            _ => DataSource::LocalApi,
+        }
+    }
+}
+
+/// Output format of the logs
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Human-readable lines
+    Text,
+    /// One JSON object per line, for Loki/ELK pipelines
+    Json,
+}
+
+impl LogFormat {
+    pub fn parse(s: &str) -> Self {
+        match s.to_lowercase().as_str() {
+            "json" => LogFormat::Json,
+            _ => LogFormat::Text,
+        }
+    }
+}
+
+/// A credential that is left out of Debug output, so it does not end up in the logs
+#[derive(Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn new(value: String) -> Self {
+        Self(value)
+    }
+
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::fmt::Debug for Secret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Secret(***)")
+    }
+}
+
+/// Per-endpoint override of the API request limits
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct EndpointLimit {
+    pub timeout_seconds: Option<u64>,
+    pub max_body_bytes: Option<usize>,
+}
+
+/// Major Traefik version the generated configuration targets
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum TraefikVersion {
+    V2,
+    V3,
+}
+
+impl TraefikVersion {
+    pub fn parse(s: &str) -> Self {
+        match s.to_lowercase().trim_start_matches('v') {
+            "2" => TraefikVersion::V2,
+            _ => TraefikVersion::V3,
+        }
+    }
+
+    /// HTTP rule matching every request
+    pub fn catch_all_http_rule(&self) -> &'static str {
+        match self {
+            // v2 only accepts named regex placeholders in HostRegexp
+            TraefikVersion::V2 => "HostRegexp(`{any:.*}`)",
+            TraefikVersion::V3 => "HostRegexp(`.*`)",
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ServiceInfo {
     pub name: String,
     pub port: Option<u16>,
     pub protocol: Protocol,
     pub scheme: String,
+    /// Routes only requests under this path, from a "service--path-api-v1" tag attribute
+    pub path_prefix: Option<String>,
+}
+
+/// Per-service attribute declared through a tag in format "service--key-value"
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TagAttribute {
+    pub service: String,
+    pub key: String,
+    pub values: Vec<String>,
+}
+
+impl TagAttribute {
+    /// Parse a tag such as "api--ratelimit-100-50" into its service, key and values.
+    /// Returns None for regular service tags.
+    pub fn parse(tag: &str) -> Option<Self> {
+        let clean_tag = tag.strip_prefix("tag:").unwrap_or(tag);
+        let (service, attribute) = clean_tag.split_once("--")?;
+        let mut parts = attribute.split('-');
+        let key = parts.next().filter(|key| !key.is_empty())?;
+
+        if service.is_empty() {
+            return None;
+        }
+
+        Some(TagAttribute {
+            service: service.to_string(),
+            key: key.to_lowercase(),
+            values: parts.map(|value| value.to_string()).collect(),
+        })
+    }
+
+    /// The values as a path, one segment each, since tags can't contain "/":
+    /// "api--path-api-v1" is "/api/v1". None without any non-empty value.
+    pub fn path(&self) -> Option<String> {
+        let segments: Vec<&str> = self
+            .values
+            .iter()
+            .map(String::as_str)
+            .filter(|segment| !segment.is_empty())
+            .collect();
+        (!segments.is_empty()).then(|| format!("/{}", segments.join("/")))
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -32,12 +245,47 @@ pub struct ProviderConfig {
     /// Custom Tailscale socket path (optional)
     pub tailscale_socket_path: Option<String>,
 
+    /// Whether nodes are read from the LocalAPI or the Tailscale admin API
+    pub data_source: DataSource,
+
+    /// Base URL of the Tailscale admin API
+    pub tailscale_api_url: String,
+
+    /// Tailnet queried through the admin API ("-" for the tailnet of the key)
+    pub tailscale_tailnet: String,
+
+    /// API access token for the admin API
+    pub tailscale_api_key: Option<Secret>,
+
+    /// OAuth client (with the devices:core:read scope) used instead of an API key
+    pub tailscale_oauth_client_id: Option<String>,
+    pub tailscale_oauth_client_secret: Option<Secret>,
+
+    /// Further tailscaled instances (name, socket path or tcp://host:port) whose peers
+    /// are published under the name prefix extended by the source name
+    pub tailscale_sources: Vec<(String, String)>,
+
+    /// Timeout of LocalAPI requests in milliseconds
+    pub tailscale_timeout_ms: u64,
+
+    /// Attempts per LocalAPI request when it fails transiently (1 disables retries)
+    pub tailscale_retry_attempts: u32,
+
+    /// Backoff before the first LocalAPI retry in milliseconds, doubling per retry
+    pub tailscale_retry_backoff_ms: u64,
+
+    /// Traefik major version the generated rules and fields target
+    pub traefik_version: TraefikVersion,
+
     /// Default port to use for services when not specified
     pub default_port: u16,
 
     /// Exclude exit nodes from configuration
     pub exclude_exit_nodes: bool,
 
+    /// Tailscale addresses used for backend servers
+    pub address_family: AddressFamily,
+
     /// Include only peers with specific tags
     pub include_tags: Option<Vec<String>>,
 
@@ -47,21 +295,118 @@ pub struct ProviderConfig {
     /// Health check path for services
     pub health_check_path: Option<String>,
 
+    /// Services generated without a health check, e.g. backends without a health
+    /// endpoint ("*" for all)
+    pub health_check_skip: Option<Vec<String>>,
+
     /// Update interval in seconds
     pub update_interval_seconds: u64,
 
+    /// Warn about node keys expiring within this many days
+    pub key_expiry_warning_days: u64,
+
+    /// Log output format (text or json)
+    pub log_format: LogFormat,
+
+    /// Log filter directives, e.g. "info" or "info,traefik_tailscale_provider=debug"
+    pub log_level: String,
+
+    /// Log the duration of generation cycles, LocalAPI calls and requests
+    pub log_span_timings: bool,
+
+    /// Log every API request with its status, latency and client address
+    pub access_log: bool,
+
+    /// OTLP/HTTP collector the tracing spans are exported to ("otel" feature)
+    pub otel_exporter_otlp_endpoint: Option<String>,
+
+    /// service.name of the exported spans
+    pub otel_service_name: String,
+
     /// HTTP server port for serving dynamic configuration
     pub server_port: u16,
 
+    /// Timeout of API requests in seconds
+    pub request_timeout_seconds: u64,
+
+    /// Maximum size of API request bodies
+    pub max_request_body_bytes: usize,
+
+    /// Maximum total size of API request headers
+    pub max_request_header_bytes: usize,
+
+    /// Maximum simultaneous API connections
+    pub max_connections: usize,
+
+    /// Per-endpoint timeouts and body limits (e.g., "/config:60,/admin/*:10:1048576")
+    pub endpoint_limits: Option<HashMap<String, EndpointLimit>>,
+
+    /// Identity of this replica in /cluster
+    pub replica_id: String,
+
+    /// Base URLs of the other provider replicas compared in /cluster
+    pub cluster_members: Option<Vec<String>>,
+
+    /// Produce identical output on every replica for the same tailnet state: versions
+    /// named after the content hash, time-based checks on a coarse clock, sorted servers
+    pub deterministic_output: bool,
+
+    /// Step the clock of time-based checks is rounded down to in deterministic mode
+    pub time_quantum_seconds: u64,
+
+    /// Only the elected replica of CLUSTER_MEMBERS generates, the others mirror it
+    pub leader_election: bool,
+
+    /// Redis shared by the replicas for the configuration and the generation lock
+    /// (connects with the REDIS_* credentials)
+    pub shared_cache_url: Option<String>,
+
+    /// Prefix of the shared cache keys, to share one Redis between deployments
+    pub shared_cache_prefix: String,
+
     /// Only include peers that have been active within this many seconds
     pub max_inactive_seconds: Option<i64>,
 
+    /// Keep the services of a peer that went offline for this many seconds
+    pub offline_grace_seconds: Option<i64>,
+
+    /// Exclude peers changing between online and offline more often than this
+    pub flap_threshold: Option<usize>,
+
+    /// Window in seconds over which online/offline transitions are counted
+    pub flap_window_seconds: i64,
+
+    /// Decay backend weights over this many seconds since a peer's last handshake
+    pub weight_decay_seconds: Option<i64>,
+
+    /// Exclude peers whose ping round trip exceeds this many milliseconds
+    pub max_latency_ms: Option<u64>,
+
+    /// Weight backends by ping round trip, favoring nearby peers
+    pub latency_weighting: bool,
+
+    /// Peer capability (capmap key) carrying self-described capacity hints
+    pub capacity_capability: Option<String>,
+
+    /// Peer capability (capmap key) declaring services from the tailnet policy file
+    pub service_capability: Option<String>,
+
     /// Only include peers with specific OS types
     pub include_os: Option<Vec<String>>,
 
+    /// Posture attributes (admin API) or peer capabilities a peer must have, as
+    /// (key, value) pairs that all have to match
+    pub posture_attributes: Option<Vec<(String, String)>>,
+
     /// Exclude peers with expired node keys
     pub exclude_expired: bool,
 
+    /// Exclude peers without a direct connection (relayed through DERP or a peer relay)
+    pub direct_connections_only: bool,
+
+    /// Exclude nodes shared into the tailnet from another tailnet
+    pub exclude_shared_nodes: bool,
+
     /// Extract port and protocol from tag format "service-port-protocol"
     pub extract_protocol_from_tag: bool,
 
@@ -74,29 +419,362 @@ pub struct ProviderConfig {
     /// Default protocol for services
     pub default_protocol: Protocol,
 
+    /// Ports whose HTTP services default to the https scheme
+    pub https_ports: Vec<u16>,
+
+    /// Skip certificate verification for https backends on HTTPS_PORTS
+    pub https_insecure_skip_verify: bool,
+
     /// Service to domain mapping (e.g., "web:app.example.net,api:api.example.net")
     pub service_domain_mapping: Option<HashMap<String, String>>,
+
+    /// Per-service HTTP router priorities (e.g., "web:10,api:20")
+    pub router_priorities: Option<HashMap<String, i32>>,
+
+    /// Priority of generated HTTP routers without a per-service priority
+    pub default_router_priority: Option<i32>,
+
+    /// Per-service rate limits (e.g., "api:100:50,web:20:10:1m")
+    pub rate_limits: Option<HashMap<String, RateLimitMiddleware>>,
+
+    /// Attach a shared compress middleware to all HTTP routers
+    pub compress_responses: bool,
+
+    /// Services whose path prefix is stripped before forwarding ("*" for all)
+    pub strip_path_prefix: Option<Vec<String>>,
+
+    /// IP address the API listens on
+    pub bind_address: String,
+
+    /// Listen on this node's Tailscale IP only instead of BIND_ADDRESS
+    pub listen_tailnet_only: bool,
+
+    /// Serve the API over HTTPS with a certificate issued by tailscaled
+    pub serve_https: bool,
+
+    /// Domain of the HTTPS certificate (default: this node's MagicDNS name)
+    pub serve_https_domain: Option<String>,
+
+    /// Bearer token required by every API endpoint
+    pub api_token: Option<Secret>,
+
+    /// Leave the `/` and `/readyz` health checks open when API_TOKEN is set
+    pub api_token_public_health: bool,
+
+    /// Tailscale login names allowed to use the admin API
+    pub admin_users: Option<Vec<String>>,
+
+    /// Tailscale tags whose nodes are allowed to use the admin API
+    pub admin_tags: Option<Vec<String>>,
+
+    /// JSON/YAML file of raw middleware definitions merged into the HTTP config
+    pub middlewares_file: Option<String>,
+
+    /// JSON/YAML file of named TLS option sets published in the tls section
+    pub tls_options_file: Option<String>,
+
+    /// Embed the config version as a headers middleware in the generated config
+    pub embed_config_version: bool,
+
+    /// Traefik dynamic config file deep-merged into every generated config
+    pub merge_config_file: Option<String>,
+
+    /// Traefik HTTP provider endpoint whose configuration is merged into ours
+    pub upstream_provider_url: Option<String>,
+
+    /// How often the upstream provider is polled
+    pub upstream_poll_interval_seconds: u64,
+
+    /// Traefik API (e.g., http://traefik:8080) /verify compares the configuration with
+    pub traefik_api_url: Option<String>,
+
+    /// File persisting the last generated configuration across restarts
+    pub state_file: Option<String>,
+
+    /// Number of past configurations kept for /config/history
+    pub config_history_size: usize,
+
+    /// URLs POSTed the changes of every new configuration
+    pub webhook_urls: Option<Vec<Secret>>,
+
+    /// ntfy, Slack and Discord channels summarizing service and peer changes
+    pub chat_channels: Vec<ChatChannel>,
+
+    /// ConfigMap ("name" or "namespace/name") the configuration is written to
+    pub kubernetes_configmap: Option<String>,
+
+    /// Key of the ConfigMap holding the configuration
+    pub kubernetes_configmap_key: String,
+
+    /// Consul HTTP API the configuration is written to, in Traefik's KV layout
+    pub consul_url: Option<String>,
+
+    /// ACL token for the Consul KV writes
+    pub consul_token: Option<Secret>,
+
+    /// etcd v3 endpoint the configuration is written to, in Traefik's KV layout
+    pub etcd_url: Option<String>,
+
+    /// User for etcd authentication, together with `etcd_password`
+    pub etcd_username: Option<String>,
+
+    pub etcd_password: Option<Secret>,
+
+    /// Redis server the configuration is written to, in Traefik's KV layout
+    /// ("redis://host:port/db", "rediss://" for TLS)
+    pub redis_url: Option<String>,
+
+    /// User for Redis ACL authentication (requires `redis_password`)
+    pub redis_username: Option<String>,
+
+    pub redis_password: Option<Secret>,
+
+    /// CA certificates verifying the Redis server instead of the web PKI roots
+    pub redis_ca_file: Option<String>,
+
+    /// Key below which the KV outputs write the configuration (Traefik's rootKey)
+    pub kv_root_key: String,
+
+    /// Caddy admin API the configuration is loaded into, as Caddy JSON
+    pub caddy_admin_url: Option<String>,
+
+    /// Server of Caddy's http app holding the routes (also at /config/caddy)
+    pub caddy_server_name: String,
+
+    /// Addresses that Caddy server listens on
+    pub caddy_listen: Vec<String>,
+
+    /// File the configuration is written to as nginx upstream and server blocks
+    pub nginx_config_file: Option<String>,
+
+    /// Shell command run after the nginx file changed (e.g. "nginx -s reload")
+    pub nginx_reload_command: Option<String>,
+
+    /// Parameters of the listen directives of the nginx server blocks
+    pub nginx_listen: Vec<String>,
+
+    /// File the configuration is written to as an HAProxy frontend and backends
+    pub haproxy_config_file: Option<String>,
+
+    /// Shell command run after the HAProxy file changed
+    pub haproxy_reload_command: Option<String>,
+
+    /// Name of the generated HAProxy frontend
+    pub haproxy_frontend_name: String,
+
+    /// Parameters of the bind lines of the HAProxy frontend
+    pub haproxy_bind: Vec<String>,
+
+    /// File the router hosts and service names are written to with their addresses
+    pub dns_file: Option<String>,
+
+    /// Shell command run after the DNS file changed
+    pub dns_reload_command: Option<String>,
+
+    pub dns_format: DnsFormat,
+
+    /// Zone the service names are published in, and the origin of the zone file
+    pub dns_zone: Option<String>,
+
+    /// Addresses of Traefik the names resolve to, instead of the peers' addresses
+    pub dns_targets: Vec<IpAddr>,
+
+    pub dns_ttl: u32,
+
+    /// Give up when tailscaled is unreachable this long after startup (default: never)
+    pub startup_max_wait_seconds: Option<u64>,
+
+    /// /readyz fails once the served configuration is stale for longer (0: never)
+    pub max_config_staleness_seconds: u64,
+
+    /// Exclude peers whose tags declare no services instead of emitting a default service
+    pub exclude_peers_without_services: bool,
+
+    /// Create HTTP services for the ports the node publishes with `tailscale serve`
+    pub serve_discovery: bool,
+
+    /// Publish the services of the node running tailscaled (from its own tags)
+    pub include_self: bool,
+
+    /// Create services for the listening ports peers advertise in their Hostinfo
+    pub hostinfo_services: bool,
+
+    /// Hostinfo listeners to publish, by description or port
+    pub hostinfo_services_allow: Option<Vec<String>>,
+
+    /// JSON/YAML file of patches keyed by generated service name
+    pub overrides_file: Option<String>,
+
+    /// Rhai script adjusting or vetoing the router of every discovered service
+    pub route_script: Option<String>,
+
+    /// JSON/YAML file of services outside the tailnet, keyed by service name
+    pub static_services_file: Option<String>,
+
+    /// Friendly names of generated services, keyed by generated service name
+    pub service_aliases: Option<HashMap<String, String>>,
+
+    /// Names or glob patterns of services and routers removed from the generated config
+    pub exclude_services: Option<Vec<String>>,
+
+    /// Default HTTP rule template (e.g., "Host(`{service}.{hostname}.example.com`)")
+    pub host_rule_template: Option<String>,
+
+    /// Never emit catch-all rules; drop services without a host/SNI mapping instead
+    pub strict_rules: bool,
+
+    /// Prefix of generated router/service/middleware names
+    pub name_prefix: String,
+
+    /// Suffix of generated router/service/middleware names
+    pub name_suffix: Option<String>,
+
+    /// Names (or "prefix*" patterns) owned by other Traefik providers that must not be generated
+    pub reserved_names: Option<Vec<String>>,
+
+    /// Handling of peers whose generated names clash with another peer's
+    pub name_collision_strategy: NameCollisionStrategy,
+
+    /// Template of generated service names with {hostname} and {service} placeholders,
+    /// replacing the prefix/hostname/service/suffix scheme
+    pub service_name_template: Option<String>,
 }
 
 impl Default for ProviderConfig {
     fn default() -> Self {
         Self {
             tailscale_socket_path: None,
+            data_source: DataSource::LocalApi,
+            tailscale_api_url: "https://api.tailscale.com".to_string(),
+            tailscale_tailnet: "-".to_string(),
+            tailscale_api_key: None,
+            tailscale_oauth_client_id: None,
+            tailscale_oauth_client_secret: None,
+            tailscale_sources: Vec::new(),
+            tailscale_timeout_ms: 10_000,
+            tailscale_retry_attempts: 3,
+            tailscale_retry_backoff_ms: 200,
+            traefik_version: TraefikVersion::V3,
             default_port: 80,
             exclude_exit_nodes: true,
+            address_family: AddressFamily::Ipv4,
             include_tags: None,
             exclude_hostnames: None,
             health_check_path: Some("/health".to_string()),
+            health_check_skip: None,
             update_interval_seconds: 30,
+            key_expiry_warning_days: 14,
+            log_format: LogFormat::Text,
+            log_level: "info".to_string(),
+            log_span_timings: false,
+            access_log: false,
+            otel_exporter_otlp_endpoint: None,
+            otel_service_name: "traefik-tailscale-provider".to_string(),
             server_port: 8080,
+            request_timeout_seconds: 30,
+            max_request_body_bytes: 64 * 1024,
+            max_request_header_bytes: 16 * 1024,
+            max_connections: 256,
+            endpoint_limits: None,
+            replica_id: "provider".to_string(),
+            cluster_members: None,
+            deterministic_output: false,
+            time_quantum_seconds: 60,
+            leader_election: false,
+            shared_cache_url: None,
+            shared_cache_prefix: "traefik-tailscale-provider".to_string(),
             max_inactive_seconds: None, // No filtering by default
-            include_os: None,           // Include all OS types by default
-            exclude_expired: true,      // Exclude expired peers by default
+            offline_grace_seconds: None,
+            flap_threshold: None,
+            flap_window_seconds: 600,
+            weight_decay_seconds: None, // Equal weights by default
+            max_latency_ms: None,
+            latency_weighting: false,
+            capacity_capability: None,
+            service_capability: None,
+            include_os: None, // Include all OS types by default
+            posture_attributes: None,
+            exclude_expired: true, // Exclude expired peers by default
+            direct_connections_only: false,
+            exclude_shared_nodes: false,
             extract_protocol_from_tag: true,
             tag_service_mapping: None,
             default_scheme: "http".to_string(),
             default_protocol: Protocol::Http,
+            https_ports: vec![443, 8443],
+            https_insecure_skip_verify: true,
             service_domain_mapping: None,
+            router_priorities: None,
+            default_router_priority: None,
+            rate_limits: None,
+            compress_responses: false,
+            strip_path_prefix: None,
+            bind_address: "0.0.0.0".to_string(),
+            listen_tailnet_only: false,
+            serve_https: false,
+            serve_https_domain: None,
+            api_token: None,
+            api_token_public_health: true,
+            admin_users: None,
+            admin_tags: None,
+            middlewares_file: None,
+            tls_options_file: None,
+            embed_config_version: true,
+            merge_config_file: None,
+            upstream_provider_url: None,
+            upstream_poll_interval_seconds: 30,
+            traefik_api_url: None,
+            state_file: None,
+            config_history_size: 10,
+            webhook_urls: None,
+            kubernetes_configmap: None,
+            kubernetes_configmap_key: "tailscale.yml".to_string(),
+            consul_url: None,
+            consul_token: None,
+            etcd_url: None,
+            etcd_username: None,
+            etcd_password: None,
+            redis_url: None,
+            redis_username: None,
+            redis_password: None,
+            redis_ca_file: None,
+            kv_root_key: "traefik".to_string(),
+            caddy_admin_url: None,
+            caddy_server_name: "tailscale".to_string(),
+            caddy_listen: vec![":443".to_string()],
+            nginx_config_file: None,
+            nginx_reload_command: None,
+            nginx_listen: vec!["80".to_string()],
+            haproxy_config_file: None,
+            haproxy_reload_command: None,
+            haproxy_frontend_name: "tailscale".to_string(),
+            haproxy_bind: vec![":80".to_string()],
+            dns_file: None,
+            dns_reload_command: None,
+            dns_format: DnsFormat::Hosts,
+            dns_zone: None,
+            dns_targets: Vec::new(),
+            dns_ttl: 60,
+            chat_channels: Vec::new(),
+            startup_max_wait_seconds: None,
+            max_config_staleness_seconds: 300,
+            exclude_peers_without_services: false,
+            serve_discovery: false,
+            include_self: false,
+            hostinfo_services: false,
+            hostinfo_services_allow: None,
+            overrides_file: None,
+            route_script: None,
+            static_services_file: None,
+            service_aliases: None,
+            exclude_services: None,
+            host_rule_template: None,
+            strict_rules: false,
+            name_prefix: "tailscale".to_string(),
+            name_suffix: None,
+            reserved_names: None,
+            name_collision_strategy: NameCollisionStrategy::NodeId,
+            service_name_template: None,
         }
     }
 }
@@ -106,6 +784,48 @@ impl ProviderConfig {
     pub fn from_env() -> Self {
         Self {
             tailscale_socket_path: std::env::var("TAILSCALE_SOCKET_PATH").ok(),
+            data_source: DataSource::parse(
+                &std::env::var("TAILSCALE_DATA_SOURCE").unwrap_or_default(),
+            ),
+            tailscale_api_url: std::env::var("TAILSCALE_API_URL")
+                .ok()
+                .filter(|s| !s.is_empty())
+                .unwrap_or_else(|| "https://api.tailscale.com".to_string()),
+            tailscale_tailnet: std::env::var("TAILSCALE_TAILNET")
+                .ok()
+                .filter(|s| !s.is_empty())
+                .unwrap_or_else(|| "-".to_string()),
+            tailscale_api_key: std::env::var("TAILSCALE_API_KEY")
+                .ok()
+                .filter(|s| !s.is_empty())
+                .map(Secret::new),
+            tailscale_oauth_client_id: std::env::var("TAILSCALE_OAUTH_CLIENT_ID")
+                .ok()
+                .filter(|s| !s.is_empty()),
+            tailscale_oauth_client_secret: std::env::var("TAILSCALE_OAUTH_CLIENT_SECRET")
+                .ok()
+                .filter(|s| !s.is_empty())
+                .map(Secret::new),
+            tailscale_sources: Self::parse_assignments(
+                &std::env::var("TAILSCALE_SOURCES").unwrap_or_default(),
+            ),
+            tailscale_timeout_ms: std::env::var("TAILSCALE_TIMEOUT_MS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .filter(|timeout| *timeout > 0)
+                .unwrap_or(10_000),
+            tailscale_retry_attempts: std::env::var("TAILSCALE_RETRY_ATTEMPTS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .filter(|attempts| *attempts > 0)
+                .unwrap_or(3),
+            tailscale_retry_backoff_ms: std::env::var("TAILSCALE_RETRY_BACKOFF_MS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(200),
+            traefik_version: TraefikVersion::parse(
+                &std::env::var("TRAEFIK_VERSION").unwrap_or_else(|_| "v3".to_string()),
+            ),
             default_port: std::env::var("DEFAULT_PORT")
                 .ok()
                 .and_then(|s| s.parse().ok())
@@ -113,6 +833,9 @@ impl ProviderConfig {
             exclude_exit_nodes: std::env::var("EXCLUDE_EXIT_NODES")
                 .map(|s| s.to_lowercase() != "false")
                 .unwrap_or(true),
+            address_family: AddressFamily::parse(
+                &std::env::var("ADDRESS_FAMILY").unwrap_or_else(|_| "ipv4".to_string()),
+            ),
             include_tags: std::env::var("INCLUDE_TAGS")
                 .ok()
                 .map(|s| s.split(',').map(|tag| tag.trim().to_string()).collect()),
@@ -120,23 +843,126 @@ impl ProviderConfig {
                 .ok()
                 .map(|s| s.split(',').map(|name| name.trim().to_string()).collect()),
             health_check_path: std::env::var("HEALTH_CHECK_PATH").ok(),
+            health_check_skip: std::env::var("HEALTH_CHECK_SKIP").ok().map(|s| {
+                s.split(',')
+                    .map(|service| service.trim().to_string())
+                    .collect()
+            }),
             update_interval_seconds: std::env::var("UPDATE_INTERVAL_SECONDS")
                 .ok()
                 .and_then(|s| s.parse().ok())
                 .unwrap_or(30),
+            key_expiry_warning_days: std::env::var("KEY_EXPIRY_WARNING_DAYS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(14),
+            log_format: std::env::var("LOG_FORMAT")
+                .map(|s| LogFormat::parse(&s))
+                .unwrap_or(LogFormat::Text),
+            log_level: std::env::var("LOG_LEVEL")
+                .or_else(|_| std::env::var("RUST_LOG"))
+                .ok()
+                .filter(|s| !s.is_empty())
+                .unwrap_or_else(|| "info".to_string()),
+            log_span_timings: std::env::var("LOG_SPAN_TIMINGS")
+                .map(|s| s.to_lowercase() == "true")
+                .unwrap_or(false),
+            access_log: std::env::var("ACCESS_LOG")
+                .map(|s| s.to_lowercase() == "true")
+                .unwrap_or(false),
+            otel_exporter_otlp_endpoint: std::env::var("OTEL_EXPORTER_OTLP_ENDPOINT")
+                .ok()
+                .filter(|s| !s.is_empty()),
+            otel_service_name: std::env::var("OTEL_SERVICE_NAME")
+                .ok()
+                .filter(|s| !s.is_empty())
+                .unwrap_or_else(|| "traefik-tailscale-provider".to_string()),
             server_port: std::env::var("SERVER_PORT")
                 .ok()
                 .and_then(|s| s.parse().ok())
                 .unwrap_or(8080),
+            request_timeout_seconds: std::env::var("REQUEST_TIMEOUT_SECONDS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(30),
+            max_request_body_bytes: std::env::var("MAX_REQUEST_BODY_BYTES")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(64 * 1024),
+            max_request_header_bytes: std::env::var("MAX_REQUEST_HEADER_BYTES")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(16 * 1024),
+            max_connections: std::env::var("MAX_CONNECTIONS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .filter(|max| *max > 0)
+                .unwrap_or(256),
+            endpoint_limits: Self::parse_endpoint_limits(
+                &std::env::var("ENDPOINT_LIMITS").unwrap_or_default(),
+            ),
+            replica_id: std::env::var("REPLICA_ID")
+                .or_else(|_| std::env::var("HOSTNAME"))
+                .unwrap_or_else(|_| "provider".to_string()),
+            cluster_members: std::env::var("CLUSTER_MEMBERS")
+                .ok()
+                .map(|s| s.split(',').map(|url| url.trim().to_string()).collect()),
+            deterministic_output: std::env::var("DETERMINISTIC_OUTPUT")
+                .map(|s| s.to_lowercase() == "true")
+                .unwrap_or(false),
+            time_quantum_seconds: std::env::var("TIME_QUANTUM_SECONDS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .filter(|seconds| *seconds > 0)
+                .unwrap_or(60),
+            leader_election: std::env::var("LEADER_ELECTION")
+                .map(|s| s.to_lowercase() == "true")
+                .unwrap_or(false),
+            shared_cache_url: std::env::var("SHARED_CACHE_URL")
+                .ok()
+                .filter(|s| !s.is_empty()),
+            shared_cache_prefix: std::env::var("SHARED_CACHE_PREFIX")
+                .unwrap_or_else(|_| "traefik-tailscale-provider".to_string()),
             max_inactive_seconds: std::env::var("MAX_INACTIVE_SECONDS")
                 .ok()
                 .and_then(|s| s.parse().ok()),
+            offline_grace_seconds: std::env::var("OFFLINE_GRACE_SECONDS")
+                .ok()
+                .and_then(|s| s.parse().ok()),
+            flap_threshold: std::env::var("FLAP_THRESHOLD")
+                .ok()
+                .and_then(|s| s.parse().ok()),
+            flap_window_seconds: std::env::var("FLAP_WINDOW_SECONDS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(600),
+            weight_decay_seconds: std::env::var("WEIGHT_DECAY_SECONDS")
+                .ok()
+                .and_then(|s| s.parse().ok()),
+            max_latency_ms: std::env::var("MAX_LATENCY_MS")
+                .ok()
+                .and_then(|s| s.parse().ok()),
+            latency_weighting: std::env::var("LATENCY_WEIGHTING")
+                .map(|s| s.to_lowercase() == "true")
+                .unwrap_or(false),
+            capacity_capability: std::env::var("CAPACITY_CAPABILITY").ok(),
+            service_capability: std::env::var("SERVICE_CAPABILITY").ok(),
             include_os: std::env::var("INCLUDE_OS")
                 .ok()
                 .map(|s| s.split(',').map(|os| os.trim().to_string()).collect()),
+            posture_attributes: Some(Self::parse_assignments(
+                &std::env::var("POSTURE_ATTRIBUTES").unwrap_or_default(),
+            ))
+            .filter(|rules| !rules.is_empty()),
             exclude_expired: std::env::var("EXCLUDE_EXPIRED")
                 .map(|s| s.to_lowercase() != "false")
                 .unwrap_or(true),
+            direct_connections_only: std::env::var("DIRECT_CONNECTIONS_ONLY")
+                .map(|s| s.to_lowercase() == "true")
+                .unwrap_or(false),
+            exclude_shared_nodes: std::env::var("EXCLUDE_SHARED_NODES")
+                .map(|s| s.to_lowercase() == "true")
+                .unwrap_or(false),
             extract_protocol_from_tag: std::env::var("EXTRACT_PROTOCOL_FROM_TAG")
                 .map(|s| s.to_lowercase() != "false")
                 .unwrap_or(true),
@@ -144,15 +970,355 @@ impl ProviderConfig {
                 &std::env::var("TAG_SERVICE_MAPPING").unwrap_or_default(),
             ),
             default_scheme: std::env::var("DEFAULT_SCHEME").unwrap_or_else(|_| "http".to_string()),
-            default_protocol: Protocol::from_str(
+            default_protocol: Protocol::parse(
                 &std::env::var("DEFAULT_PROTOCOL").unwrap_or_else(|_| "http".to_string()),
             ),
+            https_ports: std::env::var("HTTPS_PORTS")
+                .map(|s| {
+                    s.split(',')
+                        .filter_map(|port| port.trim().parse().ok())
+                        .collect()
+                })
+                .unwrap_or_else(|_| vec![443, 8443]),
+            https_insecure_skip_verify: std::env::var("HTTPS_INSECURE_SKIP_VERIFY")
+                .map(|s| s.to_lowercase() != "false")
+                .unwrap_or(true),
             service_domain_mapping: Self::parse_domain_mapping(
                 &std::env::var("SERVICE_DOMAIN_MAPPING").unwrap_or_default(),
             ),
+            router_priorities: Self::parse_router_priorities(
+                &std::env::var("ROUTER_PRIORITIES").unwrap_or_default(),
+            ),
+            default_router_priority: std::env::var("DEFAULT_ROUTER_PRIORITY")
+                .ok()
+                .and_then(|s| s.parse().ok()),
+            rate_limits: Self::parse_rate_limits(&std::env::var("RATE_LIMITS").unwrap_or_default()),
+            compress_responses: std::env::var("COMPRESS_RESPONSES")
+                .map(|s| s.to_lowercase() == "true")
+                .unwrap_or(false),
+            strip_path_prefix: std::env::var("STRIP_PATH_PREFIX").ok().map(|s| {
+                s.split(',')
+                    .map(|service| service.trim().to_string())
+                    .collect()
+            }),
+            bind_address: std::env::var("BIND_ADDRESS")
+                .ok()
+                .filter(|s| !s.is_empty())
+                .unwrap_or_else(|| "0.0.0.0".to_string()),
+            listen_tailnet_only: std::env::var("LISTEN_TAILNET_ONLY")
+                .map(|s| s.to_lowercase() == "true")
+                .unwrap_or(false),
+            serve_https: std::env::var("SERVE_HTTPS")
+                .map(|s| s.to_lowercase() == "true")
+                .unwrap_or(false),
+            serve_https_domain: std::env::var("SERVE_HTTPS_DOMAIN")
+                .ok()
+                .filter(|s| !s.is_empty()),
+            api_token: std::env::var("API_TOKEN")
+                .ok()
+                .filter(|s| !s.is_empty())
+                .map(Secret::new),
+            api_token_public_health: std::env::var("API_TOKEN_PUBLIC_HEALTH")
+                .map(|s| s.to_lowercase() != "false")
+                .unwrap_or(true),
+            admin_users: std::env::var("ADMIN_USERS")
+                .ok()
+                .map(|s| s.split(',').map(|user| user.trim().to_string()).collect()),
+            admin_tags: std::env::var("ADMIN_TAGS")
+                .ok()
+                .map(|s| s.split(',').map(|tag| tag.trim().to_string()).collect()),
+            middlewares_file: std::env::var("MIDDLEWARES_FILE").ok(),
+            tls_options_file: std::env::var("TLS_OPTIONS_FILE").ok(),
+            embed_config_version: std::env::var("EMBED_CONFIG_VERSION")
+                .map(|s| s.to_lowercase() != "false")
+                .unwrap_or(true),
+            merge_config_file: std::env::var("MERGE_CONFIG_FILE").ok(),
+            upstream_provider_url: std::env::var("UPSTREAM_PROVIDER_URL")
+                .ok()
+                .filter(|s| !s.is_empty()),
+            upstream_poll_interval_seconds: std::env::var("UPSTREAM_POLL_INTERVAL_SECONDS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .filter(|seconds| *seconds > 0)
+                .unwrap_or(30),
+            traefik_api_url: std::env::var("TRAEFIK_API_URL")
+                .ok()
+                .filter(|s| !s.is_empty()),
+            state_file: std::env::var("STATE_FILE").ok(),
+            config_history_size: std::env::var("CONFIG_HISTORY_SIZE")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(10),
+            webhook_urls: std::env::var("WEBHOOK_URLS")
+                .ok()
+                .filter(|s| !s.is_empty())
+                .map(|s| {
+                    s.split(',')
+                        .map(|url| Secret::new(url.trim().to_string()))
+                        .collect()
+                }),
+            chat_channels: [
+                Self::chat_channel(ChatKind::Ntfy, "NTFY"),
+                Self::chat_channel(ChatKind::Slack, "SLACK"),
+                Self::chat_channel(ChatKind::Discord, "DISCORD"),
+            ]
+            .into_iter()
+            .flatten()
+            .collect(),
+            kubernetes_configmap: std::env::var("KUBERNETES_CONFIGMAP")
+                .ok()
+                .filter(|s| !s.is_empty()),
+            kubernetes_configmap_key: std::env::var("KUBERNETES_CONFIGMAP_KEY")
+                .unwrap_or_else(|_| "tailscale.yml".to_string()),
+            consul_url: std::env::var("CONSUL_URL").ok().filter(|s| !s.is_empty()),
+            consul_token: std::env::var("CONSUL_TOKEN")
+                .ok()
+                .filter(|s| !s.is_empty())
+                .map(Secret::new),
+            etcd_url: std::env::var("ETCD_URL").ok().filter(|s| !s.is_empty()),
+            etcd_username: std::env::var("ETCD_USERNAME")
+                .ok()
+                .filter(|s| !s.is_empty()),
+            etcd_password: std::env::var("ETCD_PASSWORD")
+                .ok()
+                .filter(|s| !s.is_empty())
+                .map(Secret::new),
+            redis_url: std::env::var("REDIS_URL").ok().filter(|s| !s.is_empty()),
+            redis_username: std::env::var("REDIS_USERNAME")
+                .ok()
+                .filter(|s| !s.is_empty()),
+            redis_password: std::env::var("REDIS_PASSWORD")
+                .ok()
+                .filter(|s| !s.is_empty())
+                .map(Secret::new),
+            redis_ca_file: std::env::var("REDIS_CA_FILE")
+                .ok()
+                .filter(|s| !s.is_empty()),
+            kv_root_key: std::env::var("KV_ROOT_KEY").unwrap_or_else(|_| "traefik".to_string()),
+            caddy_admin_url: std::env::var("CADDY_ADMIN_URL")
+                .ok()
+                .filter(|s| !s.is_empty()),
+            caddy_server_name: std::env::var("CADDY_SERVER_NAME")
+                .ok()
+                .filter(|s| !s.is_empty())
+                .unwrap_or_else(|| "tailscale".to_string()),
+            caddy_listen: std::env::var("CADDY_LISTEN")
+                .ok()
+                .filter(|s| !s.is_empty())
+                .map(|s| {
+                    s.split(',')
+                        .map(|address| address.trim().to_string())
+                        .collect()
+                })
+                .unwrap_or_else(|| vec![":443".to_string()]),
+            nginx_config_file: std::env::var("NGINX_CONFIG_FILE")
+                .ok()
+                .filter(|s| !s.is_empty()),
+            nginx_reload_command: std::env::var("NGINX_RELOAD_COMMAND")
+                .ok()
+                .filter(|s| !s.is_empty()),
+            nginx_listen: std::env::var("NGINX_LISTEN")
+                .ok()
+                .filter(|s| !s.is_empty())
+                .map(|s| {
+                    s.split(',')
+                        .map(|listen| listen.trim().to_string())
+                        .collect()
+                })
+                .unwrap_or_else(|| vec!["80".to_string()]),
+            haproxy_config_file: std::env::var("HAPROXY_CONFIG_FILE")
+                .ok()
+                .filter(|s| !s.is_empty()),
+            haproxy_reload_command: std::env::var("HAPROXY_RELOAD_COMMAND")
+                .ok()
+                .filter(|s| !s.is_empty()),
+            haproxy_frontend_name: std::env::var("HAPROXY_FRONTEND_NAME")
+                .ok()
+                .filter(|s| !s.is_empty())
+                .unwrap_or_else(|| "tailscale".to_string()),
+            haproxy_bind: std::env::var("HAPROXY_BIND")
+                .ok()
+                .filter(|s| !s.is_empty())
+                .map(|s| s.split(',').map(|bind| bind.trim().to_string()).collect())
+                .unwrap_or_else(|| vec![":80".to_string()]),
+            dns_file: std::env::var("DNS_FILE").ok().filter(|s| !s.is_empty()),
+            dns_reload_command: std::env::var("DNS_RELOAD_COMMAND")
+                .ok()
+                .filter(|s| !s.is_empty()),
+            dns_format: DnsFormat::parse(
+                &std::env::var("DNS_FORMAT").unwrap_or_else(|_| "hosts".to_string()),
+            ),
+            dns_zone: std::env::var("DNS_ZONE").ok().filter(|s| !s.is_empty()),
+            dns_targets: std::env::var("DNS_TARGETS")
+                .map(|s| {
+                    s.split(',')
+                        .filter_map(|address| address.trim().parse().ok())
+                        .collect()
+                })
+                .unwrap_or_default(),
+            dns_ttl: std::env::var("DNS_TTL")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(60),
+            startup_max_wait_seconds: std::env::var("STARTUP_MAX_WAIT_SECONDS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .filter(|seconds| *seconds > 0),
+            max_config_staleness_seconds: std::env::var("MAX_CONFIG_STALENESS_SECONDS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(300),
+            exclude_peers_without_services: std::env::var("EXCLUDE_PEERS_WITHOUT_SERVICES")
+                .map(|s| s.to_lowercase() == "true")
+                .unwrap_or(false),
+            serve_discovery: std::env::var("SERVE_DISCOVERY")
+                .map(|s| s.to_lowercase() == "true")
+                .unwrap_or(false),
+            include_self: std::env::var("INCLUDE_SELF")
+                .map(|s| s.to_lowercase() == "true")
+                .unwrap_or(false),
+            hostinfo_services: std::env::var("HOSTINFO_SERVICES")
+                .map(|s| s.to_lowercase() == "true")
+                .unwrap_or(false),
+            hostinfo_services_allow: std::env::var("HOSTINFO_SERVICES_ALLOW").ok().map(|s| {
+                s.split(',')
+                    .map(|entry| entry.trim().to_lowercase())
+                    .filter(|entry| !entry.is_empty())
+                    .collect()
+            }),
+            overrides_file: std::env::var("OVERRIDES_FILE").ok(),
+            route_script: std::env::var("ROUTE_SCRIPT").ok().filter(|s| !s.is_empty()),
+            static_services_file: std::env::var("STATIC_SERVICES_FILE").ok(),
+            service_aliases: Self::parse_domain_mapping(
+                &std::env::var("SERVICE_ALIASES").unwrap_or_default(),
+            ),
+            exclude_services: std::env::var("EXCLUDE_SERVICES")
+                .ok()
+                .filter(|s| !s.is_empty())
+                .map(|s| s.split(',').map(|name| name.trim().to_string()).collect()),
+            host_rule_template: std::env::var("HOST_RULE_TEMPLATE").ok().or_else(|| {
+                // MagicDNS mode is shorthand for routing by each peer's tailnet FQDN
+                std::env::var("USE_MAGICDNS_HOST")
+                    .is_ok_and(|s| s.to_lowercase() == "true")
+                    .then(|| "Host(`{magicdns}`)".to_string())
+            }),
+            strict_rules: std::env::var("STRICT_RULES")
+                .map(|s| s.to_lowercase() == "true")
+                .unwrap_or(false),
+            name_prefix: std::env::var("NAME_PREFIX").unwrap_or_else(|_| "tailscale".to_string()),
+            name_suffix: std::env::var("NAME_SUFFIX").ok().filter(|s| !s.is_empty()),
+            reserved_names: std::env::var("RESERVED_NAMES")
+                .ok()
+                .map(|s| s.split(',').map(|name| name.trim().to_string()).collect()),
+            name_collision_strategy: NameCollisionStrategy::parse(
+                &std::env::var("NAME_COLLISION_STRATEGY").unwrap_or_else(|_| "node-id".to_string()),
+            ),
+            service_name_template: std::env::var("SERVICE_NAME_TEMPLATE")
+                .ok()
+                .filter(|s| !s.is_empty()),
         }
     }
 
+    /// Read a chat channel from <PREFIX>_URL and <PREFIX>_EVENTS ("services,peers")
+    fn chat_channel(kind: ChatKind, prefix: &str) -> Option<ChatChannel> {
+        let url = std::env::var(format!("{}_URL", prefix))
+            .ok()
+            .filter(|s| !s.is_empty())?;
+        let events = std::env::var(format!("{}_EVENTS", prefix))
+            .unwrap_or_else(|_| "services,peers".to_string())
+            .to_lowercase();
+        let enabled = |name: &str| events.split(',').any(|event| event.trim() == name);
+
+        Some(ChatChannel {
+            kind,
+            url: Secret::new(url),
+            services: enabled("services"),
+            peers: enabled("peers"),
+        })
+    }
+
+    /// Parse rate limits from string format "service:average:burst:period,service2:average"
+    fn parse_rate_limits(limits_str: &str) -> Option<HashMap<String, RateLimitMiddleware>> {
+        if limits_str.is_empty() {
+            return None;
+        }
+
+        let mut limits = HashMap::new();
+
+        for entry in limits_str.split(',') {
+            let parts: Vec<&str> = entry.trim().split(':').map(|part| part.trim()).collect();
+            if parts.len() < 2 {
+                continue;
+            }
+
+            if let Some(rate_limit) = Self::parse_rate_limit(&parts[1..]) {
+                limits.insert(parts[0].to_string(), rate_limit);
+            }
+        }
+
+        if limits.is_empty() {
+            None
+        } else {
+            Some(limits)
+        }
+    }
+
+    /// Parse a rate limit from its "average", "burst" and "period" parts
+    pub fn parse_rate_limit<S: AsRef<str>>(parts: &[S]) -> Option<RateLimitMiddleware> {
+        let average = parts.first()?.as_ref().parse::<u64>().ok()?;
+        let burst = match parts.get(1) {
+            Some(burst) => Some(burst.as_ref().parse::<u64>().ok()?),
+            None => None,
+        };
+        let period = parts.get(2).map(|period| period.as_ref().to_string());
+
+        Some(RateLimitMiddleware {
+            average,
+            burst,
+            period,
+        })
+    }
+
+    /// Parse endpoint limits from string format "route:timeout_seconds:max_body_bytes,..."
+    /// (either number may be empty, e.g. "/admin/*::1048576")
+    fn parse_endpoint_limits(limits_str: &str) -> Option<HashMap<String, EndpointLimit>> {
+        if limits_str.is_empty() {
+            return None;
+        }
+
+        let mut limits = HashMap::new();
+
+        for entry in limits_str.split(',') {
+            let parts: Vec<&str> = entry.trim().split(':').map(|part| part.trim()).collect();
+            if parts.len() < 2 || parts[0].is_empty() {
+                continue;
+            }
+
+            let limit = EndpointLimit {
+                timeout_seconds: parts[1].parse().ok(),
+                max_body_bytes: parts.get(2).and_then(|bytes| bytes.parse().ok()),
+            };
+            limits.insert(parts[0].to_string(), limit);
+        }
+
+        if limits.is_empty() {
+            None
+        } else {
+            Some(limits)
+        }
+    }
+
+    /// Parse assignments from string format "name=value,name2=value2", e.g. the Tailscale
+    /// sources "name=path,name2=tcp://host:port"
+    fn parse_assignments(assignments_str: &str) -> Vec<(String, String)> {
+        assignments_str
+            .split(',')
+            .filter_map(|entry| entry.split_once('='))
+            .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+            .filter(|(name, value)| !name.is_empty() && !value.is_empty())
+            .collect()
+    }
+
     /// Parse domain mapping from string format "service:domain,service2:domain2"
     fn parse_domain_mapping(mapping_str: &str) -> Option<HashMap<String, String>> {
         if mapping_str.is_empty() {
@@ -160,7 +1326,7 @@ impl ProviderConfig {
         }
 
         let mut mapping = HashMap::new();
-        
+
         for entry in mapping_str.split(',') {
             let parts: Vec<&str> = entry.trim().split(':').collect();
             if parts.len() == 2 {
@@ -169,7 +1335,7 @@ impl ProviderConfig {
                 mapping.insert(service, domain);
             }
         }
-        
+
         if mapping.is_empty() {
             None
         } else {
@@ -177,6 +1343,30 @@ impl ProviderConfig {
         }
     }
 
+    /// Parse router priorities from string format "service:priority,service2:priority2"
+    fn parse_router_priorities(priorities_str: &str) -> Option<HashMap<String, i32>> {
+        if priorities_str.is_empty() {
+            return None;
+        }
+
+        let mut priorities = HashMap::new();
+
+        for entry in priorities_str.split(',') {
+            let parts: Vec<&str> = entry.trim().split(':').collect();
+            if parts.len() == 2
+                && let Ok(priority) = parts[1].trim().parse()
+            {
+                priorities.insert(parts[0].trim().to_string(), priority);
+            }
+        }
+
+        if priorities.is_empty() {
+            None
+        } else {
+            Some(priorities)
+        }
+    }
+
     /// Parse service mapping from string format "tag:port:protocol,tag2:port2:protocol2"
     fn parse_service_mapping(mapping_str: &str) -> Option<HashMap<String, ServiceInfo>> {
         if mapping_str.is_empty() {
@@ -191,13 +1381,15 @@ impl ProviderConfig {
                 let tag = parts[0].trim().to_string();
                 if let Ok(port) = parts[1].trim().parse::<u16>() {
                     let protocol = if parts.len() >= 3 {
-                        Protocol::from_str(parts[2].trim())
+                        Protocol::parse(parts[2].trim())
                     } else {
                         Protocol::Http
                     };
 
                     let scheme = match protocol {
                         Protocol::Http => "http",
+                        // TLS passthrough is TCP with the "tls" scheme
+                        Protocol::Tcp if parts.len() >= 3 && parts[2].trim() == "tls" => "tls",
                         Protocol::Tcp => "tcp",
                         Protocol::Udp => "udp",
                     };
@@ -209,6 +1401,7 @@ impl ProviderConfig {
                             port: Some(port),
                             protocol,
                             scheme: scheme.to_string(),
+                            path_prefix: None,
                         },
                     );
                 }
@@ -227,16 +1420,67 @@ impl ProviderConfig {
     pub fn parse_service_info_from_tag(&self, tag: &str) -> Option<ServiceInfo> {
         // Remove "tag:" prefix if present (Tailscale API returns tags with this prefix)
         let clean_tag = tag.strip_prefix("tag:").unwrap_or(tag);
-        
+
+        // Attribute tags ("service--key-value") never declare a service themselves
+        if clean_tag.contains("--") {
+            return None;
+        }
+
         if !self.extract_protocol_from_tag {
             return Some(ServiceInfo {
                 name: clean_tag.to_string(),
                 port: Some(self.default_port),
                 protocol: self.default_protocol.clone(),
-                scheme: self.default_scheme.clone(),
+                scheme: self.scheme_for_port(self.default_port),
+                path_prefix: None,
             });
         }
 
+        self.parse_service_parts(clean_tag)
+    }
+
+    /// Scheme of an HTTP service without an explicit protocol - https for well-known
+    /// HTTPS ports, the default scheme otherwise
+    pub fn scheme_for_port(&self, port: u16) -> String {
+        if self.default_scheme == "http" && self.https_ports.contains(&port) {
+            "https".to_string()
+        } else {
+            self.default_scheme.clone()
+        }
+    }
+
+    /// Wrap a base name with NAME_PREFIX and NAME_SUFFIX, like every generated name
+    pub fn decorate_name(&self, base: &str) -> String {
+        let suffix = self.name_suffix.as_deref().unwrap_or_default();
+        [self.name_prefix.as_str(), base, suffix]
+            .iter()
+            .filter(|part| !part.is_empty())
+            .copied()
+            .collect::<Vec<_>>()
+            .join("-")
+    }
+
+    /// Set options that need a tailscaled's LocalAPI (whois, ping, serve config, certificates
+    /// or this node's address), which the Tailscale API data source doesn't offer
+    pub fn local_api_options(&self) -> Vec<&'static str> {
+        [
+            ("MAX_LATENCY_MS", self.max_latency_ms.is_some()),
+            ("LATENCY_WEIGHTING", self.latency_weighting),
+            ("HOSTINFO_SERVICES", self.hostinfo_services),
+            ("SERVE_DISCOVERY", self.serve_discovery),
+            ("SERVE_HTTPS", self.serve_https),
+            ("LISTEN_TAILNET_ONLY", self.listen_tailnet_only),
+            ("ADMIN_USERS", self.admin_users.is_some()),
+            ("ADMIN_TAGS", self.admin_tags.is_some()),
+        ]
+        .into_iter()
+        .filter(|(_, set)| *set)
+        .map(|(option, _)| option)
+        .collect()
+    }
+
+    /// Parse the "service-port-protocol" parts of a tag
+    fn parse_service_parts(&self, clean_tag: &str) -> Option<ServiceInfo> {
         let parts: Vec<&str> = clean_tag.split('-').collect();
 
         match parts.len() {
@@ -246,7 +1490,8 @@ impl ProviderConfig {
                     name: parts[0].to_string(),
                     port: Some(self.default_port),
                     protocol: self.default_protocol.clone(),
-                    scheme: self.default_scheme.clone(),
+                    scheme: self.scheme_for_port(self.default_port),
+                    path_prefix: None,
                 })
             }
             2 => {
@@ -256,7 +1501,8 @@ impl ProviderConfig {
                         name: parts[0].to_string(),
                         port: Some(port),
                         protocol: self.default_protocol.clone(),
-                        scheme: self.default_scheme.clone(),
+                        scheme: self.scheme_for_port(port),
+                        path_prefix: None,
                     })
                 } else {
                     // Port parsing failed - exclude
@@ -266,7 +1512,7 @@ impl ProviderConfig {
             3 => {
                 // "service-3000-tcp" → ("service", 3000, tcp)
                 if let Ok(port) = parts[1].parse::<u16>() {
-                    let protocol = Protocol::from_str(parts[2]);
+                    let protocol = Protocol::parse(parts[2]);
                     let scheme = match &protocol {
                         Protocol::Http => {
                             if parts[2].to_lowercase() == "https" {
@@ -275,6 +1521,7 @@ impl ProviderConfig {
                                 "http"
                             }
                         }
+                        Protocol::Tcp if parts[2].to_lowercase() == "tls" => "tls",
                         Protocol::Tcp => "tcp",
                         Protocol::Udp => "udp",
                     };
@@ -284,6 +1531,7 @@ impl ProviderConfig {
                         port: Some(port),
                         protocol,
                         scheme: scheme.to_string(),
+                        path_prefix: None,
                     })
                 } else {
                     // Port parsing failed - exclude
@@ -297,7 +1545,7 @@ impl ProviderConfig {
                     let service_name = service_parts.join("-");
 
                     if let Ok(port) = parts[parts.len() - 2].parse::<u16>() {
-                        let protocol = Protocol::from_str(parts[parts.len() - 1]);
+                        let protocol = Protocol::parse(parts[parts.len() - 1]);
                         let scheme = match &protocol {
                             Protocol::Http => {
                                 if parts[parts.len() - 1].to_lowercase() == "https" {
@@ -306,6 +1554,9 @@ impl ProviderConfig {
                                     "http"
                                 }
                             }
+                            Protocol::Tcp if parts[parts.len() - 1].to_lowercase() == "tls" => {
+                                "tls"
+                            }
                             Protocol::Tcp => "tcp",
                             Protocol::Udp => "udp",
                         };
@@ -315,6 +1566,7 @@ impl ProviderConfig {
                             port: Some(port),
                             protocol,
                             scheme: scheme.to_string(),
+                            path_prefix: None,
                         });
                     }
                 }
@@ -325,3 +1577,95 @@ impl ProviderConfig {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn attribute(tag: &str) -> TagAttribute {
+        TagAttribute::parse(tag).expect("attribute tag")
+    }
+
+    #[test]
+    fn tag_attribute() {
+        let parsed = attribute("tag:api--RateLimit-100-50");
+        assert_eq!(parsed.service, "api");
+        assert_eq!(parsed.key, "ratelimit");
+        assert_eq!(parsed.values, ["100", "50"]);
+
+        let switch = attribute("web--stripprefix");
+        assert_eq!(switch.key, "stripprefix");
+        assert!(switch.values.is_empty());
+
+        assert!(TagAttribute::parse("tag:api-3000-http").is_none());
+        assert!(TagAttribute::parse("tag:--path-api").is_none());
+        assert!(TagAttribute::parse("tag:api--").is_none());
+    }
+
+    #[test]
+    fn tag_attribute_path() {
+        assert_eq!(attribute("api--path-api").path().as_deref(), Some("/api"));
+        assert_eq!(
+            attribute("tag:api--path-api-v1").path().as_deref(),
+            Some("/api/v1")
+        );
+        // The first "--" separates the service, later ones are empty segments
+        assert_eq!(
+            attribute("api--path-api--v1").path().as_deref(),
+            Some("/api/v1")
+        );
+        assert_eq!(attribute("api--path").path(), None);
+    }
+
+    #[test]
+    fn notification_urls_left_out_of_debug() {
+        let config = ProviderConfig {
+            webhook_urls: Some(vec![Secret::new(
+                "https://automation.example.com/hooks/webhook-token".to_string(),
+            )]),
+            chat_channels: vec![ChatChannel {
+                kind: ChatKind::Slack,
+                url: Secret::new(
+                    "https://hooks.slack.com/services/T000/B000/slack-token".to_string(),
+                ),
+                services: true,
+                peers: true,
+            }],
+            ..Default::default()
+        };
+
+        let debug = format!("{:?}", config);
+        assert!(!debug.contains("webhook-token"));
+        assert!(!debug.contains("slack-token"));
+        assert!(debug.contains("Secret(***)"));
+    }
+
+    #[test]
+    fn service_tag() {
+        let config = ProviderConfig::default();
+
+        let info = config
+            .parse_service_info_from_tag("tag:my-web-app-3000-https")
+            .expect("service tag");
+        assert_eq!(info.name, "my-web-app");
+        assert_eq!(info.port, Some(3000));
+        assert_eq!(info.protocol, Protocol::Http);
+        assert_eq!(info.scheme, "https");
+        assert_eq!(info.path_prefix, None);
+
+        let info = config
+            .parse_service_info_from_tag("db-5432-tls")
+            .expect("service tag");
+        assert_eq!(
+            (info.protocol, info.scheme.as_str()),
+            (Protocol::Tcp, "tls")
+        );
+
+        // Attribute tags configure a service without declaring one
+        assert!(
+            config
+                .parse_service_info_from_tag("tag:api--path-api-v1")
+                .is_none()
+        );
+    }
+}
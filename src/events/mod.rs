@@ -0,0 +1,87 @@
+use crate::store::diff::ConfigDiff;
+use serde::Serialize;
+use std::sync::LazyLock;
+use tokio::sync::broadcast;
+use utoipa::ToSchema;
+
+/// Events buffered per subscriber; slower subscribers skip the oldest ones
+const EVENT_BUFFER: usize = 256;
+
+static EVENTS: LazyLock<Events> = LazyLock::new(Events::new);
+
+/// Process-wide stream of peer and service changes
+pub fn events() -> &'static Events {
+    &EVENTS
+}
+
+/// A change observed between two generations
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ProviderEvent {
+    /// A peer appeared in the tailnet
+    PeerJoined {
+        id: String,
+        hostname: String,
+    },
+    /// A peer disappeared from the tailnet
+    PeerLeft {
+        id: String,
+        hostname: String,
+    },
+    PeerOnline {
+        id: String,
+        hostname: String,
+    },
+    PeerOffline {
+        id: String,
+        hostname: String,
+    },
+    /// A service was added to the published configuration
+    ServiceAdded {
+        protocol: String,
+        name: String,
+    },
+    /// A service was removed from the published configuration
+    ServiceRemoved {
+        protocol: String,
+        name: String,
+    },
+    /// The published configuration changed, with the routers and services it changed
+    ConfigChanged(ConfigDiff),
+}
+
+impl ProviderEvent {
+    /// Name of the event, used as the SSE event type
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ProviderEvent::PeerJoined { .. } => "peer_joined",
+            ProviderEvent::PeerLeft { .. } => "peer_left",
+            ProviderEvent::PeerOnline { .. } => "peer_online",
+            ProviderEvent::PeerOffline { .. } => "peer_offline",
+            ProviderEvent::ServiceAdded { .. } => "service_added",
+            ProviderEvent::ServiceRemoved { .. } => "service_removed",
+            ProviderEvent::ConfigChanged(_) => "config_changed",
+        }
+    }
+}
+
+pub struct Events {
+    sender: broadcast::Sender<ProviderEvent>,
+}
+
+impl Events {
+    fn new() -> Self {
+        let (sender, _) = broadcast::channel(EVENT_BUFFER);
+        Self { sender }
+    }
+
+    /// Send an event to every current subscriber
+    pub fn publish(&self, event: ProviderEvent) {
+        // Without subscribers there is nobody to tell
+        let _ = self.sender.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<ProviderEvent> {
+        self.sender.subscribe()
+    }
+}
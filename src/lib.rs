@@ -0,0 +1,28 @@
+//! Discovery of services on a Tailscale network and generation of Traefik dynamic
+//! configuration for them, for programs embedding the provider instead of running its
+//! HTTP server.
+//!
+//! ```no_run
+//! use traefik_tailscale_provider::{ProviderConfig, TraefikProvider};
+//!
+//! # async fn run() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+//! let provider = TraefikProvider::new(ProviderConfig::from_env())?;
+//! let config = provider.generate_config().await?;
+//! println!("{}", serde_json::to_string_pretty(&config)?);
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod config;
+pub mod events;
+pub mod metrics;
+pub mod platform;
+#[cfg(feature = "redis")]
+pub mod redis;
+pub mod store;
+pub mod tailscale;
+pub mod traefik;
+
+pub use config::ProviderConfig;
+pub use tailscale::TailscaleClient;
+pub use traefik::{DynamicConfig, TraefikProvider};
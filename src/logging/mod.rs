@@ -0,0 +1,183 @@
+use crate::config::{LogFormat, ProviderConfig};
+use axum::{
+    extract::{ConnectInfo, Request},
+    http::header,
+    middleware::Next,
+    response::Response,
+};
+use std::net::SocketAddr;
+use std::sync::{Mutex, OnceLock};
+use std::time::Instant;
+use tracing::{Instrument, info, info_span, warn};
+use tracing_subscriber::filter::Targets;
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::{Registry, fmt, prelude::*, reload};
+
+/// Filter used when LOG_LEVEL is invalid
+const DEFAULT_LEVEL: &str = "info";
+
+static LOG_LEVEL: OnceLock<LogLevel> = OnceLock::new();
+
+/// Runtime control of the log filter, see PUT /admin/loglevel
+pub struct LogLevel {
+    handle: reload::Handle<Targets, Registry>,
+    /// Directives of the filter in effect, as given
+    current: Mutex<String>,
+}
+
+impl LogLevel {
+    pub fn get(&self) -> String {
+        self.current.lock().unwrap().clone()
+    }
+
+    /// Replace the filter, e.g. "debug" or "info,traefik_tailscale_provider=trace"
+    pub fn set(&self, directives: &str) -> Result<(), String> {
+        let filter: Targets = directives.parse().map_err(|e| format!("{}", e))?;
+        self.handle.reload(filter).map_err(|e| e.to_string())?;
+        *self.current.lock().unwrap() = directives.to_string();
+        Ok(())
+    }
+}
+
+/// The log filter of the process, once `init` has run
+pub fn log_level() -> Option<&'static LogLevel> {
+    LOG_LEVEL.get()
+}
+
+/// Install the global subscriber in the configured format and level
+pub fn init(config: &ProviderConfig) {
+    let (filter, invalid) = match config.log_level.parse::<Targets>() {
+        Ok(filter) => (filter, None),
+        Err(e) => (DEFAULT_LEVEL.parse().expect("valid default level"), Some(e)),
+    };
+    let (filter, handle) = reload::Layer::new(filter);
+
+    // Closing spans log how long they took, e.g. a generation cycle and its LocalAPI calls
+    let span_events = if config.log_span_timings {
+        FmtSpan::CLOSE
+    } else {
+        FmtSpan::NONE
+    };
+    let json = config.log_format == LogFormat::Json;
+    #[cfg(feature = "otel")]
+    let (otel, otel_error) = match otel_layer(config) {
+        Ok(layer) => (layer, None),
+        Err(e) => (None, Some(e)),
+    };
+    #[cfg(not(feature = "otel"))]
+    let (otel, otel_error) = (
+        None::<tracing_subscriber::layer::Identity>,
+        config
+            .otel_exporter_otlp_endpoint
+            .as_ref()
+            .map(|_| "this build has no \"otel\" feature".to_string()),
+    );
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(otel)
+        .with(json.then(|| fmt::layer().json().with_span_events(span_events.clone())))
+        .with((!json).then(|| fmt::layer().with_span_events(span_events)))
+        .init();
+
+    match (otel_error, &config.otel_exporter_otlp_endpoint) {
+        (Some(e), _) => warn!("Not exporting spans to OTEL_EXPORTER_OTLP_ENDPOINT: {}", e),
+        (None, Some(endpoint)) => info!("Exporting spans to {}", endpoint),
+        (None, None) => {}
+    }
+
+    let current = match invalid {
+        Some(e) => {
+            warn!(
+                "Invalid LOG_LEVEL {:?} ({}), using {}",
+                config.log_level, e, DEFAULT_LEVEL
+            );
+            DEFAULT_LEVEL.to_string()
+        }
+        None => config.log_level.clone(),
+    };
+    let _ = LOG_LEVEL.set(LogLevel {
+        handle,
+        current: Mutex::new(current),
+    });
+}
+
+/// Layer exporting the spans (generation cycles, LocalAPI calls, API requests) to the
+/// OTLP/HTTP collector at OTEL_EXPORTER_OTLP_ENDPOINT, in batches
+#[cfg(feature = "otel")]
+fn otel_layer<S>(
+    config: &ProviderConfig,
+) -> Result<
+    Option<tracing_opentelemetry::OpenTelemetryLayer<S, opentelemetry_sdk::trace::Tracer>>,
+    String,
+>
+where
+    S: tracing::Subscriber + for<'span> tracing_subscriber::registry::LookupSpan<'span>,
+{
+    use opentelemetry::trace::TracerProvider;
+    use opentelemetry_otlp::WithExportConfig;
+
+    let Some(endpoint) = &config.otel_exporter_otlp_endpoint else {
+        return Ok(None);
+    };
+    // Like the OTLP exporters of other languages, the signal path is appended to the
+    // base endpoint
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_http()
+        .with_endpoint(format!("{}/v1/traces", endpoint.trim_end_matches('/')))
+        .build()
+        .map_err(|e| e.to_string())?;
+    let resource = opentelemetry_sdk::Resource::builder()
+        .with_service_name(config.otel_service_name.clone())
+        .build();
+    let provider = opentelemetry_sdk::trace::SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(resource)
+        .build();
+    let tracer = provider.tracer(env!("CARGO_PKG_NAME"));
+    opentelemetry::global::set_tracer_provider(provider);
+    Ok(Some(tracing_opentelemetry::layer().with_tracer(tracer)))
+}
+
+/// Run each API request in a span, so its log lines can be told apart
+pub async fn trace_requests(request: Request, next: Next) -> Response {
+    let span = info_span!(
+        "request",
+        method = %request.method(),
+        path = %request.uri().path()
+    );
+    next.run(request).instrument(span).await
+}
+
+/// Log each API request once answered, including the ones rejected by the token
+/// check or the request limits (ACCESS_LOG)
+pub async fn access_log(request: Request, next: Next) -> Response {
+    let method = request.method().clone();
+    let path = request.uri().path().to_string();
+    let client = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(address)| address.ip().to_string())
+        .unwrap_or_else(|| "-".to_string());
+    let user_agent = request
+        .headers()
+        .get(header::USER_AGENT)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("-")
+        .to_string();
+    let started = Instant::now();
+
+    let response = next.run(request).await;
+    let latency_ms = started.elapsed().as_secs_f64() * 1000.0;
+    let status = response.status().as_u16();
+    info!(
+        target: "access",
+        client = %client,
+        method = %method,
+        path = %path,
+        status,
+        latency_ms,
+        user_agent = %user_agent,
+        "API request"
+    );
+    response
+}
@@ -1,39 +1,125 @@
-mod config;
-mod platform;
-mod tailscale;
-mod traefik;
+mod api;
+mod logging;
+#[cfg(feature = "notify")]
+mod notify;
+mod output;
 
+#[cfg(feature = "redis")]
+use traefik_tailscale_provider::redis;
+use traefik_tailscale_provider::{config, events, metrics, platform, store, tailscale, traefik};
+
+use api::admin::AdminAuth;
+use api::auth::{TokenAuth, require_token};
+use api::conditional::conditional_get;
+use api::limits::{LimitedListener, RequestLimits, enforce_limits};
+use api::longpoll;
+use api::staleness::mark_stale;
 use axum::{
     Router,
-    extract::State,
+    extract::{Query, State},
     http::StatusCode,
+    middleware,
     response::{IntoResponse, Json},
     routing::get,
+    serve::ListenerExt,
 };
-use config::ProviderConfig;
-use serde::Serialize;
+use chrono::{DateTime, Utc};
+use config::{AddressFamily, Protocol, ProviderConfig, Secret};
+use output::caddy::CaddyServer;
+use output::dns::DnsRenderer;
+use output::haproxy::HaproxyRenderer;
+use output::nginx::NginxRenderer;
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
 use std::sync::Arc;
 use std::time::Duration;
+use store::ConfigStore;
+use tokio::task::JoinHandle;
 use tokio::time::interval;
 use tracing::{error, info, warn};
+use traefik::filter::{ConfigFilter, filter_config};
 use traefik::{DynamicConfig, TraefikProvider};
-use utoipa::{OpenApi, ToSchema};
-use utoipa_scalar::{Scalar, Servable};
+#[cfg(feature = "docs")]
+use utoipa::OpenApi;
+use utoipa::ToSchema;
 
+#[cfg(feature = "docs")]
 #[derive(OpenApi)]
 #[openapi(
     paths(
         health_check,
+        readiness_check,
         get_dynamic_config,
-        get_tailscale_status
+        get_tailscale_status,
+        get_metrics,
+        api::events::stream_events,
+        api::history::get_config_diff,
+        api::history::get_config_history,
+        api::formats::get_caddy_config,
+        api::formats::get_nginx_config,
+        api::formats::get_haproxy_config,
+        api::formats::get_dns_config,
+        api::websocket::config_socket,
+        api::verify::verify_ingestion,
+        api::admin::whoami,
+        api::admin::refresh,
+        api::admin::get_disabled,
+        api::peers::get_peers,
+        api::peers::get_expiring_peers,
+        api::peers::explain_peer,
+        api::services::get_services,
+        api::services::get_routers,
+        api::admin::get_log_level,
+        api::admin::set_log_level,
+        api::admin::disable_peer,
+        api::admin::enable_peer,
+        api::admin::disable_service,
+        api::admin::enable_service,
+        api::state::export_state,
+        api::state::import_state,
+        api::cluster::get_cluster_status,
+        api::cluster::get_cluster_member
     ),
     components(
-        schemas(DynamicConfig, tailscale::Status, ErrorResponse, HealthResponse)
+        schemas(
+            DynamicConfig,
+            tailscale::Status,
+            ErrorResponse,
+            HealthResponse,
+            ReadinessResponse,
+            api::admin::AdminIdentity,
+            api::admin::RefreshResponse,
+            api::admin::DisabledEntries,
+            api::admin::LogLevelBody,
+            api::state::ProviderState,
+            api::state::DisabledState,
+            api::state::HistorySnapshot,
+            api::state::ImportSummary,
+            api::peers::PeerView,
+            api::peers::ExpiringPeer,
+            api::peers::PeerExplanation,
+            api::services::ServiceSummary,
+            api::services::RouterSummary,
+            traefik::provider::FilterCheck,
+            traefik::provider::TagCheck,
+            api::cluster::ClusterStatus,
+            api::cluster::ClusterMember,
+            store::diff::ConfigDiff,
+            store::diff::EntryChanges,
+            api::history::ConfigHistoryEntry,
+            api::websocket::ConfigMessage,
+            api::verify::VerifyReport,
+            api::verify::IngestionStatus,
+            events::ProviderEvent
+        )
     ),
     tags(
         (name = "Health", description = "Health check endpoints"),
         (name = "Configuration", description = "Traefik configuration management"),
-        (name = "Status", description = "Tailscale status information")
+        (name = "Status", description = "Tailscale status information"),
+        (name = "Metrics", description = "Prometheus metrics"),
+        (name = "Admin", description = "Administration endpoints authenticated by Tailscale identity"),
+        (name = "Cluster", description = "Configuration agreement across provider replicas")
     ),
     info(
         title = "Traefik Tailscale Provider",
@@ -43,16 +129,28 @@ use utoipa_scalar::{Scalar, Servable};
 )]
 struct ApiDoc;
 
+/// Response header carrying the version of the served configuration
+const CONFIG_VERSION_HEADER: &str = "X-Config-Version";
+
 #[derive(Clone)]
 struct AppState {
     provider: Arc<TraefikProvider>,
-    cached_config: Arc<tokio::sync::RwLock<Option<DynamicConfig>>>,
+    store: Arc<ConfigStore>,
+    admin_auth: Arc<AdminAuth>,
+    replica_id: Arc<str>,
+    cluster_members: Arc<[String]>,
+    /// Sent to the other replicas, which share API_TOKEN
+    api_token: Option<Secret>,
+    caddy: Arc<CaddyServer>,
+    nginx: Arc<NginxRenderer>,
+    haproxy: Arc<HaproxyRenderer>,
+    dns: Arc<DnsRenderer>,
+    max_config_staleness_seconds: u64,
+    traefik_api_url: Option<Arc<str>>,
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    tracing_subscriber::fmt::init();
-
     // Load .env file if it exists (environment variables take precedence)
     if let Err(e) = dotenvy::dotenv() {
         // Only warn if the error is not "file not found"
@@ -67,6 +165,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     }
 
     let config = ProviderConfig::from_env();
+    logging::init(&config);
     info!(
         "Starting Traefik Tailscale Provider with config: {:?}",
         config
@@ -74,74 +173,336 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
 
     let provider = Arc::new(TraefikProvider::new(config.clone())?);
 
-    // Test Tailscale connection
-    if let Err(e) = provider.test_connection().await {
-        error!("Failed to connect to Tailscale daemon: {}", e);
-        return Err(e);
-    }
-
-    let cached_config = Arc::new(tokio::sync::RwLock::new(None));
+    let store = ConfigStore::new(
+        config.embed_config_version,
+        config.state_file.clone(),
+        config.config_history_size,
+    );
+    let naming = config.clone();
+    let store = store
+        .with_marker_name(move |version| naming.decorate_name(&format!("provider-{}", version)));
+    let store = Arc::new(
+        match config.deterministic_output || config.shared_cache_url.is_some() {
+            true => store.with_hash_versions(),
+            false => store,
+        },
+    );
+    // Serve the last known good configuration until a fresh one is generated
+    store.restore().await;
 
     let state = AppState {
         provider: provider.clone(),
-        cached_config: cached_config.clone(),
+        store: store.clone(),
+        admin_auth: Arc::new(AdminAuth::from_config(&config)),
+        replica_id: config.replica_id.as_str().into(),
+        cluster_members: config.cluster_members.clone().unwrap_or_default().into(),
+        api_token: config.api_token.clone(),
+        caddy: Arc::new(CaddyServer::from_config(&config)),
+        nginx: Arc::new(NginxRenderer::from_config(&config)),
+        haproxy: Arc::new(HaproxyRenderer::from_config(&config)),
+        dns: Arc::new(DnsRenderer::from_config(&config)),
+        max_config_staleness_seconds: config.max_config_staleness_seconds,
+        traefik_api_url: config.traefik_api_url.as_deref().map(Into::into),
     };
 
+    #[cfg(feature = "notify")]
+    notify::spawn(&config);
+    #[cfg(not(feature = "notify"))]
+    if config.webhook_urls.is_some() || !config.chat_channels.is_empty() {
+        warn!(
+            "Notifications are configured but this build has no \"notify\" feature, ignoring them"
+        );
+    }
+    output::spawn(&config, store.clone())?;
+
     // Spawn background task to update configuration periodically
     let provider_clone = provider.clone();
-    let cached_config_clone = cached_config.clone();
+    let store_clone = store.clone();
     let update_interval = config.update_interval_seconds;
+    let startup_max_wait = config.startup_max_wait_seconds.map(Duration::from_secs);
+
+    // The API starts right away and answers with the restored configuration (or 503)
+    // while tailscaled, e.g. in a sidecar, is still starting. The task only ends when
+    // it gives up on tailscaled, which stops the server below.
+    let updates = tokio::spawn(async move {
+        if let Err(e) = wait_for_tailscale(&provider_clone, startup_max_wait).await {
+            return Err(format!("Giving up on the Tailscale daemon: {}", e));
+        }
 
-    tokio::spawn(async move {
+        // The first tick completes immediately and loads the initial configuration
         let mut interval = interval(Duration::from_secs(update_interval));
+        let mut ready = false;
         loop {
             interval.tick().await;
 
             match provider_clone.generate_config().await {
                 Ok(new_config) => {
-                    let mut cache = cached_config_clone.write().await;
-                    *cache = Some(new_config);
-                    info!("Updated Traefik configuration from Tailscale");
+                    let snapshot = store_clone.publish(new_config).await;
+                    info!(
+                        "Updated Traefik configuration from Tailscale ({})",
+                        snapshot.version
+                    );
+                    if !ready {
+                        platform::systemd::notify("READY=1");
+                        ready = true;
+                    }
                 }
                 Err(e) => {
-                    error!("Failed to update configuration: {}", e);
+                    store_clone.record_failure();
+                    error!(
+                        "Failed to update configuration ({} in a row): {}",
+                        store_clone.failed_generations(),
+                        e
+                    );
                 }
             }
         }
     });
 
-    // Initial configuration load
-    match provider.generate_config().await {
-        Ok(initial_config) => {
-            let mut cache = cached_config.write().await;
-            *cache = Some(initial_config);
-            info!("Loaded initial Traefik configuration");
-        }
-        Err(e) => {
-            warn!("Failed to load initial configuration: {}", e);
-        }
+    // Poll the upstream provider separately so its changes are published without
+    // waiting for the next Tailscale update
+    if config.upstream_provider_url.is_some() {
+        let provider = provider.clone();
+        let store = store.clone();
+        let poll_interval = Duration::from_secs(config.upstream_poll_interval_seconds);
+        tokio::spawn(async move {
+            let mut interval = interval(poll_interval);
+            loop {
+                interval.tick().await;
+
+                match provider.refresh_upstream().await {
+                    Ok(true) => match provider.generate_config().await {
+                        Ok(new_config) => {
+                            let snapshot = store.publish(new_config).await;
+                            info!(
+                                "Updated Traefik configuration from the upstream provider ({})",
+                                snapshot.version
+                            );
+                        }
+                        Err(e) => {
+                            store.record_failure();
+                            warn!("Failed to update configuration: {}", e);
+                        }
+                    },
+                    Ok(false) => {}
+                    Err(e) => warn!("Failed to fetch the upstream provider {}", e),
+                }
+            }
+        });
     }
 
     let app = Router::new()
         .route("/", get(health_check))
-        .route("/config", get(get_dynamic_config))
+        .route("/readyz", get(readiness_check))
+        .route(
+            "/config",
+            get(get_dynamic_config).layer(middleware::from_fn_with_state(
+                store.clone(),
+                conditional_get,
+            )),
+        )
         .route("/status", get(get_tailscale_status))
-        .merge(Scalar::with_url("/docs", ApiDoc::openapi()))
-        .with_state(state);
+        .route("/metrics", get(get_metrics))
+        .merge(api::history::router())
+        .merge(api::formats::router())
+        .merge(api::events::router())
+        .merge(api::websocket::router())
+        .merge(api::verify::router())
+        .merge(api::peers::router())
+        .merge(api::services::router())
+        .merge(api::admin::router(state.clone()))
+        .merge(api::state::router(state.clone()))
+        .merge(api::cluster::router());
 
-    let bind_addr = format!("0.0.0.0:{}", config.server_port);
-    let listener = tokio::net::TcpListener::bind(&bind_addr).await?;
+    #[cfg(feature = "docs")]
+    let app = app.merge(api::docs::router(ApiDoc::openapi()));
+    let app = match TokenAuth::from_config(&config) {
+        Some(auth) => app.layer(middleware::from_fn_with_state(
+            Arc::new(auth),
+            require_token,
+        )),
+        None => app,
+    };
+    let limits = Arc::new(RequestLimits::from_config(&config));
+    let app = app
+        .with_state(state)
+        .layer(middleware::from_fn_with_state(store.clone(), mark_stale))
+        .layer(middleware::from_fn_with_state(limits, enforce_limits))
+        .layer(middleware::from_fn(logging::trace_requests));
+    let app = match config.access_log {
+        true => app.layer(middleware::from_fn(logging::access_log)),
+        false => app,
+    };
+
+    #[cfg(not(feature = "https"))]
+    if config.serve_https {
+        return Err("SERVE_HTTPS is set but this build has no \"https\" feature".into());
+    }
+
+    let tcp_listeners = match platform::systemd::activated_listener()? {
+        Some(listener) => {
+            info!("Using the socket passed by systemd, SERVER_PORT is ignored");
+            vec![tokio::net::TcpListener::from_std(listener)?]
+        }
+        None => {
+            let bind_ips = if config.listen_tailnet_only {
+                tailnet_addresses(&provider, &config).await?
+            } else {
+                vec![config.bind_address.clone()]
+            };
+            let mut tcp_listeners = Vec::new();
+            for bind_ip in bind_ips {
+                let bind_ip = bind_ip
+                    .parse()
+                    .map_err(|e| format!("Invalid BIND_ADDRESS {}: {}", bind_ip, e))?;
+                let bind_addr = SocketAddr::new(bind_ip, config.server_port);
+                let tcp_listener = tokio::net::TcpListener::bind(&bind_addr)
+                    .await
+                    .map_err(|e| format!("Failed to listen on {}: {}", bind_addr, e))?;
+                tcp_listeners.push(tcp_listener);
+            }
+            tcp_listeners
+        }
+    };
+    let scheme = if config.serve_https { "https" } else { "http" };
+    for tcp_listener in &tcp_listeners {
+        info!(
+            "Traefik Tailscale Provider running on {}://{}",
+            scheme,
+            tcp_listener.local_addr()?
+        );
+    }
+    let listener = LimitedListener::new(tcp_listeners, config.max_connections);
+    platform::systemd::spawn_watchdog();
 
-    info!("Traefik Tailscale Provider running on http://{}", bind_addr);
     info!("Endpoints:");
     info!("  GET /        - Health check");
-    info!("  GET /config  - Traefik dynamic configuration (JSON)");
+    info!("  GET /readyz  - Readiness, failing while the configuration is stale too long");
+    info!("  GET /config  - Traefik dynamic configuration (JSON, ?protocol=&tag=&hostname=)");
+    info!("  GET /config/diff - Changes made by the last configuration update");
+    info!("  GET /config/history - Past configurations (fetch with /config?version=)");
+    info!("  GET /config/caddy - The HTTP routers as a Caddy JSON config");
+    info!("  GET /config/nginx - The HTTP routers as nginx upstream and server blocks");
+    info!("  GET /config/haproxy - The HTTP routers as an HAProxy frontend and backends");
+    info!("  GET /config/ws - WebSocket pushing the configuration (or ?diff=true) on changes");
+    info!("  GET /config/dns - Router hosts and service names as a hosts or zone file");
+    info!("  GET /verify  - Generated routers and services Traefik has loaded or is missing");
+    info!("  GET /services - Generated services with their peers and ports");
+    info!("  GET /routers - Generated routers with their rules");
     info!("  GET /status  - Tailscale status");
-    info!("  GET /docs    - API documentation (Scalar)");
+    info!("  GET /metrics - Prometheus metrics");
+    info!("  GET /peers   - Peers and why they are included or not (?online=&tag=&os=&included=)");
+    info!("  GET /peers/expiring - Nodes whose key expires soon");
+    info!("  GET /explain/<hostname> - Why a peer is or is not routed");
+    info!("  GET /events  - Peer and service changes (server-sent events)");
+    info!("  GET /cluster - Configuration agreement across replicas");
+    #[cfg(feature = "docs")]
+    info!("  GET /docs    - API documentation (Scalar, spec at /openapi.json)");
+    info!("  GET /admin/* - Administration (Tailscale identity)");
+    info!("  POST /refresh - Regenerate the configuration now (admin)");
+    info!("  PUT /admin/loglevel - Change the log level at runtime (admin)");
+    info!(
+        "  GET /state/export, POST /state/import - Back up or restore the provider state (admin)"
+    );
 
-    axum::serve(listener, app).await?;
+    // Tapping the listener gives it the ConnectInfo<SocketAddr> support of plain listeners
+    #[cfg(feature = "https")]
+    if config.serve_https {
+        let resolver = api::tls::TailscaleCertResolver::spawn(
+            provider.tailscale_client.clone(),
+            config.serve_https_domain.clone(),
+        );
+        let listener = api::tls::TlsListener::new(listener, resolver)?.tap_io(|_| {});
+        let server = axum::serve(
+            listener,
+            app.into_make_service_with_connect_info::<SocketAddr>(),
+        );
+        return serve_until_failed(server.into_future(), updates).await;
+    }
 
-    Ok(())
+    let server = axum::serve(
+        listener.tap_io(|_| {}),
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    );
+    serve_until_failed(server.into_future(), updates).await
+}
+
+/// Run the server until it fails or the update task ends, returning the error of
+/// whichever stopped first so main exits with it
+async fn serve_until_failed(
+    server: impl Future<Output = std::io::Result<()>>,
+    updates: JoinHandle<Result<(), String>>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    tokio::select! {
+        result = server => Ok(result?),
+        result = updates => match result {
+            Ok(result) => Ok(result?),
+            Err(e) => Err(format!("The configuration update task failed: {}", e).into()),
+        },
+    }
+}
+
+/// Tailscale IPs of this node to listen on for LISTEN_TAILNET_ONLY, one per tailscaled
+/// (those of TAILSCALE_SOURCES included), preferring the configured address family
+async fn tailnet_addresses(
+    provider: &TraefikProvider,
+    config: &ProviderConfig,
+) -> Result<Vec<String>, Box<dyn std::error::Error + Send + Sync>> {
+    wait_for_tailscale(
+        provider,
+        config.startup_max_wait_seconds.map(Duration::from_secs),
+    )
+    .await?;
+    let prefer_ipv6 = config.address_family == AddressFamily::Ipv6;
+    let address = |status: tailscale::Status| {
+        status
+            .tailscale_ips
+            .iter()
+            .find(|ip| ip.contains(':') == prefer_ipv6)
+            .or_else(|| status.tailscale_ips.first())
+            .cloned()
+    };
+
+    let status = provider.tailscale_client.get_status_without_peers().await?;
+    let mut addresses = vec![address(status).ok_or("This node has no Tailscale IP to listen on")?];
+    for (name, client) in provider.source_clients() {
+        match client.get_status_without_peers().await.map(address) {
+            Ok(Some(ip)) if !addresses.contains(&ip) => addresses.push(ip),
+            Ok(_) => {}
+            Err(e) => warn!(
+                "Not listening on the tailnet of Tailscale source {}: {}",
+                name, e
+            ),
+        }
+    }
+    Ok(addresses)
+}
+
+/// Retry the connection to the Tailscale daemon with exponential backoff (1s doubling up to
+/// 30s), for at most `max_wait` when given
+async fn wait_for_tailscale(
+    provider: &TraefikProvider,
+    max_wait: Option<Duration>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let started = tokio::time::Instant::now();
+    let mut backoff = Duration::from_secs(1);
+
+    loop {
+        let e = match provider.test_connection().await {
+            Ok(()) => return Ok(()),
+            Err(e) => e,
+        };
+        if max_wait.is_some_and(|max_wait| started.elapsed() + backoff > max_wait) {
+            return Err(e);
+        }
+
+        warn!(
+            "Tailscale daemon not reachable yet, retrying in {:?}: {}",
+            backoff, e
+        );
+        tokio::time::sleep(backoff).await;
+        backoff = (backoff * 2).min(Duration::from_secs(30));
+    }
 }
 
 #[utoipa::path(
@@ -149,16 +510,87 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     path = "/",
     tag = "Health",
     summary = "Health check",
-    description = "Returns health status of the provider",
+    description = "Returns health status of the provider with the state of tailscaled and the age of the served configuration",
     responses(
-        (status = 200, description = "Health check successful", body = HealthResponse)
+        (status = 200, description = "tailscaled is running", body = HealthResponse),
+        (status = 503, description = "tailscaled is unreachable or not running", body = HealthResponse)
     )
 )]
-async fn health_check() -> Json<HealthResponse> {
-    Json(HealthResponse {
-        status: "OK".to_string(),
+async fn health_check(State(state): State<AppState>) -> impl IntoResponse {
+    let (backend_state, tailscale_health, error) = match state
+        .provider
+        .tailscale_client
+        .get_status_without_peers()
+        .await
+    {
+        Ok(status) => (Some(status.backend_state), status.health, None),
+        Err(e) => (None, Vec::new(), Some(e.to_string())),
+    };
+    let running = backend_state.as_deref() == Some("Running");
+    let config_age_seconds = state
+        .store
+        .current()
+        .await
+        .map(|snapshot| (Utc::now() - snapshot.created_at).num_seconds());
+
+    let response = HealthResponse {
+        status: if running { "OK" } else { "Unavailable" }.to_string(),
         service: "Traefik Tailscale Provider".to_string(),
-    })
+        backend_state,
+        tailscale_health,
+        last_generated_at: state.store.last_published_at().await,
+        config_age_seconds,
+        error,
+    };
+    let status_code = if running {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status_code, Json(response))
+}
+
+#[utoipa::path(
+    get,
+    path = "/readyz",
+    tag = "Health",
+    summary = "Readiness check",
+    description = "Ready while a configuration is served that is fresh, or stale (the generations since it failed) for no longer than MAX_CONFIG_STALENESS_SECONDS",
+    responses(
+        (status = 200, description = "A usable configuration is served", body = ReadinessResponse),
+        (status = 503, description = "No configuration yet, or it is stale for too long", body = ReadinessResponse)
+    )
+)]
+async fn readiness_check(State(state): State<AppState>) -> impl IntoResponse {
+    let published = state.store.current().await.is_some();
+    let stale_for = state.store.stale_for().await;
+    let max = state.max_config_staleness_seconds;
+    let reason = if !published {
+        Some("No configuration was generated yet".to_string())
+    } else {
+        stale_for
+            .filter(|age| max > 0 && *age > max as i64)
+            .map(|age| {
+                format!(
+                    "Configuration is stale for {}s, longer than the {}s allowed",
+                    age, max
+                )
+            })
+    };
+
+    let response = ReadinessResponse {
+        ready: reason.is_none(),
+        stale: stale_for.is_some(),
+        config_age_seconds: stale_for,
+        failed_generations: state.store.failed_generations(),
+        reason,
+    };
+    let status_code = if response.ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status_code, Json(response))
 }
 
 #[utoipa::path(
@@ -166,35 +598,133 @@ async fn health_check() -> Json<HealthResponse> {
     path = "/config",
     tag = "Configuration",
     summary = "Get dynamic configuration",
-    description = "Returns Traefik dynamic configuration generated from Tailscale network",
+    description = "Returns Traefik dynamic configuration generated from Tailscale network. The protocol, tag and hostname parameters narrow it down to part of the tailnet, e.g. for several Traefik instances that each serve some of the peers. Tag and hostname keep the services of matching peers with the routers, middlewares and transports they use.",
+    params(
+        ("version" = Option<String>, Query, description = "Version or content hash of a configuration in /config/history to return instead of the current one"),
+        ("protocol" = Option<String>, Query, description = "Only the http, tcp or udp section"),
+        ("tag" = Option<String>, Query, description = "Only services of peers with a tag containing this text, matched like INCLUDE_TAGS"),
+        ("hostname" = Option<String>, Query, description = "Only services of the peer with this hostname (case-insensitive)"),
+        ("wait" = Option<String>, Query, description = "Long poll: hold the request until the configuration differs from hash, for at most this long (e.g. 30s, 2m, 500ms; up to 5m)"),
+        ("hash" = Option<String>, Query, description = "Version (X-Config-Version) or content hash of the configuration the client has, required with wait")
+    ),
     responses(
-        (status = 200, description = "Successful response with dynamic configuration", body = DynamicConfig),
+        (status = 200, description = "Successful response with dynamic configuration", body = DynamicConfig,
+            headers(
+                ("X-Config-Version" = String, description = "Provider generation the configuration came from"),
+                ("ETag" = String, description = "Hash of the configuration version and filters served, for If-None-Match"),
+                ("Last-Modified" = String, description = "When the configuration content last changed, for If-Modified-Since")
+            )),
+        (status = 304, description = "Unchanged since the If-None-Match ETag or If-Modified-Since date, or still hash when the wait was over"),
+        (status = 400, description = "Unknown protocol, invalid wait, or wait without hash", body = ErrorResponse),
+        (status = 404, description = "The requested version is not in the history", body = ErrorResponse),
         (status = 503, description = "Service unavailable - failed to generate configuration", body = ErrorResponse)
     )
 )]
-async fn get_dynamic_config(State(state): State<AppState>) -> axum::response::Response {
-    let cache = state.cached_config.read().await;
+async fn get_dynamic_config(
+    State(state): State<AppState>,
+    Query(query): Query<ConfigQuery>,
+) -> axum::response::Response {
+    let protocol = match query.protocol.as_deref().map(str::to_lowercase).as_deref() {
+        None => None,
+        Some("http") => Some(Protocol::Http),
+        Some("tcp") => Some(Protocol::Tcp),
+        Some("udp") => Some(Protocol::Udp),
+        Some(other) => {
+            let error_response = ErrorResponse {
+                error: format!("Unknown protocol {}, expected http, tcp or udp", other),
+            };
+            return (StatusCode::BAD_REQUEST, Json(error_response)).into_response();
+        }
+    };
 
-    match cache.as_ref() {
-        Some(config) => (StatusCode::OK, Json(config.clone())).into_response(),
-        None => {
-            drop(cache);
-            // Try to generate config on-demand if not cached
-            match state.provider.generate_config().await {
-                Ok(config) => {
-                    let mut cache = state.cached_config.write().await;
-                    *cache = Some(config.clone());
-                    (StatusCode::OK, Json(config)).into_response()
-                }
-                Err(_) => {
-                    let error_response = ErrorResponse {
-                        error: "Failed to generate configuration from Tailscale".to_string(),
-                    };
-                    (StatusCode::SERVICE_UNAVAILABLE, Json(error_response)).into_response()
+    if let Some(wait) = &query.wait
+        && query.version.is_none()
+    {
+        let Some(wait) = longpoll::parse_wait(wait) else {
+            let error_response = ErrorResponse {
+                error: format!("Invalid wait {}, expected e.g. 30s, 2m or 500ms", wait),
+            };
+            return (StatusCode::BAD_REQUEST, Json(error_response)).into_response();
+        };
+        let Some(known) = &query.hash else {
+            let error_response = ErrorResponse {
+                error: "wait requires the hash of the configuration to wait for changes of"
+                    .to_string(),
+            };
+            return (StatusCode::BAD_REQUEST, Json(error_response)).into_response();
+        };
+        if !longpoll::wait_for_change(&state.store, known, wait).await {
+            let version = state.store.current().await.map(|s| s.version.clone());
+            return match version {
+                Some(version) => {
+                    (StatusCode::NOT_MODIFIED, [(CONFIG_VERSION_HEADER, version)]).into_response()
                 }
-            }
+                None => StatusCode::NOT_MODIFIED.into_response(),
+            };
         }
     }
+
+    let snapshot = if let Some(version) = &query.version {
+        let Some(snapshot) = state.store.find(version).await else {
+            let error_response = ErrorResponse {
+                error: format!("Configuration version {} is not in the history", version),
+            };
+            return (StatusCode::NOT_FOUND, Json(error_response)).into_response();
+        };
+        snapshot
+    } else {
+        // Generate the config on demand if not cached, once for all concurrent requests
+        let generated = state
+            .store
+            .current_or_generate(|| state.provider.generate_config())
+            .await;
+        match generated {
+            Ok(snapshot) => snapshot,
+            Err(_) => {
+                let error_response = ErrorResponse {
+                    error: "Failed to generate configuration from Tailscale".to_string(),
+                };
+                return (StatusCode::SERVICE_UNAVAILABLE, Json(error_response)).into_response();
+            }
+        }
+    };
+
+    let filter = ConfigFilter {
+        protocol,
+        tag: query.tag,
+        hostname: query.hostname,
+    };
+    let config = if filter.protocol.is_none() && filter.tag.is_none() && filter.hostname.is_none() {
+        snapshot.config.clone()
+    } else {
+        let keep: Vec<String> = store::version_marker(&snapshot.config, &snapshot.version)
+            .into_iter()
+            .collect();
+        filter_config(
+            &snapshot.config,
+            &filter,
+            &state.provider.service_owners(),
+            &keep,
+        )
+    };
+
+    (
+        StatusCode::OK,
+        [(CONFIG_VERSION_HEADER, snapshot.version.clone())],
+        Json(config),
+    )
+        .into_response()
+}
+
+#[derive(Deserialize)]
+struct ConfigQuery {
+    version: Option<String>,
+    protocol: Option<String>,
+    tag: Option<String>,
+    hostname: Option<String>,
+    /// Long poll: how long to wait for the configuration to differ from `hash`
+    wait: Option<String>,
+    hash: Option<String>,
 }
 
 #[derive(Serialize, ToSchema)]
@@ -206,6 +736,33 @@ struct ErrorResponse {
 struct HealthResponse {
     status: String,
     service: String,
+    /// BackendState of tailscaled, e.g. "Running" or "NeedsLogin" (absent when unreachable)
+    backend_state: Option<String>,
+    /// Health warnings reported by tailscaled
+    tailscale_health: Vec<String>,
+    /// When a configuration was last generated successfully
+    last_generated_at: Option<DateTime<Utc>>,
+    /// Seconds since the served configuration content was first published
+    config_age_seconds: Option<i64>,
+    /// Why tailscaled could not be queried
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct ReadinessResponse {
+    ready: bool,
+    /// The generations since the served configuration failed, or it was restored from
+    /// STATE_FILE and none succeeded yet
+    stale: bool,
+    /// Seconds since the served configuration was generated, when it is stale
+    #[serde(skip_serializing_if = "Option::is_none")]
+    config_age_seconds: Option<i64>,
+    /// Failed generations since the last successful one
+    failed_generations: u64,
+    /// Why the provider is not ready
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<String>,
 }
 
 #[utoipa::path(
@@ -230,3 +787,23 @@ async fn get_tailscale_status(State(state): State<AppState>) -> axum::response::
         }
     }
 }
+
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    tag = "Metrics",
+    summary = "Get metrics",
+    description = "Returns provider metrics in the Prometheus text exposition format",
+    responses(
+        (status = 200, description = "Prometheus metrics", body = String, content_type = "text/plain")
+    )
+)]
+async fn get_metrics() -> impl IntoResponse {
+    (
+        [(
+            axum::http::header::CONTENT_TYPE,
+            "text/plain; version=0.0.4",
+        )],
+        metrics::metrics().render(),
+    )
+}
@@ -0,0 +1,211 @@
+use prometheus::{
+    Encoder, IntCounter, IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry, TextEncoder,
+};
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
+
+static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+/// Process-wide Prometheus metrics
+pub fn metrics() -> &'static Metrics {
+    &METRICS
+}
+
+pub struct Metrics {
+    registry: Registry,
+    /// Included peers whose tags produced no valid service in the last generation
+    pub peers_without_services: IntGauge,
+    /// Peers currently suppressed for flapping online/offline
+    pub dampened_peers: IntGauge,
+    /// Times a peer started being suppressed for flapping
+    pub peer_dampenings: IntCounter,
+    /// Byte counters of published peers as reported by tailscaled, by hostname
+    peer_rx_bytes: IntGaugeVec,
+    peer_tx_bytes: IntGaugeVec,
+    /// Bytes transferred between refreshes, surviving tailscaled restarts
+    peer_rx_bytes_total: IntCounterVec,
+    peer_tx_bytes_total: IntCounterVec,
+    /// Services published for each peer (always 1), to join traffic with services
+    peer_services: IntGaugeVec,
+    /// Seconds until the key of each node with key expiry expires (negative once expired)
+    peer_key_expiry_seconds: IntGaugeVec,
+    /// Nodes whose key expires within KEY_EXPIRY_WARNING_DAYS or has expired
+    pub peers_key_expiring: IntGauge,
+    /// Byte counters of the previous refresh, by hostname
+    last_traffic: Mutex<HashMap<String, (i64, i64)>>,
+}
+
+/// Traffic of a published peer at one refresh
+#[derive(Clone)]
+pub struct PeerTraffic {
+    pub hostname: String,
+    pub rx_bytes: i64,
+    pub tx_bytes: i64,
+    pub services: Vec<String>,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new();
+
+        let peers_without_services = IntGauge::new(
+            "tailscale_provider_peers_without_services",
+            "Peers that passed filtering but produced no services in the last generation",
+        )
+        .expect("valid metric definition");
+        registry
+            .register(Box::new(peers_without_services.clone()))
+            .expect("metric registered once");
+
+        let dampened_peers = IntGauge::new(
+            "tailscale_provider_dampened_peers",
+            "Peers excluded for flapping online/offline in the last generation",
+        )
+        .expect("valid metric definition");
+        registry
+            .register(Box::new(dampened_peers.clone()))
+            .expect("metric registered once");
+
+        let peer_dampenings = IntCounter::new(
+            "tailscale_provider_peer_dampenings_total",
+            "Times a peer started being excluded for flapping online/offline",
+        )
+        .expect("valid metric definition");
+        registry
+            .register(Box::new(peer_dampenings.clone()))
+            .expect("metric registered once");
+
+        let gauge_vec = |name: &str, help: &str, labels: &[&str]| {
+            let gauge =
+                IntGaugeVec::new(Opts::new(name, help), labels).expect("valid metric definition");
+            registry
+                .register(Box::new(gauge.clone()))
+                .expect("metric registered once");
+            gauge
+        };
+        let counter_vec = |name: &str, help: &str| {
+            let counter = IntCounterVec::new(Opts::new(name, help), &["hostname"])
+                .expect("valid metric definition");
+            registry
+                .register(Box::new(counter.clone()))
+                .expect("metric registered once");
+            counter
+        };
+
+        let peer_rx_bytes = gauge_vec(
+            "tailscale_provider_peer_rx_bytes",
+            "Bytes received from the peer since tailscaled started",
+            &["hostname"],
+        );
+        let peer_tx_bytes = gauge_vec(
+            "tailscale_provider_peer_tx_bytes",
+            "Bytes sent to the peer since tailscaled started",
+            &["hostname"],
+        );
+        let peer_rx_bytes_total = counter_vec(
+            "tailscale_provider_peer_rx_bytes_total",
+            "Bytes received from the peer, summed over refreshes",
+        );
+        let peer_tx_bytes_total = counter_vec(
+            "tailscale_provider_peer_tx_bytes_total",
+            "Bytes sent to the peer, summed over refreshes",
+        );
+        let peer_services = gauge_vec(
+            "tailscale_provider_peer_service",
+            "Services published for the peer (always 1)",
+            &["hostname", "service"],
+        );
+
+        let peer_key_expiry_seconds = gauge_vec(
+            "tailscale_provider_peer_key_expiry_seconds",
+            "Seconds until the node key expires, negative once expired",
+            &["hostname"],
+        );
+        let peers_key_expiring = IntGauge::new(
+            "tailscale_provider_peers_key_expiring",
+            "Nodes whose key expires within the warning window or has expired",
+        )
+        .expect("valid metric definition");
+        registry
+            .register(Box::new(peers_key_expiring.clone()))
+            .expect("metric registered once");
+
+        Self {
+            registry,
+            peers_without_services,
+            dampened_peers,
+            peer_dampenings,
+            peer_rx_bytes,
+            peer_tx_bytes,
+            peer_rx_bytes_total,
+            peer_tx_bytes_total,
+            peer_services,
+            peer_key_expiry_seconds,
+            peers_key_expiring,
+            last_traffic: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Record the seconds until the key of each node expires
+    pub fn record_key_expiry(&self, expiries: &[(String, i64)]) {
+        self.peer_key_expiry_seconds.reset();
+        for (hostname, seconds) in expiries {
+            self.peer_key_expiry_seconds
+                .with_label_values(&[hostname.as_str()])
+                .set(*seconds);
+        }
+    }
+
+    /// Record the traffic of the peers published by a generation. Peers that are no
+    /// longer published drop out of the gauges.
+    pub fn record_peer_traffic(&self, peers: &[PeerTraffic]) {
+        self.peer_rx_bytes.reset();
+        self.peer_tx_bytes.reset();
+        self.peer_services.reset();
+
+        let mut last_traffic = self.last_traffic.lock().unwrap();
+        let mut traffic = HashMap::new();
+        for peer in peers {
+            let hostname = [peer.hostname.as_str()];
+            self.peer_rx_bytes
+                .with_label_values(&hostname)
+                .set(peer.rx_bytes);
+            self.peer_tx_bytes
+                .with_label_values(&hostname)
+                .set(peer.tx_bytes);
+            for service in &peer.services {
+                self.peer_services
+                    .with_label_values(&[peer.hostname.as_str(), service.as_str()])
+                    .set(1);
+            }
+
+            // tailscaled counts from zero again after a restart
+            if let Some(&(last_rx, last_tx)) = last_traffic.get(&peer.hostname) {
+                let delta = |current: i64, last: i64| {
+                    if current >= last {
+                        current - last
+                    } else {
+                        current
+                    }
+                };
+                self.peer_rx_bytes_total
+                    .with_label_values(&hostname)
+                    .inc_by(delta(peer.rx_bytes, last_rx) as u64);
+                self.peer_tx_bytes_total
+                    .with_label_values(&hostname)
+                    .inc_by(delta(peer.tx_bytes, last_tx) as u64);
+            }
+            traffic.insert(peer.hostname.clone(), (peer.rx_bytes, peer.tx_bytes));
+        }
+        *last_traffic = traffic;
+    }
+
+    /// Render all metrics in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let mut buffer = Vec::new();
+        if let Err(e) = TextEncoder::new().encode(&self.registry.gather(), &mut buffer) {
+            tracing::error!("Failed to encode metrics: {}", e);
+        }
+        String::from_utf8(buffer).unwrap_or_default()
+    }
+}
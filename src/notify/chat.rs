@@ -0,0 +1,142 @@
+use super::{HttpsClient, deliver};
+use crate::config::{ChatChannel, ChatKind};
+use crate::events::{ProviderEvent, events};
+use hyper::body::Bytes;
+use serde_json::json;
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::warn;
+
+/// Changes are sent once nothing else happened for this long, so one refresh
+/// results in one message
+const QUIET_PERIOD: Duration = Duration::from_secs(5);
+
+/// Discord rejects messages longer than this
+const DISCORD_MAX_LENGTH: usize = 2000;
+
+const TITLE: &str = "Traefik Tailscale provider";
+
+/// Changes collected since the last message
+#[derive(Default)]
+struct Summary {
+    services_added: Vec<String>,
+    services_removed: Vec<String>,
+    peers_offline: Vec<String>,
+}
+
+impl Summary {
+    fn is_empty(&self) -> bool {
+        self.services_added.is_empty()
+            && self.services_removed.is_empty()
+            && self.peers_offline.is_empty()
+    }
+
+    fn record(&mut self, event: ProviderEvent) {
+        match event {
+            ProviderEvent::ServiceAdded { name, .. } => self.services_added.push(name),
+            ProviderEvent::ServiceRemoved { name, .. } => self.services_removed.push(name),
+            ProviderEvent::PeerOffline { hostname, .. } => self.peers_offline.push(hostname),
+            ProviderEvent::PeerLeft { hostname, .. } => {
+                self.peers_offline.push(format!("{} (left)", hostname))
+            }
+            _ => {}
+        }
+    }
+
+    /// Message lines for a channel, empty when none of its changes happened
+    fn text_for(&self, channel: &ChatChannel) -> String {
+        let mut lines = Vec::new();
+        if channel.services {
+            if !self.services_added.is_empty() {
+                lines.push(format!(
+                    "Services added: {}",
+                    self.services_added.join(", ")
+                ));
+            }
+            if !self.services_removed.is_empty() {
+                lines.push(format!(
+                    "Services removed: {}",
+                    self.services_removed.join(", ")
+                ));
+            }
+        }
+        if channel.peers && !self.peers_offline.is_empty() {
+            lines.push(format!("Peers offline: {}", self.peers_offline.join(", ")));
+        }
+        lines.join("\n")
+    }
+}
+
+/// Collect service and peer changes and send a summary to every channel
+pub async fn notify_channels(client: HttpsClient, channels: Vec<ChatChannel>) {
+    let mut receiver = events().subscribe();
+    let mut summary = Summary::default();
+
+    loop {
+        let received = if summary.is_empty() {
+            receiver.recv().await
+        } else {
+            match tokio::time::timeout(QUIET_PERIOD, receiver.recv()).await {
+                Ok(received) => received,
+                Err(_) => {
+                    send(&client, &channels, &std::mem::take(&mut summary)).await;
+                    continue;
+                }
+            }
+        };
+
+        match received {
+            Ok(event) => summary.record(event),
+            Err(RecvError::Lagged(skipped)) => {
+                warn!("Chat notifications fell behind, skipped {} events", skipped);
+            }
+            Err(RecvError::Closed) => break,
+        }
+    }
+}
+
+async fn send(client: &HttpsClient, channels: &[ChatChannel], summary: &Summary) {
+    for channel in channels {
+        let text = summary.text_for(channel);
+        if text.is_empty() {
+            continue;
+        }
+
+        match channel.kind {
+            ChatKind::Ntfy => {
+                let headers = [("Title", TITLE), ("Tags", "traefik")];
+                deliver(client, &channel.url, "text/plain", &headers, text.into()).await;
+            }
+            ChatKind::Slack => {
+                let body = json!({ "text": format!("*{}*\n{}", TITLE, text) });
+                deliver(
+                    client,
+                    &channel.url,
+                    "application/json",
+                    &[],
+                    json_body(&body),
+                )
+                .await;
+            }
+            ChatKind::Discord => {
+                let content: String = format!("**{}**\n{}", TITLE, text)
+                    .chars()
+                    .take(DISCORD_MAX_LENGTH)
+                    .collect();
+                let body = json!({ "content": content });
+                deliver(
+                    client,
+                    &channel.url,
+                    "application/json",
+                    &[],
+                    json_body(&body),
+                )
+                .await;
+            }
+        }
+    }
+}
+
+fn json_body(value: &serde_json::Value) -> Bytes {
+    Bytes::from(value.to_string())
+}
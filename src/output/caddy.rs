@@ -0,0 +1,282 @@
+//! Caddy output: the HTTP routers rendered as a server of Caddy's JSON config, served
+//! at /config/caddy and loaded through Caddy's admin API
+
+use crate::config::ProviderConfig;
+use crate::output::ConfigWriter;
+use crate::output::kv::{self, HttpClient};
+use crate::output::rules::{self, PathMatch, RuleMatch, strip_provider};
+use crate::traefik::{DynamicConfig, HttpConfig, Router, Service};
+use http_body_util::Full;
+use hyper::body::Bytes;
+use hyper::{Method, Request, header};
+use hyper_util::client::legacy::{Client, connect::HttpConnector};
+use hyper_util::rt::TokioExecutor;
+use serde_json::{Map, Value, json};
+use tracing::debug;
+
+/// The server of Caddy's http app the routers are rendered into
+pub struct CaddyServer {
+    name: String,
+    listen: Vec<String>,
+}
+
+impl CaddyServer {
+    pub fn from_config(config: &ProviderConfig) -> Self {
+        Self {
+            name: config.caddy_server_name.clone(),
+            listen: config.caddy_listen.clone(),
+        }
+    }
+
+    /// A complete Caddy config with the HTTP routers as the routes of one server. Routers
+    /// whose rule has no Caddy equivalent are left out; TCP and UDP have none either.
+    pub fn render(&self, config: &DynamicConfig) -> Value {
+        let mut servers = Map::new();
+        servers.insert(self.name.clone(), self.render_server(config));
+        json!({ "apps": { "http": { "servers": servers } } })
+    }
+
+    fn render_server(&self, config: &DynamicConfig) -> Value {
+        let empty = HttpConfig::default();
+        let http = config.http.as_ref().unwrap_or(&empty);
+
+        let routes: Vec<Value> = rules::by_priority(http)
+            .into_iter()
+            .filter_map(|(name, router)| {
+                let Some(matched) = rules::parse_rule(&router.rule) else {
+                    debug!(
+                        "Caddy: skipping router {}, unsupported rule {}",
+                        name, router.rule
+                    );
+                    return None;
+                };
+                let service = http.services.get(strip_provider(&router.service))?;
+                Some(route(http, router, &matched, service))
+            })
+            .collect();
+
+        json!({ "listen": self.listen, "routes": routes })
+    }
+}
+
+fn route(http: &HttpConfig, router: &Router, matched: &RuleMatch, service: &Service) -> Value {
+    let mut handle: Vec<Value> =
+        router
+            .middlewares
+            .iter()
+            .flatten()
+            .filter_map(|name| http.middlewares.get(strip_provider(name)))
+            .flat_map(|middleware| {
+                let mut handlers = Vec::new();
+                if let Some(strip_prefix) = &middleware.strip_prefix {
+                    handlers.extend(strip_prefix.prefixes.iter().map(
+                        |prefix| json!({ "handler": "rewrite", "strip_path_prefix": prefix }),
+                    ));
+                }
+                if let Some(headers) = &middleware.headers {
+                    let mut handler = Map::new();
+                    handler.insert("handler".to_string(), json!("headers"));
+                    if let Some(request) = &headers.custom_request_headers {
+                        handler.insert("request".to_string(), header_ops(request, false));
+                    }
+                    if let Some(response) = &headers.custom_response_headers {
+                        handler.insert("response".to_string(), header_ops(response, true));
+                    }
+                    handlers.push(Value::Object(handler));
+                }
+                if middleware.compress.is_some() {
+                    handlers.push(
+                        json!({ "handler": "encode", "encodings": { "zstd": {}, "gzip": {} } }),
+                    );
+                }
+                handlers
+            })
+            .collect();
+    handle.push(reverse_proxy(http, service));
+
+    let mut route = Map::new();
+    let mut matcher = Map::new();
+    if !matched.hosts.is_empty() {
+        matcher.insert("host".to_string(), json!(matched.hosts));
+    }
+    if !matched.paths.is_empty() {
+        let paths: Vec<String> = matched
+            .paths
+            .iter()
+            .map(|path| match path {
+                PathMatch::Exact(path) => path.clone(),
+                PathMatch::Prefix(prefix) => format!("{}*", prefix),
+            })
+            .collect();
+        matcher.insert("path".to_string(), json!(paths));
+    }
+    if !matcher.is_empty() {
+        route.insert("match".to_string(), json!([matcher]));
+    }
+    route.insert("handle".to_string(), json!(handle));
+    route.insert("terminal".to_string(), json!(true));
+    Value::Object(route)
+}
+
+/// Headers to set, and to delete for Traefik's empty values
+fn header_ops(headers: &std::collections::BTreeMap<String, String>, deferred: bool) -> Value {
+    let set: Map<String, Value> = headers
+        .iter()
+        .filter(|(_, value)| !value.is_empty())
+        .map(|(name, value)| (name.clone(), json!([value])))
+        .collect();
+    let delete: Vec<&String> = headers
+        .iter()
+        .filter(|(_, value)| value.is_empty())
+        .map(|(name, _)| name)
+        .collect();
+    let mut ops = json!({ "set": set, "delete": delete });
+    if deferred {
+        // Applied once the upstream answered, over its headers
+        ops["deferred"] = json!(true);
+    }
+    ops
+}
+
+fn reverse_proxy(http: &HttpConfig, service: &Service) -> Value {
+    let load_balancer = &service.load_balancer;
+    let upstreams: Vec<Value> = load_balancer
+        .servers
+        .iter()
+        .map(|server| json!({ "dial": rules::server_address(&server.url) }))
+        .collect();
+
+    let mut handler = json!({ "handler": "reverse_proxy", "upstreams": upstreams });
+    let weights: Vec<i32> = load_balancer
+        .servers
+        .iter()
+        .map(|server| server.weight.unwrap_or(1))
+        .collect();
+    handler["load_balancing"] = if weights.windows(2).any(|pair| pair[0] != pair[1]) {
+        json!({ "selection_policy": { "policy": "weighted_round_robin", "weights": weights } })
+    } else {
+        json!({ "selection_policy": { "policy": "round_robin" } })
+    };
+
+    if load_balancer
+        .servers
+        .iter()
+        .any(|server| server.url.starts_with("https://"))
+    {
+        let insecure = load_balancer
+            .servers_transport
+            .as_deref()
+            .and_then(|name| http.servers_transports.get(strip_provider(name)))
+            .and_then(|transport| transport.insecure_skip_verify)
+            .unwrap_or(false);
+        let tls = if insecure {
+            json!({ "insecure_skip_verify": true })
+        } else {
+            json!({})
+        };
+        handler["transport"] = json!({ "protocol": "http", "tls": tls });
+    }
+
+    if let Some(health_check) = &load_balancer.health_check {
+        let mut active = json!({ "uri": health_check.path });
+        if let Some(interval) = &health_check.interval {
+            active["interval"] = json!(interval);
+        }
+        if let Some(timeout) = &health_check.timeout {
+            active["timeout"] = json!(timeout);
+        }
+        handler["health_checks"] = json!({ "active": active });
+    }
+    handler
+}
+
+/// Loads the rendered server through Caddy's admin API, leaving the rest of Caddy's
+/// config alone
+pub struct CaddyWriter {
+    url: String,
+    server: CaddyServer,
+    client: HttpClient,
+}
+
+impl CaddyWriter {
+    /// Writer for CADDY_ADMIN_URL, None when it is not set
+    pub fn from_config(config: &ProviderConfig) -> Option<Self> {
+        let url = config.caddy_admin_url.as_ref()?;
+        Some(Self {
+            url: url.trim_end_matches('/').to_string(),
+            server: CaddyServer::from_config(config),
+            client: Client::builder(TokioExecutor::new()).build(HttpConnector::new()),
+        })
+    }
+
+    async fn call(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<&Value>,
+    ) -> Result<Bytes, String> {
+        let body = body.map(Value::to_string).unwrap_or_default();
+        let request = Request::builder()
+            .method(method)
+            .uri(format!("{}{}", self.url, path))
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Full::new(Bytes::from(body)))
+            .map_err(|e| format!("Failed to build request: {}", e))?;
+        kv::send(&self.client, request).await
+    }
+}
+
+impl ConfigWriter for CaddyWriter {
+    fn describe(&self) -> String {
+        format!("Caddy {} (server {})", self.url, self.server.name)
+    }
+
+    /// Replace the server, creating the parts of the config leading to it that are
+    /// missing, and the whole config when Caddy runs without one
+    async fn write(&self, config: &DynamicConfig) -> Result<(), String> {
+        let document = self.server.render(config);
+        let current = self.call(Method::GET, "/config/", None).await?;
+        let current: Value =
+            serde_json::from_slice(&current).map_err(|e| format!("Invalid Caddy config: {}", e))?;
+        if current.is_null() {
+            debug!("Caddy: no config loaded, loading a new one");
+            self.call(Method::POST, "/load", Some(&document)).await?;
+            return Ok(());
+        }
+
+        let path = ["apps", "http", "servers", self.server.name.as_str()];
+        let existing = path
+            .iter()
+            .scan(&current, |node, key| {
+                *node = node.get(key).filter(|value| !value.is_null())?;
+                Some(*node)
+            })
+            .count();
+        if existing == path.len()
+            && current.pointer(&pointer(&path)) == document.pointer(&pointer(&path))
+        {
+            return Ok(());
+        }
+
+        // POST sets the first missing key, or replaces the server
+        let depth = (existing + 1).min(path.len());
+        let value = document
+            .pointer(&pointer(&path[..depth]))
+            .ok_or("Rendered config is missing the server")?;
+        debug!("Caddy: posting /config/{}", path[..depth].join("/"));
+        self.call(
+            Method::POST,
+            &format!("/config/{}", path[..depth].join("/")),
+            Some(value),
+        )
+        .await?;
+        Ok(())
+    }
+}
+
+/// JSON pointer to a path of object keys
+fn pointer(path: &[&str]) -> String {
+    path.iter()
+        .map(|key| format!("/{}", key.replace('~', "~0").replace('/', "~1")))
+        .collect()
+}
@@ -0,0 +1,227 @@
+//! Consul KV output, for Traefik's Consul provider
+
+use crate::config::{ProviderConfig, Secret};
+use crate::output::ConfigWriter;
+use crate::output::kv::{self, HttpClient};
+use crate::traefik::DynamicConfig;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use http_body_util::Full;
+use hyper::body::Bytes;
+use hyper::{Method, Request, header};
+use hyper_util::client::legacy::{Client, connect::HttpConnector};
+use hyper_util::rt::TokioExecutor;
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::HashMap;
+use tracing::debug;
+
+/// Operations Consul accepts in a single transaction
+const MAX_TXN_OPS: usize = 64;
+
+pub struct ConsulWriter {
+    url: String,
+    token: Option<Secret>,
+    root: String,
+    client: HttpClient,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct KvPair {
+    key: String,
+    /// Base64-encoded, null for folders and empty values
+    value: Option<String>,
+}
+
+impl ConsulWriter {
+    /// Writer for CONSUL_URL, None when it is not set
+    pub fn from_config(config: &ProviderConfig) -> Option<Self> {
+        let url = config.consul_url.as_ref()?;
+        Some(Self {
+            url: url.trim_end_matches('/').to_string(),
+            token: config.consul_token.clone(),
+            root: config.kv_root_key.trim_matches('/').to_string(),
+            client: Client::builder(TokioExecutor::new()).build(HttpConnector::new()),
+        })
+    }
+
+    fn request(
+        &self,
+        method: Method,
+        path: &str,
+        body: Bytes,
+    ) -> Result<Request<Full<Bytes>>, String> {
+        let mut request = Request::builder()
+            .method(method)
+            .uri(format!("{}{}", self.url, path))
+            .header(header::CONTENT_TYPE, "application/json");
+        if let Some(token) = &self.token {
+            request = request.header("X-Consul-Token", token.expose());
+        }
+        request
+            .body(Full::new(body))
+            .map_err(|e| format!("Failed to build request: {}", e))
+    }
+
+    /// Keys currently stored below the root key, with their values
+    async fn stored_keys(&self) -> Result<HashMap<String, String>, String> {
+        let path = format!("/v1/kv/{}/?recurse=true", self.root);
+        let request = self.request(Method::GET, &path, Bytes::new())?;
+        let body = match kv::send(&self.client, request).await {
+            Ok(body) => body,
+            // Nothing stored below the root yet
+            Err(e) if e.starts_with("HTTP 404") => return Ok(HashMap::new()),
+            Err(e) => return Err(e),
+        };
+
+        let pairs: Vec<KvPair> =
+            serde_json::from_slice(&body).map_err(|e| format!("Invalid KV listing: {}", e))?;
+        Ok(pairs
+            .into_iter()
+            .filter(|pair| !pair.key.ends_with('/'))
+            .map(|pair| {
+                let value = pair
+                    .value
+                    .and_then(|value| BASE64.decode(value).ok())
+                    .map(|value| String::from_utf8_lossy(&value).into_owned())
+                    .unwrap_or_default();
+                (pair.key, value)
+            })
+            .collect())
+    }
+}
+
+impl ConfigWriter for ConsulWriter {
+    fn describe(&self) -> String {
+        format!("Consul KV {}/{}", self.url, self.root)
+    }
+
+    /// Set the keys whose values changed and delete the keys of whatever is gone, in
+    /// transactions of up to 64 operations
+    async fn write(&self, config: &DynamicConfig) -> Result<(), String> {
+        let keys = kv::flatten(config, &self.root)?;
+        let stored = self.stored_keys().await?;
+
+        let (sets, deletes) = kv::changes(&keys, &stored);
+        let sets = sets.into_iter().map(|(key, value)| {
+            json!({ "KV": { "Verb": "set", "Key": key, "Value": BASE64.encode(value) } })
+        });
+        let deletes = deletes
+            .into_iter()
+            .map(|key| json!({ "KV": { "Verb": "delete", "Key": key } }));
+        let operations: Vec<_> = sets.chain(deletes).collect();
+        debug!(
+            "Consul KV: {} keys, {} operations",
+            keys.len(),
+            operations.len()
+        );
+
+        for batch in operations.chunks(MAX_TXN_OPS) {
+            let body = serde_json::to_vec(batch)
+                .map_err(|e| format!("Failed to serialize the transaction: {}", e))?;
+            let request = self.request(Method::PUT, "/v1/txn", Bytes::from(body))?;
+            kv::send(&self.client, request).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::extract::{Path, State};
+    use axum::http::StatusCode;
+    use axum::response::{IntoResponse, Response};
+    use axum::{Json, Router, routing};
+    use serde_json::Value;
+    use std::collections::BTreeMap;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Default)]
+    struct MockConsul {
+        keys: BTreeMap<String, String>,
+        operations: Vec<usize>,
+    }
+
+    type Shared = State<Arc<Mutex<MockConsul>>>;
+
+    async fn list(State(consul): Shared, Path(prefix): Path<String>) -> Response {
+        let consul = consul.lock().unwrap();
+        let pairs: Vec<Value> = consul
+            .keys
+            .iter()
+            .filter(|(key, _)| key.starts_with(&prefix))
+            .map(|(key, value)| json!({ "Key": key, "Value": BASE64.encode(value) }))
+            .collect();
+        if pairs.is_empty() {
+            return StatusCode::NOT_FOUND.into_response();
+        }
+        Json(pairs).into_response()
+    }
+
+    async fn txn(State(consul): Shared, Json(operations): Json<Vec<Value>>) -> StatusCode {
+        let mut consul = consul.lock().unwrap();
+        consul.operations.push(operations.len());
+        for operation in operations {
+            let key = operation["KV"]["Key"].as_str().unwrap().to_string();
+            match operation["KV"]["Verb"].as_str() {
+                Some("set") => {
+                    let value = BASE64.decode(operation["KV"]["Value"].as_str().unwrap());
+                    let value = String::from_utf8(value.unwrap()).unwrap();
+                    consul.keys.insert(key, value);
+                }
+                Some("delete") => {
+                    consul.keys.remove(&key);
+                }
+                verb => panic!("unexpected verb {:?}", verb),
+            }
+        }
+        StatusCode::OK
+    }
+
+    fn config(servers: &[(&str, &str)]) -> DynamicConfig {
+        let services: serde_json::Map<String, Value> = servers
+            .iter()
+            .map(|(name, url)| {
+                let service = json!({ "loadBalancer": { "servers": [{ "url": url }] } });
+                (name.to_string(), service)
+            })
+            .collect();
+        serde_json::from_value(json!({ "http": { "services": services } })).unwrap()
+    }
+
+    #[tokio::test]
+    async fn syncs_changes_and_deletes_stale_keys() {
+        let consul = Arc::new(Mutex::new(MockConsul::default()));
+        consul
+            .lock()
+            .unwrap()
+            .keys
+            .insert("other/key".to_string(), "kept".to_string());
+        let app = Router::new()
+            .route("/v1/kv/{*prefix}", routing::get(list))
+            .route("/v1/txn", routing::put(txn))
+            .with_state(consul.clone());
+        let writer = ConsulWriter::from_config(&ProviderConfig {
+            consul_url: Some(crate::api::serve(app).await),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let first = config(&[
+            ("web", "http://100.64.0.1:80"),
+            ("db", "http://100.64.0.2:5432"),
+        ]);
+        writer.write(&first).await.unwrap();
+        let second = config(&[("web", "http://100.64.0.3:80")]);
+        writer.write(&second).await.unwrap();
+
+        let consul = consul.lock().unwrap();
+        let mut expected = kv::flatten(&second, "traefik").unwrap();
+        expected.insert("other/key".to_string(), "kept".to_string());
+        assert_eq!(consul.keys, expected);
+        // The second write changes the web server and deletes the db keys only
+        assert_eq!(consul.operations, [2, 2]);
+    }
+}
@@ -0,0 +1,215 @@
+//! DNS output: the hosts of the HTTP routers and the names of the services as a zone
+//! file or a hosts file (CoreDNS hosts plugin), served at /config/dns and written to a
+//! file the DNS server loads
+
+use crate::config::{DnsFormat, ProviderConfig};
+use crate::output::ConfigWriter;
+use crate::output::file::ConfigFile;
+use crate::output::rules::{self, strip_provider};
+use crate::traefik::DynamicConfig;
+use chrono::Utc;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+use std::net::IpAddr;
+use tracing::debug;
+
+/// Renders the names Traefik serves with the addresses they resolve to
+pub struct DnsRenderer {
+    format: DnsFormat,
+    /// Without a trailing dot
+    zone: Option<String>,
+    /// Addresses Traefik listens on; the peers' own addresses when empty
+    targets: Vec<IpAddr>,
+    ttl: u32,
+}
+
+impl DnsRenderer {
+    pub fn from_config(config: &ProviderConfig) -> Self {
+        Self {
+            format: config.dns_format,
+            zone: config
+                .dns_zone
+                .as_ref()
+                .map(|zone| zone.trim_matches('.').to_lowercase()),
+            targets: config.dns_targets.clone(),
+            ttl: config.dns_ttl,
+        }
+    }
+
+    /// The file for the configured format. `serial` versions the zone.
+    pub fn render(&self, config: &DynamicConfig, serial: u32) -> String {
+        let records = self.records(config);
+        match self.format {
+            DnsFormat::Hosts => self.render_hosts(&records),
+            DnsFormat::Zone => self.render_zone(&records, serial),
+        }
+    }
+
+    /// Names with their addresses: the hosts of the HTTP routers, and with a zone, each
+    /// service as a name in it
+    fn records(&self, config: &DynamicConfig) -> BTreeMap<String, BTreeSet<IpAddr>> {
+        let mut services: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+        if let Some(http) = &config.http {
+            for (name, service) in &http.services {
+                let servers = service.load_balancer.servers.iter();
+                services.insert(name, servers.map(|server| server.url.as_str()).collect());
+            }
+        }
+        if let Some(tcp) = &config.tcp {
+            for (name, service) in &tcp.services {
+                let servers = service.load_balancer.servers.iter();
+                services.insert(
+                    name,
+                    servers.map(|server| server.address.as_str()).collect(),
+                );
+            }
+        }
+        if let Some(udp) = &config.udp {
+            for (name, service) in &udp.services {
+                let servers = service.load_balancer.servers.iter();
+                services.insert(
+                    name,
+                    servers.map(|server| server.address.as_str()).collect(),
+                );
+            }
+        }
+
+        let addresses = |service: &str| -> BTreeSet<IpAddr> {
+            let servers = services.get(service).map(Vec::as_slice).unwrap_or_default();
+            // Server-less services, like the embedded version, get no records
+            if servers.is_empty() {
+                return BTreeSet::new();
+            }
+            if !self.targets.is_empty() {
+                return self.targets.iter().copied().collect();
+            }
+            servers
+                .iter()
+                .filter_map(|server| server_ip(server))
+                .collect()
+        };
+
+        let mut records: BTreeMap<String, BTreeSet<IpAddr>> = BTreeMap::new();
+        for (name, router) in config.http.iter().flat_map(|http| &http.routers) {
+            let Some(matched) = rules::parse_rule(&router.rule) else {
+                debug!(
+                    "DNS: skipping router {}, unsupported rule {}",
+                    name, router.rule
+                );
+                continue;
+            };
+            for host in matched.hosts {
+                records
+                    .entry(host.to_lowercase())
+                    .or_default()
+                    .extend(addresses(strip_provider(&router.service)));
+            }
+        }
+        if let Some(zone) = &self.zone {
+            for service in services.keys() {
+                let name = format!("{}.{}", dns_label(service), zone);
+                records.entry(name).or_default().extend(addresses(service));
+            }
+        }
+        records.retain(|_, addresses| !addresses.is_empty());
+        records
+    }
+
+    /// "address name..." lines, for the hosts plugin or /etc/hosts
+    fn render_hosts(&self, records: &BTreeMap<String, BTreeSet<IpAddr>>) -> String {
+        let mut names: BTreeMap<IpAddr, Vec<&str>> = BTreeMap::new();
+        for (name, addresses) in records {
+            for address in addresses {
+                names.entry(*address).or_default().push(name);
+            }
+        }
+
+        let mut out = String::from("# Generated by traefik-tailscale-provider, do not edit\n");
+        for (address, names) in names {
+            let _ = writeln!(out, "{} {}", address, names.join(" "));
+        }
+        out
+    }
+
+    /// A zone file for DNS_ZONE; names outside of it are left out
+    fn render_zone(&self, records: &BTreeMap<String, BTreeSet<IpAddr>>, serial: u32) -> String {
+        let Some(zone) = &self.zone else {
+            return "; DNS_ZONE is not set\n".to_string();
+        };
+
+        let mut out = String::from("; Generated by traefik-tailscale-provider, do not edit\n");
+        let _ = writeln!(out, "$ORIGIN {}.", zone);
+        let _ = writeln!(out, "$TTL {}", self.ttl);
+        let _ = writeln!(
+            out,
+            "@ IN SOA ns.{zone}. hostmaster.{zone}. {serial} 3600 600 86400 {ttl}",
+            zone = zone,
+            serial = serial,
+            ttl = self.ttl
+        );
+        for (name, addresses) in records {
+            let label = if name == zone {
+                "@"
+            } else if let Some(label) = name.strip_suffix(&format!(".{}", zone)) {
+                label
+            } else {
+                debug!("DNS: skipping {}, outside of zone {}", name, zone);
+                continue;
+            };
+            for address in addresses {
+                let kind = if address.is_ipv4() { "A" } else { "AAAA" };
+                let _ = writeln!(out, "{} IN {} {}", label, kind, address);
+            }
+        }
+        out
+    }
+}
+
+/// IP address of a server URL or address; None for hostnames
+fn server_ip(server: &str) -> Option<IpAddr> {
+    let address = rules::server_address(server);
+    let (host, _) = address.rsplit_once(':')?;
+    host.trim_start_matches('[')
+        .trim_end_matches(']')
+        .parse()
+        .ok()
+}
+
+/// A service name as a single DNS label
+fn dns_label(name: &str) -> String {
+    name.to_lowercase()
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | '0'..='9' | '-' => c,
+            _ => '-',
+        })
+        .collect()
+}
+
+/// Writes the rendered records to a file and runs a command reloading the DNS server
+pub struct DnsWriter {
+    file: ConfigFile,
+    renderer: DnsRenderer,
+}
+
+impl DnsWriter {
+    /// Writer for DNS_FILE, None when it is not set
+    pub fn from_config(config: &ProviderConfig) -> Option<Self> {
+        let path = config.dns_file.clone()?;
+        Some(Self {
+            file: ConfigFile::new(path, config.dns_reload_command.clone()),
+            renderer: DnsRenderer::from_config(config),
+        })
+    }
+}
+
+impl ConfigWriter for DnsWriter {
+    fn describe(&self) -> String {
+        format!("DNS {}", self.file.path())
+    }
+
+    async fn write(&self, config: &DynamicConfig) -> Result<(), String> {
+        let serial = u32::try_from(Utc::now().timestamp()).unwrap_or(u32::MAX);
+        self.file.apply(self.renderer.render(config, serial)).await
+    }
+}
@@ -0,0 +1,336 @@
+//! etcd v3 output, for Traefik's etcd provider, through etcd's JSON gateway
+
+use crate::config::{ProviderConfig, Secret};
+use crate::output::ConfigWriter;
+use crate::output::kv::{self, HttpClient};
+use crate::traefik::DynamicConfig;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use http_body_util::Full;
+use hyper::body::Bytes;
+use hyper::{Method, Request, header};
+use hyper_util::client::legacy::{Client, connect::HttpConnector};
+use hyper_util::rt::TokioExecutor;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use tracing::debug;
+
+/// Operations etcd accepts in a single transaction (its --max-txn-ops default)
+const MAX_TXN_OPS: usize = 128;
+
+pub struct EtcdWriter {
+    url: String,
+    credentials: Option<(String, Secret)>,
+    root: String,
+    client: HttpClient,
+}
+
+#[derive(Debug, Deserialize)]
+struct RangeResponse {
+    header: ResponseHeader,
+    #[serde(default)]
+    kvs: Vec<KeyValue>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ResponseHeader {
+    /// int64 values are strings in the JSON gateway
+    revision: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct KeyValue {
+    key: String,
+    #[serde(default)]
+    value: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TxnResponse {
+    /// Left out when false
+    #[serde(default)]
+    succeeded: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct AuthResponse {
+    token: String,
+}
+
+impl EtcdWriter {
+    /// Writer for ETCD_URL, None when it is not set
+    pub fn from_config(config: &ProviderConfig) -> Option<Self> {
+        let url = config.etcd_url.as_ref()?;
+        Some(Self {
+            url: url.trim_end_matches('/').to_string(),
+            credentials: config
+                .etcd_username
+                .clone()
+                .zip(config.etcd_password.clone()),
+            root: config.kv_root_key.trim_matches('/').to_string(),
+            client: Client::builder(TokioExecutor::new()).build(HttpConnector::new()),
+        })
+    }
+
+    /// POST to the JSON gateway and decode the response
+    async fn call<T: for<'de> Deserialize<'de>>(
+        &self,
+        path: &str,
+        body: &Value,
+        token: Option<&str>,
+    ) -> Result<T, String> {
+        let mut request = Request::builder()
+            .method(Method::POST)
+            .uri(format!("{}{}", self.url, path))
+            .header(header::CONTENT_TYPE, "application/json");
+        if let Some(token) = token {
+            request = request.header(header::AUTHORIZATION, token);
+        }
+        let request = request
+            .body(Full::new(Bytes::from(body.to_string())))
+            .map_err(|e| format!("Failed to build request: {}", e))?;
+        let body = kv::send(&self.client, request).await?;
+        serde_json::from_slice(&body).map_err(|e| format!("Invalid response from {}: {}", path, e))
+    }
+
+    /// Authenticate for this write; tokens of etcd's simple auth expire after minutes
+    async fn token(&self) -> Result<Option<String>, String> {
+        let Some((name, password)) = &self.credentials else {
+            return Ok(None);
+        };
+        let body = json!({ "name": name, "password": password.expose() });
+        let response: AuthResponse = self
+            .call("/v3/auth/authenticate", &body, None)
+            .await
+            .map_err(|e| format!("Authentication failed: {}", e))?;
+        Ok(Some(response.token))
+    }
+}
+
+impl ConfigWriter for EtcdWriter {
+    fn describe(&self) -> String {
+        format!("etcd {}/{}", self.url, self.root)
+    }
+
+    /// Put the keys whose values changed and delete the keys of whatever is gone in
+    /// one transaction, which only applies when nothing below the root key changed
+    /// since it was read. Changes too large for one transaction put the keys first and
+    /// delete in the last ones.
+    async fn write(&self, config: &DynamicConfig) -> Result<(), String> {
+        let keys = kv::flatten(config, &self.root)?;
+        let token = self.token().await?;
+        let token = token.as_deref();
+
+        let prefix = format!("{}/", self.root);
+        // The range of all keys starting with the prefix ends before "<root>0"
+        let range_end = format!("{}0", self.root);
+        let range =
+            json!({ "key": BASE64.encode(&prefix), "range_end": BASE64.encode(&range_end) });
+        let stored: RangeResponse = self.call("/v3/kv/range", &range, token).await?;
+        let revision: i64 = stored
+            .header
+            .revision
+            .parse()
+            .map_err(|e| format!("Invalid revision: {}", e))?;
+        let stored: HashMap<String, String> = stored
+            .kvs
+            .into_iter()
+            .filter_map(|kv| {
+                let key = String::from_utf8(BASE64.decode(kv.key).ok()?).ok()?;
+                let value = String::from_utf8_lossy(&BASE64.decode(kv.value).ok()?).into_owned();
+                Some((key, value))
+            })
+            .collect();
+
+        let (puts, deletes) = kv::changes(&keys, &stored);
+        let puts = puts.into_iter().map(|(key, value)| {
+            json!({ "request_put": { "key": BASE64.encode(key), "value": BASE64.encode(value) } })
+        });
+        let deletes = deletes
+            .into_iter()
+            .map(|key| json!({ "request_delete_range": { "key": BASE64.encode(key) } }));
+        let operations: Vec<_> = puts.chain(deletes).collect();
+        debug!("etcd: {} keys, {} operations", keys.len(), operations.len());
+
+        for (index, batch) in operations.chunks(MAX_TXN_OPS).enumerate() {
+            // Only the first transaction can tell whether the keys were changed behind
+            // our back, the later ones run after our own changes
+            let compare = if index == 0 {
+                vec![json!({
+                    "target": "MOD",
+                    "key": BASE64.encode(&prefix),
+                    "range_end": BASE64.encode(&range_end),
+                    "result": "LESS",
+                    "mod_revision": (revision + 1).to_string(),
+                })]
+            } else {
+                Vec::new()
+            };
+            let txn = json!({ "compare": compare, "success": batch });
+            let response: TxnResponse = self.call("/v3/kv/txn", &txn, token).await?;
+            if !response.succeeded {
+                return Err("keys were modified concurrently, retrying later".to_string());
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::extract::State;
+    use axum::{Json, Router, routing};
+    use std::collections::BTreeMap;
+    use std::sync::{Arc, Mutex};
+
+    /// etcd's JSON gateway over an in-memory key space, keeping the revision each key
+    /// was last modified at
+    #[derive(Default)]
+    struct MockEtcd {
+        revision: i64,
+        keys: BTreeMap<String, (String, i64)>,
+        /// Modify a key right after the next range read, like a concurrent writer
+        interfere: bool,
+    }
+
+    type Shared = State<Arc<Mutex<MockEtcd>>>;
+
+    fn decode(value: &Value) -> String {
+        String::from_utf8(BASE64.decode(value.as_str().unwrap()).unwrap()).unwrap()
+    }
+
+    impl MockEtcd {
+        fn in_range(&self, range: &Value) -> impl Iterator<Item = (&String, &(String, i64))> {
+            let (start, end) = (decode(&range["key"]), decode(&range["range_end"]));
+            self.keys
+                .iter()
+                .filter(move |(key, _)| **key >= start && **key < end)
+        }
+
+        fn put(&mut self, key: String, value: String) {
+            self.revision += 1;
+            self.keys.insert(key, (value, self.revision));
+        }
+    }
+
+    async fn range(State(etcd): Shared, Json(range): Json<Value>) -> Json<Value> {
+        let mut etcd = etcd.lock().unwrap();
+        let kvs: Vec<Value> = etcd
+            .in_range(&range)
+            .map(|(key, (value, _))| {
+                json!({ "key": BASE64.encode(key), "value": BASE64.encode(value) })
+            })
+            .collect();
+        let response = json!({ "header": { "revision": etcd.revision.to_string() }, "kvs": kvs });
+        if std::mem::take(&mut etcd.interfere) {
+            etcd.put(
+                "traefik/http/routers/other/rule".to_string(),
+                "Host(`other`)".to_string(),
+            );
+        }
+        Json(response)
+    }
+
+    async fn txn(State(etcd): Shared, Json(txn): Json<Value>) -> Json<Value> {
+        let mut etcd = etcd.lock().unwrap();
+        for compare in txn["compare"].as_array().unwrap() {
+            assert_eq!(compare["target"], "MOD");
+            assert_eq!(compare["result"], "LESS");
+            let below: i64 = compare["mod_revision"].as_str().unwrap().parse().unwrap();
+            if etcd
+                .in_range(compare)
+                .any(|(_, (_, modified))| *modified >= below)
+            {
+                return Json(json!({ "header": {} }));
+            }
+        }
+        for operation in txn["success"].as_array().unwrap() {
+            if let Some(put) = operation.get("request_put") {
+                etcd.put(decode(&put["key"]), decode(&put["value"]));
+            } else {
+                let key = decode(&operation["request_delete_range"]["key"]);
+                etcd.keys.remove(&key);
+                etcd.revision += 1;
+            }
+        }
+        Json(json!({ "header": {}, "succeeded": true }))
+    }
+
+    async fn writer(etcd: &Arc<Mutex<MockEtcd>>) -> EtcdWriter {
+        let app = Router::new()
+            .route("/v3/kv/range", routing::post(range))
+            .route("/v3/kv/txn", routing::post(txn))
+            .with_state(etcd.clone());
+        EtcdWriter::from_config(&ProviderConfig {
+            etcd_url: Some(crate::api::serve(app).await),
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    fn config(servers: &[(&str, &str)]) -> DynamicConfig {
+        let services: serde_json::Map<String, Value> = servers
+            .iter()
+            .map(|(name, url)| {
+                let service = json!({ "loadBalancer": { "servers": [{ "url": url }] } });
+                (name.to_string(), service)
+            })
+            .collect();
+        serde_json::from_value(json!({ "http": { "services": services } })).unwrap()
+    }
+
+    fn stored(etcd: &Arc<Mutex<MockEtcd>>) -> BTreeMap<String, String> {
+        let etcd = etcd.lock().unwrap();
+        etcd.keys
+            .iter()
+            .map(|(key, (value, _))| (key.clone(), value.clone()))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn syncs_changes_and_deletes_stale_keys() {
+        let etcd = Arc::new(Mutex::new(MockEtcd::default()));
+        etcd.lock()
+            .unwrap()
+            .put("traefik0".to_string(), "outside the root".to_string());
+        let writer = writer(&etcd).await;
+
+        let first = config(&[
+            ("web", "http://100.64.0.1:80"),
+            ("db", "http://100.64.0.2:5432"),
+        ]);
+        writer.write(&first).await.unwrap();
+        let second = config(&[("web", "http://100.64.0.3:80")]);
+        writer.write(&second).await.unwrap();
+
+        let mut expected = kv::flatten(&second, "traefik").unwrap();
+        expected.insert("traefik0".to_string(), "outside the root".to_string());
+        assert_eq!(stored(&etcd), expected);
+    }
+
+    #[tokio::test]
+    async fn concurrent_changes_abort_the_write() {
+        let etcd = Arc::new(Mutex::new(MockEtcd::default()));
+        let writer = writer(&etcd).await;
+        let first = config(&[("web", "http://100.64.0.1:80")]);
+        writer.write(&first).await.unwrap();
+
+        etcd.lock().unwrap().interfere = true;
+        let second = config(&[("db", "http://100.64.0.2:5432")]);
+        let error = writer.write(&second).await.unwrap_err();
+        assert!(error.contains("modified concurrently"), "{}", error);
+        // Nothing of the aborted transaction applied
+        let mut expected = kv::flatten(&first, "traefik").unwrap();
+        expected.insert(
+            "traefik/http/routers/other/rule".to_string(),
+            "Host(`other`)".to_string(),
+        );
+        assert_eq!(stored(&etcd), expected);
+
+        // The next write sees the concurrent change and replaces it
+        writer.write(&second).await.unwrap();
+        assert_eq!(stored(&etcd), kv::flatten(&second, "traefik").unwrap());
+    }
+}
@@ -0,0 +1,77 @@
+//! Configuration files of other proxies, replaced and reloaded on every change
+
+use std::sync::Mutex;
+use std::time::Duration;
+use tracing::debug;
+
+/// How long the reload command may take
+const RELOAD_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A file holding rendered configuration, and the command that makes its reader pick
+/// up a new version
+pub struct ConfigFile {
+    path: String,
+    reload_command: Option<String>,
+    /// Last content the reload command ran for, to skip changes the file has no part in
+    applied: Mutex<Option<String>>,
+}
+
+impl ConfigFile {
+    pub fn new(path: String, reload_command: Option<String>) -> Self {
+        Self {
+            path,
+            reload_command,
+            applied: Mutex::new(None),
+        }
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Replace the file through a temporary one and run the reload command, when the
+    /// content changed. A failed reload is repeated with the next write.
+    pub async fn apply(&self, content: String) -> Result<(), String> {
+        if self.applied.lock().unwrap().as_deref() == Some(content.as_str()) {
+            debug!("{}: content unchanged", self.path);
+            return Ok(());
+        }
+
+        let temp_path = format!("{}.tmp", self.path);
+        tokio::fs::write(&temp_path, &content)
+            .await
+            .map_err(|e| format!("Failed to write {}: {}", temp_path, e))?;
+        tokio::fs::rename(&temp_path, &self.path)
+            .await
+            .map_err(|e| format!("Failed to replace {}: {}", self.path, e))?;
+
+        if let Some(command) = &self.reload_command {
+            reload(command).await?;
+        }
+        *self.applied.lock().unwrap() = Some(content);
+        Ok(())
+    }
+}
+
+/// Run the reload command through sh
+async fn reload(command: &str) -> Result<(), String> {
+    let output = tokio::process::Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .kill_on_drop(true)
+        .output();
+    let output = tokio::time::timeout(RELOAD_TIMEOUT, output)
+        .await
+        .map_err(|_| format!("{} timed out", command))?
+        .map_err(|e| format!("Failed to run {}: {}", command, e))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!(
+            "{} failed ({}): {}",
+            command,
+            output.status,
+            stderr.trim()
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,276 @@
+//! HAProxy output: a frontend routing the HTTP routers to a backend per service,
+//! served at /config/haproxy and written to a file HAProxy loads
+
+use crate::config::ProviderConfig;
+use crate::output::ConfigWriter;
+use crate::output::file::ConfigFile;
+use crate::output::rules::{self, PathMatch, strip_provider};
+use crate::traefik::{DynamicConfig, HttpConfig, Middleware, Router};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use tracing::debug;
+
+/// Transaction variable holding the route a request matched
+const ROUTE_VAR: &str = "txn.tailscale_route";
+
+/// Renders the HTTP routers as an HAProxy frontend and backends
+pub struct HaproxyRenderer {
+    frontend: String,
+    bind: Vec<String>,
+}
+
+impl HaproxyRenderer {
+    pub fn from_config(config: &ProviderConfig) -> Self {
+        Self {
+            frontend: config.haproxy_frontend_name.clone(),
+            bind: config.haproxy_bind.clone(),
+        }
+    }
+
+    /// A frontend that marks each request with the first route matching it, in Traefik's
+    /// priority order, applies that route's middlewares and picks its backend. Routers
+    /// whose rule has no HAProxy equivalent are left out.
+    pub fn render(&self, config: &DynamicConfig) -> String {
+        let empty = HttpConfig::default();
+        let http = config.http.as_ref().unwrap_or(&empty);
+
+        let mut matches = String::new();
+        let mut actions = String::new();
+        let mut backends = String::new();
+        // Services with the routers using them, to decide on compression
+        let mut services: BTreeMap<&str, Vec<&Router>> = BTreeMap::new();
+        let mut index = 0;
+        for (name, router) in rules::by_priority(http) {
+            let Some(matched) = rules::parse_rule(&router.rule) else {
+                debug!(
+                    "HAProxy: skipping router {}, unsupported rule {}",
+                    name, router.rule
+                );
+                continue;
+            };
+            let service_name = strip_provider(&router.service);
+            if !http.services.contains_key(service_name) {
+                continue;
+            }
+
+            let route = format!("r{}", index);
+            index += 1;
+            let _ = writeln!(matches, "    # {}", name);
+            let mut conditions = vec![format!("!{{ var({}) -m found }}", ROUTE_VAR)];
+            if !matched.hosts.is_empty() {
+                let hosts: Vec<String> = matched.hosts.iter().map(|host| quote(host)).collect();
+                conditions.push(format!(
+                    "{{ req.hdr(host),field(1,:) -i {} }}",
+                    hosts.join(" ")
+                ));
+            }
+            let exact: Vec<String> = matched
+                .paths
+                .iter()
+                .filter_map(|path| match path {
+                    PathMatch::Exact(path) => Some(quote(path)),
+                    PathMatch::Prefix(_) => None,
+                })
+                .collect();
+            let prefixes: Vec<String> = matched
+                .paths
+                .iter()
+                .filter_map(|path| match path {
+                    PathMatch::Prefix(prefix) => Some(quote(prefix)),
+                    PathMatch::Exact(_) => None,
+                })
+                .collect();
+            // Alternatives of exact paths and prefixes can't be combined in one condition
+            // next to the host, each gets its own rule
+            let mut paths = Vec::new();
+            if !exact.is_empty() {
+                paths.push(format!(" {{ path {} }}", exact.join(" ")));
+            }
+            if !prefixes.is_empty() {
+                paths.push(format!(" {{ path_beg {} }}", prefixes.join(" ")));
+            }
+            if paths.is_empty() {
+                paths.push(String::new());
+            }
+            for path in paths {
+                let _ = writeln!(
+                    matches,
+                    "    http-request set-var({}) str({}) if {}{}",
+                    ROUTE_VAR,
+                    route,
+                    conditions.join(" "),
+                    path
+                );
+            }
+
+            let selected = format!("{{ var({}) -m str {} }}", ROUTE_VAR, route);
+            for middleware in router
+                .middlewares
+                .iter()
+                .flatten()
+                .filter_map(|name| http.middlewares.get(strip_provider(name)))
+            {
+                render_middleware(middleware, &selected, &mut actions);
+            }
+            let _ = writeln!(
+                backends,
+                "    use_backend {} if {}",
+                rules::identifier(service_name),
+                selected
+            );
+            services.entry(service_name).or_default().push(router);
+        }
+
+        let mut out = String::from("# Generated by traefik-tailscale-provider, do not edit\n");
+        let _ = writeln!(out, "\nfrontend {}", self.frontend);
+        out.push_str("    mode http\n");
+        for bind in &self.bind {
+            let _ = writeln!(out, "    bind {}", bind);
+        }
+        out.push_str("    option forwardfor\n");
+        out.push_str(&matches);
+        out.push_str(&actions);
+        out.push_str(&backends);
+
+        for (service_name, routers) in services {
+            render_backend(http, service_name, &routers, &mut out);
+        }
+        out
+    }
+}
+
+/// http-request and http-response rules of a middleware, applied to the selected route
+fn render_middleware(middleware: &Middleware, selected: &str, out: &mut String) {
+    if let Some(strip_prefix) = &middleware.strip_prefix {
+        for prefix in &strip_prefix.prefixes {
+            let pattern = format!(
+                "^{}/?(.*)$",
+                rules::regex_escape(prefix.trim_end_matches('/'))
+            );
+            let _ = writeln!(
+                out,
+                "    http-request replace-path {} /\\1 if {}",
+                quote(&pattern),
+                selected
+            );
+        }
+    }
+    if let Some(headers) = &middleware.headers {
+        let phases = [
+            ("http-request", &headers.custom_request_headers),
+            ("http-response", &headers.custom_response_headers),
+        ];
+        for (phase, headers) in phases {
+            for (name, value) in headers.iter().flatten() {
+                // Traefik removes headers given an empty value
+                if value.is_empty() {
+                    let _ = writeln!(out, "    {} del-header {} if {}", phase, name, selected);
+                } else {
+                    let _ = writeln!(
+                        out,
+                        "    {} set-header {} {} if {}",
+                        phase,
+                        name,
+                        // Header values are log-format strings
+                        quote(&value.replace('%', "%%")),
+                        selected
+                    );
+                }
+            }
+        }
+    }
+}
+
+fn render_backend(http: &HttpConfig, service_name: &str, routers: &[&Router], out: &mut String) {
+    let load_balancer = &http.services[service_name].load_balancer;
+    let _ = writeln!(out, "\nbackend {}", rules::identifier(service_name));
+    out.push_str("    mode http\n    balance roundrobin\n");
+
+    // Compression is set per backend in HAProxy
+    let compress = routers.iter().any(|router| {
+        router
+            .middlewares
+            .iter()
+            .flatten()
+            .filter_map(|name| http.middlewares.get(strip_provider(name)))
+            .any(|middleware| middleware.compress.is_some())
+    });
+    if compress {
+        out.push_str("    filter compression\n    compression algo gzip\n");
+    }
+
+    let mut check = String::new();
+    if let Some(health_check) = &load_balancer.health_check {
+        let _ = writeln!(out, "    option httpchk GET {}", health_check.path);
+        check.push_str(" check");
+        if let Some(interval) = &health_check.interval {
+            let _ = write!(check, " inter {}", interval);
+        }
+    }
+    let tls = match load_balancer
+        .servers_transport
+        .as_deref()
+        .and_then(|name| http.servers_transports.get(strip_provider(name)))
+        .and_then(|transport| transport.insecure_skip_verify)
+    {
+        Some(true) => " ssl verify none",
+        _ => " ssl verify required ca-file @system-ca",
+    };
+
+    for (index, server) in load_balancer.servers.iter().enumerate() {
+        let _ = writeln!(
+            out,
+            "    server s{} {}{}{}{}",
+            index,
+            rules::server_address(&server.url),
+            server
+                .weight
+                .map(|weight| format!(" weight {}", weight.clamp(0, 256)))
+                .unwrap_or_default(),
+            check,
+            if server.url.starts_with("https://") {
+                tls
+            } else {
+                ""
+            }
+        );
+    }
+}
+
+/// A double-quoted HAProxy argument
+fn quote(value: &str) -> String {
+    format!(
+        "\"{}\"",
+        value
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('$', "\\$")
+    )
+}
+
+/// Writes the rendered configuration to a file and runs a command reloading HAProxy
+pub struct HaproxyWriter {
+    file: ConfigFile,
+    renderer: HaproxyRenderer,
+}
+
+impl HaproxyWriter {
+    /// Writer for HAPROXY_CONFIG_FILE, None when it is not set
+    pub fn from_config(config: &ProviderConfig) -> Option<Self> {
+        let path = config.haproxy_config_file.clone()?;
+        Some(Self {
+            file: ConfigFile::new(path, config.haproxy_reload_command.clone()),
+            renderer: HaproxyRenderer::from_config(config),
+        })
+    }
+}
+
+impl ConfigWriter for HaproxyWriter {
+    fn describe(&self) -> String {
+        format!("HAProxy {}", self.file.path())
+    }
+
+    async fn write(&self, config: &DynamicConfig) -> Result<(), String> {
+        self.file.apply(self.renderer.render(config)).await
+    }
+}
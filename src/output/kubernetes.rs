@@ -0,0 +1,148 @@
+//! ConfigMap output, written with the in-cluster service account
+
+use crate::config::ProviderConfig;
+use crate::output::ConfigWriter;
+use crate::traefik::DynamicConfig;
+use http_body_util::{BodyExt, Full};
+use hyper::body::Bytes;
+use hyper::{Method, Request, header};
+use hyper_rustls::{HttpsConnector, HttpsConnectorBuilder};
+use hyper_util::client::legacy::{Client, connect::HttpConnector};
+use hyper_util::rt::TokioExecutor;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio_rustls::rustls::crypto::ring::default_provider;
+use tokio_rustls::rustls::pki_types::CertificateDer;
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use tokio_rustls::rustls::{ClientConfig, RootCertStore};
+
+/// Credentials, CA and namespace mounted into every pod with a service account
+const SERVICE_ACCOUNT_DIR: &str = "/var/run/secrets/kubernetes.io/serviceaccount";
+
+/// Owner of the applied fields, shown in the ConfigMap's managedFields
+const FIELD_MANAGER: &str = "traefik-tailscale-provider";
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+type HttpsClient = Client<HttpsConnector<HttpConnector>, Full<Bytes>>;
+
+pub struct ConfigMapWriter {
+    url: String,
+    namespace: String,
+    name: String,
+    key: String,
+    client: HttpsClient,
+}
+
+impl ConfigMapWriter {
+    /// Writer for KUBERNETES_CONFIGMAP, None when it is not set
+    pub fn from_config(
+        config: &ProviderConfig,
+    ) -> Result<Option<Self>, Box<dyn std::error::Error + Send + Sync>> {
+        let Some(configmap) = &config.kubernetes_configmap else {
+            return Ok(None);
+        };
+
+        let host = std::env::var("KUBERNETES_SERVICE_HOST")
+            .ok()
+            .filter(|host| !host.is_empty())
+            .ok_or("KUBERNETES_CONFIGMAP requires running in a Kubernetes pod")?;
+        let port = std::env::var("KUBERNETES_SERVICE_PORT").unwrap_or_else(|_| "443".to_string());
+        // IPv6 service hosts come without brackets
+        let host = if host.contains(':') {
+            format!("[{}]", host)
+        } else {
+            host
+        };
+
+        let (namespace, name) = match configmap.split_once('/') {
+            Some((namespace, name)) => (namespace.to_string(), name.to_string()),
+            None => {
+                let path = format!("{SERVICE_ACCOUNT_DIR}/namespace");
+                let namespace = std::fs::read_to_string(path)
+                    .map_err(|e| format!("Failed to read the pod namespace: {}", e))?;
+                (namespace.trim().to_string(), configmap.clone())
+            }
+        };
+
+        let ca = std::fs::read(format!("{SERVICE_ACCOUNT_DIR}/ca.crt"))
+            .map_err(|e| format!("Failed to read the cluster CA: {}", e))?;
+        let mut roots = RootCertStore::empty();
+        for cert in CertificateDer::pem_slice_iter(&ca) {
+            roots.add(cert.map_err(|e| format!("Invalid cluster CA: {}", e))?)?;
+        }
+        let tls = ClientConfig::builder_with_provider(Arc::new(default_provider()))
+            .with_safe_default_protocol_versions()?
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        let connector = HttpsConnectorBuilder::new()
+            .with_tls_config(tls)
+            .https_only()
+            .enable_http1()
+            .build();
+
+        Ok(Some(Self {
+            url: format!("https://{}:{}", host, port),
+            namespace,
+            name,
+            key: config.kubernetes_configmap_key.clone(),
+            client: Client::builder(TokioExecutor::new()).build(connector),
+        }))
+    }
+}
+
+impl ConfigWriter for ConfigMapWriter {
+    fn describe(&self) -> String {
+        format!("ConfigMap {}/{}", self.namespace, self.name)
+    }
+
+    /// Apply the ConfigMap server-side, creating it when missing and leaving data
+    /// keys and metadata owned by others alone
+    async fn write(&self, config: &DynamicConfig) -> Result<(), String> {
+        let yaml = serde_yaml::to_string(config)
+            .map_err(|e| format!("Failed to serialize the configuration: {}", e))?;
+        let configmap = serde_json::json!({
+            "apiVersion": "v1",
+            "kind": "ConfigMap",
+            "metadata": { "name": self.name, "namespace": self.namespace },
+            "data": { &self.key: yaml },
+        });
+
+        // Tokens are rotated by the kubelet, so read the current one for each write
+        let token = std::fs::read_to_string(format!("{SERVICE_ACCOUNT_DIR}/token"))
+            .map_err(|e| format!("Failed to read the service account token: {}", e))?;
+        let request = Request::builder()
+            .method(Method::PATCH)
+            .uri(format!(
+                "{}/api/v1/namespaces/{}/configmaps/{}?fieldManager={}&force=true",
+                self.url, self.namespace, self.name, FIELD_MANAGER
+            ))
+            .header(header::AUTHORIZATION, format!("Bearer {}", token.trim()))
+            // JSON is valid YAML, which is what apply patches are sent as
+            .header(header::CONTENT_TYPE, "application/apply-patch+yaml")
+            .header(header::ACCEPT, "application/json")
+            .body(Full::new(Bytes::from(configmap.to_string())))
+            .map_err(|e| format!("Failed to build request: {}", e))?;
+
+        let response = tokio::time::timeout(REQUEST_TIMEOUT, self.client.request(request))
+            .await
+            .map_err(|_| "Request timed out".to_string())?
+            .map_err(|e| format!("Request failed: {}", e))?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response
+                .into_body()
+                .collect()
+                .await
+                .map(|body| body.to_bytes())
+                .unwrap_or_default();
+            // The API server explains refusals (e.g. missing RBAC) in a Status object
+            let message = serde_json::from_slice::<serde_json::Value>(&body)
+                .ok()
+                .and_then(|status| status["message"].as_str().map(str::to_string))
+                .unwrap_or_else(|| String::from_utf8_lossy(&body).into_owned());
+            return Err(format!("HTTP {}: {}", status, message));
+        }
+        Ok(())
+    }
+}
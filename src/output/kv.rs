@@ -0,0 +1,190 @@
+//! Traefik's key-value provider layout, shared by the KV store outputs
+
+use crate::traefik::DynamicConfig;
+use http_body_util::{BodyExt, Full};
+use hyper::Request;
+use hyper::body::Bytes;
+use hyper_util::client::legacy::{Client, connect::HttpConnector};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub type HttpClient = Client<HttpConnector, Full<Bytes>>;
+
+/// Flatten a configuration into the keys Traefik's KV providers read below `root`, e.g.
+/// "traefik/http/routers/web/rule" or "traefik/http/services/web/loadBalancer/servers/0/url".
+/// Lists are indexed from 0; empty maps and lists produce no keys. Traefik splits keys
+/// at every "/" and has no escaping for it, so a name containing one (e.g. of a
+/// middleware from MIDDLEWARES_FILE) can't be stored and fails the whole write.
+pub fn flatten(config: &DynamicConfig, root: &str) -> Result<BTreeMap<String, String>, String> {
+    let value = serde_json::to_value(config)
+        .map_err(|e| format!("Failed to serialize the configuration: {}", e))?;
+    let mut keys = BTreeMap::new();
+    flatten_value(root.trim_end_matches('/').to_string(), &value, &mut keys)?;
+    Ok(keys)
+}
+
+fn flatten_value(
+    key: String,
+    value: &Value,
+    keys: &mut BTreeMap<String, String>,
+) -> Result<(), String> {
+    match value {
+        Value::Null => {}
+        Value::Object(fields) => {
+            for (name, value) in fields {
+                if name.contains('/') {
+                    return Err(format!(
+                        "{}/{} can't be stored: Traefik reads the / in {:?} as a key separator",
+                        key, name, name
+                    ));
+                }
+                flatten_value(format!("{}/{}", key, name), value, keys)?;
+            }
+        }
+        Value::Array(items) => {
+            for (index, value) in items.iter().enumerate() {
+                flatten_value(format!("{}/{}", key, index), value, keys)?;
+            }
+        }
+        Value::String(s) => {
+            keys.insert(key, s.clone());
+        }
+        Value::Bool(_) | Value::Number(_) => {
+            keys.insert(key, value.to_string());
+        }
+    }
+    Ok(())
+}
+
+/// Keys to set because they are new or their value changed, and stored keys to delete
+/// because the configuration no longer has them, sorted
+pub fn changes<'a>(
+    keys: &'a BTreeMap<String, String>,
+    stored: &'a HashMap<String, String>,
+) -> (Vec<(&'a str, &'a str)>, Vec<&'a str>) {
+    let sets = keys
+        .iter()
+        .filter(|(key, value)| stored.get(*key) != Some(*value))
+        .map(|(key, value)| (key.as_str(), value.as_str()))
+        .collect();
+    let mut deletes: Vec<&str> = stored
+        .keys()
+        .filter(|key| !keys.contains_key(*key))
+        .map(String::as_str)
+        .collect();
+    deletes.sort();
+    (sets, deletes)
+}
+
+/// Send a request to a KV store, returning the body of a successful response
+pub async fn send(client: &HttpClient, request: Request<Full<Bytes>>) -> Result<Bytes, String> {
+    let response = tokio::time::timeout(REQUEST_TIMEOUT, client.request(request))
+        .await
+        .map_err(|_| "Request timed out".to_string())?
+        .map_err(|e| format!("Request failed: {}", e))?;
+    let status = response.status();
+    let body = response
+        .into_body()
+        .collect()
+        .await
+        .map_err(|e| format!("Failed to read response: {}", e))?
+        .to_bytes();
+    if !status.is_success() {
+        return Err(format!(
+            "HTTP {}: {}",
+            status,
+            String::from_utf8_lossy(&body).trim()
+        ));
+    }
+    Ok(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn config(value: serde_json::Value) -> DynamicConfig {
+        serde_json::from_value(value).expect("configuration")
+    }
+
+    #[test]
+    fn flattens_nested_maps_and_lists() {
+        let config = config(json!({
+            "http": {
+                "routers": {
+                    "web": {"rule": "PathPrefix(`/api`)", "service": "web", "priority": 10}
+                },
+                "services": {
+                    "web": {"loadBalancer": {
+                        "servers": [{"url": "http://100.64.0.1:80"}, {"url": "http://100.64.0.2:80"}],
+                        "serversTransport": "insecure"
+                    }}
+                },
+                "serversTransports": {"insecure": {"insecureSkipVerify": true}},
+                "middlewares": {}
+            }
+        }));
+
+        let keys = flatten(&config, "traefik/").unwrap();
+        let expected: BTreeMap<String, String> = [
+            ("traefik/http/routers/web/priority", "10"),
+            ("traefik/http/routers/web/rule", "PathPrefix(`/api`)"),
+            ("traefik/http/routers/web/service", "web"),
+            (
+                "traefik/http/services/web/loadBalancer/serversTransport",
+                "insecure",
+            ),
+            (
+                "traefik/http/services/web/loadBalancer/servers/0/url",
+                "http://100.64.0.1:80",
+            ),
+            (
+                "traefik/http/services/web/loadBalancer/servers/1/url",
+                "http://100.64.0.2:80",
+            ),
+            (
+                "traefik/http/serversTransports/insecure/insecureSkipVerify",
+                "true",
+            ),
+        ]
+        .into_iter()
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect();
+        assert_eq!(keys, expected);
+    }
+
+    #[test]
+    fn refuses_names_containing_the_separator() {
+        let config = config(json!({
+            "http": {"middlewares": {"team/auth": {"basicAuth": {"users": ["a:b"]}}}}
+        }));
+
+        let error = flatten(&config, "traefik").unwrap_err();
+        assert!(
+            error.contains("traefik/http/middlewares/team/auth"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn changes_set_new_and_changed_keys_and_delete_stale_ones() {
+        let keys: BTreeMap<String, String> = [("t/a", "1"), ("t/b", "2"), ("t/c", "3")]
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+        let stored: HashMap<String, String> =
+            [("t/a", "1"), ("t/b", "old"), ("t/y", "9"), ("t/x", "8")]
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect();
+
+        let (sets, deletes) = changes(&keys, &stored);
+        assert_eq!(sets, [("t/b", "2"), ("t/c", "3")]);
+        assert_eq!(deletes, ["t/x", "t/y"]);
+    }
+}
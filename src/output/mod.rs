@@ -0,0 +1,105 @@
+//! Writers mirroring the published configuration into other systems Traefik can read
+//! it from, for setups that don't use Traefik's HTTP provider, into other proxies, or
+//! into DNS
+
+pub mod caddy;
+pub mod consul;
+pub mod dns;
+pub mod etcd;
+pub mod file;
+pub mod haproxy;
+#[cfg(feature = "kubernetes")]
+pub mod kubernetes;
+pub mod kv;
+pub mod nginx;
+#[cfg(feature = "redis")]
+pub mod redis;
+pub mod rules;
+
+use crate::config::ProviderConfig;
+use crate::store::ConfigStore;
+use crate::traefik::DynamicConfig;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// How soon a failed write is retried
+const RETRY_INTERVAL: Duration = Duration::from_secs(30);
+
+/// A destination the published configuration is written to
+pub trait ConfigWriter: Send + Sync + 'static {
+    /// Human-readable destination, for the logs
+    fn describe(&self) -> String;
+
+    fn write(&self, config: &DynamicConfig) -> impl Future<Output = Result<(), String>> + Send;
+}
+
+/// Start the writers of the configured outputs
+pub fn spawn(
+    config: &ProviderConfig,
+    store: Arc<ConfigStore>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    if let Some(writer) = consul::ConsulWriter::from_config(config) {
+        tokio::spawn(run(writer, store.clone()));
+    }
+    if let Some(writer) = etcd::EtcdWriter::from_config(config) {
+        tokio::spawn(run(writer, store.clone()));
+    }
+    if let Some(writer) = caddy::CaddyWriter::from_config(config) {
+        tokio::spawn(run(writer, store.clone()));
+    }
+    if let Some(writer) = nginx::NginxWriter::from_config(config) {
+        tokio::spawn(run(writer, store.clone()));
+    }
+    if let Some(writer) = haproxy::HaproxyWriter::from_config(config) {
+        tokio::spawn(run(writer, store.clone()));
+    }
+    if let Some(writer) = dns::DnsWriter::from_config(config) {
+        tokio::spawn(run(writer, store.clone()));
+    }
+    #[cfg(feature = "redis")]
+    if let Some(writer) = redis::RedisWriter::from_config(config)? {
+        tokio::spawn(run(writer, store.clone()));
+    }
+    #[cfg(not(feature = "redis"))]
+    if config.redis_url.is_some() {
+        warn!("REDIS_URL is set but this build has no \"redis\" feature, ignoring it");
+    }
+    #[cfg(feature = "kubernetes")]
+    if let Some(writer) = kubernetes::ConfigMapWriter::from_config(config)? {
+        tokio::spawn(run(writer, store.clone()));
+    }
+    #[cfg(not(feature = "kubernetes"))]
+    if config.kubernetes_configmap.is_some() {
+        warn!(
+            "KUBERNETES_CONFIGMAP is set but this build has no \"kubernetes\" feature, ignoring it"
+        );
+    }
+
+    Ok(())
+}
+
+/// Write every configuration the store publishes, retrying failed writes
+async fn run<W: ConfigWriter>(writer: W, store: Arc<ConfigStore>) {
+    info!("Writing the configuration to {}", writer.describe());
+    let mut generations = store.subscribe();
+    let mut written: Option<String> = None;
+    loop {
+        if let Some(snapshot) = store.current().await
+            && written.as_deref() != Some(snapshot.hash.as_str())
+        {
+            match writer.write(&snapshot.config).await {
+                Ok(()) => {
+                    info!("Wrote {} to {}", snapshot.version, writer.describe());
+                    written = Some(snapshot.hash.clone());
+                }
+                Err(e) => warn!("Failed to write to {}: {}", writer.describe(), e),
+            }
+        }
+
+        // Wait for the next generation, or until a failed write is due for a retry
+        if let Ok(Err(_)) = tokio::time::timeout(RETRY_INTERVAL, generations.changed()).await {
+            return;
+        }
+    }
+}
@@ -0,0 +1,241 @@
+//! nginx output: upstream and server blocks for the HTTP routers, served at
+//! /config/nginx and written to a file nginx includes
+
+use crate::config::ProviderConfig;
+use crate::output::ConfigWriter;
+use crate::output::file::ConfigFile;
+use crate::output::rules::{self, PathMatch, strip_provider};
+use crate::traefik::{DynamicConfig, HttpConfig, Middleware, Router};
+use std::collections::BTreeSet;
+use std::fmt::Write;
+use tracing::debug;
+
+/// Renders the HTTP routers as nginx configuration, one server block per set of hosts
+pub struct NginxRenderer {
+    listen: Vec<String>,
+}
+
+/// A server block being assembled
+struct ServerBlock {
+    /// Sorted, empty for routers matching any host
+    hosts: Vec<String>,
+    locations: Vec<(String, String)>,
+}
+
+impl NginxRenderer {
+    pub fn from_config(config: &ProviderConfig) -> Self {
+        Self {
+            listen: config.nginx_listen.clone(),
+        }
+    }
+
+    /// An upstream block per service and a server block per set of hosts, with a location
+    /// per path. Routers whose rule has no nginx equivalent are left out, and so is a
+    /// location already taken by a router of higher priority.
+    pub fn render(&self, config: &DynamicConfig) -> String {
+        let empty = HttpConfig::default();
+        let http = config.http.as_ref().unwrap_or(&empty);
+
+        let mut upstreams = BTreeSet::new();
+        let mut servers: Vec<ServerBlock> = Vec::new();
+        for (name, router) in rules::by_priority(http) {
+            let Some(matched) = rules::parse_rule(&router.rule) else {
+                debug!(
+                    "nginx: skipping router {}, unsupported rule {}",
+                    name, router.rule
+                );
+                continue;
+            };
+            let service_name = strip_provider(&router.service);
+            // nginx refuses upstreams without servers
+            if http
+                .services
+                .get(service_name)
+                .is_none_or(|service| service.load_balancer.servers.is_empty())
+            {
+                continue;
+            }
+
+            let mut hosts = matched.hosts;
+            hosts.sort();
+            hosts.dedup();
+            let index = match servers.iter().position(|server| server.hosts == hosts) {
+                Some(index) => index,
+                None => {
+                    servers.push(ServerBlock {
+                        hosts,
+                        locations: Vec::new(),
+                    });
+                    servers.len() - 1
+                }
+            };
+            let server = &mut servers[index];
+
+            let paths = if matched.paths.is_empty() {
+                vec![PathMatch::Prefix("/".to_string())]
+            } else {
+                matched.paths
+            };
+            for path in paths {
+                let location = match &path {
+                    PathMatch::Exact(path) => format!("= {}", quote(path)),
+                    PathMatch::Prefix(prefix) => quote(prefix),
+                };
+                if server.locations.iter().any(|(taken, _)| *taken == location) {
+                    debug!(
+                        "nginx: skipping router {}, location {} is taken",
+                        name, location
+                    );
+                    continue;
+                }
+                server
+                    .locations
+                    .push((location, location_body(http, router, service_name)));
+                upstreams.insert(service_name);
+            }
+        }
+
+        let mut out = String::from("# Generated by traefik-tailscale-provider, do not edit\n");
+        for service_name in upstreams {
+            let servers = &http.services[service_name].load_balancer.servers;
+            let weights: Vec<i32> = servers.iter().map(|s| s.weight.unwrap_or(1)).collect();
+            let weighted = weights.windows(2).any(|pair| pair[0] != pair[1]);
+
+            let _ = writeln!(out, "\nupstream {} {{", rules::identifier(service_name));
+            for (server, weight) in servers.iter().zip(weights) {
+                let address = rules::server_address(&server.url);
+                if weighted {
+                    let _ = writeln!(out, "    server {} weight={};", address, weight.max(1));
+                } else {
+                    let _ = writeln!(out, "    server {};", address);
+                }
+            }
+            out.push_str("}\n");
+        }
+
+        for server in &servers {
+            out.push_str("\nserver {\n");
+            for listen in &self.listen {
+                let _ = writeln!(out, "    listen {};", listen);
+            }
+            let names: Vec<String> = server.hosts.iter().map(|host| quote(host)).collect();
+            let names = if names.is_empty() {
+                "_".to_string()
+            } else {
+                names.join(" ")
+            };
+            let _ = writeln!(out, "    server_name {};", names);
+            for (location, body) in &server.locations {
+                let _ = write!(out, "\n    location {} {{\n{}    }}\n", location, body);
+            }
+            out.push_str("}\n");
+        }
+        out
+    }
+}
+
+/// Directives of a location: the router's middlewares nginx has an equivalent for, then
+/// the proxying to the service
+fn location_body(http: &HttpConfig, router: &Router, service_name: &str) -> String {
+    let mut body = String::new();
+    let middlewares = router
+        .middlewares
+        .iter()
+        .flatten()
+        .filter_map(|name| http.middlewares.get(strip_provider(name)));
+    let mut request_headers = Vec::new();
+    for middleware in middlewares {
+        render_middleware(middleware, &mut body, &mut request_headers);
+    }
+
+    let scheme = match http.services[service_name]
+        .load_balancer
+        .servers
+        .iter()
+        .any(|server| server.url.starts_with("https://"))
+    {
+        true => "https",
+        false => "http",
+    };
+    let _ = writeln!(
+        body,
+        "        proxy_pass {}://{};",
+        scheme,
+        rules::identifier(service_name)
+    );
+    body.push_str("        proxy_http_version 1.1;\n");
+    body.push_str("        proxy_set_header Host $host;\n");
+    body.push_str("        proxy_set_header X-Forwarded-For $proxy_add_x_forwarded_for;\n");
+    body.push_str("        proxy_set_header X-Forwarded-Proto $scheme;\n");
+    // An empty value keeps nginx from sending the header, like Traefik
+    for (name, value) in request_headers {
+        let _ = writeln!(body, "        proxy_set_header {} {};", name, quote(&value));
+    }
+    body
+}
+
+fn render_middleware(
+    middleware: &Middleware,
+    body: &mut String,
+    request_headers: &mut Vec<(String, String)>,
+) {
+    if let Some(strip_prefix) = &middleware.strip_prefix {
+        for prefix in &strip_prefix.prefixes {
+            let _ = writeln!(
+                body,
+                "        rewrite {} /$1 break;",
+                quote(&format!(
+                    "^{}/?(.*)$",
+                    rules::regex_escape(prefix.trim_end_matches('/'))
+                ))
+            );
+        }
+    }
+    if let Some(headers) = &middleware.headers {
+        if let Some(request) = &headers.custom_request_headers {
+            request_headers.extend(request.iter().map(|(k, v)| (k.clone(), v.clone())));
+        }
+        for (name, value) in headers.custom_response_headers.iter().flatten() {
+            if value.is_empty() {
+                let _ = writeln!(body, "        proxy_hide_header {};", name);
+            } else {
+                let _ = writeln!(body, "        add_header {} {} always;", name, quote(value));
+            }
+        }
+    }
+    if middleware.compress.is_some() {
+        body.push_str("        gzip on;\n        gzip_proxied any;\n");
+    }
+}
+
+/// A double-quoted nginx string
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Writes the rendered configuration to a file and runs a command reloading nginx
+pub struct NginxWriter {
+    file: ConfigFile,
+    renderer: NginxRenderer,
+}
+
+impl NginxWriter {
+    /// Writer for NGINX_CONFIG_FILE, None when it is not set
+    pub fn from_config(config: &ProviderConfig) -> Option<Self> {
+        let path = config.nginx_config_file.clone()?;
+        Some(Self {
+            file: ConfigFile::new(path, config.nginx_reload_command.clone()),
+            renderer: NginxRenderer::from_config(config),
+        })
+    }
+}
+
+impl ConfigWriter for NginxWriter {
+    fn describe(&self) -> String {
+        format!("nginx {}", self.file.path())
+    }
+
+    async fn write(&self, config: &DynamicConfig) -> Result<(), String> {
+        self.file.apply(self.renderer.render(config)).await
+    }
+}
@@ -0,0 +1,322 @@
+//! Redis output, for Traefik's Redis provider
+
+use crate::config::ProviderConfig;
+use crate::output::{ConfigWriter, kv};
+use crate::redis::{Connection, RedisClient, Value};
+use crate::traefik::DynamicConfig;
+use std::collections::HashMap;
+use std::time::Duration;
+use tracing::debug;
+
+/// How long a whole write, from connecting to EXEC, may take
+const WRITE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Keys fetched per SCAN and MGET round trip
+const BATCH_SIZE: usize = 500;
+
+pub struct RedisWriter {
+    client: RedisClient,
+    root: String,
+}
+
+impl RedisWriter {
+    /// Writer for REDIS_URL, None when it is not set
+    pub fn from_config(config: &ProviderConfig) -> Result<Option<Self>, String> {
+        let Some(url) = &config.redis_url else {
+            return Ok(None);
+        };
+        let client = RedisClient::new(
+            url,
+            config.redis_username.clone(),
+            config.redis_password.clone(),
+            config.redis_ca_file.as_deref(),
+        )?;
+        Ok(Some(Self {
+            client,
+            root: config.kv_root_key.trim_matches('/').to_string(),
+        }))
+    }
+
+    /// Keys currently stored below the root key, with their values
+    async fn stored_keys(
+        &self,
+        connection: &mut Connection,
+    ) -> Result<HashMap<String, String>, String> {
+        let pattern = format!("{}/*", self.root);
+        let count = BATCH_SIZE.to_string();
+        let mut keys = Vec::new();
+        let mut cursor = "0".to_string();
+        loop {
+            let reply = connection
+                .command(&["SCAN", &cursor, "MATCH", &pattern, "COUNT", &count])
+                .await?;
+            let Value::Array(mut reply) = reply else {
+                return Err("Invalid SCAN reply".to_string());
+            };
+            let (Some(Value::Array(batch)), Some(next)) = (reply.pop(), reply.pop()) else {
+                return Err("Invalid SCAN reply".to_string());
+            };
+            keys.extend(batch.into_iter().filter_map(Value::into_string));
+            cursor = next.into_string().ok_or("Invalid SCAN cursor")?;
+            if cursor == "0" {
+                break;
+            }
+        }
+        // SCAN may return a key more than once
+        keys.sort();
+        keys.dedup();
+
+        let mut stored = HashMap::new();
+        for batch in keys.chunks(BATCH_SIZE) {
+            let mut mget = vec!["MGET"];
+            mget.extend(batch.iter().map(String::as_str));
+            let Value::Array(values) = connection.command(&mget).await? else {
+                return Err("Invalid MGET reply".to_string());
+            };
+            for (key, value) in batch.iter().zip(values) {
+                // Keys deleted since the scan come back as nil
+                if let Some(value) = value.into_string() {
+                    stored.insert(key.clone(), value);
+                }
+            }
+        }
+        Ok(stored)
+    }
+
+    async fn sync(&self, config: &DynamicConfig) -> Result<(), String> {
+        let keys = kv::flatten(config, &self.root)?;
+        let mut connection = self.client.connect().await?;
+        let stored = self.stored_keys(&mut connection).await?;
+
+        let (sets, stale) = kv::changes(&keys, &stored);
+        let mut commands: Vec<Vec<&str>> = vec![vec!["MULTI"]];
+        commands.extend(sets.into_iter().map(|(key, value)| vec!["SET", key, value]));
+        for batch in stale.chunks(BATCH_SIZE) {
+            let mut del = vec!["DEL"];
+            del.extend(batch);
+            commands.push(del);
+        }
+        debug!(
+            "Redis: {} keys, {} commands",
+            keys.len(),
+            commands.len() - 1
+        );
+        if commands.len() == 1 {
+            return Ok(());
+        }
+        commands.push(vec!["EXEC"]);
+
+        // Queuing errors (e.g. a read-only replica) are reported in place of QUEUED and
+        // abort the transaction
+        let replies = connection.pipeline(&commands).await?;
+        let results = match replies.last() {
+            Some(Value::Array(results)) => results.as_slice(),
+            _ => &[],
+        };
+        let error = replies.iter().chain(results).find_map(|reply| match reply {
+            Value::Error(e) => Some(e.clone()),
+            _ => None,
+        });
+        match (error, replies.last()) {
+            (Some(e), _) => Err(e),
+            (None, Some(Value::Array(_))) => Ok(()),
+            (None, _) => Err("Transaction was aborted".to_string()),
+        }
+    }
+}
+
+impl ConfigWriter for RedisWriter {
+    fn describe(&self) -> String {
+        format!("Redis {}/{}", self.client.describe(), self.root)
+    }
+
+    /// Set the keys whose values changed and delete the keys of whatever is gone, in
+    /// one MULTI/EXEC transaction
+    async fn write(&self, config: &DynamicConfig) -> Result<(), String> {
+        tokio::time::timeout(WRITE_TIMEOUT, self.sync(config))
+            .await
+            .unwrap_or_else(|_| Err("Write timed out".to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use std::sync::{Arc, Mutex};
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufStream};
+    use tokio::net::{TcpListener, TcpStream};
+
+    /// A Redis server keeping its keys in memory, with SCAN returning two pages and
+    /// writes only inside MULTI/EXEC
+    #[derive(Default)]
+    struct MockRedis {
+        keys: BTreeMap<String, String>,
+        /// Reply to SET with a READONLY error, like a replica
+        read_only: bool,
+    }
+
+    async fn read_command(stream: &mut BufStream<TcpStream>) -> Option<Vec<String>> {
+        let mut line = String::new();
+        stream.read_line(&mut line).await.ok()?;
+        let count: usize = line.trim().strip_prefix('*')?.parse().ok()?;
+        let mut args = Vec::with_capacity(count);
+        for _ in 0..count {
+            line.clear();
+            stream.read_line(&mut line).await.ok()?;
+            let length: usize = line.trim().strip_prefix('$')?.parse().ok()?;
+            let mut data = vec![0; length + 2];
+            stream.read_exact(&mut data).await.ok()?;
+            data.truncate(length);
+            args.push(String::from_utf8(data).ok()?);
+        }
+        Some(args)
+    }
+
+    fn bulk(value: Option<&String>) -> String {
+        match value {
+            Some(value) => format!("${}\r\n{}\r\n", value.len(), value),
+            None => "$-1\r\n".to_string(),
+        }
+    }
+
+    fn array(items: Vec<String>) -> String {
+        format!("*{}\r\n{}", items.len(), items.concat())
+    }
+
+    async fn serve(redis: Arc<Mutex<MockRedis>>, stream: TcpStream) {
+        let mut stream = BufStream::new(stream);
+        let mut queued: Option<Vec<Vec<String>>> = None;
+        let mut aborted = false;
+        while let Some(args) = read_command(&mut stream).await {
+            let reply = {
+                let mut redis = redis.lock().unwrap();
+                match (args[0].as_str(), &mut queued) {
+                    ("MULTI", _) => {
+                        queued = Some(Vec::new());
+                        "+OK\r\n".to_string()
+                    }
+                    ("EXEC", _) => {
+                        let commands = queued.take().unwrap_or_default();
+                        if std::mem::take(&mut aborted) {
+                            "-EXECABORT Transaction discarded\r\n".to_string()
+                        } else {
+                            for command in &commands {
+                                if command[0] == "SET" {
+                                    redis.keys.insert(command[1].clone(), command[2].clone());
+                                } else {
+                                    for key in &command[1..] {
+                                        redis.keys.remove(key);
+                                    }
+                                }
+                            }
+                            array(commands.iter().map(|_| "+OK\r\n".to_string()).collect())
+                        }
+                    }
+                    ("SET", Some(_)) if redis.read_only => {
+                        aborted = true;
+                        "-READONLY You can't write against a read only replica.\r\n".to_string()
+                    }
+                    ("SET" | "DEL", Some(queued)) => {
+                        queued.push(args);
+                        "+QUEUED\r\n".to_string()
+                    }
+                    ("SCAN", None) => {
+                        let prefix = args[3].trim_end_matches('*');
+                        let keys: Vec<&String> = redis
+                            .keys
+                            .keys()
+                            .filter(|key| key.starts_with(prefix))
+                            .collect();
+                        let (page, next) = match args[1].as_str() {
+                            "0" => (&keys[..keys.len() / 2], "7"),
+                            // The second page repeats a key of the first, as SCAN may
+                            _ => (&keys[(keys.len() / 2).saturating_sub(1)..], "0"),
+                        };
+                        let page = page.iter().map(|key| bulk(Some(key))).collect();
+                        array(vec![bulk(Some(&next.to_string())), array(page)])
+                    }
+                    ("MGET", None) => array(
+                        args[1..]
+                            .iter()
+                            .map(|key| bulk(redis.keys.get(key)))
+                            .collect(),
+                    ),
+                    (command, _) => panic!("unexpected command {}", command),
+                }
+            };
+            if stream.write_all(reply.as_bytes()).await.is_err() || stream.flush().await.is_err() {
+                return;
+            }
+        }
+    }
+
+    async fn writer(redis: &Arc<Mutex<MockRedis>>) -> RedisWriter {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let redis = redis.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(serve(redis.clone(), stream));
+            }
+        });
+        RedisWriter::from_config(&ProviderConfig {
+            redis_url: Some(format!("redis://{}", addr)),
+            ..Default::default()
+        })
+        .unwrap()
+        .unwrap()
+    }
+
+    fn config(servers: &[(&str, &str)]) -> DynamicConfig {
+        let services: serde_json::Map<String, serde_json::Value> = servers
+            .iter()
+            .map(|(name, url)| {
+                let service =
+                    serde_json::json!({ "loadBalancer": { "servers": [{ "url": url }] } });
+                (name.to_string(), service)
+            })
+            .collect();
+        serde_json::from_value(serde_json::json!({ "http": { "services": services } })).unwrap()
+    }
+
+    #[tokio::test]
+    async fn syncs_changes_and_deletes_stale_keys() {
+        let redis = Arc::new(Mutex::new(MockRedis::default()));
+        redis
+            .lock()
+            .unwrap()
+            .keys
+            .insert("other/key".to_string(), "kept".to_string());
+        let writer = writer(&redis).await;
+
+        let first = config(&[
+            ("web", "http://100.64.0.1:80"),
+            ("db", "http://100.64.0.2:5432"),
+            ("cache", "http://100.64.0.4:6379"),
+        ]);
+        writer.write(&first).await.unwrap();
+        let second = config(&[("web", "http://100.64.0.3:80")]);
+        writer.write(&second).await.unwrap();
+
+        let mut expected = kv::flatten(&second, "traefik").unwrap();
+        expected.insert("other/key".to_string(), "kept".to_string());
+        assert_eq!(redis.lock().unwrap().keys, expected);
+    }
+
+    #[tokio::test]
+    async fn queuing_errors_abort_the_transaction() {
+        let redis = Arc::new(Mutex::new(MockRedis {
+            read_only: true,
+            ..Default::default()
+        }));
+        let writer = writer(&redis).await;
+
+        let error = writer
+            .write(&config(&[("web", "http://100.64.0.1:80")]))
+            .await
+            .unwrap_err();
+        assert!(error.starts_with("READONLY"), "{}", error);
+        assert!(redis.lock().unwrap().keys.is_empty());
+    }
+}
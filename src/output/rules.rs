@@ -0,0 +1,176 @@
+//! Traefik router rules and servers in terms other proxies understand, for the outputs
+//! rendering the configuration for them
+
+use crate::traefik::{HttpConfig, Router};
+use std::cmp::Reverse;
+
+/// Hosts and paths a Traefik rule matches. Empty lists match anything.
+#[derive(Debug, Default)]
+pub struct RuleMatch {
+    pub hosts: Vec<String>,
+    pub paths: Vec<PathMatch>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PathMatch {
+    /// Path(`/x`)
+    Exact(String),
+    /// PathPrefix(`/x`)
+    Prefix(String),
+}
+
+/// The HTTP routers in the order Traefik tries them: by priority, which defaults to the
+/// rule length
+pub fn by_priority(http: &HttpConfig) -> Vec<(&String, &Router)> {
+    let mut routers: Vec<(&String, &Router)> = http.routers.iter().collect();
+    routers.sort_by_key(|(name, router)| {
+        (
+            Reverse(router.priority.unwrap_or(router.rule.len() as i32)),
+            *name,
+        )
+    });
+    routers
+}
+
+/// "host:port" of a server URL, with the scheme's port when it has none
+pub fn server_address(url: &str) -> String {
+    let (default_port, rest) = match url.split_once("://") {
+        Some(("https", rest)) => (443, rest),
+        Some((_, rest)) => (80, rest),
+        None => (80, url),
+    };
+    let authority = rest.split('/').next().unwrap_or(rest);
+    // The port follows the last colon, unless that is inside an IPv6 literal
+    match authority.rsplit_once(':') {
+        Some((_, port)) if !port.contains(']') => authority.to_string(),
+        _ => format!("{}:{}", authority, default_port),
+    }
+}
+
+/// Name without the "@provider" suffix of references across Traefik providers
+pub fn strip_provider(name: &str) -> &str {
+    name.split('@').next().unwrap_or(name)
+}
+
+/// Name of a service usable as a bare word in other proxies' configuration
+pub fn identifier(name: &str) -> String {
+    name.chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' | '.' => c,
+            _ => '_',
+        })
+        .collect()
+}
+
+/// Text matching itself literally in a PCRE regex
+pub fn regex_escape(text: &str) -> String {
+    text.chars().fold(String::new(), |mut escaped, c| {
+        if "\\.+*?()|[]{}^$".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+        escaped
+    })
+}
+
+/// Translate a rule made of Host, HostRegexp catch-alls, Path and PathPrefix, combined
+/// with && of || alternatives of the same kind. None for anything else.
+pub fn parse_rule(rule: &str) -> Option<RuleMatch> {
+    let mut matched = RuleMatch::default();
+    for clause in split_top_level(rule, "&&") {
+        let mut hosts = Vec::new();
+        let mut paths = Vec::new();
+        let mut catch_all = false;
+        for alternative in split_top_level(unwrap_parens(clause), "||") {
+            let (name, args) = parse_call(alternative)?;
+            match name {
+                "Host" => hosts.extend(args),
+                "HostRegexp" if args.iter().all(|arg| arg == ".*" || arg == "{any:.*}") => {
+                    catch_all = true
+                }
+                "Path" => paths.extend(args.into_iter().map(PathMatch::Exact)),
+                "PathPrefix" => paths.extend(args.into_iter().map(PathMatch::Prefix)),
+                _ => return None,
+            }
+        }
+
+        let host_clause = catch_all || !hosts.is_empty();
+        match (host_clause, paths.is_empty()) {
+            // A catch-all among the alternatives matches any host
+            (true, true) if catch_all => {}
+            (true, true) if matched.hosts.is_empty() => matched.hosts = hosts,
+            (false, false) if matched.paths.is_empty() => matched.paths = paths,
+            // Mixed alternatives, or hosts and paths restricted twice
+            _ => return None,
+        }
+    }
+    Some(matched)
+}
+
+/// Split at a separator outside of backquotes and parentheses
+fn split_top_level<'a>(rule: &'a str, separator: &str) -> Vec<&'a str> {
+    let mut parts = Vec::new();
+    let (mut depth, mut quoted, mut start) = (0, false, 0);
+    for (index, c) in rule.char_indices() {
+        match c {
+            '`' => quoted = !quoted,
+            '(' if !quoted => depth += 1,
+            ')' if !quoted => depth -= 1,
+            _ if !quoted
+                && depth == 0
+                && rule[index..].starts_with(separator)
+                && index >= start =>
+            {
+                parts.push(rule[start..index].trim());
+                start = index + separator.len();
+            }
+            _ => {}
+        }
+    }
+    parts.push(rule[start..].trim());
+    parts
+}
+
+/// The clause without parentheses around all of it
+fn unwrap_parens(clause: &str) -> &str {
+    let clause = clause.trim();
+    match clause.strip_prefix('(').and_then(|c| c.strip_suffix(')')) {
+        Some(inner) if balanced(inner) => unwrap_parens(inner),
+        _ => clause,
+    }
+}
+
+fn balanced(text: &str) -> bool {
+    let mut depth = 0;
+    let mut quoted = false;
+    for c in text.chars() {
+        match c {
+            '`' => quoted = !quoted,
+            '(' if !quoted => depth += 1,
+            ')' if !quoted => {
+                depth -= 1;
+                if depth < 0 {
+                    return false;
+                }
+            }
+            _ => {}
+        }
+    }
+    depth == 0
+}
+
+/// Name and backquoted arguments of a matcher like Host(`a`, `b`)
+fn parse_call(text: &str) -> Option<(&str, Vec<String>)> {
+    let (name, rest) = text.trim().split_once('(')?;
+    let args = rest.strip_suffix(')')?;
+    let args = args
+        .split(',')
+        .map(|arg| {
+            arg.trim()
+                .strip_prefix('`')
+                .and_then(|arg| arg.strip_suffix('`'))
+                .map(str::to_string)
+        })
+        .collect::<Option<Vec<_>>>()?;
+    Some((name.trim(), args))
+}
@@ -1,6 +1,10 @@
+pub mod systemd;
+
 use std::error::Error;
 use std::fmt;
 
+// Not every variant is constructed on every target platform
+#[allow(dead_code)]
 #[derive(Debug)]
 pub enum PlatformError {
     UnsupportedOS(String),
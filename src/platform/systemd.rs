@@ -0,0 +1,111 @@
+// systemd integration: socket activation (LISTEN_FDS) and sd_notify readiness and
+// watchdog messages (NOTIFY_SOCKET, WATCHDOG_USEC). Everything is a no-op when the
+// provider is not started by systemd.
+
+use std::io;
+use std::time::Duration;
+use tracing::{debug, warn};
+
+/// First file descriptor passed by systemd (SD_LISTEN_FDS_START)
+#[cfg(unix)]
+const LISTEN_FDS_START: i32 = 3;
+
+/// Take over the first socket passed by systemd, if it was meant for this process
+#[cfg(unix)]
+pub fn activated_listener() -> io::Result<Option<std::net::TcpListener>> {
+    use std::os::fd::FromRawFd;
+
+    let for_us = std::env::var("LISTEN_PID")
+        .ok()
+        .and_then(|pid| pid.parse::<u32>().ok())
+        .is_some_and(|pid| pid == std::process::id());
+    let fds: i32 = std::env::var("LISTEN_FDS")
+        .ok()
+        .and_then(|fds| fds.parse().ok())
+        .unwrap_or(0);
+    if !for_us || fds < 1 {
+        return Ok(None);
+    }
+    if fds > 1 {
+        warn!("systemd passed {} sockets, only the first one is used", fds);
+    }
+
+    // SAFETY: systemd hands this descriptor over to the process named by LISTEN_PID,
+    // and nothing else in the process owns it
+    let listener = unsafe { std::net::TcpListener::from_raw_fd(LISTEN_FDS_START) };
+    listener.local_addr().map_err(|e| {
+        io::Error::new(
+            e.kind(),
+            format!("socket passed by systemd is not a TCP listener: {}", e),
+        )
+    })?;
+    listener.set_nonblocking(true)?;
+    Ok(Some(listener))
+}
+
+#[cfg(not(unix))]
+pub fn activated_listener() -> io::Result<Option<std::net::TcpListener>> {
+    Ok(None)
+}
+
+/// Send a state change such as "READY=1" to the service manager
+pub fn notify(state: &str) {
+    let Ok(socket_path) = std::env::var("NOTIFY_SOCKET") else {
+        return;
+    };
+    match send(&socket_path, state) {
+        Ok(()) => debug!("Notified systemd: {}", state),
+        Err(e) => warn!("Failed to notify systemd ({}): {}", state, e),
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn send(socket_path: &str, state: &str) -> io::Result<()> {
+    use std::os::linux::net::SocketAddrExt;
+    use std::os::unix::net::{SocketAddr, UnixDatagram};
+
+    let address = match socket_path.strip_prefix('@') {
+        Some(name) => SocketAddr::from_abstract_name(name)?,
+        None => SocketAddr::from_pathname(socket_path)?,
+    };
+    UnixDatagram::unbound()?.send_to_addr(state.as_bytes(), &address)?;
+    Ok(())
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+fn send(socket_path: &str, state: &str) -> io::Result<()> {
+    std::os::unix::net::UnixDatagram::unbound()?.send_to(state.as_bytes(), socket_path)?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn send(_socket_path: &str, _state: &str) -> io::Result<()> {
+    Ok(())
+}
+
+/// Ping the systemd watchdog at half its timeout, when WatchdogSec= is set
+pub fn spawn_watchdog() {
+    let for_us = std::env::var("WATCHDOG_PID")
+        .ok()
+        .and_then(|pid| pid.parse::<u32>().ok())
+        .is_none_or(|pid| pid == std::process::id());
+    let Some(timeout) = std::env::var("WATCHDOG_USEC")
+        .ok()
+        .and_then(|usec| usec.parse().ok())
+        .filter(|usec| *usec > 0)
+        .map(Duration::from_micros)
+    else {
+        return;
+    };
+    if !for_us {
+        return;
+    }
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(timeout / 2);
+        loop {
+            interval.tick().await;
+            notify("WATCHDOG=1");
+        }
+    });
+}
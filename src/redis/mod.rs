@@ -0,0 +1,274 @@
+//! Minimal Redis client (RESP2 over TCP or TLS), enough for the KV output and the
+//! shared cache
+
+use crate::config::Secret;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufStream};
+use tokio::net::TcpStream;
+use tokio_rustls::TlsConnector;
+use tokio_rustls::rustls::crypto::ring::default_provider;
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use tokio_rustls::rustls::pki_types::{CertificateDer, ServerName};
+use tokio_rustls::rustls::{ClientConfig, RootCertStore};
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A reply to a command. Error replies are returned as `Err` by [`Connection::command`]
+/// and kept in place by [`Connection::pipeline`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Nil,
+    Int(i64),
+    Data(Vec<u8>),
+    Status(String),
+    Array(Vec<Value>),
+    Error(String),
+}
+
+impl Value {
+    pub fn into_string(self) -> Option<String> {
+        match self {
+            Value::Data(data) => String::from_utf8(data).ok(),
+            Value::Status(status) => Some(status),
+            _ => None,
+        }
+    }
+}
+
+/// Where and how to connect, from a redis:// or rediss:// (TLS) URL
+pub struct RedisClient {
+    host: String,
+    port: u16,
+    db: u32,
+    username: Option<String>,
+    password: Option<Secret>,
+    tls: Option<TlsConnector>,
+}
+
+trait Io: AsyncRead + AsyncWrite + Unpin + Send {}
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Io for T {}
+
+pub struct Connection {
+    stream: BufStream<Box<dyn Io>>,
+}
+
+impl RedisClient {
+    /// `url` is "redis://host[:port][/db]" or "rediss://..." for TLS, verified against
+    /// the certificates in `ca_file` or the web PKI roots. Credentials are kept out of
+    /// the URL so they don't end up in the logs.
+    pub fn new(
+        url: &str,
+        username: Option<String>,
+        password: Option<Secret>,
+        ca_file: Option<&str>,
+    ) -> Result<Self, String> {
+        let (tls, rest) = if let Some(rest) = url.strip_prefix("rediss://") {
+            (true, rest)
+        } else if let Some(rest) = url.strip_prefix("redis://") {
+            (false, rest)
+        } else {
+            return Err(format!(
+                "Invalid Redis URL {}: expected redis:// or rediss://",
+                url
+            ));
+        };
+        if rest.contains('@') {
+            return Err("Redis credentials go into REDIS_USERNAME and REDIS_PASSWORD".to_string());
+        }
+
+        let (address, db) = rest.split_once('/').unwrap_or((rest, ""));
+        let db = match db {
+            "" => 0,
+            db => db
+                .parse()
+                .map_err(|_| format!("Invalid Redis database: {}", db))?,
+        };
+        let (host, port) = match address.rsplit_once(':') {
+            Some((host, port)) if !port.contains(']') => (
+                host,
+                port.parse()
+                    .map_err(|_| format!("Invalid Redis port: {}", port))?,
+            ),
+            _ => (address, 6379),
+        };
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        if host.is_empty() {
+            return Err(format!("Invalid Redis URL {}: no host", url));
+        }
+
+        let tls = tls.then(|| tls_connector(ca_file)).transpose()?;
+        Ok(Self {
+            host: host.to_string(),
+            port,
+            db,
+            username,
+            password,
+            tls,
+        })
+    }
+
+    pub fn describe(&self) -> String {
+        let scheme = if self.tls.is_some() {
+            "rediss"
+        } else {
+            "redis"
+        };
+        format!("{}://{}:{}/{}", scheme, self.host, self.port, self.db)
+    }
+
+    /// Open an authenticated connection with the database selected
+    pub async fn connect(&self) -> Result<Connection, String> {
+        let tcp = tokio::time::timeout(
+            CONNECT_TIMEOUT,
+            TcpStream::connect((self.host.as_str(), self.port)),
+        )
+        .await
+        .map_err(|_| "Connection timed out".to_string())?
+        .map_err(|e| format!("Failed to connect: {}", e))?;
+
+        let stream: Box<dyn Io> = match &self.tls {
+            Some(connector) => {
+                let name = ServerName::try_from(self.host.clone())
+                    .map_err(|e| format!("Invalid server name: {}", e))?;
+                Box::new(
+                    connector
+                        .connect(name, tcp)
+                        .await
+                        .map_err(|e| format!("TLS handshake failed: {}", e))?,
+                )
+            }
+            None => Box::new(tcp),
+        };
+
+        let mut connection = Connection {
+            stream: BufStream::new(stream),
+        };
+        if let Some(password) = &self.password {
+            let mut auth = vec!["AUTH"];
+            auth.extend(self.username.as_deref());
+            auth.push(password.expose());
+            connection.command(&auth).await?;
+        }
+        if self.db != 0 {
+            connection
+                .command(&["SELECT", &self.db.to_string()])
+                .await?;
+        }
+        Ok(connection)
+    }
+}
+
+fn tls_connector(ca_file: Option<&str>) -> Result<TlsConnector, String> {
+    let mut roots = RootCertStore::empty();
+    match ca_file {
+        Some(path) => {
+            let pem = std::fs::read(path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+            for cert in CertificateDer::pem_slice_iter(&pem) {
+                let cert = cert.map_err(|e| format!("Invalid certificate in {}: {}", path, e))?;
+                roots
+                    .add(cert)
+                    .map_err(|e| format!("Invalid certificate in {}: {}", path, e))?;
+            }
+        }
+        None => roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned()),
+    }
+    let config = ClientConfig::builder_with_provider(Arc::new(default_provider()))
+        .with_safe_default_protocol_versions()
+        .map_err(|e| e.to_string())?
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    Ok(TlsConnector::from(Arc::new(config)))
+}
+
+impl Connection {
+    /// Send a command and read its reply
+    pub async fn command<A: AsRef<[u8]>>(&mut self, args: &[A]) -> Result<Value, String> {
+        match self.pipeline(&[args]).await?.pop() {
+            Some(Value::Error(e)) => Err(e),
+            Some(value) => Ok(value),
+            None => Err("No reply".to_string()),
+        }
+    }
+
+    /// Send several commands at once and read their replies, in order
+    pub async fn pipeline<A: AsRef<[u8]>, C: AsRef<[A]>>(
+        &mut self,
+        commands: &[C],
+    ) -> Result<Vec<Value>, String> {
+        let mut buffer = Vec::new();
+        for command in commands {
+            let args = command.as_ref();
+            buffer.extend_from_slice(format!("*{}\r\n", args.len()).as_bytes());
+            for arg in args {
+                let arg = arg.as_ref();
+                buffer.extend_from_slice(format!("${}\r\n", arg.len()).as_bytes());
+                buffer.extend_from_slice(arg);
+                buffer.extend_from_slice(b"\r\n");
+            }
+        }
+        let io = |e: std::io::Error| format!("Connection failed: {}", e);
+        self.stream.write_all(&buffer).await.map_err(io)?;
+        self.stream.flush().await.map_err(io)?;
+
+        let mut replies = Vec::with_capacity(commands.len());
+        for _ in commands {
+            replies.push(self.read_value().await?);
+        }
+        Ok(replies)
+    }
+
+    async fn read_line(&mut self) -> Result<String, String> {
+        let mut line = String::new();
+        let read = self
+            .stream
+            .read_line(&mut line)
+            .await
+            .map_err(|e| format!("Connection failed: {}", e))?;
+        if read == 0 {
+            return Err("Connection closed".to_string());
+        }
+        Ok(line.trim_end_matches(['\r', '\n']).to_string())
+    }
+
+    fn read_value(
+        &mut self,
+    ) -> std::pin::Pin<Box<dyn Future<Output = Result<Value, String>> + Send + '_>> {
+        Box::pin(async move {
+            let line = self.read_line().await?;
+            let (kind, rest) = line.split_at_checked(1).ok_or("Empty reply")?;
+            let length = || {
+                rest.parse::<i64>()
+                    .map_err(|_| format!("Invalid reply: {}", line))
+            };
+            match kind {
+                "+" => Ok(Value::Status(rest.to_string())),
+                "-" => Ok(Value::Error(rest.to_string())),
+                ":" => Ok(Value::Int(length()?)),
+                "$" => {
+                    let Ok(length) = usize::try_from(length()?) else {
+                        return Ok(Value::Nil);
+                    };
+                    let mut data = vec![0; length + 2];
+                    self.stream
+                        .read_exact(&mut data)
+                        .await
+                        .map_err(|e| format!("Connection failed: {}", e))?;
+                    data.truncate(length);
+                    Ok(Value::Data(data))
+                }
+                "*" => {
+                    let Ok(length) = usize::try_from(length()?) else {
+                        return Ok(Value::Nil);
+                    };
+                    let mut items = Vec::with_capacity(length);
+                    for _ in 0..length {
+                        items.push(self.read_value().await?);
+                    }
+                    Ok(Value::Array(items))
+                }
+                _ => Err(format!("Invalid reply: {}", line)),
+            }
+        })
+    }
+}
@@ -0,0 +1,122 @@
+use crate::store::ConfigSnapshot;
+use crate::traefik::DynamicConfig;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use utoipa::ToSchema;
+
+/// Names of the entries of one kind that were added, removed or modified
+#[derive(Debug, Clone, Default, Serialize, ToSchema)]
+pub struct EntryChanges {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub modified: Vec<String>,
+}
+
+/// Routers and services changed between two published configurations. Entries are
+/// named "<protocol>.<name>", e.g. "http.tailscale-nas-web".
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ConfigDiff {
+    /// Version the changes are relative to (None for the first published configuration)
+    pub from_version: Option<String>,
+    pub to_version: String,
+    /// When the newer configuration was published
+    pub changed_at: DateTime<Utc>,
+    pub routers: EntryChanges,
+    pub services: EntryChanges,
+}
+
+impl ConfigDiff {
+    pub fn between(from: Option<&ConfigSnapshot>, to: &ConfigSnapshot) -> Self {
+        let (from_routers, from_services) = from
+            .map(|snapshot| entries(&snapshot.config))
+            .unwrap_or_default();
+        let (to_routers, to_services) = entries(&to.config);
+
+        Self {
+            from_version: from.map(|snapshot| snapshot.version.clone()),
+            to_version: to.version.clone(),
+            changed_at: to.created_at,
+            routers: changes(&from_routers, &to_routers),
+            services: changes(&from_services, &to_services),
+        }
+    }
+
+    /// One line per change, e.g. "+ service http.tailscale-nas-web", "- router
+    /// http.tailscale-old-api-router" or "~ service tcp.tailscale-db-postgres"
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        for (kind, changes) in [("router", &self.routers), ("service", &self.services)] {
+            for (sign, names) in [
+                ('+', &changes.added),
+                ('-', &changes.removed),
+                ('~', &changes.modified),
+            ] {
+                lines.extend(
+                    names
+                        .iter()
+                        .map(|name| format!("{} {} {}", sign, kind, name)),
+                );
+            }
+        }
+        lines
+    }
+
+    pub fn is_empty(&self) -> bool {
+        [&self.routers, &self.services].iter().all(|changes| {
+            changes.added.is_empty() && changes.removed.is_empty() && changes.modified.is_empty()
+        })
+    }
+}
+
+type Entries = BTreeMap<String, Value>;
+
+/// Routers and services of a configuration as JSON, keyed by "<protocol>.<name>"
+fn entries(config: &DynamicConfig) -> (Entries, Entries) {
+    let mut routers = Entries::new();
+    let mut services = Entries::new();
+
+    if let Some(http) = &config.http {
+        collect(&mut routers, "http", &http.routers);
+        collect(&mut services, "http", &http.services);
+    }
+    if let Some(tcp) = &config.tcp {
+        collect(&mut routers, "tcp", &tcp.routers);
+        collect(&mut services, "tcp", &tcp.services);
+    }
+    if let Some(udp) = &config.udp {
+        collect(&mut routers, "udp", &udp.routers);
+        collect(&mut services, "udp", &udp.services);
+    }
+
+    (routers, services)
+}
+
+fn collect<T: Serialize>(target: &mut Entries, protocol: &str, source: &BTreeMap<String, T>) {
+    for (name, value) in source {
+        target.insert(
+            format!("{}.{}", protocol, name),
+            serde_json::to_value(value).unwrap_or_default(),
+        );
+    }
+}
+
+fn changes(from: &Entries, to: &Entries) -> EntryChanges {
+    let mut changes = EntryChanges::default();
+
+    for (name, value) in to {
+        match from.get(name) {
+            None => changes.added.push(name.clone()),
+            Some(previous) if previous != value => changes.modified.push(name.clone()),
+            Some(_) => {}
+        }
+    }
+    changes.removed = from
+        .keys()
+        .filter(|name| !to.contains_key(*name))
+        .cloned()
+        .collect();
+
+    changes
+}
@@ -0,0 +1,540 @@
+pub mod diff;
+
+use crate::config::file::load_file;
+use crate::events::{ProviderEvent, events};
+use crate::traefik::{DynamicConfig, HeadersMiddleware, Middleware};
+use chrono::{DateTime, Utc};
+use diff::ConfigDiff;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, VecDeque};
+use std::error::Error;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use tokio::sync::{Mutex, RwLock, watch};
+use tracing::{info, warn};
+
+/// A published dynamic configuration together with its version metadata
+#[derive(Debug, Clone)]
+pub struct ConfigSnapshot {
+    pub config: DynamicConfig,
+    /// Increments every time the generated content changes
+    pub generation: u64,
+    /// SHA-256 of the generated content (before version metadata is embedded)
+    pub hash: String,
+    /// Human-readable version, e.g. "gen-000123-ab12cd", or "cfg-<hash>" with hash versions
+    pub version: String,
+    /// When this generation was first published
+    pub created_at: DateTime<Utc>,
+}
+
+/// Holds the configuration currently served to Traefik
+pub struct ConfigStore {
+    current: RwLock<Option<Arc<ConfigSnapshot>>>,
+    last_published_at: RwLock<Option<DateTime<Utc>>>,
+    /// Configurations published most recently, oldest first
+    history: RwLock<VecDeque<Arc<ConfigSnapshot>>>,
+    history_size: usize,
+    /// Changes made by the most recent generation that changed the content
+    last_diff: RwLock<Option<Arc<ConfigDiff>>>,
+    embed_version: bool,
+    /// Names the middleware the version is embedded as
+    marker_name: Box<dyn Fn(&str) -> String + Send + Sync>,
+    /// Name versions after the content only, so replicas agree on them
+    hash_versions: bool,
+    /// File the last generated configuration is persisted to, to survive restarts
+    state_file: Option<String>,
+    /// Held by the on-demand generation in flight; holds the error of the last failed one
+    flight: Mutex<Option<String>>,
+    /// Number of failed on-demand generations
+    failed_flights: AtomicU64,
+    /// Generation of the current configuration, for consumers following every change
+    generation: watch::Sender<u64>,
+    /// Scheduled generations that failed since the last successful one
+    failed_generations: AtomicU64,
+    /// The current configuration came from the state file and no generation confirmed it
+    restored: AtomicBool,
+}
+
+impl ConfigStore {
+    pub fn new(embed_version: bool, state_file: Option<String>, history_size: usize) -> Self {
+        Self {
+            current: RwLock::new(None),
+            last_published_at: RwLock::new(None),
+            history: RwLock::new(VecDeque::with_capacity(history_size)),
+            history_size,
+            last_diff: RwLock::new(None),
+            embed_version,
+            marker_name: Box::new(|version| format!("provider-{}", version)),
+            hash_versions: false,
+            state_file,
+            flight: Mutex::new(None),
+            failed_flights: AtomicU64::new(0),
+            generation: watch::Sender::new(0),
+            failed_generations: AtomicU64::new(0),
+            restored: AtomicBool::new(false),
+        }
+    }
+
+    /// Name versions "cfg-<hash>" instead of after the generation, which differs
+    /// between replicas that started at different times
+    pub fn with_hash_versions(mut self) -> Self {
+        self.hash_versions = true;
+        self
+    }
+
+    /// Name the middleware carrying the version, e.g. with NAME_PREFIX and NAME_SUFFIX
+    pub fn with_marker_name(
+        mut self,
+        marker_name: impl Fn(&str) -> String + Send + Sync + 'static,
+    ) -> Self {
+        self.marker_name = Box::new(marker_name);
+        self
+    }
+
+    /// Return the current configuration, generating it when nothing was published yet.
+    /// Concurrent callers share a single generation: they wait for the one in flight and
+    /// get its result instead of starting their own.
+    pub async fn current_or_generate<F, Fut>(
+        &self,
+        generate: F,
+    ) -> Result<Arc<ConfigSnapshot>, String>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<DynamicConfig, Box<dyn Error + Send + Sync>>>,
+    {
+        if let Some(snapshot) = self.current().await {
+            return Ok(snapshot);
+        }
+
+        let failed_flights = self.failed_flights.load(Ordering::SeqCst);
+        let mut flight = self.flight.lock().await;
+        if let Some(snapshot) = self.current().await {
+            return Ok(snapshot);
+        }
+        // The generation we waited for failed - report its error instead of retrying
+        if self.failed_flights.load(Ordering::SeqCst) != failed_flights
+            && let Some(error) = flight.as_ref()
+        {
+            return Err(error.clone());
+        }
+
+        match generate().await {
+            Ok(config) => Ok(self.publish(config).await),
+            Err(e) => {
+                *flight = Some(e.to_string());
+                self.failed_flights.fetch_add(1, Ordering::SeqCst);
+                Err(e.to_string())
+            }
+        }
+    }
+
+    /// Publish the configuration persisted by a previous run, if there is one
+    pub async fn restore(&self) -> Option<Arc<ConfigSnapshot>> {
+        let path = self.state_file.as_ref()?;
+        if !std::path::Path::new(path).exists() {
+            return None;
+        }
+
+        match load_file::<DynamicConfig>(path) {
+            Ok(config) => {
+                // Generated when the file was last written, not now; publishing rewrites it
+                let modified = std::fs::metadata(path).and_then(|metadata| metadata.modified());
+                let snapshot = self.publish(config).await;
+                *self.last_published_at.write().await = modified.ok().map(DateTime::from);
+                self.restored.store(true, Ordering::SeqCst);
+                info!(
+                    "Restored last known good configuration from {} ({})",
+                    path, snapshot.version
+                );
+                Some(snapshot)
+            }
+            Err(e) => {
+                warn!("Ignoring persisted configuration: {}", e);
+                None
+            }
+        }
+    }
+
+    pub async fn current(&self) -> Option<Arc<ConfigSnapshot>> {
+        self.current.read().await.clone()
+    }
+
+    /// Watch the generation of the current configuration, which changes whenever a
+    /// configuration with new content is published, including the first one
+    pub fn subscribe(&self) -> watch::Receiver<u64> {
+        self.generation.subscribe()
+    }
+
+    /// When a configuration was last published, whether or not its content changed
+    pub async fn last_published_at(&self) -> Option<DateTime<Utc>> {
+        *self.last_published_at.read().await
+    }
+
+    /// Count a failed generation; the current configuration is stale until the next
+    /// one succeeds
+    pub fn record_failure(&self) {
+        self.failed_generations.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Seconds since the served configuration was generated, when it is stale: the
+    /// generations since have failed, or it was restored and none succeeded yet
+    pub async fn stale_for(&self) -> Option<i64> {
+        if self.failed_generations.load(Ordering::SeqCst) == 0
+            && !self.restored.load(Ordering::SeqCst)
+        {
+            return None;
+        }
+        let generated_at = self.last_published_at().await?;
+        Some((Utc::now() - generated_at).num_seconds().max(0))
+    }
+
+    /// Failed generations since the last successful one
+    pub fn failed_generations(&self) -> u64 {
+        self.failed_generations.load(Ordering::SeqCst)
+    }
+
+    /// Configurations kept in the history, newest first
+    pub async fn history(&self) -> Vec<Arc<ConfigSnapshot>> {
+        self.history.read().await.iter().rev().cloned().collect()
+    }
+
+    /// Add the configurations of an exported history, e.g. from another host. Versions
+    /// already in the history are skipped and only the newest CONFIG_HISTORY_SIZE are
+    /// kept; returns how many imported ones were kept. Nothing is imported when the hash
+    /// or version of a snapshot doesn't match its content.
+    pub async fn import_history(&self, snapshots: Vec<ConfigSnapshot>) -> Result<usize, String> {
+        for snapshot in &snapshots {
+            verify_snapshot(snapshot)?;
+        }
+        let mut history = self.history.write().await;
+        let mut imported = Vec::new();
+        for snapshot in snapshots {
+            if history
+                .iter()
+                .any(|known| known.version == snapshot.version)
+            {
+                continue;
+            }
+            imported.push(snapshot.version.clone());
+            history.push_back(Arc::new(snapshot));
+        }
+        history
+            .make_contiguous()
+            .sort_by_key(|snapshot| snapshot.created_at);
+        while history.len() > self.history_size {
+            history.pop_front();
+        }
+        Ok(history
+            .iter()
+            .filter(|snapshot| imported.contains(&snapshot.version))
+            .count())
+    }
+
+    /// Look up the current or a past configuration by its version or content hash
+    pub async fn find(&self, version: &str) -> Option<Arc<ConfigSnapshot>> {
+        let matches =
+            |snapshot: &ConfigSnapshot| snapshot.version == version || snapshot.hash == version;
+
+        if let Some(snapshot) = self.current().await
+            && matches(&snapshot)
+        {
+            return Some(snapshot);
+        }
+        self.history
+            .read()
+            .await
+            .iter()
+            .find(|snapshot| matches(snapshot))
+            .cloned()
+    }
+
+    /// Routers and services changed by the most recent content change
+    pub async fn last_diff(&self) -> Option<Arc<ConfigDiff>> {
+        self.last_diff.read().await.clone()
+    }
+
+    /// Publish a freshly generated configuration. The generation only advances when
+    /// the content differs from the currently published one.
+    pub async fn publish(&self, config: DynamicConfig) -> Arc<ConfigSnapshot> {
+        let hash = content_hash(&config);
+        let mut current = self.current.write().await;
+        *self.last_published_at.write().await = Some(Utc::now());
+        self.failed_generations.store(0, Ordering::SeqCst);
+        self.restored.store(false, Ordering::SeqCst);
+
+        if let Some(snapshot) = current.as_ref()
+            && snapshot.hash == hash
+        {
+            return snapshot.clone();
+        }
+
+        let generation = current.as_ref().map(|s| s.generation).unwrap_or(0) + 1;
+        let version = if self.hash_versions {
+            hash_version(&hash)
+        } else {
+            generation_version(generation, &hash)
+        };
+
+        if let Some(path) = &self.state_file {
+            persist(path, &config).await;
+        }
+
+        let mut config = config;
+        if self.embed_version {
+            embed_version_marker(&mut config, &(self.marker_name)(&version), &version);
+        }
+
+        let snapshot = Arc::new(ConfigSnapshot {
+            config,
+            generation,
+            hash,
+            version,
+            created_at: Utc::now(),
+        });
+        let diff = ConfigDiff::between(current.as_deref(), &snapshot);
+        // Everything is new in the first configuration, which is not worth announcing
+        if current.is_some() {
+            log_diff(&diff);
+            publish_service_events(&diff);
+            events().publish(ProviderEvent::ConfigChanged(diff.clone()));
+        }
+        *self.last_diff.write().await = Some(Arc::new(diff));
+        if self.history_size > 0 {
+            let mut history = self.history.write().await;
+            if history.len() == self.history_size {
+                history.pop_front();
+            }
+            history.push_back(snapshot.clone());
+        }
+        *current = Some(snapshot.clone());
+        self.generation.send_replace(generation);
+        snapshot
+    }
+}
+
+/// Log the routers and services a new configuration changed, for auditing from logs
+fn log_diff(diff: &ConfigDiff) {
+    let from = diff.from_version.as_deref().unwrap_or("nothing");
+    if diff.is_empty() {
+        info!(
+            "Configuration {} -> {}: no router or service changes",
+            from, diff.to_version
+        );
+        return;
+    }
+    info!("Configuration {} -> {}:", from, diff.to_version);
+    for line in diff.lines() {
+        info!("  {}", line);
+    }
+}
+
+/// Announce the services a new configuration added and removed
+fn publish_service_events(diff: &ConfigDiff) {
+    let split = |entry: &String| {
+        let (protocol, name) = entry.split_once('.').unwrap_or(("http", entry));
+        (protocol.to_string(), name.to_string())
+    };
+
+    for (protocol, name) in diff.services.added.iter().map(split) {
+        events().publish(ProviderEvent::ServiceAdded { protocol, name });
+    }
+    for (protocol, name) in diff.services.removed.iter().map(split) {
+        events().publish(ProviderEvent::ServiceRemoved { protocol, name });
+    }
+}
+
+/// Write the generated configuration (without version metadata) through a temporary
+/// file, so a crash never leaves a truncated state file behind
+async fn persist(path: &str, config: &DynamicConfig) {
+    let json = match serde_json::to_vec_pretty(config) {
+        Ok(json) => json,
+        Err(e) => {
+            warn!("Failed to serialize configuration for {}: {}", path, e);
+            return;
+        }
+    };
+
+    let temp_path = format!("{}.tmp", path);
+    let result = match tokio::fs::write(&temp_path, json).await {
+        Ok(()) => tokio::fs::rename(&temp_path, path).await,
+        Err(e) => Err(e),
+    };
+    if let Err(e) = result {
+        warn!("Failed to persist configuration to {}: {}", path, e);
+    }
+}
+
+/// Hash the canonical JSON form of a configuration (object keys sorted)
+pub fn content_hash(config: &DynamicConfig) -> String {
+    let canonical = serde_json::to_value(config)
+        .map(|value| value.to_string())
+        .unwrap_or_default();
+    hex::encode(Sha256::digest(canonical.as_bytes()))
+}
+
+/// Version named after the content only, the same on every replica
+fn hash_version(hash: &str) -> String {
+    format!("cfg-{}", &hash[..12])
+}
+
+/// Version named after the generation that published the content
+fn generation_version(generation: u64, hash: &str) -> String {
+    format!("gen-{:06}-{}", generation, &hash[..6])
+}
+
+/// Check that a snapshot from outside, e.g. an imported history, carries the hash of
+/// its content and a version named after that hash, so /config?version= and the ETag
+/// never serve content under another configuration's hash
+pub fn verify_snapshot(snapshot: &ConfigSnapshot) -> Result<(), String> {
+    // The hash is taken before the version marker is embedded, which may have added
+    // the HTTP section
+    let mut config = snapshot.config.clone();
+    if let Some(marker) = version_marker(&config, &snapshot.version)
+        && let Some(http) = &mut config.http
+    {
+        http.middlewares.remove(&marker);
+    }
+    let mut hashes = vec![content_hash(&config)];
+    if let Some(http) = &config.http
+        && http.routers.is_empty()
+        && http.services.is_empty()
+        && http.middlewares.is_empty()
+        && http.servers_transports.is_empty()
+    {
+        config.http = None;
+        hashes.push(content_hash(&config));
+    }
+    if !hashes.contains(&snapshot.hash) {
+        return Err(format!(
+            "Hash of configuration {} doesn't match its content",
+            snapshot.version
+        ));
+    }
+
+    if snapshot.version != hash_version(&snapshot.hash)
+        && snapshot.version != generation_version(snapshot.generation, &snapshot.hash)
+    {
+        return Err(format!(
+            "Version {} isn't named after generation {} and hash {}",
+            snapshot.version, snapshot.generation, snapshot.hash
+        ));
+    }
+    Ok(())
+}
+
+/// Response header the version marker middleware carries the version in
+const VERSION_MARKER_HEADER: &str = "X-Config-Version";
+
+/// Add an unreferenced headers middleware carrying the version, so the provider
+/// generation can be read from Traefik's dashboard or API. Traefik loads it without
+/// any router or server.
+fn embed_version_marker(config: &mut DynamicConfig, name: &str, version: &str) {
+    let http = config.http.get_or_insert_with(Default::default);
+
+    http.middlewares.insert(
+        name.to_string(),
+        Middleware {
+            headers: Some(HeadersMiddleware {
+                custom_request_headers: None,
+                custom_response_headers: Some(BTreeMap::from([(
+                    VERSION_MARKER_HEADER.to_string(),
+                    version.to_string(),
+                )])),
+            }),
+            ..Default::default()
+        },
+    );
+}
+
+/// Name of the middleware a configuration embeds its version in, if it has one
+pub fn version_marker(config: &DynamicConfig, version: &str) -> Option<String> {
+    let http = config.http.as_ref()?;
+    http.middlewares
+        .iter()
+        .find(|(_, middleware)| {
+            middleware
+                .headers
+                .as_ref()
+                .and_then(|headers| headers.custom_response_headers.as_ref())
+                .and_then(|headers| headers.get(VERSION_MARKER_HEADER))
+                .is_some_and(|marked| marked == version)
+        })
+        .map(|(name, _)| name.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn config(url: &str) -> DynamicConfig {
+        serde_json::from_value(json!({
+            "http": {
+                "routers": {},
+                "services": {"web": {"loadBalancer": {"servers": [{"url": url}]}}}
+            },
+            "tcp": null,
+            "udp": null
+        }))
+        .expect("configuration")
+    }
+
+    fn owned(snapshots: Vec<Arc<ConfigSnapshot>>) -> Vec<ConfigSnapshot> {
+        snapshots
+            .iter()
+            .map(|snapshot| (**snapshot).clone())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn history_import_keeps_exported_snapshots() {
+        let exporting = ConfigStore::new(true, None, 10);
+        exporting.publish(config("http://100.64.0.1:80")).await;
+        exporting.publish(config("http://100.64.0.2:80")).await;
+        let empty = ConfigStore::new(true, None, 10).with_hash_versions();
+        empty
+            .publish(DynamicConfig {
+                http: None,
+                tcp: None,
+                udp: None,
+                tls: None,
+            })
+            .await;
+
+        let importing = ConfigStore::new(false, None, 10);
+        let mut snapshots = owned(exporting.history().await);
+        snapshots.extend(owned(empty.history().await));
+        assert_eq!(importing.import_history(snapshots.clone()).await, Ok(3));
+        for snapshot in &snapshots {
+            let found = importing.find(&snapshot.hash).await.expect("imported");
+            assert_eq!(found.version, snapshot.version);
+        }
+        // Known versions are skipped
+        assert_eq!(importing.import_history(snapshots).await, Ok(0));
+    }
+
+    #[tokio::test]
+    async fn history_import_rejects_mismatched_hashes() {
+        let exporting = ConfigStore::new(false, None, 10);
+        let snapshot = (*exporting.publish(config("http://100.64.0.1:80")).await).clone();
+        let importing = ConfigStore::new(false, None, 10);
+
+        let tampered = ConfigSnapshot {
+            config: config("http://203.0.113.1:80"),
+            ..snapshot.clone()
+        };
+        let stale_hash = ConfigSnapshot {
+            hash: content_hash(&config("http://100.64.0.2:80")),
+            ..snapshot.clone()
+        };
+        let renamed = ConfigSnapshot {
+            version: "gen-000042-abcdef".to_string(),
+            ..snapshot.clone()
+        };
+        for bad in [tampered, stale_hash, renamed] {
+            let imported = importing.import_history(vec![snapshot.clone(), bad]).await;
+            assert!(imported.is_err());
+            assert!(importing.history().await.is_empty());
+        }
+    }
+}
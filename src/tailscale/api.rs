@@ -0,0 +1,442 @@
+//! Tailscale admin API (api.tailscale.com) as a data source for nodes without tailscaled
+
+use crate::tailscale::client::TailscaleError;
+use crate::tailscale::{NodePublic, PeerStatus, StableNodeID, Status, TailnetStatus, UserID};
+use chrono::{DateTime, Duration, Utc};
+use http_body_util::{BodyExt, Full};
+use hyper::body::Bytes;
+use hyper::{Method, Request, header};
+use hyper_rustls::{HttpsConnector, HttpsConnectorBuilder};
+use hyper_util::client::legacy::{Client, connect::HttpConnector};
+use hyper_util::rt::TokioExecutor;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::{Mutex, Semaphore};
+use tokio::task::JoinSet;
+use tracing::{debug, warn};
+
+type HttpsClient = Client<HttpsConnector<HttpConnector>, Full<Bytes>>;
+
+/// Devices not seen by the coordination server for this long count as offline when
+/// the API doesn't report the connection state
+const ONLINE_WINDOW: Duration = Duration::minutes(5);
+
+/// Access tokens are replaced this long before they expire
+const TOKEN_REFRESH_MARGIN: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// Posture attribute requests in flight at once, to stay clear of the API rate limits
+const ATTRIBUTE_REQUESTS: usize = 8;
+
+pub struct TailscaleApi {
+    base_url: String,
+    tailnet: String,
+    credentials: Credentials,
+    /// Whether the posture attributes of every device are fetched along with the list
+    posture_attributes: bool,
+    client: HttpsClient,
+}
+
+/// How requests to the admin API are authorized
+pub enum Credentials {
+    /// Long-lived API access token
+    ApiKey(String),
+    /// OAuth client exchanging its secret for short-lived access tokens
+    OAuth {
+        client_id: String,
+        client_secret: String,
+        /// Current access token and when it is due for renewal
+        token: Mutex<Option<(String, Instant)>>,
+    },
+}
+
+impl Credentials {
+    pub fn oauth(client_id: &str, client_secret: &str) -> Self {
+        Credentials::OAuth {
+            client_id: client_id.to_string(),
+            client_secret: client_secret.to_string(),
+            token: Mutex::new(None),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct DeviceAttributes {
+    #[serde(default)]
+    attributes: HashMap<String, serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct DeviceList {
+    devices: Vec<Device>,
+}
+
+/// Device as listed by GET /api/v2/tailnet/{tailnet}/devices?fields=all
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Device {
+    node_id: String,
+    #[serde(default)]
+    node_key: String,
+    hostname: String,
+    /// MagicDNS name, e.g. "nas.tail1234.ts.net"
+    name: String,
+    #[serde(default)]
+    os: String,
+    #[serde(default)]
+    addresses: Vec<String>,
+    #[serde(default)]
+    tags: Vec<String>,
+    created: Option<String>,
+    last_seen: Option<String>,
+    connected_to_control: Option<bool>,
+    #[serde(default)]
+    key_expiry_disabled: bool,
+    expires: Option<String>,
+    #[serde(default)]
+    is_external: bool,
+    #[serde(default)]
+    enabled_routes: Vec<String>,
+}
+
+impl TailscaleApi {
+    /// `tailnet` is the tailnet name or "-" for the tailnet of the API key
+    pub fn new(base_url: &str, tailnet: &str, credentials: Credentials) -> Self {
+        let connector = HttpsConnectorBuilder::new()
+            .with_webpki_roots()
+            .https_or_http()
+            .enable_http1()
+            .build();
+
+        Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            tailnet: tailnet.to_string(),
+            credentials,
+            posture_attributes: false,
+            client: Client::builder(TokioExecutor::new()).build(connector),
+        }
+    }
+
+    /// Also fetch the posture attributes of each device (one request per device)
+    pub fn with_posture_attributes(mut self) -> Self {
+        self.posture_attributes = true;
+        self
+    }
+
+    /// Fetch the devices of the tailnet and present them like the LocalAPI status of a
+    /// node that is not itself part of the tailnet
+    pub async fn status(&self, include_peers: bool) -> Result<Status, TailscaleError> {
+        let url = format!(
+            "{}/api/v2/tailnet/{}/devices?fields=all",
+            self.base_url, self.tailnet
+        );
+        let body = match (self.get(&url).await, &self.credentials) {
+            // The access token may have been revoked before it expired
+            (Err(TailscaleError::ApiError(e)), Credentials::OAuth { token, .. })
+                if e.starts_with("HTTP 401") =>
+            {
+                token.lock().await.take();
+                self.get(&url).await?
+            }
+            (result, _) => result?,
+        };
+        let devices: DeviceList = serde_json::from_slice(&body)?;
+        let attributes = if self.posture_attributes && include_peers {
+            self.fetch_posture_attributes(&devices.devices).await?
+        } else {
+            HashMap::new()
+        };
+
+        Ok(self.to_status(devices.devices, include_peers, attributes))
+    }
+
+    async fn get(&self, url: &str) -> Result<Bytes, TailscaleError> {
+        let token = self.access_token().await?;
+        send(&self.client, get_request(url, &token)?).await
+    }
+
+    /// Fetch the posture attributes of the devices, by node ID. Devices whose attributes
+    /// can't be fetched are left out, so they match no posture rule.
+    async fn fetch_posture_attributes(
+        &self,
+        devices: &[Device],
+    ) -> Result<HashMap<String, HashMap<String, serde_json::Value>>, TailscaleError> {
+        let token = self.access_token().await?;
+        let permits = Arc::new(Semaphore::new(ATTRIBUTE_REQUESTS));
+        let mut requests = JoinSet::new();
+        for device in devices {
+            let url = format!(
+                "{}/api/v2/device/{}/attributes",
+                self.base_url, device.node_id
+            );
+            let (client, token, permits) = (self.client.clone(), token.clone(), permits.clone());
+            let node_id = device.node_id.clone();
+            requests.spawn(async move {
+                let _permit = permits.acquire().await;
+                let result = async {
+                    let body = send(&client, get_request(&url, &token)?).await?;
+                    Ok::<_, TailscaleError>(serde_json::from_slice::<DeviceAttributes>(&body)?)
+                }
+                .await;
+                (node_id, result)
+            });
+        }
+
+        let mut attributes = HashMap::new();
+        while let Some(joined) = requests.join_next().await {
+            match joined {
+                Ok((node_id, Ok(device_attributes))) => {
+                    attributes.insert(node_id, device_attributes.attributes);
+                }
+                Ok((node_id, Err(e))) => {
+                    warn!(
+                        "Failed to fetch the posture attributes of device {}: {}",
+                        node_id, e
+                    );
+                }
+                Err(e) => warn!("Posture attribute request failed: {}", e),
+            }
+        }
+        Ok(attributes)
+    }
+
+    /// The API key, or an OAuth access token that is renewed shortly before it expires
+    async fn access_token(&self) -> Result<String, TailscaleError> {
+        let (client_id, client_secret, token) = match &self.credentials {
+            Credentials::ApiKey(api_key) => return Ok(api_key.clone()),
+            Credentials::OAuth {
+                client_id,
+                client_secret,
+                token,
+            } => (client_id, client_secret, token),
+        };
+
+        // Held while renewing, so concurrent requests share one renewal
+        let mut token = token.lock().await;
+        if let Some((access_token, renew_at)) = token.as_ref()
+            && Instant::now() < *renew_at
+        {
+            return Ok(access_token.clone());
+        }
+
+        let form = format!(
+            "grant_type=client_credentials&client_id={}&client_secret={}",
+            form_encode(client_id),
+            form_encode(client_secret)
+        );
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri(format!("{}/api/v2/oauth/token", self.base_url))
+            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .header(header::ACCEPT, "application/json")
+            .header(header::USER_AGENT, "traefik-tailscale-provider")
+            .body(Full::new(Bytes::from(form)))
+            .map_err(|e| TailscaleError::HttpRequest(format!("Failed to build request: {}", e)))?;
+        let body = send(&self.client, request).await.map_err(|e| match e {
+            TailscaleError::ApiError(e) => {
+                TailscaleError::ApiError(format!("OAuth token request failed: {}", e))
+            }
+            e => e,
+        })?;
+        let response: TokenResponse = serde_json::from_slice(&body)?;
+
+        let lifetime = std::time::Duration::from_secs(response.expires_in);
+        let renew_at = Instant::now() + lifetime.saturating_sub(TOKEN_REFRESH_MARGIN);
+        debug!(
+            "Obtained a Tailscale API access token valid for {:?}",
+            lifetime
+        );
+        *token = Some((response.access_token.clone(), renew_at));
+        Ok(response.access_token)
+    }
+
+    fn to_status(
+        &self,
+        devices: Vec<Device>,
+        include_peers: bool,
+        mut attributes: HashMap<String, HashMap<String, serde_json::Value>>,
+    ) -> Status {
+        let now = Utc::now();
+        let magic_dns_suffix = devices
+            .iter()
+            .find_map(|device| {
+                device
+                    .name
+                    .strip_prefix(&device.hostname)
+                    .and_then(|rest| rest.strip_prefix('.'))
+            })
+            .unwrap_or_default()
+            .trim_end_matches('.')
+            .to_string();
+
+        let peers = include_peers.then(|| {
+            devices
+                .into_iter()
+                .map(|device| {
+                    let posture_attributes = attributes.remove(&device.node_id);
+                    let peer = peer_status(device, now, posture_attributes);
+                    (peer.public_key.clone(), Some(peer))
+                })
+                .collect::<HashMap<_, _>>()
+        });
+
+        Status {
+            version: "api".to_string(),
+            tun: false,
+            backend_state: "Running".to_string(),
+            have_node_key: None,
+            auth_url: String::new(),
+            tailscale_ips: Vec::new(),
+            self_peer: None,
+            exit_node_status: None,
+            health: Vec::new(),
+            magic_dns_suffix: magic_dns_suffix.clone(),
+            current_tailnet: Some(TailnetStatus {
+                name: self.tailnet.clone(),
+                magic_dns_suffix,
+                magic_dns_enabled: true,
+            }),
+            cert_domains: None,
+            peers,
+            user: None,
+            client_version: None,
+        }
+    }
+}
+
+fn get_request(url: &str, token: &str) -> Result<Request<Full<Bytes>>, TailscaleError> {
+    Request::builder()
+        .method(Method::GET)
+        .uri(url)
+        .header(header::AUTHORIZATION, format!("Bearer {}", token))
+        .header(header::ACCEPT, "application/json")
+        .header(header::USER_AGENT, "traefik-tailscale-provider")
+        .body(Full::new(Bytes::new()))
+        .map_err(|e| TailscaleError::HttpRequest(format!("Failed to build request: {}", e)))
+}
+
+async fn send(
+    client: &HttpsClient,
+    request: Request<Full<Bytes>>,
+) -> Result<Bytes, TailscaleError> {
+    let response = client
+        .request(request)
+        .await
+        .map_err(|e| TailscaleError::SocketConnection(format!("Failed to send request: {}", e)))?;
+    let status_code = response.status();
+    if !status_code.is_success() {
+        return Err(TailscaleError::ApiError(format!(
+            "HTTP {}: {}",
+            status_code,
+            status_code.canonical_reason().unwrap_or("Unknown")
+        )));
+    }
+    Ok(response
+        .into_body()
+        .collect()
+        .await
+        .map_err(|e| {
+            TailscaleError::SocketConnection(format!("Failed to read response body: {}", e))
+        })?
+        .to_bytes())
+}
+
+/// Percent-encode a value for an application/x-www-form-urlencoded body
+fn form_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+/// Map a device onto the peer fields the LocalAPI reports. Connection details (current
+/// address, relay, traffic) are only known to tailscaled and stay empty.
+fn peer_status(
+    device: Device,
+    now: DateTime<Utc>,
+    posture_attributes: Option<HashMap<String, serde_json::Value>>,
+) -> PeerStatus {
+    let parse = |time: &Option<String>| {
+        time.as_deref()
+            .and_then(|time| DateTime::parse_from_rfc3339(time).ok())
+            .map(|time| time.with_timezone(&Utc))
+    };
+    let last_seen = parse(&device.last_seen);
+    let online = device
+        .connected_to_control
+        .unwrap_or_else(|| last_seen.is_some_and(|seen| now - seen <= ONLINE_WINDOW));
+    let key_expiry = parse(&device.expires).filter(|_| !device.key_expiry_disabled);
+    let allowed_ips = device
+        .addresses
+        .iter()
+        .map(|ip| match ip.contains(':') {
+            true => format!("{}/128", ip),
+            false => format!("{}/32", ip),
+        })
+        .chain(device.enabled_routes.iter().cloned())
+        .collect();
+    let is_default_route = |route: &String| route == "0.0.0.0/0" || route == "::/0";
+
+    PeerStatus {
+        id: StableNodeID(device.node_id.clone()),
+        // Node keys are unique; fall back to the node ID for devices without one
+        public_key: NodePublic(if device.node_key.is_empty() {
+            device.node_id
+        } else {
+            device.node_key
+        }),
+        hostname: device.hostname,
+        dns_name: format!("{}.", device.name.trim_end_matches('.')),
+        os: device.os,
+        user_id: UserID(0),
+        alt_sharer_user_id: None,
+        tailscale_ips: device.addresses,
+        allowed_ips: Some(allowed_ips),
+        primary_routes: None,
+        tags: (!device.tags.is_empty()).then_some(device.tags),
+        addrs: None,
+        cur_addr: String::new(),
+        relay: String::new(),
+        peer_relay: String::new(),
+        rx_bytes: 0,
+        tx_bytes: 0,
+        created: parse(&device.created).unwrap_or(DateTime::UNIX_EPOCH),
+        last_write: DateTime::UNIX_EPOCH,
+        last_seen: if online {
+            now
+        } else {
+            last_seen.unwrap_or(DateTime::UNIX_EPOCH)
+        },
+        last_handshake: DateTime::UNIX_EPOCH,
+        online: Some(online),
+        exit_node: false,
+        exit_node_option: device.enabled_routes.iter().any(is_default_route),
+        active: false,
+        peer_api_url: None,
+        in_network_map: true,
+        in_magic_sock: false,
+        in_engine: false,
+        taildrop_target: None,
+        no_file_sharing_reason: None,
+        capabilities: None,
+        cap_map: None,
+        ssh_host_keys: None,
+        sharee_node: Some(device.is_external),
+        key_expiry,
+        expired: Some(key_expiry.is_some_and(|expiry| expiry <= now)),
+        location: None,
+        posture_attributes,
+    }
+}
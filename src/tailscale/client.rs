@@ -1,12 +1,17 @@
 use crate::platform::SocketPath;
-use crate::tailscale::types::Status;
+#[cfg(feature = "api")]
+use crate::tailscale::api::TailscaleApi;
+use crate::tailscale::types::{PingResult, ServeConfig, Status, WhoIsResponse};
 use base64::Engine;
 use http_body_util::{BodyExt, Full};
 use hyper::body::Bytes;
 use hyper_util::client::legacy::connect::HttpConnector;
 use hyper_util::{client::legacy::Client, rt::TokioExecutor};
+use serde::de::DeserializeOwned;
 use std::error::Error;
 use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{debug, instrument};
 
 #[cfg(unix)]
 use hyperlocal::{UnixConnector, Uri};
@@ -20,6 +25,7 @@ pub enum TailscaleError {
     HttpRequest(String),
     JsonParse(serde_json::Error),
     ApiError(String),
+    Timeout(Duration),
 }
 
 impl fmt::Display for TailscaleError {
@@ -29,19 +35,76 @@ impl fmt::Display for TailscaleError {
             TailscaleError::HttpRequest(msg) => write!(f, "HTTP request error: {}", msg),
             TailscaleError::JsonParse(err) => write!(f, "JSON parse error: {}", err),
             TailscaleError::ApiError(msg) => write!(f, "Tailscale API error: {}", msg),
+            TailscaleError::Timeout(timeout) => {
+                write!(f, "Tailscale API request timed out after {:?}", timeout)
+            }
         }
     }
 }
 
 impl Error for TailscaleError {}
 
+impl TailscaleError {
+    /// Failures that may go away on their own, e.g. while tailscaled restarts
+    fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            TailscaleError::SocketConnection(_) | TailscaleError::Timeout(_)
+        )
+    }
+}
+
 impl From<serde_json::Error> for TailscaleError {
     fn from(err: serde_json::Error) -> Self {
         TailscaleError::JsonParse(err)
     }
 }
 
-pub enum TailscaleClient {
+/// Default limit for a LocalAPI request, including reading the response
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Limit for fetching a certificate, which may have to be issued first
+#[cfg(feature = "https")]
+const CERT_TIMEOUT: Duration = Duration::from_secs(120);
+
+pub struct TailscaleClient {
+    transport: Transport,
+    timeout: Duration,
+    retry: RetryPolicy,
+}
+
+/// How often and how patiently transient LocalAPI failures are retried
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    /// Total attempts per request, including the first one
+    pub attempts: u32,
+    /// Backoff before the first retry, doubling for each further retry
+    pub backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            attempts: 3,
+            backoff: Duration::from_millis(200),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Backoff before retry number `retry` (starting at 1), with half of it randomized so
+    /// replicas restarting together don't retry in lockstep
+    fn backoff(&self, retry: u32) -> Duration {
+        let backoff = self.backoff.saturating_mul(1 << (retry - 1).min(16));
+        let jitter = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|now| now.subsec_nanos() as f64 / 1e9)
+            .unwrap_or(0.5);
+        backoff / 2 + backoff.mul_f64(jitter / 2.0)
+    }
+}
+
+enum Transport {
     #[cfg(unix)]
     Unix {
         socket_path: String,
@@ -57,20 +120,12 @@ pub enum TailscaleClient {
         token: Option<String>,
         client: Client<HttpConnector, Full<Bytes>>,
     },
+    /// The Tailscale admin API instead of a tailscaled, only serving the status
+    #[cfg(feature = "api")]
+    Api(TailscaleApi),
 }
 
-impl TailscaleClient {
-    pub fn new() -> Result<Self, TailscaleError> {
-        let socket_path = SocketPath::default_socket_path()
-            .map_err(|e| TailscaleError::SocketConnection(e.to_string()))?;
-        
-        Self::from_socket_path(socket_path)
-    }
-
-    pub fn with_socket_path(socket_path: String) -> Result<Self, TailscaleError> {
-        Self::from_socket_path(socket_path)
-    }
-    
+impl Transport {
     fn from_socket_path(socket_path: String) -> Result<Self, TailscaleError> {
         if socket_path.starts_with("tcp://") {
             let connector = HttpConnector::new();
@@ -97,7 +152,7 @@ impl TailscaleClient {
                 )
             };
 
-            Ok(TailscaleClient::Tcp {
+            Ok(Transport::Tcp {
                 base_url,
                 token,
                 client,
@@ -108,7 +163,7 @@ impl TailscaleClient {
                 let connector = UnixConnector;
                 let client = Client::builder(TokioExecutor::new()).build(connector);
 
-                Ok(TailscaleClient::Unix {
+                Ok(Transport::Unix {
                     socket_path,
                     client,
                 })
@@ -119,7 +174,7 @@ impl TailscaleClient {
                 let connector = NamedPipeConnector;
                 let client = Client::builder(TokioExecutor::new()).build(connector);
 
-                Ok(TailscaleClient::NamedPipe {
+                Ok(Transport::NamedPipe {
                     pipe_path: socket_path,
                     client,
                 })
@@ -132,6 +187,49 @@ impl TailscaleClient {
             }
         }
     }
+}
+
+impl TailscaleClient {
+    pub fn new() -> Result<Self, TailscaleError> {
+        let socket_path = SocketPath::default_socket_path()
+            .map_err(|e| TailscaleError::SocketConnection(e.to_string()))?;
+
+        Self::from_socket_path(socket_path)
+    }
+
+    pub fn with_socket_path(socket_path: String) -> Result<Self, TailscaleError> {
+        Self::from_socket_path(socket_path)
+    }
+
+    /// Read the status from the Tailscale admin API instead of a tailscaled
+    #[cfg(feature = "api")]
+    pub fn with_api(api: TailscaleApi) -> Self {
+        Self {
+            transport: Transport::Api(api),
+            timeout: DEFAULT_TIMEOUT,
+            retry: RetryPolicy::default(),
+        }
+    }
+
+    /// Limit every LocalAPI request, including reading the response, to `timeout`
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Retry transient failures (connection errors and timeouts) before reporting them
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    fn from_socket_path(socket_path: String) -> Result<Self, TailscaleError> {
+        Ok(Self {
+            transport: Transport::from_socket_path(socket_path)?,
+            timeout: DEFAULT_TIMEOUT,
+            retry: RetryPolicy::default(),
+        })
+    }
 
     pub async fn get_status(&self) -> Result<Status, TailscaleError> {
         self.get_status_with_peers(true).await
@@ -142,26 +240,142 @@ impl TailscaleClient {
     }
 
     async fn get_status_with_peers(&self, include_peers: bool) -> Result<Status, TailscaleError> {
+        #[cfg(feature = "api")]
+        if let Transport::Api(api) = &self.transport {
+            return self
+                .retrying("devices", || async {
+                    tokio::time::timeout(self.timeout, api.status(include_peers))
+                        .await
+                        .map_err(|_| TailscaleError::Timeout(self.timeout))?
+                })
+                .await;
+        }
+
         let path = if include_peers {
             "/localapi/v0/status"
         } else {
             "/localapi/v0/status?peers=false"
         };
 
-        let response = match self {
+        self.get_json(path).await
+    }
+
+    /// Look up the node and user owning a tailnet address ("ip" or "ip:port")
+    pub async fn whois(&self, addr: &str) -> Result<WhoIsResponse, TailscaleError> {
+        self.get_json(&format!("/localapi/v0/whois?addr={}", addr))
+            .await
+    }
+
+    /// Fetch the `tailscale serve` configuration of the node this client talks to
+    pub async fn get_serve_config(&self) -> Result<ServeConfig, TailscaleError> {
+        // Nodes that never ran `tailscale serve` return null
+        let config: Option<ServeConfig> = self.get_json("/localapi/v0/serve-config").await?;
+        Ok(config.unwrap_or_default())
+    }
+
+    /// Measure the round trip to a peer with a disco ping (the path `tailscale ping` uses)
+    pub async fn ping(&self, ip: &str) -> Result<PingResult, TailscaleError> {
+        let result: PingResult = self
+            .post_json(&format!("/localapi/v0/ping?ip={}&type=disco", ip))
+            .await?;
+        if !result.err.is_empty() {
+            return Err(TailscaleError::ApiError(result.err));
+        }
+        Ok(result)
+    }
+
+    /// Fetch the private key and certificate (PEM, key first) of one of this node's
+    /// domains, issued and renewed by tailscaled through Let's Encrypt
+    #[cfg(feature = "https")]
+    #[instrument(skip(self))]
+    pub async fn get_cert_pair(&self, domain: &str) -> Result<Bytes, TailscaleError> {
+        let request = async {
+            let path = format!("/localapi/v0/cert/{}?type=pair", domain);
+            let response = self.send_request(hyper::Method::GET, &path).await?;
+            Self::read_body(response).await
+        };
+
+        // Issuing a new certificate takes an ACME round trip
+        tokio::time::timeout(CERT_TIMEOUT, request)
+            .await
+            .map_err(|_| TailscaleError::Timeout(CERT_TIMEOUT))?
+    }
+
+    async fn get_json<T: DeserializeOwned>(&self, path: &str) -> Result<T, TailscaleError> {
+        self.request_json(hyper::Method::GET, path).await
+    }
+
+    async fn post_json<T: DeserializeOwned>(&self, path: &str) -> Result<T, TailscaleError> {
+        self.request_json(hyper::Method::POST, path).await
+    }
+
+    #[instrument(name = "localapi", skip(self), fields(%method))]
+    async fn request_json<T: DeserializeOwned>(
+        &self,
+        method: hyper::Method,
+        path: &str,
+    ) -> Result<T, TailscaleError> {
+        self.retrying(path, || self.attempt_json(method.clone(), path))
+            .await
+    }
+
+    /// Run a request, repeating it while it fails transiently
+    async fn retrying<T, F, Fut>(&self, what: &str, request: F) -> Result<T, TailscaleError>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<T, TailscaleError>>,
+    {
+        let mut attempt = 1;
+        loop {
+            match request().await {
+                Err(e) if e.is_transient() && attempt < self.retry.attempts => {
+                    let backoff = self.retry.backoff(attempt);
+                    debug!(
+                        "Tailscale request {} failed (attempt {}/{}), retrying in {:?}: {}",
+                        what, attempt, self.retry.attempts, backoff, e
+                    );
+                    tokio::time::sleep(backoff).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    async fn attempt_json<T: DeserializeOwned>(
+        &self,
+        method: hyper::Method,
+        path: &str,
+    ) -> Result<T, TailscaleError> {
+        let request = async {
+            let response = self.send_request(method, path).await?;
+            self.handle_response(response).await
+        };
+
+        tokio::time::timeout(self.timeout, request)
+            .await
+            .map_err(|_| TailscaleError::Timeout(self.timeout))?
+    }
+
+    async fn send_request(
+        &self,
+        method: hyper::Method,
+        path: &str,
+    ) -> Result<hyper::Response<hyper::body::Incoming>, TailscaleError> {
+        let response = match &self.transport {
             #[cfg(unix)]
-            TailscaleClient::Unix {
+            Transport::Unix {
                 socket_path,
                 client,
             } => {
                 let uri = Uri::new(socket_path, path);
-                let request = self.build_request(uri, None)?;
+                let request = self.build_request(method, uri, None)?;
                 client.request(request).await.map_err(|e| {
                     TailscaleError::SocketConnection(format!("Failed to send request: {}", e))
                 })?
             }
             #[cfg(windows)]
-            TailscaleClient::NamedPipe { pipe_path, client } => {
+            Transport::NamedPipe { pipe_path, client } => {
                 // Hex encode the pipe path for hyper-named-pipe
                 let hex_encoded_pipe = hex::encode(pipe_path.as_bytes());
                 let uri: hyper::Uri =
@@ -170,12 +384,12 @@ impl TailscaleClient {
                         .map_err(|e| {
                             TailscaleError::SocketConnection(format!("Invalid URI: {}", e))
                         })?;
-                let request = self.build_request(uri, None)?;
+                let request = self.build_request(method, uri, None)?;
                 client.request(request).await.map_err(|e| {
                     TailscaleError::SocketConnection(format!("Failed to send request: {}", e))
                 })?
             }
-            TailscaleClient::Tcp {
+            Transport::Tcp {
                 base_url,
                 token,
                 client,
@@ -183,19 +397,31 @@ impl TailscaleClient {
                 let uri: hyper::Uri = format!("{}{}", base_url, path)
                     .parse()
                     .map_err(|e| TailscaleError::SocketConnection(format!("Invalid URI: {}", e)))?;
-                let request = self.build_request(uri, token.as_deref())?;
+                let request = self.build_request(method, uri, token.as_deref())?;
                 client.request(request).await.map_err(|e| {
                     TailscaleError::SocketConnection(format!("Failed to send request: {}", e))
                 })?
             }
+            #[cfg(feature = "api")]
+            Transport::Api(_) => {
+                return Err(TailscaleError::ApiError(format!(
+                    "{} is not available with the Tailscale API data source",
+                    path
+                )));
+            }
         };
 
-        self.handle_response(response).await
+        Ok(response)
     }
-    
-    fn build_request(&self, uri: impl Into<hyper::Uri>, token: Option<&str>) -> Result<hyper::Request<Full<Bytes>>, TailscaleError> {
+
+    fn build_request(
+        &self,
+        method: hyper::Method,
+        uri: impl Into<hyper::Uri>,
+        token: Option<&str>,
+    ) -> Result<hyper::Request<Full<Bytes>>, TailscaleError> {
         let mut request_builder = hyper::Request::builder()
-            .method(hyper::Method::GET)
+            .method(method)
             .uri(uri.into())
             .header("Host", "local-tailscaled.sock");
 
@@ -208,13 +434,25 @@ impl TailscaleClient {
 
         request_builder
             .body(Full::new(Bytes::new()))
-            .map_err(|e| TailscaleError::SocketConnection(format!("Failed to build request: {}", e)))
+            .map_err(|e| TailscaleError::HttpRequest(format!("Failed to build request: {}", e)))
     }
 
-    async fn handle_response(
+    async fn handle_response<T: DeserializeOwned>(
         &self,
         response: hyper::Response<hyper::body::Incoming>,
-    ) -> Result<Status, TailscaleError> {
+    ) -> Result<T, TailscaleError> {
+        let body_bytes = Self::read_body(response).await?;
+
+        let value: T = serde_json::from_slice(&body_bytes).map_err(|e| {
+            tracing::error!("Failed to parse Tailscale LocalAPI JSON: {}", e);
+            TailscaleError::JsonParse(e)
+        })?;
+        Ok(value)
+    }
+
+    async fn read_body(
+        response: hyper::Response<hyper::body::Incoming>,
+    ) -> Result<Bytes, TailscaleError> {
         let status_code = response.status();
         if !status_code.is_success() {
             return Err(TailscaleError::ApiError(format!(
@@ -224,20 +462,14 @@ impl TailscaleClient {
             )));
         }
 
-        let body_bytes = response
+        Ok(response
             .into_body()
             .collect()
             .await
             .map_err(|e| {
                 TailscaleError::SocketConnection(format!("Failed to read response body: {}", e))
             })?
-            .to_bytes();
-
-        let status: Status = serde_json::from_slice(&body_bytes).map_err(|e| {
-            tracing::error!("Failed to parse Tailscale status JSON: {}", e);
-            TailscaleError::JsonParse(e)
-        })?;
-        Ok(status)
+            .to_bytes())
     }
 
     pub async fn test_connection(&self) -> Result<(), TailscaleError> {
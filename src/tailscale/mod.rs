@@ -1,6 +1,8 @@
 // Based on Tailscale 1.87.0
+#[cfg(feature = "api")]
+pub mod api;
 pub mod client;
 pub mod types;
 
-pub use client::TailscaleClient;
+pub use client::{RetryPolicy, TailscaleClient};
 pub use types::*;
@@ -190,6 +190,15 @@ pub struct PeerStatus {
 
     #[serde(rename = "Location")]
     pub location: Option<Location>,
+
+    // Not part of the LocalAPI status: filled in by the admin API data source
+    #[serde(
+        rename = "PostureAttributes",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    #[schema(value_type = Object)]
+    pub posture_attributes: Option<HashMap<String, serde_json::Value>>,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize, ToSchema)]
@@ -231,6 +240,111 @@ pub struct UserProfile {
     pub profile_pic_url: Option<String>,
 }
 
+#[derive(Debug, Clone, Deserialize, Serialize, ToSchema)]
+pub struct WhoIsResponse {
+    #[serde(rename = "Node")]
+    pub node: Node,
+
+    #[serde(rename = "UserProfile")]
+    pub user_profile: UserProfile,
+
+    #[serde(rename = "CapMap", skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Object)]
+    pub cap_map: Option<NodeCapMap>,
+}
+
+// Subset of tailcfg.Node returned by the whois LocalAPI
+#[derive(Debug, Clone, Deserialize, Serialize, ToSchema)]
+pub struct Node {
+    #[serde(rename = "ID")]
+    pub id: i64,
+
+    #[serde(rename = "StableID")]
+    pub stable_id: StableNodeID,
+
+    #[serde(rename = "Name")]
+    pub name: String,
+
+    #[serde(rename = "User")]
+    pub user: UserID,
+
+    #[serde(rename = "Addresses")]
+    pub addresses: Option<Vec<String>>,
+
+    #[serde(rename = "Tags", skip_serializing_if = "Option::is_none")]
+    pub tags: Option<Vec<String>>,
+
+    #[serde(rename = "ComputedName", skip_serializing_if = "Option::is_none")]
+    pub computed_name: Option<String>,
+
+    // Peer status doesn't carry Hostinfo, so whois is the only place to read it from
+    #[serde(rename = "Hostinfo", skip_serializing_if = "Option::is_none")]
+    pub hostinfo: Option<Hostinfo>,
+}
+
+// Subset of tailcfg.Hostinfo
+#[derive(Debug, Clone, Default, Deserialize, Serialize, ToSchema)]
+pub struct Hostinfo {
+    #[serde(rename = "Services", skip_serializing_if = "Option::is_none")]
+    pub services: Option<Vec<HostinfoService>>,
+}
+
+// tailcfg.Service - a listening socket the node advertises (requires services
+// collection to be enabled for the tailnet)
+#[derive(Debug, Clone, Deserialize, Serialize, ToSchema)]
+pub struct HostinfoService {
+    #[serde(rename = "Proto")]
+    pub proto: String,
+
+    #[serde(rename = "Port")]
+    pub port: u16,
+
+    #[serde(rename = "Description", default)]
+    pub description: String,
+}
+
+// Subset of ipn.ServeConfig returned by the serve-config LocalAPI
+#[derive(Debug, Clone, Default, Deserialize, Serialize, ToSchema)]
+pub struct ServeConfig {
+    #[serde(rename = "TCP", skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Object)]
+    pub tcp: Option<HashMap<u16, TcpPortHandler>>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize, ToSchema)]
+pub struct TcpPortHandler {
+    #[serde(rename = "HTTPS", default)]
+    pub https: bool,
+
+    #[serde(rename = "HTTP", default)]
+    pub http: bool,
+
+    #[serde(rename = "TCPForward", skip_serializing_if = "Option::is_none")]
+    pub tcp_forward: Option<String>,
+}
+
+// Subset of ipnstate.PingResult returned by the ping LocalAPI
+#[derive(Debug, Clone, Deserialize, Serialize, ToSchema)]
+pub struct PingResult {
+    #[serde(rename = "IP")]
+    pub ip: String,
+
+    #[serde(rename = "NodeName", default)]
+    pub node_name: String,
+
+    #[serde(rename = "Err", default)]
+    pub err: String,
+
+    #[serde(rename = "LatencySeconds", default)]
+    pub latency_seconds: f64,
+
+    #[serde(rename = "Endpoint", default)]
+    pub endpoint: String,
+
+    #[serde(rename = "DERPRegionID", default)]
+    pub derp_region_id: i32,
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize, ToSchema)]
 pub struct ClientVersion {
     #[serde(rename = "RunningLatest", skip_serializing_if = "Option::is_none")]
@@ -0,0 +1,46 @@
+use crate::tailscale::{NodeCapability, PeerStatus};
+use serde::Deserialize;
+use tracing::warn;
+
+/// Capacity a node advertises about itself through a capmap entry, e.g. a nodeAttrs
+/// grant `"app": {"example.com/cap/traefik": [{"maxConcurrent": 50, "weight": 3}]}`
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CapacityHint {
+    /// Service the hint applies to; hints without a service apply to every service
+    pub service: Option<String>,
+    /// Maximum simultaneous in-flight requests, mapped to an inFlightReq middleware
+    pub max_concurrent: Option<u64>,
+    /// Relative server weight
+    pub weight: Option<u32>,
+}
+
+/// Find the capacity hint of a peer's service. A hint naming the service takes
+/// precedence over a hint for all services.
+pub fn capacity_hint(peer: &PeerStatus, capability: &str, service: &str) -> Option<CapacityHint> {
+    let key = NodeCapability(capability.to_string());
+    let values = peer.cap_map.as_ref()?.get(&key)?.as_ref()?;
+
+    let hints: Vec<CapacityHint> = values
+        .iter()
+        .filter_map(
+            |value| match serde_json::from_value::<CapacityHint>(value.clone()) {
+                Ok(hint) => Some(hint),
+                Err(e) => {
+                    warn!(
+                        "Peer {} has an invalid {} capacity hint: {}",
+                        peer.hostname, capability, e
+                    );
+                    None
+                }
+            },
+        )
+        .collect();
+
+    let for_service = hints
+        .iter()
+        .find(|hint| hint.service.as_deref() == Some(service));
+    for_service
+        .or_else(|| hints.iter().find(|hint| hint.service.is_none()))
+        .cloned()
+}
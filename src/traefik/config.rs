@@ -1,5 +1,5 @@
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 use utoipa::ToSchema;
 
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
@@ -7,30 +7,233 @@ pub struct DynamicConfig {
     pub http: Option<HttpConfig>,
     pub tcp: Option<TcpConfig>,
     pub udp: Option<UdpConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls: Option<TlsSection>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+/// Top-level TLS section: the option sets of TLS_OPTIONS_FILE, and whatever else merged
+/// configurations bring (certificates, stores), passed through verbatim
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct TlsSection {
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub options: BTreeMap<String, TlsOptions>,
+    #[serde(flatten)]
+    #[schema(value_type = Object)]
+    pub other: BTreeMap<String, serde_json::Value>,
+}
+
+/// Named TLS option set routers reference with `tls.options`
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct TlsOptions {
+    /// e.g. "VersionTLS13"
+    #[serde(rename = "minVersion", skip_serializing_if = "Option::is_none")]
+    pub min_version: Option<String>,
+    #[serde(rename = "maxVersion", skip_serializing_if = "Option::is_none")]
+    pub max_version: Option<String>,
+    #[serde(rename = "cipherSuites", skip_serializing_if = "Option::is_none")]
+    pub cipher_suites: Option<Vec<String>>,
+    #[serde(rename = "clientAuth", skip_serializing_if = "Option::is_none")]
+    pub client_auth: Option<TlsClientAuth>,
+    // Options this crate doesn't model (curvePreferences, sniStrict etc.) are passed
+    // through verbatim
+    #[serde(flatten)]
+    #[schema(value_type = Object)]
+    pub other: BTreeMap<String, serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct TlsClientAuth {
+    /// CA certificates client certificates are verified against
+    #[serde(rename = "caFiles", skip_serializing_if = "Option::is_none")]
+    pub ca_files: Option<Vec<String>>,
+    /// e.g. "RequireAndVerifyClientCert"
+    #[serde(rename = "clientAuthType", skip_serializing_if = "Option::is_none")]
+    pub client_auth_type: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
 pub struct HttpConfig {
-    pub routers: HashMap<String, Router>,
-    pub services: HashMap<String, Service>,
-    #[serde(skip_serializing_if = "HashMap::is_empty")]
-    pub middlewares: HashMap<String, Middleware>,
+    #[serde(default)]
+    pub routers: BTreeMap<String, Router>,
+    #[serde(default)]
+    pub services: BTreeMap<String, Service>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub middlewares: BTreeMap<String, Middleware>,
+    #[serde(
+        rename = "serversTransports",
+        default,
+        skip_serializing_if = "BTreeMap::is_empty"
+    )]
+    pub servers_transports: BTreeMap<String, ServersTransport>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
 pub struct TcpConfig {
-    pub routers: HashMap<String, TcpRouter>,
-    pub services: HashMap<String, TcpService>,
+    #[serde(default)]
+    pub routers: BTreeMap<String, TcpRouter>,
+    #[serde(default)]
+    pub services: BTreeMap<String, TcpService>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
 pub struct UdpConfig {
-    pub routers: HashMap<String, UdpRouter>,
-    pub services: HashMap<String, UdpService>,
+    #[serde(default)]
+    pub routers: BTreeMap<String, UdpRouter>,
+    #[serde(default)]
+    pub services: BTreeMap<String, UdpService>,
+}
+
+impl DynamicConfig {
+    /// Deep-merge another configuration into this one. Entries from `other` replace
+    /// entries with the same name; the replaced names are returned (prefixed with their
+    /// section) so callers can report collisions.
+    pub fn merge(&mut self, other: DynamicConfig) -> Vec<String> {
+        let mut collisions = Vec::new();
+
+        if let Some(other_http) = other.http {
+            let http = self.http.get_or_insert_with(Default::default);
+            merge_map(
+                &mut http.routers,
+                other_http.routers,
+                "http.routers",
+                &mut collisions,
+            );
+            merge_map(
+                &mut http.services,
+                other_http.services,
+                "http.services",
+                &mut collisions,
+            );
+            merge_map(
+                &mut http.middlewares,
+                other_http.middlewares,
+                "http.middlewares",
+                &mut collisions,
+            );
+            merge_map(
+                &mut http.servers_transports,
+                other_http.servers_transports,
+                "http.serversTransports",
+                &mut collisions,
+            );
+        }
+
+        if let Some(other_tcp) = other.tcp {
+            let tcp = self.tcp.get_or_insert_with(Default::default);
+            merge_map(
+                &mut tcp.routers,
+                other_tcp.routers,
+                "tcp.routers",
+                &mut collisions,
+            );
+            merge_map(
+                &mut tcp.services,
+                other_tcp.services,
+                "tcp.services",
+                &mut collisions,
+            );
+        }
+
+        if let Some(other_udp) = other.udp {
+            let udp = self.udp.get_or_insert_with(Default::default);
+            merge_map(
+                &mut udp.routers,
+                other_udp.routers,
+                "udp.routers",
+                &mut collisions,
+            );
+            merge_map(
+                &mut udp.services,
+                other_udp.services,
+                "udp.services",
+                &mut collisions,
+            );
+        }
+
+        if let Some(other_tls) = other.tls {
+            let tls = self.tls.get_or_insert_with(Default::default);
+            merge_map(
+                &mut tls.options,
+                other_tls.options,
+                "tls.options",
+                &mut collisions,
+            );
+            for (key, value) in other_tls.other {
+                match tls.other.get_mut(&key) {
+                    Some(existing) => merge_json(existing, value),
+                    None => {
+                        tls.other.insert(key, value);
+                    }
+                }
+            }
+        }
+
+        collisions
+    }
+
+    /// Order the servers of every service by URL or address, so the output doesn't
+    /// depend on the order peers reported their addresses in
+    pub fn sort_servers(&mut self) {
+        if let Some(http) = &mut self.http {
+            for service in http.services.values_mut() {
+                service
+                    .load_balancer
+                    .servers
+                    .sort_by(|a, b| a.url.cmp(&b.url));
+            }
+        }
+        if let Some(tcp) = &mut self.tcp {
+            for service in tcp.services.values_mut() {
+                service
+                    .load_balancer
+                    .servers
+                    .sort_by(|a, b| a.address.cmp(&b.address));
+            }
+        }
+        if let Some(udp) = &mut self.udp {
+            for service in udp.services.values_mut() {
+                service
+                    .load_balancer
+                    .servers
+                    .sort_by(|a, b| a.address.cmp(&b.address));
+            }
+        }
+    }
+}
+
+fn merge_map<T>(
+    target: &mut BTreeMap<String, T>,
+    source: BTreeMap<String, T>,
+    section: &str,
+    collisions: &mut Vec<String>,
+) {
+    for (name, value) in source {
+        if target.insert(name.clone(), value).is_some() {
+            collisions.push(format!("{}.{}", section, name));
+        }
+    }
+}
+
+fn merge_json(target: &mut serde_json::Value, source: serde_json::Value) {
+    match (target, source) {
+        (serde_json::Value::Object(target), serde_json::Value::Object(source)) => {
+            for (key, value) in source {
+                match target.get_mut(&key) {
+                    Some(existing) => merge_json(existing, value),
+                    None => {
+                        target.insert(key, value);
+                    }
+                }
+            }
+        }
+        (target, source) => *target = source,
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct Router {
+    #[serde(rename = "entryPoints", skip_serializing_if = "Option::is_none")]
+    pub entry_points: Option<Vec<String>>,
     pub rule: String,
     pub service: String,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -50,8 +253,16 @@ pub struct Service {
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct LoadBalancer {
     pub servers: Vec<Server>,
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "healthCheck", skip_serializing_if = "Option::is_none")]
     pub health_check: Option<HealthCheck>,
+    #[serde(rename = "serversTransport", skip_serializing_if = "Option::is_none")]
+    pub servers_transport: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct ServersTransport {
+    #[serde(rename = "insecureSkipVerify", skip_serializing_if = "Option::is_none")]
+    pub insecure_skip_verify: Option<bool>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
@@ -61,30 +272,64 @@ pub struct Server {
     pub weight: Option<i32>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
 pub struct HealthCheck {
     pub path: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub interval: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub timeout: Option<String>,
+    /// Checked instead of the server's port
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub port: Option<u16>,
+    /// Checked with instead of the server's scheme
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scheme: Option<String>,
+    /// Host header of the check requests
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hostname: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub headers: Option<BTreeMap<String, String>>,
+    #[serde(rename = "followRedirects", skip_serializing_if = "Option::is_none")]
+    pub follow_redirects: Option<bool>,
+    /// HTTP method of the check requests (Traefik's default is GET)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub method: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
 pub struct Middleware {
     // Common middlewares - can be extended as needed
     #[serde(skip_serializing_if = "Option::is_none")]
     pub headers: Option<HeadersMiddleware>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub retry: Option<RetryMiddleware>,
+    #[serde(rename = "rateLimit", skip_serializing_if = "Option::is_none")]
+    pub rate_limit: Option<RateLimitMiddleware>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compress: Option<CompressMiddleware>,
+    #[serde(rename = "stripPrefix", skip_serializing_if = "Option::is_none")]
+    pub strip_prefix: Option<StripPrefixMiddleware>,
+    #[serde(rename = "inFlightReq", skip_serializing_if = "Option::is_none")]
+    pub in_flight_req: Option<InFlightReqMiddleware>,
+    // Middlewares this crate doesn't model (plugins etc.) are passed through verbatim
+    #[serde(flatten)]
+    #[schema(value_type = Object)]
+    pub other: BTreeMap<String, serde_json::Value>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct HeadersMiddleware {
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub custom_request_headers: Option<HashMap<String, String>>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub custom_response_headers: Option<HashMap<String, String>>,
+    #[serde(
+        rename = "customRequestHeaders",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub custom_request_headers: Option<BTreeMap<String, String>>,
+    #[serde(
+        rename = "customResponseHeaders",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub custom_response_headers: Option<BTreeMap<String, String>>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
@@ -92,15 +337,42 @@ pub struct RetryMiddleware {
     pub attempts: i32,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema, PartialEq, Eq)]
+pub struct RateLimitMiddleware {
+    pub average: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub burst: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub period: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct CompressMiddleware {}
+
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct StripPrefixMiddleware {
+    pub prefixes: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct InFlightReqMiddleware {
+    pub amount: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
 pub struct TlsConfig {
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "certResolver", skip_serializing_if = "Option::is_none")]
     pub cert_resolver: Option<String>,
+    /// Name of a TLS option set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub options: Option<String>,
 }
 
 // TCP Router and Service types
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct TcpRouter {
+    #[serde(rename = "entryPoints", skip_serializing_if = "Option::is_none")]
+    pub entry_points: Option<Vec<String>>,
     pub rule: String,
     pub service: String,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -134,6 +406,8 @@ pub struct TcpTlsConfig {
 // UDP Router and Service types
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct UdpRouter {
+    #[serde(rename = "entryPoints", skip_serializing_if = "Option::is_none")]
+    pub entry_points: Option<Vec<String>>,
     pub service: String,
 }
 
@@ -154,3 +428,274 @@ pub struct UdpServer {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub weight: Option<i32>,
 }
+
+#[cfg(test)]
+mod tests {
+    //! Serialization checks against the field names of the Traefik v3 dynamic
+    //! configuration reference (https://doc.traefik.io/traefik/reference/dynamic-configuration/file/)
+    use super::*;
+    use serde_json::json;
+
+    fn to_json<T: Serialize>(value: &T) -> serde_json::Value {
+        serde_json::to_value(value).expect("serializable")
+    }
+
+    #[test]
+    fn router() {
+        let router = Router {
+            entry_points: Some(vec!["websecure".to_string()]),
+            rule: "Host(`nas.example.com`)".to_string(),
+            service: "nas".to_string(),
+            middlewares: Some(vec!["auth".to_string()]),
+            priority: Some(10),
+            tls: Some(TlsConfig {
+                cert_resolver: Some("letsencrypt".to_string()),
+                options: None,
+            }),
+        };
+
+        assert_eq!(
+            to_json(&router),
+            json!({
+                "entryPoints": ["websecure"],
+                "rule": "Host(`nas.example.com`)",
+                "service": "nas",
+                "middlewares": ["auth"],
+                "priority": 10,
+                "tls": { "certResolver": "letsencrypt" }
+            })
+        );
+    }
+
+    #[test]
+    fn router_omits_unset_fields() {
+        let router = Router {
+            entry_points: None,
+            rule: "HostRegexp(`.*`)".to_string(),
+            service: "nas".to_string(),
+            middlewares: None,
+            priority: None,
+            tls: None,
+        };
+
+        assert_eq!(
+            to_json(&router),
+            json!({ "rule": "HostRegexp(`.*`)", "service": "nas" })
+        );
+    }
+
+    #[test]
+    fn service() {
+        let service = Service {
+            load_balancer: LoadBalancer {
+                servers: vec![Server {
+                    url: "http://100.64.0.1:3000".to_string(),
+                    weight: Some(1),
+                }],
+                health_check: Some(HealthCheck {
+                    path: "/health".to_string(),
+                    interval: Some("30s".to_string()),
+                    timeout: Some("5s".to_string()),
+                    ..Default::default()
+                }),
+                servers_transport: Some("insecure".to_string()),
+            },
+        };
+
+        assert_eq!(
+            to_json(&service),
+            json!({
+                "loadBalancer": {
+                    "servers": [{ "url": "http://100.64.0.1:3000", "weight": 1 }],
+                    "healthCheck": { "path": "/health", "interval": "30s", "timeout": "5s" },
+                    "serversTransport": "insecure"
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn servers_transport() {
+        let transport = ServersTransport {
+            insecure_skip_verify: Some(true),
+        };
+
+        assert_eq!(to_json(&transport), json!({ "insecureSkipVerify": true }));
+    }
+
+    #[test]
+    fn middlewares() {
+        let mut other = BTreeMap::new();
+        other.insert(
+            "basicAuth".to_string(),
+            json!({ "usersFile": "/etc/users" }),
+        );
+
+        let middleware = Middleware {
+            headers: Some(HeadersMiddleware {
+                custom_request_headers: Some(BTreeMap::from([(
+                    "X-Forwarded-Proto".to_string(),
+                    "https".to_string(),
+                )])),
+                custom_response_headers: Some(BTreeMap::from([(
+                    "X-Frame-Options".to_string(),
+                    "DENY".to_string(),
+                )])),
+            }),
+            retry: Some(RetryMiddleware { attempts: 3 }),
+            rate_limit: Some(RateLimitMiddleware {
+                average: 100,
+                burst: Some(50),
+                period: Some("1s".to_string()),
+            }),
+            compress: Some(CompressMiddleware {}),
+            strip_prefix: Some(StripPrefixMiddleware {
+                prefixes: vec!["/api".to_string()],
+            }),
+            in_flight_req: Some(InFlightReqMiddleware { amount: 50 }),
+            other,
+        };
+
+        assert_eq!(
+            to_json(&middleware),
+            json!({
+                "headers": {
+                    "customRequestHeaders": { "X-Forwarded-Proto": "https" },
+                    "customResponseHeaders": { "X-Frame-Options": "DENY" }
+                },
+                "retry": { "attempts": 3 },
+                "rateLimit": { "average": 100, "burst": 50, "period": "1s" },
+                "compress": {},
+                "stripPrefix": { "prefixes": ["/api"] },
+                "inFlightReq": { "amount": 50 },
+                "basicAuth": { "usersFile": "/etc/users" }
+            })
+        );
+    }
+
+    #[test]
+    fn middleware_round_trips_camel_case() {
+        let input = json!({
+            "headers": { "customRequestHeaders": { "X-Test": "1" } },
+            "rateLimit": { "average": 10 }
+        });
+
+        let middleware: Middleware = serde_json::from_value(input.clone()).unwrap();
+        assert!(middleware.other.is_empty());
+        assert_eq!(to_json(&middleware), input);
+    }
+
+    #[test]
+    fn tcp_router_and_service() {
+        let router = TcpRouter {
+            entry_points: Some(vec!["postgres".to_string()]),
+            rule: "HostSNI(`*`)".to_string(),
+            service: "db".to_string(),
+            tls: Some(TcpTlsConfig {
+                passthrough: Some(true),
+            }),
+        };
+        let service = TcpService {
+            load_balancer: TcpLoadBalancer {
+                servers: vec![TcpServer {
+                    address: "100.64.0.2:5432".to_string(),
+                    weight: Some(1),
+                }],
+            },
+        };
+
+        assert_eq!(
+            to_json(&router),
+            json!({
+                "entryPoints": ["postgres"],
+                "rule": "HostSNI(`*`)",
+                "service": "db",
+                "tls": { "passthrough": true }
+            })
+        );
+        assert_eq!(
+            to_json(&service),
+            json!({
+                "loadBalancer": { "servers": [{ "address": "100.64.0.2:5432", "weight": 1 }] }
+            })
+        );
+    }
+
+    #[test]
+    fn udp_router_and_service() {
+        let router = UdpRouter {
+            entry_points: Some(vec!["dns".to_string()]),
+            service: "dns".to_string(),
+        };
+        let service = UdpService {
+            load_balancer: UdpLoadBalancer {
+                servers: vec![UdpServer {
+                    address: "100.64.0.3:53".to_string(),
+                    weight: None,
+                }],
+            },
+        };
+
+        assert_eq!(
+            to_json(&router),
+            json!({ "entryPoints": ["dns"], "service": "dns" })
+        );
+        assert_eq!(
+            to_json(&service),
+            json!({ "loadBalancer": { "servers": [{ "address": "100.64.0.3:53" }] } })
+        );
+    }
+
+    #[test]
+    fn dynamic_config_sections() {
+        let config = DynamicConfig {
+            http: Some(HttpConfig::default()),
+            tcp: Some(TcpConfig::default()),
+            udp: None,
+            tls: None,
+        };
+
+        assert_eq!(
+            to_json(&config),
+            json!({
+                "http": { "routers": {}, "services": {} },
+                "tcp": { "routers": {}, "services": {} },
+                "udp": null
+            })
+        );
+    }
+
+    #[test]
+    fn tls_section() {
+        let options = TlsOptions {
+            min_version: Some("VersionTLS13".to_string()),
+            client_auth: Some(TlsClientAuth {
+                ca_files: Some(vec!["/certs/ca.pem".to_string()]),
+                client_auth_type: Some("RequireAndVerifyClientCert".to_string()),
+            }),
+            other: BTreeMap::from([("sniStrict".to_string(), json!(true))]),
+            ..Default::default()
+        };
+        let tls = TlsSection {
+            options: BTreeMap::from([("mtls".to_string(), options)]),
+            other: BTreeMap::from([("stores".to_string(), json!({ "default": {} }))]),
+        };
+
+        assert_eq!(
+            to_json(&tls),
+            json!({
+                "options": {
+                    "mtls": {
+                        "minVersion": "VersionTLS13",
+                        "clientAuth": {
+                            "caFiles": ["/certs/ca.pem"],
+                            "clientAuthType": "RequireAndVerifyClientCert"
+                        },
+                        "sniStrict": true
+                    }
+                },
+                "stores": { "default": {} }
+            })
+        );
+    }
+}
@@ -0,0 +1,103 @@
+use crate::config::Protocol;
+use crate::traefik::DynamicConfig;
+use std::collections::{BTreeSet, HashMap};
+
+/// Peer a generated service belongs to
+#[derive(Debug, Clone)]
+pub struct ServiceOwner {
+    pub hostname: String,
+    pub tags: Vec<String>,
+}
+
+/// Part of the configuration requested with GET /config?protocol=&tag=&hostname=
+#[derive(Debug, Clone, Default)]
+pub struct ConfigFilter {
+    pub protocol: Option<Protocol>,
+    /// Text contained in one of the owning peer's tags, matched like INCLUDE_TAGS
+    pub tag: Option<String>,
+    /// Hostname of the owning peer (case-insensitive)
+    pub hostname: Option<String>,
+}
+
+impl ConfigFilter {
+    fn selects_peers(&self) -> bool {
+        self.tag.is_some() || self.hostname.is_some()
+    }
+
+    fn matches(&self, owner: &ServiceOwner) -> bool {
+        let tag = self
+            .tag
+            .as_deref()
+            .map(|tag| tag.strip_prefix("tag:").unwrap_or(tag));
+        self.hostname
+            .as_deref()
+            .is_none_or(|hostname| owner.hostname.eq_ignore_ascii_case(hostname))
+            && tag.is_none_or(|tag| {
+                owner.tags.iter().any(|owner_tag| {
+                    owner_tag
+                        .strip_prefix("tag:")
+                        .unwrap_or(owner_tag)
+                        .contains(tag)
+                })
+            })
+    }
+}
+
+/// Keep the sections of the requested protocol and, when a tag or hostname is given,
+/// the services of matching peers with the routers, middlewares and transports they use.
+/// Middlewares in `keep` (e.g. the version marker) survive the peer filters.
+pub fn filter_config(
+    config: &DynamicConfig,
+    filter: &ConfigFilter,
+    owners: &HashMap<String, ServiceOwner>,
+    keep: &[String],
+) -> DynamicConfig {
+    let mut config = config.clone();
+    match filter.protocol {
+        Some(Protocol::Http) => (config.tcp, config.udp) = (None, None),
+        Some(Protocol::Tcp) => (config.http, config.udp) = (None, None),
+        Some(Protocol::Udp) => (config.http, config.tcp) = (None, None),
+        None => {}
+    }
+    if !filter.selects_peers() {
+        return config;
+    }
+
+    let selected = |name: &String| owners.get(name).is_some_and(|owner| filter.matches(owner));
+
+    if let Some(http) = &mut config.http {
+        http.services.retain(|name, _| selected(name));
+        http.routers
+            .retain(|_, router| http.services.contains_key(&router.service));
+
+        let middlewares: BTreeSet<&String> = http
+            .routers
+            .values()
+            .flat_map(|router| router.middlewares.iter().flatten())
+            .collect();
+        http.middlewares
+            .retain(|name, _| middlewares.contains(name) || keep.contains(name));
+
+        let transports: BTreeSet<&String> = http
+            .services
+            .values()
+            .filter_map(|service| service.load_balancer.servers_transport.as_ref())
+            .collect();
+        http.servers_transports
+            .retain(|name, _| transports.contains(name));
+    }
+
+    if let Some(tcp) = &mut config.tcp {
+        tcp.services.retain(|name, _| selected(name));
+        tcp.routers
+            .retain(|_, router| tcp.services.contains_key(&router.service));
+    }
+
+    if let Some(udp) = &mut config.udp {
+        udp.services.retain(|name, _| selected(name));
+        udp.routers
+            .retain(|_, router| udp.services.contains_key(&router.service));
+    }
+
+    config
+}
@@ -0,0 +1,86 @@
+use crate::config::{Protocol, ProviderConfig, ServiceInfo};
+use crate::tailscale::{NodeCapability, PeerStatus};
+use serde::Deserialize;
+use tracing::warn;
+
+/// Service a node is granted through a capmap entry, e.g. a nodeAttrs grant
+/// `"app": {"example.com/cap/traefik-services": [{"name": "web", "port": 3000}]}`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServiceGrant {
+    pub name: String,
+    pub port: u16,
+    /// "http", "https", "tcp", "tls" or "udp" (default: DEFAULT_PROTOCOL)
+    pub protocol: Option<String>,
+    /// Backend scheme of HTTP services (default: derived from the port)
+    pub scheme: Option<String>,
+    /// Router rule replacing the generated one
+    pub rule: Option<String>,
+    /// Middlewares referenced by the router, defined in MIDDLEWARES_FILE
+    #[serde(default)]
+    pub middlewares: Vec<String>,
+}
+
+impl ServiceGrant {
+    pub fn service_info(&self, config: &ProviderConfig) -> ServiceInfo {
+        let protocol = self
+            .protocol
+            .as_deref()
+            .map(Protocol::parse)
+            .unwrap_or_else(|| config.default_protocol.clone());
+        let scheme = match (self.scheme.as_deref(), self.protocol.as_deref()) {
+            (Some(scheme), _) => scheme.to_string(),
+            (None, Some(protocol @ ("https" | "tls"))) => protocol.to_string(),
+            (None, _) => config.scheme_for_port(self.port),
+        };
+
+        ServiceInfo {
+            name: self.name.clone(),
+            port: Some(self.port),
+            protocol,
+            scheme,
+            path_prefix: None,
+        }
+    }
+}
+
+/// All services granted to a peer through the capability
+pub fn service_grants(peer: &PeerStatus, capability: &str) -> Vec<ServiceGrant> {
+    let key = NodeCapability(capability.to_string());
+    let Some(values) = peer
+        .cap_map
+        .as_ref()
+        .and_then(|cap_map| cap_map.get(&key))
+        .and_then(|values| values.as_ref())
+    else {
+        return Vec::new();
+    };
+
+    values
+        .iter()
+        .filter_map(
+            |value| match serde_json::from_value::<ServiceGrant>(value.clone()) {
+                Ok(grant) => Some(grant),
+                Err(e) => {
+                    warn!(
+                        "Peer {} has an invalid {} service grant: {}",
+                        peer.hostname, capability, e
+                    );
+                    None
+                }
+            },
+        )
+        .collect()
+}
+
+/// The grant of a single service, without reporting invalid entries again
+pub fn service_grant(peer: &PeerStatus, capability: &str, service: &str) -> Option<ServiceGrant> {
+    let key = NodeCapability(capability.to_string());
+    peer.cap_map
+        .as_ref()?
+        .get(&key)?
+        .as_ref()?
+        .iter()
+        .filter_map(|value| serde_json::from_value::<ServiceGrant>(value.clone()).ok())
+        .find(|grant| grant.name == service)
+}
@@ -0,0 +1,164 @@
+use crate::config::Secret;
+use crate::store::version_marker;
+use crate::traefik::DynamicConfig;
+use http_body_util::{BodyExt, Full};
+use hyper::body::Bytes;
+use hyper::{Request, Response, header};
+use hyper_util::client::legacy::{Client, connect::HttpConnector};
+use hyper_util::rt::TokioExecutor;
+use serde::Deserialize;
+use std::sync::Mutex;
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+/// How long another replica gets to answer
+const MEMBER_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Header carrying the version of the configuration served at /config
+const CONFIG_VERSION_HEADER: &str = "X-Config-Version";
+
+/// Picks one replica of CLUSTER_MEMBERS to generate the configuration, which the others
+/// mirror. The leader is the reachable replica with the lowest REPLICA_ID among those
+/// that published a configuration, so every replica comes to the same conclusion
+/// without coordination, and the next one takes over when the leader goes away.
+pub struct LeaderElection {
+    replica_id: String,
+    members: Vec<String>,
+    /// Sent to the other replicas, which share API_TOKEN
+    api_token: Option<Secret>,
+    client: Client<HttpConnector, Full<Bytes>>,
+    /// Replica followed after the last election, None while leading
+    following: Mutex<Option<String>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Member {
+    replica_id: String,
+}
+
+impl LeaderElection {
+    pub fn new(replica_id: String, members: Vec<String>, api_token: Option<Secret>) -> Self {
+        Self {
+            replica_id,
+            members,
+            api_token,
+            client: Client::builder(TokioExecutor::new()).build(HttpConnector::new()),
+            following: Mutex::new(None),
+        }
+    }
+
+    /// REPLICA_ID and URL of the leader when that is another replica, None while this
+    /// replica leads
+    pub async fn leader(&self) -> Option<(String, String)> {
+        let mut leader: Option<(String, &str)> = None;
+        for url in &self.members {
+            match self.member(url).await {
+                Ok(member) => {
+                    if leader
+                        .as_ref()
+                        .is_none_or(|(id, _)| member.replica_id < *id)
+                    {
+                        leader = Some((member.replica_id, url));
+                    }
+                }
+                // Also the answer of replicas that published nothing yet (503)
+                Err(e) => debug!("Cluster member {} can't lead: {}", url, e),
+            }
+        }
+
+        leader
+            .filter(|(id, _)| *id < self.replica_id)
+            .map(|(id, url)| (id, url.to_string()))
+    }
+
+    /// The configuration of the leader when that is another replica. None while this
+    /// replica leads, and when the leader's configuration can't be fetched, so that the
+    /// replica generates its own instead of serving nothing.
+    pub async fn follow(&self) -> Option<DynamicConfig> {
+        let Some((leader_id, url)) = self.leader().await else {
+            if self.following.lock().unwrap().take().is_some() {
+                info!("Leading the cluster, generating the configuration");
+            }
+            return None;
+        };
+
+        match self.leader_config(&url).await {
+            Ok(config) => {
+                let mut following = self.following.lock().unwrap();
+                if following.as_deref() != Some(leader_id.as_str()) {
+                    info!(
+                        "Following the configuration of leader {} ({})",
+                        leader_id, url
+                    );
+                    *following = Some(leader_id);
+                }
+                Some(config)
+            }
+            Err(e) => {
+                warn!(
+                    "Failed to fetch the configuration of leader {}, generating it here: {}",
+                    leader_id, e
+                );
+                None
+            }
+        }
+    }
+
+    async fn member(&self, url: &str) -> Result<Member, String> {
+        let response = self.get(url, "/cluster/member").await?;
+        let body = body(response).await?;
+        serde_json::from_slice(&body).map_err(|e| e.to_string())
+    }
+
+    /// The leader's current configuration without the version it embedded, which this
+    /// replica's store adds again
+    async fn leader_config(&self, url: &str) -> Result<DynamicConfig, String> {
+        let response = self.get(url, "/config").await?;
+        let version = response
+            .headers()
+            .get(CONFIG_VERSION_HEADER)
+            .and_then(|version| version.to_str().ok())
+            .map(str::to_string);
+        let body = body(response).await?;
+        let mut config: DynamicConfig = serde_json::from_slice(&body).map_err(|e| e.to_string())?;
+
+        if let Some(version) = version
+            && let Some(marker) = version_marker(&config, &version)
+            && let Some(http) = &mut config.http
+        {
+            http.middlewares.remove(&marker);
+        }
+        Ok(config)
+    }
+
+    async fn get(&self, url: &str, path: &str) -> Result<Response<hyper::body::Incoming>, String> {
+        let uri: hyper::Uri = format!("{}{}", url.trim_end_matches('/'), path)
+            .parse()
+            .map_err(|e| format!("invalid URL: {}", e))?;
+        let mut request = Request::get(uri);
+        if let Some(token) = &self.api_token {
+            request = request.header(header::AUTHORIZATION, format!("Bearer {}", token.expose()));
+        }
+        let request = request
+            .body(Full::new(Bytes::new()))
+            .map_err(|e| e.to_string())?;
+
+        let response = tokio::time::timeout(MEMBER_TIMEOUT, self.client.request(request))
+            .await
+            .map_err(|_| "timed out".to_string())?
+            .map_err(|e| e.to_string())?;
+        if !response.status().is_success() {
+            return Err(format!("HTTP {}", response.status()));
+        }
+        Ok(response)
+    }
+}
+
+async fn body(response: Response<hyper::body::Incoming>) -> Result<Bytes, String> {
+    let body = response.into_body().collect();
+    tokio::time::timeout(MEMBER_TIMEOUT, body)
+        .await
+        .map_err(|_| "timed out".to_string())?
+        .map(|body| body.to_bytes())
+        .map_err(|e| e.to_string())
+}
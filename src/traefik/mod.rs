@@ -1,5 +1,16 @@
+pub mod capacity;
 pub mod config;
+pub mod filter;
+pub mod grants;
+pub mod leader;
+pub mod overrides;
 pub mod provider;
+#[cfg(feature = "scripting")]
+pub mod script;
+#[cfg(feature = "redis")]
+pub mod shared_cache;
+pub mod statics;
+pub mod upstream;
 
 pub use config::*;
 pub use provider::TraefikProvider;
@@ -0,0 +1,461 @@
+use crate::traefik::{DynamicConfig, HealthCheck};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use tracing::{info, warn};
+
+/// Partial patch applied to a generated service and the routers pointing at it
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ServiceOverride {
+    #[serde(default)]
+    pub router: RouterPatch,
+    #[serde(default)]
+    pub service: ServicePatch,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RouterPatch {
+    /// Replaces the generated rule
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rule: Option<String>,
+    /// Appended to the generated middlewares (HTTP only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub middlewares: Option<Vec<String>>,
+    /// Replaces the router priority (HTTP only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub priority: Option<i32>,
+    /// Name of the TLS option set of the router (HTTP only)
+    #[serde(alias = "tlsOptions", skip_serializing_if = "Option::is_none")]
+    pub tls_options: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ServicePatch {
+    /// Replaces the scheme of every server URL (HTTP only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scheme: Option<String>,
+    /// Fields replacing those of the generated health check (HTTP only)
+    #[serde(alias = "healthCheck", skip_serializing_if = "Option::is_none")]
+    pub health_check: Option<HealthCheckPatch>,
+}
+
+/// Health check fields to set, named like in Traefik. Without a generated health
+/// check (HEALTH_CHECK_PATH) a patch only adds one when it has a path.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "camelCase")]
+pub struct HealthCheckPatch {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub interval: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub port: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scheme: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hostname: Option<String>,
+    /// Merged into the generated headers
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub headers: Option<BTreeMap<String, String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub follow_redirects: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub method: Option<String>,
+}
+
+impl HealthCheckPatch {
+    /// Set one field from a "service--healthcheck-<field>-<value>" tag attribute
+    pub fn set(&mut self, field: &str, value: String) -> Result<(), String> {
+        match field {
+            "path" => self.path = Some(value),
+            "interval" => self.interval = Some(value),
+            "timeout" => self.timeout = Some(value),
+            "port" => {
+                self.port = Some(
+                    value
+                        .parse()
+                        .map_err(|_| format!("invalid port {}", value))?,
+                )
+            }
+            "scheme" => self.scheme = Some(value),
+            "hostname" => self.hostname = Some(value),
+            "followredirects" => self.follow_redirects = Some(value != "false"),
+            "method" => self.method = Some(value.to_uppercase()),
+            _ => return Err(format!("unknown health check field {}", field)),
+        }
+        Ok(())
+    }
+
+    pub fn apply(&self, health_check: &mut Option<HealthCheck>) {
+        let health_check = match (health_check.as_mut(), &self.path) {
+            (Some(health_check), _) => health_check,
+            (None, Some(path)) => health_check.insert(HealthCheck {
+                path: path.clone(),
+                ..Default::default()
+            }),
+            (None, None) => return,
+        };
+        if let Some(path) = &self.path {
+            health_check.path = path.clone();
+        }
+        if let Some(interval) = &self.interval {
+            health_check.interval = Some(interval.clone());
+        }
+        if let Some(timeout) = &self.timeout {
+            health_check.timeout = Some(timeout.clone());
+        }
+        if let Some(port) = self.port {
+            health_check.port = Some(port);
+        }
+        if let Some(scheme) = &self.scheme {
+            health_check.scheme = Some(scheme.clone());
+        }
+        if let Some(hostname) = &self.hostname {
+            health_check.hostname = Some(hostname.clone());
+        }
+        if let Some(headers) = &self.headers {
+            health_check
+                .headers
+                .get_or_insert_with(BTreeMap::new)
+                .extend(headers.clone());
+        }
+        if let Some(follow_redirects) = self.follow_redirects {
+            health_check.follow_redirects = Some(follow_redirects);
+        }
+        if let Some(method) = &self.method {
+            health_check.method = Some(method.clone());
+        }
+    }
+}
+
+/// Apply overrides keyed by generated service name
+pub fn apply_overrides(config: &mut DynamicConfig, overrides: &HashMap<String, ServiceOverride>) {
+    for (service_name, service_override) in overrides {
+        let mut applied = false;
+
+        if let Some(http) = &mut config.http {
+            if let Some(service) = http.services.get_mut(service_name) {
+                applied = true;
+                if let Some(scheme) = &service_override.service.scheme {
+                    for server in &mut service.load_balancer.servers {
+                        if let Some((_, rest)) = server.url.split_once("://") {
+                            server.url = format!("{}://{}", scheme, rest);
+                        }
+                    }
+                }
+                if let Some(patch) = &service_override.service.health_check {
+                    patch.apply(&mut service.load_balancer.health_check);
+                }
+            }
+
+            for router in http
+                .routers
+                .values_mut()
+                .filter(|router| &router.service == service_name)
+            {
+                applied = true;
+                let patch = &service_override.router;
+                if let Some(rule) = &patch.rule {
+                    router.rule = rule.clone();
+                }
+                if let Some(middlewares) = &patch.middlewares {
+                    router
+                        .middlewares
+                        .get_or_insert_with(Vec::new)
+                        .extend(middlewares.iter().cloned());
+                }
+                if let Some(priority) = patch.priority {
+                    router.priority = Some(priority);
+                }
+                if let Some(tls_options) = &patch.tls_options {
+                    router.tls.get_or_insert_with(Default::default).options =
+                        Some(tls_options.clone());
+                }
+            }
+        }
+
+        if let Some(tcp) = &mut config.tcp {
+            applied |= tcp.services.contains_key(service_name);
+            for router in tcp
+                .routers
+                .values_mut()
+                .filter(|router| &router.service == service_name)
+            {
+                applied = true;
+                if let Some(rule) = &service_override.router.rule {
+                    router.rule = rule.clone();
+                }
+            }
+        }
+
+        if let Some(udp) = &config.udp {
+            applied |= udp.services.contains_key(service_name);
+        }
+
+        if !applied {
+            warn!(
+                "Override for unknown service {} was not applied",
+                service_name
+            );
+        }
+    }
+}
+
+/// Rename generated services to their aliases and repoint the routers using them
+pub fn apply_aliases(config: &mut DynamicConfig, aliases: &HashMap<String, String>) {
+    // Sorted, so the first of two services aliased to the same name wins on every run
+    let aliases: BTreeMap<&String, &String> = aliases.iter().collect();
+    for (service_name, alias) in aliases {
+        let mut found = false;
+
+        if let Some(http) = &mut config.http
+            && http.services.contains_key(service_name)
+        {
+            found = true;
+            if rename_service(&mut http.services, service_name, alias) {
+                for router in http
+                    .routers
+                    .values_mut()
+                    .filter(|router| &router.service == service_name)
+                {
+                    router.service = alias.clone();
+                }
+            }
+        }
+
+        if let Some(tcp) = &mut config.tcp
+            && tcp.services.contains_key(service_name)
+        {
+            found = true;
+            if rename_service(&mut tcp.services, service_name, alias) {
+                for router in tcp
+                    .routers
+                    .values_mut()
+                    .filter(|router| &router.service == service_name)
+                {
+                    router.service = alias.clone();
+                }
+            }
+        }
+
+        if let Some(udp) = &mut config.udp
+            && udp.services.contains_key(service_name)
+        {
+            found = true;
+            if rename_service(&mut udp.services, service_name, alias) {
+                for router in udp
+                    .routers
+                    .values_mut()
+                    .filter(|router| &router.service == service_name)
+                {
+                    router.service = alias.clone();
+                }
+            }
+        }
+
+        if !found {
+            warn!("Alias for unknown service {} was not applied", service_name);
+        }
+    }
+}
+
+fn rename_service<T>(services: &mut BTreeMap<String, T>, service_name: &str, alias: &str) -> bool {
+    if services.contains_key(alias) {
+        warn!(
+            "Alias {} of service {} is already taken, keeping the generated name",
+            alias, service_name
+        );
+        return false;
+    }
+
+    if let Some(service) = services.remove(service_name) {
+        services.insert(alias.to_string(), service);
+    }
+    true
+}
+
+/// Drop services whose name matches one of the patterns, together with the routers
+/// pointing at them. Routers can also be dropped by their own name.
+pub fn exclude_services(config: &mut DynamicConfig, patterns: &[String]) {
+    let excluded = |name: &str| patterns.iter().any(|pattern| glob_match(pattern, name));
+    let mut removed = 0;
+
+    if let Some(http) = &mut config.http {
+        let before = http.services.len() + http.routers.len();
+        http.services.retain(|name, _| !excluded(name));
+        http.routers
+            .retain(|name, router| !excluded(name) && !excluded(&router.service));
+        removed += before - http.services.len() - http.routers.len();
+    }
+
+    if let Some(tcp) = &mut config.tcp {
+        let before = tcp.services.len() + tcp.routers.len();
+        tcp.services.retain(|name, _| !excluded(name));
+        tcp.routers
+            .retain(|name, router| !excluded(name) && !excluded(&router.service));
+        removed += before - tcp.services.len() - tcp.routers.len();
+    }
+
+    if let Some(udp) = &mut config.udp {
+        let before = udp.services.len() + udp.routers.len();
+        udp.services.retain(|name, _| !excluded(name));
+        udp.routers
+            .retain(|name, router| !excluded(name) && !excluded(&router.service));
+        removed += before - udp.services.len() - udp.routers.len();
+    }
+
+    if removed > 0 {
+        info!("Excluded {} routers and services", removed);
+    }
+}
+
+/// Match a name against a pattern where "*" stands for any run of characters
+/// and "?" for a single character
+fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // Position after the last "*" and the name position it was tried against
+    let mut backtrack = None;
+
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p + 1, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                Some((star_p, star_n)) => {
+                    p = star_p;
+                    n = star_n + 1;
+                    backtrack = Some((star_p, star_n + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// HTTP and TCP sections with one router per service, named "<service>-router"
+    fn config(http: &[&str], tcp: &[&str]) -> DynamicConfig {
+        let section = |services: &[&str]| {
+            let routers: serde_json::Map<_, _> = services
+                .iter()
+                .map(|name| {
+                    let router = json!({ "rule": format!("Host(`{}`)", name), "service": name });
+                    (format!("{}-router", name), router)
+                })
+                .collect();
+            let services: serde_json::Map<_, _> = services
+                .iter()
+                .map(|name| {
+                    let service = json!({ "loadBalancer": { "servers": [] } });
+                    (name.to_string(), service)
+                })
+                .collect();
+            json!({ "routers": routers, "services": services })
+        };
+        serde_json::from_value(json!({ "http": section(http), "tcp": section(tcp) })).unwrap()
+    }
+
+    fn names(config: &DynamicConfig) -> Vec<String> {
+        let http = config.http.as_ref().unwrap();
+        let tcp = config.tcp.as_ref().unwrap();
+        let http = http.routers.keys().chain(http.services.keys());
+        let tcp = tcp.routers.keys().chain(tcp.services.keys());
+        http.chain(tcp).cloned().collect()
+    }
+
+    #[test]
+    fn glob_patterns() {
+        assert!(glob_match("tailscale-*-web", "tailscale-nas-web"));
+        assert!(glob_match("tailscale-*-web", "tailscale--web"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("db-?", "db-1"));
+        assert!(!glob_match("db-?", "db-10"));
+        assert!(glob_match("*-a*b", "x-aab-ab"));
+        assert!(!glob_match("*-a*b", "x-aab-a"));
+        assert!(!glob_match("web", "web2"));
+        assert!(glob_match("wéb-*", "wéb-1"));
+    }
+
+    #[test]
+    fn excluding_drops_services_and_their_routers() {
+        let mut config = config(&["nas-web", "nas-admin", "pi-web"], &["nas-ssh"]);
+
+        let patterns = [
+            "*-admin".to_string(),
+            "nas-ssh".to_string(),
+            "pi-web-router".to_string(),
+        ];
+        exclude_services(&mut config, &patterns);
+
+        // The pi-web service stays although its router was dropped by name
+        assert_eq!(names(&config), ["nas-web-router", "nas-web", "pi-web"]);
+    }
+
+    #[test]
+    fn aliases_rename_services_and_repoint_routers() {
+        let mut config = config(&["nas-web", "pi-web", "web"], &["nas-ssh"]);
+        let aliases = HashMap::from([
+            ("nas-web".to_string(), "files".to_string()),
+            ("nas-ssh".to_string(), "ssh".to_string()),
+            // Taken by a generated service, so pi-web keeps its name
+            ("pi-web".to_string(), "web".to_string()),
+            ("gone".to_string(), "nothing".to_string()),
+        ]);
+
+        apply_aliases(&mut config, &aliases);
+
+        assert_eq!(
+            names(&config),
+            [
+                "nas-web-router",
+                "pi-web-router",
+                "web-router",
+                "files",
+                "pi-web",
+                "web",
+                "nas-ssh-router",
+                "ssh",
+            ]
+        );
+        let http = config.http.as_ref().unwrap();
+        assert_eq!(http.routers["nas-web-router"].service, "files");
+        assert_eq!(http.routers["pi-web-router"].service, "pi-web");
+        assert_eq!(
+            config.tcp.as_ref().unwrap().routers["nas-ssh-router"].service,
+            "ssh"
+        );
+    }
+
+    #[test]
+    fn aliases_to_the_same_name_keep_the_first_service() {
+        let mut config = config(&["a-web", "b-web"], &[]);
+        let aliases = HashMap::from([
+            ("b-web".to_string(), "web".to_string()),
+            ("a-web".to_string(), "web".to_string()),
+        ]);
+
+        apply_aliases(&mut config, &aliases);
+
+        let http = config.http.as_ref().unwrap();
+        assert_eq!(http.routers["a-web-router"].service, "web");
+        assert_eq!(http.routers["b-web-router"].service, "b-web");
+    }
+}
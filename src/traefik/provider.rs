@@ -1,121 +1,1012 @@
-use crate::config::{Protocol, ProviderConfig, ServiceInfo};
-use crate::tailscale::{PeerStatus, TailscaleClient};
+use crate::config::file::{ConfigFileError, load_file, save_file};
+use crate::config::{
+    AddressFamily, DataSource, NameCollisionStrategy, Protocol, ProviderConfig, ServiceInfo,
+    TagAttribute,
+};
+use crate::events::{ProviderEvent, events};
+use crate::metrics::{PeerTraffic, metrics};
+#[cfg(feature = "redis")]
+use crate::redis::RedisClient;
+#[cfg(feature = "api")]
+use crate::tailscale::api::{Credentials, TailscaleApi};
+use crate::tailscale::client::TailscaleError;
+use crate::tailscale::{
+    HostinfoService, NodeCapability, NodePublic, PeerStatus, RetryPolicy, StableNodeID, Status,
+    TailscaleClient, WhoIsResponse,
+};
+use crate::traefik::capacity::{CapacityHint, capacity_hint};
+use crate::traefik::filter::ServiceOwner;
+use crate::traefik::grants::{ServiceGrant, service_grant, service_grants};
+use crate::traefik::leader::LeaderElection;
+use crate::traefik::overrides::{
+    HealthCheckPatch, ServiceOverride, apply_aliases, apply_overrides, exclude_services,
+};
+#[cfg(feature = "scripting")]
+use crate::traefik::script::RouteScript;
+#[cfg(feature = "redis")]
+use crate::traefik::shared_cache::SharedCache;
+use crate::traefik::statics::{StaticService, static_config};
+use crate::traefik::upstream::UpstreamProvider;
 use crate::traefik::{
-    DynamicConfig, HttpConfig, LoadBalancer, Router, Server, Service, TcpConfig, TcpLoadBalancer,
-    TcpRouter, TcpServer, TcpService, UdpConfig, UdpLoadBalancer, UdpRouter, UdpServer, UdpService,
+    CompressMiddleware, DynamicConfig, HealthCheck, HttpConfig, InFlightReqMiddleware,
+    LoadBalancer, Middleware, RateLimitMiddleware, Router, Server, ServersTransport, Service,
+    StripPrefixMiddleware, TcpConfig, TcpLoadBalancer, TcpRouter, TcpServer, TcpService,
+    TcpTlsConfig, TlsConfig, TlsOptions, UdpConfig, UdpLoadBalancer, UdpRouter, UdpServer,
+    UdpService,
 };
-use std::collections::HashMap;
-use tracing::{info, warn};
+use chrono::{DateTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use tokio::task::JoinSet;
+use tracing::{debug, info, instrument, warn};
+use utoipa::ToSchema;
+
+/// Weight of the best backend when weight decay or latency weighting is enabled
+const MAX_SERVER_WEIGHT: i32 = 100;
+
+/// Steps the weight decay window is divided into. The age of a peer only changes its
+/// weight when it crosses a step, so weights (and the configuration hash) stay put
+/// between most refreshes.
+const WEIGHT_DECAY_STEPS: i64 = 10;
+
+/// How long the Hostinfo listeners of a node are reused before whois is asked again
+const HOSTINFO_CACHE_TTL: Duration = Duration::from_secs(300);
+
+/// How long to wait for a peer to answer a latency ping
+const PING_TIMEOUT: Duration = Duration::from_secs(2);
 
 pub struct TraefikProvider {
-    pub tailscale_client: TailscaleClient,
+    pub tailscale_client: Arc<TailscaleClient>,
     config: ProviderConfig,
+    /// Ping round trips of the last refresh in seconds, when latency filtering or weighting is on
+    latencies: RwLock<HashMap<StableNodeID, f64>>,
+    /// Listeners advertised in the Hostinfo of each node key, with when they were looked up
+    hostinfo: Mutex<HashMap<NodePublic, (Instant, Vec<HostinfoService>)>>,
+    /// When peers were first seen offline, for OFFLINE_GRACE_SECONDS
+    offline_since: Mutex<HashMap<StableNodeID, DateTime<Utc>>>,
+    file_middlewares: Arc<BTreeMap<String, Middleware>>,
+    /// Bundles of MIDDLEWARES_FILE, expanded to the middlewares they attach in order
+    middleware_bundles: Arc<BTreeMap<String, Vec<String>>>,
+    /// Option sets of TLS_OPTIONS_FILE
+    tls_options: Arc<BTreeMap<String, TlsOptions>>,
+    merge_config: Option<DynamicConfig>,
+    /// Routers and services of STATIC_SERVICES_FILE
+    static_config: Option<DynamicConfig>,
+    upstream: Option<UpstreamProvider>,
+    /// Last configuration fetched from UPSTREAM_PROVIDER_URL
+    upstream_config: RwLock<Option<DynamicConfig>>,
+    /// Patches of OVERRIDES_FILE, replaced by /state/import
+    service_overrides: RwLock<HashMap<String, ServiceOverride>>,
+    /// Online/offline transitions per peer, for flap damping
+    flaps: Mutex<HashMap<StableNodeID, FlapHistory>>,
+    /// Output of each peer from the last generation, keyed by the fingerprint of its inputs
+    peer_outputs: Mutex<HashMap<StableNodeID, (u64, Arc<PeerOutput>)>>,
+    /// Hostname and online state of every peer at the last refresh, for /events
+    /// (None until the first refresh)
+    known_peers: Mutex<Option<HashMap<StableNodeID, (String, bool)>>>,
+    /// Further tailscaled instances merged into the configuration (TAILSCALE_SOURCES)
+    sources: Vec<TailnetSource>,
+    /// Peer owning each generated service at the last refresh, for filtering /config
+    service_owners: RwLock<HashMap<String, ServiceOwner>>,
+    /// Peers and services disabled through the admin API, shared with the providers of
+    /// TAILSCALE_SOURCES so disabling applies whichever tailscaled a peer comes from
+    disabled: Arc<RwLock<Disabled>>,
+    /// Election of the replica generating for the others (LEADER_ELECTION)
+    leader: Option<LeaderElection>,
+    /// Redis holding the configuration and generation lock of all replicas
+    #[cfg(feature = "redis")]
+    shared_cache: Option<SharedCache>,
+    /// ROUTE_SCRIPT, deciding on every generated router
+    #[cfg(feature = "scripting")]
+    route_script: Option<Arc<RouteScript>>,
+}
+
+/// Everything generated for a single peer
+#[derive(Default)]
+struct PeerOutput {
+    http_services: BTreeMap<String, Service>,
+    http_routers: BTreeMap<String, Router>,
+    http_middlewares: BTreeMap<String, Middleware>,
+    servers_transports: BTreeMap<String, ServersTransport>,
+    tcp_services: BTreeMap<String, TcpService>,
+    tcp_routers: BTreeMap<String, TcpRouter>,
+    udp_services: BTreeMap<String, UdpService>,
+    udp_routers: BTreeMap<String, UdpRouter>,
+}
+
+/// Peer status fields that change without affecting the generated configuration
+const VOLATILE_PEER_FIELDS: &[&str] = &[
+    "RxBytes",
+    "TxBytes",
+    "LastWrite",
+    "LastSeen",
+    "LastHandshake",
+    "CurAddr",
+    "Relay",
+    "PeerRelay",
+    "Active",
+    "KeyExpiry",
+];
+
+/// Outcome of one peer filter, for /explain
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct FilterCheck {
+    pub name: &'static str,
+    pub passed: bool,
+    pub detail: String,
+}
+
+impl FilterCheck {
+    fn new(name: &'static str, passed: bool, detail: String) -> Self {
+        Self {
+            name,
+            passed,
+            detail,
+        }
+    }
+}
+
+/// How one tag of a peer was read, for /explain
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct TagCheck {
+    pub tag: String,
+    /// Service the tag declares, if any
+    pub service: Option<String>,
+    pub port: Option<u16>,
+    pub scheme: Option<String>,
+    /// Whether the tag contributes a service
+    pub passed: bool,
+    pub detail: String,
+}
+
+impl TagCheck {
+    fn from_service(tag: &str, info: &ServiceInfo, passed: bool) -> Self {
+        Self {
+            tag: tag.to_string(),
+            service: Some(info.name.clone()),
+            port: info.port,
+            scheme: Some(info.scheme.clone()),
+            passed,
+            detail: String::new(),
+        }
+    }
+
+    fn without_service(tag: &str, detail: &str) -> Self {
+        Self {
+            tag: tag.to_string(),
+            service: None,
+            port: None,
+            scheme: None,
+            passed: false,
+            detail: detail.to_string(),
+        }
+    }
+}
+
+/// Peers and services taken out of the configuration through the admin API
+#[derive(Default)]
+struct Disabled {
+    /// Peer hostnames
+    peers: BTreeSet<String>,
+    /// Service names (or globs)
+    services: BTreeSet<String>,
+}
+
+/// Configuration generated from one tailscaled, with the figures its metrics are built from
+#[derive(Clone)]
+struct TailnetConfig {
+    config: DynamicConfig,
+    traffic: Vec<PeerTraffic>,
+    /// Seconds until the key of each node expires, by hostname
+    key_expiries: Vec<(String, i64)>,
+    keys_expiring: i64,
+    peers_without_services: i64,
+    owners: HashMap<String, ServiceOwner>,
+}
+
+/// A further tailscaled, generated by a provider of its own with the source name
+/// added to the name prefix
+struct TailnetSource {
+    name: String,
+    provider: TraefikProvider,
+    /// Last configuration generated from the source, kept while it is unreachable
+    last: Mutex<Option<TailnetConfig>>,
+}
+
+/// Entry of MIDDLEWARES_FILE: a raw Traefik middleware definition, or a bundle naming
+/// the middlewares it stands for
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum MiddlewareEntry {
+    Bundle(Vec<String>),
+    Definition(BTreeMap<String, serde_json::Value>),
+}
+
+/// Recent online/offline transitions of a peer
+struct FlapHistory {
+    online: bool,
+    transitions: VecDeque<DateTime<Utc>>,
+    dampened: bool,
 }
 
 impl TraefikProvider {
     pub fn new(config: ProviderConfig) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
-        let tailscale_client = if let Some(socket_path) = &config.tailscale_socket_path {
-            TailscaleClient::with_socket_path(socket_path.clone())?
-        } else {
-            TailscaleClient::new()?
+        // Traefik only stops sending traffic to a broken address path once a health check
+        // marks that server down
+        if config.address_family == AddressFamily::Both && config.health_check_path.is_none() {
+            warn!(
+                "ADDRESS_FAMILY=both without HEALTH_CHECK_PATH: HTTP services won't fail over between address families"
+            );
+        }
+
+        if let Some(template) = &config.service_name_template
+            && !template.contains("{hostname}")
+            && !template.contains("{service}")
+        {
+            warn!(
+                "SERVICE_NAME_TEMPLATE {} has neither {{hostname}} nor {{service}}: every service gets the same name",
+                template
+            );
+        }
+
+        if config.hostinfo_services
+            && config.hostinfo_services_allow.is_none()
+            && config.include_tags.is_none()
+        {
+            warn!(
+                "HOSTINFO_SERVICES without HOSTINFO_SERVICES_ALLOW or INCLUDE_TAGS: no Hostinfo listener is published"
+            );
+        }
+
+        if config.deterministic_output
+            && (config.latency_weighting
+                || config.max_latency_ms.is_some()
+                || config.flap_threshold.is_some()
+                || config.offline_grace_seconds.is_some())
+        {
+            warn!(
+                "DETERMINISTIC_OUTPUT with latency, flap or offline grace settings: these depend on each replica's observations and can make outputs differ"
+            );
+        }
+
+        let leader = match &config.cluster_members {
+            Some(members) if config.leader_election && !members.is_empty() => {
+                if !config.deterministic_output {
+                    warn!(
+                        "LEADER_ELECTION without DETERMINISTIC_OUTPUT: replicas serve the same content under different versions"
+                    );
+                }
+                Some(LeaderElection::new(
+                    config.replica_id.clone(),
+                    members.clone(),
+                    config.api_token.clone(),
+                ))
+            }
+            _ if config.leader_election => {
+                warn!("LEADER_ELECTION requires CLUSTER_MEMBERS, ignoring it");
+                None
+            }
+            _ => None,
+        };
+
+        #[cfg(feature = "redis")]
+        let shared_cache = match &config.shared_cache_url {
+            Some(url) => {
+                let client = RedisClient::new(
+                    url,
+                    config.redis_username.clone(),
+                    config.redis_password.clone(),
+                    config.redis_ca_file.as_deref(),
+                )?;
+                // Survives two missed refreshes of the holder
+                let lock_ttl = Duration::from_secs(config.update_interval_seconds.max(5) * 3);
+                Some(SharedCache::new(
+                    client,
+                    &config.shared_cache_prefix,
+                    config.replica_id.clone(),
+                    lock_ttl,
+                ))
+            }
+            None => None,
         };
+        #[cfg(not(feature = "redis"))]
+        if config.shared_cache_url.is_some() {
+            return Err("SHARED_CACHE_URL is set but this build has no \"redis\" feature".into());
+        }
+
+        let tailscale_client = match config.data_source {
+            #[cfg(feature = "api")]
+            DataSource::Api => {
+                let options = config.local_api_options();
+                if !options.is_empty() {
+                    return Err(format!(
+                        "{} need a tailscaled and can't be used with TAILSCALE_DATA_SOURCE=api",
+                        options.join(", ")
+                    )
+                    .into());
+                }
+                let credentials = match (
+                    &config.tailscale_oauth_client_id,
+                    &config.tailscale_oauth_client_secret,
+                    &config.tailscale_api_key,
+                ) {
+                    (Some(client_id), Some(client_secret), _) => {
+                        Credentials::oauth(client_id, client_secret.expose())
+                    }
+                    (None, None, Some(api_key)) => {
+                        Credentials::ApiKey(api_key.expose().to_string())
+                    }
+                    (None, None, None) => return Err(
+                        "TAILSCALE_DATA_SOURCE=api requires TAILSCALE_OAUTH_CLIENT_ID/SECRET or TAILSCALE_API_KEY"
+                            .into(),
+                    ),
+                    _ => return Err(
+                        "TAILSCALE_OAUTH_CLIENT_ID and TAILSCALE_OAUTH_CLIENT_SECRET must be set together"
+                            .into(),
+                    ),
+                };
+                info!(
+                    "Reading tailnet {} from the Tailscale API at {}",
+                    config.tailscale_tailnet, config.tailscale_api_url
+                );
+                let api = TailscaleApi::new(
+                    &config.tailscale_api_url,
+                    &config.tailscale_tailnet,
+                    credentials,
+                );
+                TailscaleClient::with_api(match config.posture_attributes {
+                    Some(_) => api.with_posture_attributes(),
+                    None => api,
+                })
+            }
+            #[cfg(not(feature = "api"))]
+            DataSource::Api => {
+                return Err(
+                    "TAILSCALE_DATA_SOURCE=api but this build has no \"api\" feature".into(),
+                );
+            }
+            DataSource::LocalApi => match &config.tailscale_socket_path {
+                Some(socket_path) => TailscaleClient::with_socket_path(socket_path.clone())?,
+                None => TailscaleClient::new()?,
+            },
+        }
+        .with_timeout(Duration::from_millis(config.tailscale_timeout_ms))
+        .with_retry(RetryPolicy {
+            attempts: config.tailscale_retry_attempts,
+            backoff: Duration::from_millis(config.tailscale_retry_backoff_ms),
+        });
+
+        let (file_middlewares, middleware_bundles) = match &config.middlewares_file {
+            Some(path) => {
+                let entries: BTreeMap<String, MiddlewareEntry> = load_file(path)?;
+                let mut definitions = BTreeMap::new();
+                let mut bundles = BTreeMap::new();
+                for (name, entry) in entries {
+                    match entry {
+                        MiddlewareEntry::Bundle(members) => {
+                            bundles.insert(name, members);
+                        }
+                        // Kept as raw JSON so definitions are emitted exactly as written
+                        MiddlewareEntry::Definition(other) => {
+                            definitions.insert(
+                                name,
+                                Middleware {
+                                    other,
+                                    ..Default::default()
+                                },
+                            );
+                        }
+                    }
+                }
+                let bundles = expand_bundles(&bundles)
+                    .map_err(|e| format!("Invalid middleware bundle in {}: {}", path, e))?;
+                for (bundle, members) in &bundles {
+                    for member in members {
+                        // Middlewares of other providers ("name@file") aren't known here
+                        if !definitions.contains_key(member) && !member.contains('@') {
+                            warn!(
+                                "Middleware bundle {} references unknown middleware {}",
+                                bundle, member
+                            );
+                        }
+                    }
+                }
+                info!(
+                    "Loaded {} middlewares and {} bundles from {}",
+                    definitions.len(),
+                    bundles.len(),
+                    path
+                );
+                (definitions, bundles)
+            }
+            None => (BTreeMap::new(), BTreeMap::new()),
+        };
+
+        let tls_options = match &config.tls_options_file {
+            Some(path) => {
+                let options: BTreeMap<String, TlsOptions> = load_file(path)?;
+                info!("Loaded {} TLS option sets from {}", options.len(), path);
+                options
+            }
+            None => BTreeMap::new(),
+        };
+
+        let merge_config = match &config.merge_config_file {
+            Some(path) => {
+                let fragment: DynamicConfig = load_file(path)?;
+                info!("Loaded dynamic configuration fragment from {}", path);
+                Some(fragment)
+            }
+            None => None,
+        };
+
+        let upstream = config
+            .upstream_provider_url
+            .clone()
+            .map(UpstreamProvider::new);
+
+        let static_config = match &config.static_services_file {
+            Some(path) => {
+                let services: BTreeMap<String, StaticService> = load_file(path)?;
+                info!("Loaded {} static services from {}", services.len(), path);
+                Some(static_config(&services)?)
+            }
+            None => None,
+        };
+
+        #[cfg(feature = "scripting")]
+        let route_script = config
+            .route_script
+            .as_deref()
+            .map(RouteScript::load)
+            .transpose()?
+            .map(Arc::new);
+        #[cfg(not(feature = "scripting"))]
+        if config.route_script.is_some() {
+            return Err("ROUTE_SCRIPT is set but this build has no \"scripting\" feature".into());
+        }
+
+        let service_overrides = match &config.overrides_file {
+            Some(path) => {
+                let overrides: HashMap<String, ServiceOverride> = load_file(path)?;
+                info!("Loaded {} service overrides from {}", overrides.len(), path);
+                overrides
+            }
+            None => HashMap::new(),
+        };
+
+        let mut provider = Self {
+            tailscale_client: Arc::new(tailscale_client),
+            config,
+            latencies: RwLock::new(HashMap::new()),
+            hostinfo: Mutex::new(HashMap::new()),
+            offline_since: Mutex::new(HashMap::new()),
+            flaps: Mutex::new(HashMap::new()),
+            peer_outputs: Mutex::new(HashMap::new()),
+            known_peers: Mutex::new(None),
+            sources: Vec::new(),
+            service_owners: RwLock::new(HashMap::new()),
+            disabled: Arc::new(RwLock::new(Disabled::default())),
+            leader,
+            #[cfg(feature = "redis")]
+            shared_cache,
+            #[cfg(feature = "scripting")]
+            route_script,
+            file_middlewares: Arc::new(file_middlewares),
+            middleware_bundles: Arc::new(middleware_bundles),
+            tls_options: Arc::new(tls_options),
+            merge_config,
+            static_config,
+            upstream,
+            upstream_config: RwLock::new(None),
+            service_overrides: RwLock::new(service_overrides),
+        };
+
+        for (name, socket_path) in &provider.config.tailscale_sources {
+            if !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+            {
+                return Err(format!("Invalid TAILSCALE_SOURCES name {}", name).into());
+            }
+            info!("Adding Tailscale source {} at {}", name, socket_path);
+            let source = TailnetSource {
+                name: name.clone(),
+                provider: provider.source_provider(name, socket_path)?,
+                last: Mutex::new(None),
+            };
+            provider.sources.push(source);
+        }
+        Ok(provider)
+    }
+
+    /// Provider of a further tailscaled of TAILSCALE_SOURCES, generating its peers with
+    /// the source name added to the name prefix. It shares the disabled entries and the
+    /// middlewares, TLS options and script loaded here; merging, overrides and
+    /// exclusions happen once, on the combined configuration.
+    fn source_provider(
+        &self,
+        name: &str,
+        socket_path: &str,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let config = ProviderConfig {
+            data_source: DataSource::LocalApi,
+            tailscale_socket_path: Some(socket_path.to_string()),
+            tailscale_sources: Vec::new(),
+            name_prefix: [self.config.name_prefix.as_str(), name]
+                .iter()
+                .filter(|part| !part.is_empty())
+                .copied()
+                .collect::<Vec<_>>()
+                .join("-"),
+            service_name_template: self
+                .config
+                .service_name_template
+                .as_ref()
+                .map(|template| format!("{}-{}", name, template)),
+            ..self.config.clone()
+        };
+        let tailscale_client = TailscaleClient::with_socket_path(socket_path.to_string())?
+            .with_timeout(Duration::from_millis(config.tailscale_timeout_ms))
+            .with_retry(RetryPolicy {
+                attempts: config.tailscale_retry_attempts,
+                backoff: Duration::from_millis(config.tailscale_retry_backoff_ms),
+            });
 
         Ok(Self {
-            tailscale_client,
+            tailscale_client: Arc::new(tailscale_client),
             config,
+            latencies: RwLock::new(HashMap::new()),
+            hostinfo: Mutex::new(HashMap::new()),
+            offline_since: Mutex::new(HashMap::new()),
+            flaps: Mutex::new(HashMap::new()),
+            peer_outputs: Mutex::new(HashMap::new()),
+            known_peers: Mutex::new(None),
+            sources: Vec::new(),
+            service_owners: RwLock::new(HashMap::new()),
+            disabled: self.disabled.clone(),
+            leader: None,
+            #[cfg(feature = "redis")]
+            shared_cache: None,
+            #[cfg(feature = "scripting")]
+            route_script: self.route_script.clone(),
+            file_middlewares: self.file_middlewares.clone(),
+            middleware_bundles: self.middleware_bundles.clone(),
+            tls_options: self.tls_options.clone(),
+            merge_config: None,
+            static_config: None,
+            upstream: None,
+            upstream_config: RwLock::new(None),
+            service_overrides: RwLock::new(HashMap::new()),
         })
     }
 
-    /// Generate Traefik dynamic configuration from Tailscale status
+    /// Generate Traefik dynamic configuration from Tailscale status, patched by the
+    /// service overrides and merged with the user-supplied configuration fragment
+    #[instrument(skip_all)]
     pub async fn generate_config(
         &self,
     ) -> Result<DynamicConfig, Box<dyn std::error::Error + Send + Sync>> {
+        if let Some(leader) = &self.leader
+            && let Some(config) = leader.follow().await
+        {
+            return Ok(config);
+        }
+        #[cfg(feature = "redis")]
+        if let Some(cache) = &self.shared_cache {
+            return cache.generate_or_load(|| self.generate_local()).await;
+        }
+        self.generate_local().await
+    }
+
+    /// Generate the configuration from this replica's Tailscale sources
+    async fn generate_local(
+        &self,
+    ) -> Result<DynamicConfig, Box<dyn std::error::Error + Send + Sync>> {
+        let mut tailnet = self.generate_tailscale_config().await?;
+        for source in &self.sources {
+            match source.provider.generate_tailscale_config().await {
+                Ok(generated) => {
+                    *source.last.lock().unwrap() = Some(generated.clone());
+                    self.add_source(&mut tailnet, &source.name, generated);
+                }
+                Err(e) => {
+                    let last = source.last.lock().unwrap().clone();
+                    match last {
+                        Some(last) => {
+                            warn!(
+                                "Failed to query Tailscale source {}, keeping its last configuration: {}",
+                                source.name, e
+                            );
+                            self.add_source(&mut tailnet, &source.name, last);
+                        }
+                        None => warn!("Failed to query Tailscale source {}: {}", source.name, e),
+                    }
+                }
+            }
+        }
+        metrics()
+            .peers_without_services
+            .set(tailnet.peers_without_services);
+        metrics().record_peer_traffic(&tailnet.traffic);
+        metrics().record_key_expiry(&tailnet.key_expiries);
+        metrics().peers_key_expiring.set(tailnet.keys_expiring);
+        *self.service_owners.write().unwrap() = tailnet.owners;
+
+        let mut config = tailnet.config;
+        // The MIDDLEWARES_FILE definitions, once for the peers of every source; generated
+        // middlewares of the same name take precedence
+        if let Some(http) = &mut config.http {
+            for (middleware, definition) in self.file_middlewares.iter() {
+                http.middlewares
+                    .entry(middleware.clone())
+                    .or_insert_with(|| definition.clone());
+            }
+        }
+        if !self.tls_options.is_empty() {
+            config
+                .tls
+                .get_or_insert_with(Default::default)
+                .options
+                .extend((*self.tls_options).clone());
+        }
+        if let Some(static_config) = &self.static_config {
+            for collision in config.merge(static_config.clone()) {
+                warn!("Static service replaces generated entry {}", collision);
+            }
+        }
+        if let Some(upstream_config) = self.upstream_config.read().unwrap().clone() {
+            for collision in config.merge(upstream_config) {
+                warn!("Upstream provider replaces generated entry {}", collision);
+            }
+        }
+        apply_overrides(&mut config, &self.service_overrides.read().unwrap());
+        if let Some(aliases) = &self.config.service_aliases {
+            apply_aliases(&mut config, aliases);
+        }
+        if let Some(patterns) = &self.config.exclude_services {
+            exclude_services(&mut config, patterns);
+        }
+        let disabled_services: Vec<String> = self
+            .disabled
+            .read()
+            .unwrap()
+            .services
+            .iter()
+            .cloned()
+            .collect();
+        if !disabled_services.is_empty() {
+            exclude_services(&mut config, &disabled_services);
+        }
+
+        if let Some(fragment) = &self.merge_config {
+            for collision in config.merge(fragment.clone()) {
+                warn!(
+                    "Merged configuration overrides generated entry {}",
+                    collision
+                );
+            }
+        }
+
+        if self.config.deterministic_output {
+            config.sort_servers();
+        }
+        Ok(config)
+    }
+
+    /// Fetch the configuration of the upstream provider; returns whether it changed.
+    /// The last fetched configuration stays in use when the upstream is unreachable.
+    pub async fn refresh_upstream(&self) -> Result<bool, String> {
+        let Some(upstream) = &self.upstream else {
+            return Ok(false);
+        };
+        let fetched = upstream
+            .fetch()
+            .await
+            .map_err(|e| format!("{}: {}", upstream.url(), e))?;
+
+        let mut current = self.upstream_config.write().unwrap();
+        let changed = match current.as_ref() {
+            Some(previous) => {
+                serde_json::to_value(previous).ok() != serde_json::to_value(&fetched).ok()
+            }
+            None => true,
+        };
+        *current = Some(fetched);
+        Ok(changed)
+    }
+
+    /// Peer owning each service of the last generation
+    pub fn service_owners(&self) -> HashMap<String, ServiceOwner> {
+        self.service_owners.read().unwrap().clone()
+    }
+
+    pub fn key_expiry_warning_days(&self) -> u64 {
+        self.config.key_expiry_warning_days
+    }
+
+    /// Export key expiry metrics and warn about nodes whose key expires soon
+    fn key_expiries(&self, status: &Status) -> (Vec<(String, i64)>, i64) {
+        let now = Utc::now();
+        let window = chrono::Duration::days(self.config.key_expiry_warning_days as i64);
+        let mut expiries = Vec::new();
+        let mut expiring = 0;
+        let nodes = status
+            .peers
+            .iter()
+            .flat_map(|peers| peers.values().flatten())
+            .chain(status.self_peer.as_ref());
+        for node in nodes {
+            let Some(key_expiry) = node.key_expiry else {
+                continue;
+            };
+            let remaining = key_expiry - now;
+            expiries.push((node.hostname.clone(), remaining.num_seconds()));
+            if remaining <= window {
+                expiring += 1;
+                debug!("Key of {} expires at {}", node.hostname, key_expiry);
+            }
+        }
+        (expiries, expiring)
+    }
+
+    /// REPLICA_ID and URL of the replica this one mirrors under LEADER_ELECTION. Changes
+    /// made here (disabling, overrides, refreshes) don't show in a mirrored configuration.
+    pub async fn leader(&self) -> Option<(String, String)> {
+        self.leader.as_ref()?.leader().await
+    }
+
+    /// Disable or re-enable a peer by hostname; returns whether anything changed
+    pub fn set_peer_disabled(&self, hostname: &str, disabled: bool) -> bool {
+        let peers = &mut self.disabled.write().unwrap().peers;
+        if disabled {
+            peers.insert(hostname.to_string())
+        } else {
+            peers.remove(hostname)
+        }
+    }
+
+    /// Disable or re-enable a service by name; returns whether anything changed
+    pub fn set_service_disabled(&self, name: &str, disabled: bool) -> bool {
+        let services = &mut self.disabled.write().unwrap().services;
+        if disabled {
+            services.insert(name.to_string())
+        } else {
+            services.remove(name)
+        }
+    }
+
+    /// Replace the disabled peers and services, e.g. with an imported state
+    pub fn set_disabled(&self, peers: Vec<String>, services: Vec<String>) {
+        *self.disabled.write().unwrap() = Disabled {
+            peers: peers.into_iter().collect(),
+            services: services.into_iter().collect(),
+        };
+    }
+
+    /// Service overrides in effect
+    pub fn overrides(&self) -> HashMap<String, ServiceOverride> {
+        self.service_overrides.read().unwrap().clone()
+    }
+
+    /// Replace the service overrides, writing them to OVERRIDES_FILE when it is set so
+    /// they survive a restart
+    pub fn set_overrides(
+        &self,
+        overrides: HashMap<String, ServiceOverride>,
+    ) -> Result<(), ConfigFileError> {
+        if let Some(path) = &self.config.overrides_file {
+            save_file(path, &overrides)?;
+        }
+        *self.service_overrides.write().unwrap() = overrides;
+        Ok(())
+    }
+
+    /// Peer hostnames and service names currently disabled
+    pub fn disabled(&self) -> (Vec<String>, Vec<String>) {
+        let disabled = self.disabled.read().unwrap();
+        (
+            disabled.peers.iter().cloned().collect(),
+            disabled.services.iter().cloned().collect(),
+        )
+    }
+
+    /// Generate Traefik dynamic configuration from Tailscale status
+    #[instrument(skip_all)]
+    async fn generate_tailscale_config(
+        &self,
+    ) -> Result<TailnetConfig, Box<dyn std::error::Error + Send + Sync>> {
         info!("Fetching Tailscale status");
         let status = self.tailscale_client.get_status().await?;
+        let tailnet = status.magic_dns_suffix.trim_end_matches('.');
+
+        self.publish_peer_events(&status);
+        let (key_expiries, keys_expiring) = self.key_expiries(&status);
 
         let peer_count = status.peers.as_ref().map(|p| p.len()).unwrap_or(0);
         info!("Generating Traefik configuration for {} peers", peer_count);
 
-        let mut http_services = HashMap::new();
-        let mut http_routers = HashMap::new();
-        let mut tcp_services = HashMap::new();
-        let mut tcp_routers = HashMap::new();
-        let mut udp_services = HashMap::new();
-        let mut udp_routers = HashMap::new();
+        let mut http_services = BTreeMap::new();
+        let mut http_routers = BTreeMap::new();
+        let mut http_middlewares = BTreeMap::new();
+        let mut servers_transports = BTreeMap::new();
+        let mut tcp_services = BTreeMap::new();
+        let mut tcp_routers = BTreeMap::new();
+        let mut udp_services = BTreeMap::new();
+        let mut udp_routers = BTreeMap::new();
+        let mut peers_without_services = 0;
 
         // Process each online peer
         let Some(peers) = &status.peers else {
             warn!("No peers available in status");
-            return Ok(DynamicConfig {
+            let config = DynamicConfig {
                 http: Some(HttpConfig {
-                    routers: HashMap::new(),
-                    services: HashMap::new(),
-                    middlewares: HashMap::new(),
+                    routers: BTreeMap::new(),
+                    services: BTreeMap::new(),
+                    middlewares: BTreeMap::new(),
+                    servers_transports: BTreeMap::new(),
                 }),
                 tcp: Some(TcpConfig {
-                    routers: HashMap::new(),
-                    services: HashMap::new(),
+                    routers: BTreeMap::new(),
+                    services: BTreeMap::new(),
                 }),
                 udp: Some(UdpConfig {
-                    routers: HashMap::new(),
-                    services: HashMap::new(),
+                    routers: BTreeMap::new(),
+                    services: BTreeMap::new(),
                 }),
+                tls: None,
+            };
+            return Ok(TailnetConfig {
+                config,
+                traffic: Vec::new(),
+                key_expiries,
+                keys_expiring,
+                peers_without_services: 0,
+                owners: HashMap::new(),
             });
         };
 
-        for (_peer_key, peer_opt) in peers {
-            let Some(peer) = peer_opt else { continue };
-            if !self.should_include_peer(peer) {
+        // Forget the offline time and flap history of peers that left the tailnet
+        let in_tailnet = |id: &StableNodeID| peers.values().flatten().any(|peer| &peer.id == id);
+        self.offline_since
+            .lock()
+            .unwrap()
+            .retain(|id, _| in_tailnet(id));
+        self.flaps.lock().unwrap().retain(|id, _| in_tailnet(id));
+
+        let included: Vec<&PeerStatus> = peers
+            .values()
+            .flatten()
+            .filter(|peer| self.should_include_peer(peer))
+            .collect();
+        if self.config.max_latency_ms.is_some() || self.config.latency_weighting {
+            self.measure_latencies(&included).await;
+        }
+
+        // The node running tailscaled, published through its own tags like any peer
+        let self_node = status
+            .self_peer
+            .as_ref()
+            .filter(|node| self.config.include_self && self.should_include_peer(node));
+
+        if self.config.hostinfo_services {
+            let nodes: Vec<&PeerStatus> = included.iter().copied().chain(self_node).collect();
+            self.fetch_hostinfo(&nodes).await;
+        }
+
+        let mut targets = Vec::new();
+        for peer in included.into_iter().chain(self_node) {
+            // There is no round trip to the local node
+            let is_self = self_node.is_some_and(|node| node.id == peer.id);
+            if let Some(max_latency_ms) = self.config.max_latency_ms
+                && !is_self
+                && !self.within_latency(peer, max_latency_ms)
+            {
+                debug!(
+                    "Peer {} exceeds {}ms round trip",
+                    peer.hostname, max_latency_ms
+                );
                 continue;
             }
 
             // Get all services from this peer's tags
-            let service_infos = self.extract_service_infos_from_peer(peer);
+            let mut service_infos = self.extract_service_infos_from_peer(peer);
+            let granted = self.granted_service_infos(peer);
+            if !granted.is_empty() {
+                // Granted services replace the catch-all default of untagged peers
+                service_infos.retain(|service_info| service_info.name != "default");
+                service_infos.extend(granted);
+            }
+            if self.config.hostinfo_services {
+                service_infos.extend(self.hostinfo_service_infos(peer));
+            }
+            if service_infos.is_empty() {
+                debug!("Peer {} has no valid services", peer.hostname);
+                peers_without_services += 1;
+            }
+            targets.push((peer, service_infos));
+        }
 
-            for service_info in service_infos {
-                let service_name = self.generate_service_name_from_info(peer, &service_info);
-                let router_name = self.generate_router_name_from_info(peer, &service_info);
+        // Ports the node itself publishes with `tailscale serve`
+        if self.config.serve_discovery
+            && let Some(self_peer) = &status.self_peer
+        {
+            let service_infos = self.serve_service_infos().await;
+            match targets.iter_mut().find(|(peer, _)| peer.id == self_peer.id) {
+                Some((_, self_services)) => self_services.extend(service_infos),
+                None if !service_infos.is_empty() => targets.push((self_peer, service_infos)),
+                None => {}
+            }
+        }
 
-                match service_info.protocol {
-                    Protocol::Http => {
-                        if let Some(service) =
-                            self.create_http_service_from_peer(peer, &service_info)
-                        {
-                            http_services.insert(service_name.clone(), service);
-                            if let Some(router) =
-                                self.create_http_router_for_peer(peer, &service_info, &service_name)
-                            {
-                                http_routers.insert(router_name, router);
-                            }
-                        }
-                    }
-                    Protocol::Tcp => {
-                        if let Some(service) =
-                            self.create_tcp_service_from_peer(peer, &service_info)
-                        {
-                            tcp_services.insert(service_name.clone(), service);
-                            if let Some(router) =
-                                self.create_tcp_router_for_peer(peer, &service_info, &service_name)
-                            {
-                                tcp_routers.insert(router_name, router);
-                            }
-                        }
-                    }
-                    Protocol::Udp => {
-                        if let Some(service) =
-                            self.create_udp_service_from_peer(peer, &service_info)
-                        {
-                            udp_services.insert(service_name.clone(), service);
-                            if let Some(router) =
-                                self.create_udp_router_for_peer(peer, &service_info, &service_name)
-                            {
-                                udp_routers.insert(router_name, router);
-                            }
-                        }
-                    }
+        let targets = self.disambiguate_names(targets);
+
+        let mut outputs = self.peer_outputs.lock().unwrap();
+        let mut reused = 0;
+        let mut current_ids = Vec::new();
+        let mut traffic = Vec::new();
+        let mut owners = HashMap::new();
+        for (peer, service_infos, node_tag) in &targets {
+            let node_tag = node_tag.as_deref();
+            let fingerprint = self.peer_fingerprint(peer, service_infos, tailnet, node_tag);
+            let output = match outputs.get(&peer.id) {
+                Some((cached, output)) if *cached == fingerprint => {
+                    reused += 1;
+                    output.clone()
+                }
+                _ => {
+                    let output =
+                        Arc::new(self.build_peer_output(peer, service_infos, tailnet, node_tag));
+                    outputs.insert(peer.id.clone(), (fingerprint, output.clone()));
+                    output
                 }
+            };
+            current_ids.push(peer.id.clone());
+            let owner = ServiceOwner {
+                hostname: peer.hostname.clone(),
+                tags: peer.tags.clone().unwrap_or_default(),
+            };
+            for name in output
+                .http_services
+                .keys()
+                .chain(output.tcp_services.keys())
+                .chain(output.udp_services.keys())
+            {
+                owners.insert(name.clone(), owner.clone());
             }
+            traffic.push(PeerTraffic {
+                hostname: peer.hostname.clone(),
+                rx_bytes: peer.rx_bytes,
+                tx_bytes: peer.tx_bytes,
+                services: output
+                    .http_services
+                    .keys()
+                    .chain(output.tcp_services.keys())
+                    .chain(output.udp_services.keys())
+                    .cloned()
+                    .collect(),
+            });
+
+            http_services.extend(output.http_services.clone());
+            http_routers.extend(output.http_routers.clone());
+            http_middlewares.extend(output.http_middlewares.clone());
+            servers_transports.extend(output.servers_transports.clone());
+            tcp_services.extend(output.tcp_services.clone());
+            tcp_routers.extend(output.tcp_routers.clone());
+            udp_services.extend(output.udp_services.clone());
+            udp_routers.extend(output.udp_routers.clone());
         }
+        outputs.retain(|id, _| current_ids.contains(id));
+        drop(outputs);
+        debug!(
+            "Reused the cached output of {} of {} peers",
+            reused,
+            targets.len()
+        );
 
         let http_config = if http_services.is_empty() && http_routers.is_empty() {
             None
@@ -123,7 +1014,8 @@ impl TraefikProvider {
             Some(HttpConfig {
                 services: http_services,
                 routers: http_routers,
-                middlewares: HashMap::new(),
+                middlewares: http_middlewares,
+                servers_transports,
             })
         };
 
@@ -145,13 +1037,320 @@ impl TraefikProvider {
             })
         };
 
-        Ok(DynamicConfig {
-            http: http_config,
-            tcp: tcp_config,
-            udp: udp_config,
+        Ok(TailnetConfig {
+            config: DynamicConfig {
+                http: http_config,
+                tcp: tcp_config,
+                udp: udp_config,
+                tls: None,
+            },
+            traffic,
+            key_expiries,
+            keys_expiring,
+            peers_without_services,
+            owners,
         })
     }
 
+    /// Merge the configuration of a further Tailscale source into the combined one.
+    /// Its nodes are reported in the metrics as "<source>/<hostname>".
+    fn add_source(&self, tailnet: &mut TailnetConfig, name: &str, source: TailnetConfig) {
+        for collision in tailnet.config.merge(source.config) {
+            warn!(
+                "Tailscale source {} replaces generated entry {}",
+                name, collision
+            );
+        }
+
+        let qualify = |hostname: &str| format!("{}/{}", name, hostname);
+        tailnet
+            .traffic
+            .extend(source.traffic.into_iter().map(|peer| PeerTraffic {
+                hostname: qualify(&peer.hostname),
+                ..peer
+            }));
+        tailnet.key_expiries.extend(
+            source
+                .key_expiries
+                .into_iter()
+                .map(|(hostname, seconds)| (qualify(&hostname), seconds)),
+        );
+        tailnet.keys_expiring += source.keys_expiring;
+        tailnet.peers_without_services += source.peers_without_services;
+        tailnet.owners.extend(source.owners);
+    }
+
+    /// Announce peers that joined, left, came online or went offline since the last refresh
+    fn publish_peer_events(&self, status: &Status) {
+        let current: HashMap<StableNodeID, (String, bool)> = status
+            .peers
+            .iter()
+            .flat_map(|peers| peers.values().flatten())
+            .map(|peer| {
+                (
+                    peer.id.clone(),
+                    (peer.hostname.clone(), peer.online.unwrap_or(false)),
+                )
+            })
+            .collect();
+
+        let mut known = self.known_peers.lock().unwrap();
+        // The first refresh only establishes what is known
+        if let Some(previous) = known.as_ref() {
+            for (node_id, (hostname, online)) in &current {
+                let (id, hostname) = (node_id.0.clone(), hostname.clone());
+                let event = match previous.get(node_id) {
+                    None => ProviderEvent::PeerJoined { id, hostname },
+                    Some((_, was_online)) if was_online == online => continue,
+                    Some(_) if *online => ProviderEvent::PeerOnline { id, hostname },
+                    Some(_) => ProviderEvent::PeerOffline { id, hostname },
+                };
+                events().publish(event);
+            }
+            for (id, (hostname, _)) in previous {
+                if !current.contains_key(id) {
+                    events().publish(ProviderEvent::PeerLeft {
+                        id: id.0.clone(),
+                        hostname: hostname.clone(),
+                    });
+                }
+            }
+        }
+        *known = Some(current);
+    }
+
+    /// Pick the base of each peer's generated names. Peers are visited in hostname order
+    /// and a peer whose service names were already claimed by an earlier one is renamed
+    /// or dropped according to the configured strategy.
+    fn disambiguate_names<'a>(
+        &self,
+        mut targets: Vec<(&'a PeerStatus, Vec<ServiceInfo>)>,
+    ) -> Vec<(&'a PeerStatus, Vec<ServiceInfo>, Option<String>)> {
+        targets.sort_by(|(a, _), (b, _)| {
+            a.hostname
+                .cmp(&b.hostname)
+                .then_with(|| a.id.0.cmp(&b.id.0))
+        });
+
+        let mut claimed: HashMap<String, String> = HashMap::new();
+        let mut named = Vec::with_capacity(targets.len());
+        for (peer, service_infos) in targets {
+            let mut node_tag = None;
+            let clash = self
+                .claimed_names(peer, None, &service_infos)
+                .into_iter()
+                .find_map(|name| claimed.get(&name).map(|owner| (name, owner.clone())));
+
+            if let Some((name, owner)) = clash {
+                match self.config.name_collision_strategy {
+                    NameCollisionStrategy::NodeId => {
+                        let tag = Self::short_node_id(peer);
+                        warn!(
+                            "Peer {} collides with peer {} on {}, tagging its names with {}",
+                            peer.hostname, owner, name, tag
+                        );
+                        node_tag = Some(tag);
+                    }
+                    NameCollisionStrategy::Skip => {
+                        warn!(
+                            "Peer {} collides with peer {} on {}, skipping it",
+                            peer.hostname, owner, name
+                        );
+                        continue;
+                    }
+                }
+            }
+
+            for name in self.claimed_names(peer, node_tag.as_deref(), &service_infos) {
+                claimed.entry(name).or_insert_with(|| peer.hostname.clone());
+            }
+            named.push((peer, service_infos, node_tag));
+        }
+        named
+    }
+
+    /// Section-qualified names of the routers, services and middlewares a peer would
+    /// generate, e.g. "http.services.tailscale-nas-web". The compress middleware shared by
+    /// every router is left out.
+    fn claimed_names(
+        &self,
+        peer: &PeerStatus,
+        node_tag: Option<&str>,
+        service_infos: &[ServiceInfo],
+    ) -> Vec<String> {
+        let shared_compress = self.config.decorate_name("compress");
+        let mut names = Vec::new();
+        for service_info in service_infos {
+            let section = match service_info.protocol {
+                Protocol::Http => "http",
+                Protocol::Tcp => "tcp",
+                Protocol::Udp => "udp",
+            };
+            let service_name = self.generate_service_name_from_info(peer, node_tag, service_info);
+            let router_name = self.generate_router_name_from_info(peer, node_tag, service_info);
+            if service_info.protocol == Protocol::Http {
+                names.extend(
+                    self.create_http_middlewares_for_peer(peer, service_info, &service_name)
+                        .into_iter()
+                        .filter(|(name, _)| *name != shared_compress)
+                        .map(|(name, _)| format!("http.middlewares.{}", name)),
+                );
+            }
+            names.push(format!("{}.routers.{}", section, router_name));
+            names.push(format!("{}.services.{}", section, service_name));
+        }
+        names
+    }
+
+    /// First six alphanumeric characters of the stable node ID, lowercased
+    fn short_node_id(peer: &PeerStatus) -> String {
+        peer.id
+            .0
+            .chars()
+            .filter(|c| c.is_ascii_alphanumeric())
+            .take(6)
+            .collect::<String>()
+            .to_lowercase()
+    }
+
+    /// Derive the routers, services and middlewares of one peer
+    fn build_peer_output(
+        &self,
+        peer: &PeerStatus,
+        service_infos: &[ServiceInfo],
+        tailnet: &str,
+        node_tag: Option<&str>,
+    ) -> PeerOutput {
+        let mut output = PeerOutput::default();
+
+        for service_info in service_infos {
+            let service_name = self.generate_service_name_from_info(peer, node_tag, service_info);
+            let router_name = self.generate_router_name_from_info(peer, node_tag, service_info);
+            if self.is_reserved_name(&service_name) || self.is_reserved_name(&router_name) {
+                warn!(
+                    target: "audit",
+                    "Dropping service {} of peer {}: generated name {} is reserved",
+                    service_info.name,
+                    peer.hostname,
+                    service_name
+                );
+                continue;
+            }
+
+            match service_info.protocol {
+                Protocol::Http => {
+                    let Some(service) = self.create_http_service_from_peer(peer, service_info)
+                    else {
+                        continue;
+                    };
+                    let Some(mut router) = self.create_http_router_for_peer(
+                        peer,
+                        service_info,
+                        &service_name,
+                        tailnet,
+                    ) else {
+                        continue;
+                    };
+
+                    let middlewares =
+                        self.create_http_middlewares_for_peer(peer, service_info, &service_name);
+                    let mut middleware_names: Vec<String> =
+                        middlewares.iter().map(|(name, _)| name.clone()).collect();
+                    middleware_names.extend(self.referenced_middlewares(peer, service_info));
+                    if !middleware_names.is_empty() {
+                        router.middlewares = Some(middleware_names);
+                    }
+                    #[cfg(feature = "scripting")]
+                    if let Some(script) = &self.route_script
+                        && !script.apply_http(peer, service_info, &mut router)
+                    {
+                        continue;
+                    }
+                    output.http_middlewares.extend(middlewares);
+
+                    if let Some(transport) = &service.load_balancer.servers_transport {
+                        output
+                            .servers_transports
+                            .entry(transport.clone())
+                            .or_insert_with(|| ServersTransport {
+                                insecure_skip_verify: Some(true),
+                            });
+                    }
+
+                    output.http_services.insert(service_name, service);
+                    output.http_routers.insert(router_name, router);
+                }
+                Protocol::Tcp => {
+                    let Some(service) = self.create_tcp_service_from_peer(peer, service_info)
+                    else {
+                        continue;
+                    };
+                    #[allow(unused_mut)]
+                    let Some(mut router) =
+                        self.create_tcp_router_for_peer(peer, service_info, &service_name)
+                    else {
+                        continue;
+                    };
+                    #[cfg(feature = "scripting")]
+                    if let Some(script) = &self.route_script
+                        && !script.apply_tcp(peer, service_info, &mut router)
+                    {
+                        continue;
+                    }
+
+                    output.tcp_services.insert(service_name, service);
+                    output.tcp_routers.insert(router_name, router);
+                }
+                Protocol::Udp => {
+                    #[cfg(feature = "scripting")]
+                    if let Some(script) = &self.route_script
+                        && !script.apply_udp(peer, service_info)
+                    {
+                        continue;
+                    }
+                    if let Some(service) = self.create_udp_service_from_peer(peer, service_info) {
+                        output.udp_services.insert(service_name.clone(), service);
+                        if let Some(router) =
+                            self.create_udp_router_for_peer(peer, service_info, &service_name)
+                        {
+                            output.udp_routers.insert(router_name, router);
+                        }
+                    }
+                }
+            }
+        }
+
+        output
+    }
+
+    /// Hash of everything a peer's output is derived from. Traffic counters and
+    /// timestamps are left out; the weights derived from them are hashed instead.
+    fn peer_fingerprint(
+        &self,
+        peer: &PeerStatus,
+        service_infos: &[ServiceInfo],
+        tailnet: &str,
+        node_tag: Option<&str>,
+    ) -> u64 {
+        let mut peer_value = serde_json::to_value(peer).unwrap_or_default();
+        if let Some(fields) = peer_value.as_object_mut() {
+            for volatile in VOLATILE_PEER_FIELDS {
+                fields.remove(*volatile);
+            }
+        }
+        let weights: Vec<i32> = service_infos
+            .iter()
+            .map(|service_info| self.server_weight(peer, service_info))
+            .collect();
+
+        // serde_json::Value sorts object keys, so the text is stable across HashMap orders
+        let fingerprint =
+            serde_json::json!([peer_value, service_infos, weights, tailnet, node_tag]);
+        let mut hasher = DefaultHasher::new();
+        fingerprint.to_string().hash(&mut hasher);
+        hasher.finish()
+    }
+
     /// Extract all service infos from a peer's tags
     fn extract_service_infos_from_peer(&self, peer: &PeerStatus) -> Vec<ServiceInfo> {
         let mut service_infos = Vec::new();
@@ -174,203 +1373,1224 @@ impl TraefikProvider {
                     }
                 }
             }
-        } else if self.config.include_tags.is_none() {
+        } else if self.config.include_tags.is_none() && !self.config.exclude_peers_without_services
+        {
             // No tags on peer, but no filter either - use default service
             service_infos.push(ServiceInfo {
                 name: "default".to_string(),
                 port: Some(self.config.default_port),
                 protocol: self.config.default_protocol.clone(),
-                scheme: self.config.default_scheme.clone(),
+                scheme: self.config.scheme_for_port(self.config.default_port),
+                path_prefix: None,
+            });
+        }
+
+        // Check tag-service mapping for additional services
+        if let Some(mapping) = &self.config.tag_service_mapping
+            && let Some(peer_tags) = &peer.tags
+        {
+            for peer_tag in peer_tags {
+                // Remove "tag:" prefix if present
+                let clean_tag = peer_tag.strip_prefix("tag:").unwrap_or(peer_tag);
+                if let Some(mapped_service) = mapping.get(clean_tag) {
+                    // Check if this service should be included
+                    if let Some(include_tags) = &self.config.include_tags {
+                        if include_tags.contains(&mapped_service.name) {
+                            service_infos.push(mapped_service.clone());
+                        }
+                    } else {
+                        service_infos.push(mapped_service.clone());
+                    }
+                }
+            }
+        }
+
+        for service_info in &mut service_infos {
+            service_info.path_prefix = self
+                .tag_attributes_for_service(peer, &service_info.name)
+                .filter(|attribute| attribute.key == "path")
+                .find_map(|attribute| attribute.path());
+        }
+        service_infos
+    }
+
+    /// Service infos for the HTTP(S) ports in the node's `tailscale serve` configuration
+    #[instrument(skip_all)]
+    async fn serve_service_infos(&self) -> Vec<ServiceInfo> {
+        let serve_config = match self.tailscale_client.get_serve_config().await {
+            Ok(serve_config) => serve_config,
+            Err(e) => {
+                warn!("Failed to fetch Tailscale serve configuration: {}", e);
+                return Vec::new();
+            }
+        };
+
+        let mut service_infos: Vec<ServiceInfo> = serve_config
+            .tcp
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|(_, handler)| handler.http || handler.https)
+            .map(|(port, handler)| ServiceInfo {
+                name: format!("serve-{}", port),
+                port: Some(port),
+                protocol: Protocol::Http,
+                scheme: if handler.https { "https" } else { "http" }.to_string(),
+                path_prefix: None,
+            })
+            .collect();
+        service_infos.sort_by_key(|service_info| service_info.port);
+        service_infos
+    }
+
+    /// Look up the Hostinfo of the nodes concurrently, except for node keys looked up
+    /// within HOSTINFO_CACHE_TTL. Node keys that are gone are forgotten.
+    #[instrument(skip_all, fields(nodes = nodes.len()))]
+    async fn fetch_hostinfo(&self, nodes: &[&PeerStatus]) {
+        let mut lookups = JoinSet::new();
+        {
+            let mut cache = self.hostinfo.lock().unwrap();
+            cache.retain(|key, _| nodes.iter().any(|node| node.public_key == *key));
+            for node in nodes {
+                if cache
+                    .get(&node.public_key)
+                    .is_some_and(|(looked_up, _)| looked_up.elapsed() < HOSTINFO_CACHE_TTL)
+                {
+                    continue;
+                }
+                let Some(addr) = node.tailscale_ips.first().cloned() else {
+                    continue;
+                };
+                let client = self.tailscale_client.clone();
+                let key = node.public_key.clone();
+                let hostname = node.hostname.clone();
+                lookups.spawn(async move {
+                    match client.whois(&addr).await {
+                        Ok(whois) => {
+                            let services = whois
+                                .node
+                                .hostinfo
+                                .and_then(|hostinfo| hostinfo.services)
+                                .unwrap_or_default();
+                            Some((key, services))
+                        }
+                        Err(e) => {
+                            warn!("Failed to look up Hostinfo of peer {}: {}", hostname, e);
+                            None
+                        }
+                    }
+                });
+            }
+        }
+
+        while let Some(result) = lookups.join_next().await {
+            if let Ok(Some((key, services))) = result {
+                let mut cache = self.hostinfo.lock().unwrap();
+                cache.insert(key, (Instant::now(), services));
+            }
+        }
+    }
+
+    /// Service infos for the listening ports a peer advertises in its Hostinfo
+    fn hostinfo_service_infos(&self, peer: &PeerStatus) -> Vec<ServiceInfo> {
+        let cache = self.hostinfo.lock().unwrap();
+        let Some((_, services)) = cache.get(&peer.public_key) else {
+            return Vec::new();
+        };
+        services
+            .iter()
+            .filter_map(|service| self.hostinfo_service_info(service))
+            .collect()
+    }
+
+    /// The service of a Hostinfo listener, if it is opted in: its description or port
+    /// is in HOSTINFO_SERVICES_ALLOW, or its description in INCLUDE_TAGS. Anything else
+    /// (sshd, databases, ...) would get a catch-all router otherwise.
+    fn hostinfo_service_info(&self, service: &HostinfoService) -> Option<ServiceInfo> {
+        let protocol = match service.proto.as_str() {
+            "tcp" => self.config.default_protocol.clone(),
+            "udp" => Protocol::Udp,
+            // peerapi4/peerapi6/peerapi-dns-proxy are Tailscale's own listeners
+            _ => return None,
+        };
+        let description = service
+            .description
+            .to_lowercase()
+            .replace(['.', '_', ' '], "-");
+        let port = service.port.to_string();
+        let allowed = self
+            .config
+            .hostinfo_services_allow
+            .as_ref()
+            .is_some_and(|allow| allow.contains(&description) || allow.contains(&port))
+            || self
+                .config
+                .include_tags
+                .as_ref()
+                .is_some_and(|include_tags| include_tags.contains(&description));
+        if !allowed {
+            return None;
+        }
+        let name = if description.is_empty() {
+            format!("port-{}", service.port)
+        } else {
+            format!("{}-{}", description, service.port)
+        };
+
+        Some(ServiceInfo {
+            name,
+            port: Some(service.port),
+            protocol,
+            scheme: self.config.scheme_for_port(service.port),
+            path_prefix: None,
+        })
+    }
+
+    /// Generate service name from service info. The node tag of a peer whose names
+    /// collided with another peer's is appended to its hostname.
+    fn generate_service_name_from_info(
+        &self,
+        peer: &PeerStatus,
+        node_tag: Option<&str>,
+        service_info: &ServiceInfo,
+    ) -> String {
+        let hostname_safe = match node_tag {
+            Some(tag) => format!("{}-{}", Self::hostname_safe(peer), tag),
+            None => Self::hostname_safe(peer),
+        };
+
+        if let Some(template) = &self.config.service_name_template {
+            let name = template
+                .replace("{hostname}", &hostname_safe)
+                .replace("{service}", &service_info.name);
+            // Without the hostname in the template the tag has nowhere else to go
+            return match node_tag {
+                Some(tag) if !template.contains("{hostname}") => format!("{}-{}", name, tag),
+                _ => name,
+            };
+        }
+
+        if service_info.name == "default" {
+            self.config.decorate_name(&hostname_safe)
+        } else {
+            self.config
+                .decorate_name(&format!("{}-{}", hostname_safe, service_info.name))
+        }
+    }
+
+    /// Check a generated name against the names reserved for other Traefik providers
+    fn is_reserved_name(&self, name: &str) -> bool {
+        let Some(reserved_names) = &self.config.reserved_names else {
+            return false;
+        };
+
+        reserved_names
+            .iter()
+            .any(|reserved| match reserved.strip_suffix('*') {
+                Some(prefix) => name.starts_with(prefix),
+                None => name == reserved,
+            })
+    }
+
+    /// Generate router name from service info
+    fn generate_router_name_from_info(
+        &self,
+        peer: &PeerStatus,
+        node_tag: Option<&str>,
+        service_info: &ServiceInfo,
+    ) -> String {
+        if self.config.service_name_template.is_some() {
+            let service_name = self.generate_service_name_from_info(peer, node_tag, service_info);
+            return format!("{}-router", service_name);
+        }
+
+        let hostname_safe = match node_tag {
+            Some(tag) => format!("{}-{}", Self::hostname_safe(peer), tag),
+            None => Self::hostname_safe(peer),
+        };
+        if service_info.name == "default" {
+            self.config
+                .decorate_name(&format!("{}-router", hostname_safe))
+        } else {
+            self.config
+                .decorate_name(&format!("{}-{}-router", hostname_safe, service_info.name))
+        }
+    }
+
+    /// Check if peer should be included in Traefik configuration
+    fn should_include_peer(&self, peer: &PeerStatus) -> bool {
+        if self.is_flapping(peer) {
+            return false;
+        }
+
+        // Only include online peers, or peers still within their offline grace period
+        if !self.online_or_within_grace(peer) {
+            return false;
+        }
+
+        self.filter_exclusion(peer).is_none()
+    }
+
+    /// Why the peer is left out of the configuration by the peer filters, if it is.
+    /// Unlike `should_include_peer` this does not record flaps or offline times.
+    pub fn exclusion_reason(&self, peer: &PeerStatus) -> Option<String> {
+        self.inclusion_checks(peer)
+            .into_iter()
+            .find(|check| !check.passed)
+            .map(|check| check.detail)
+    }
+
+    /// Every peer filter with its outcome for the peer, in the order they are applied
+    pub fn inclusion_checks(&self, peer: &PeerStatus) -> Vec<FilterCheck> {
+        let dampened = self
+            .flaps
+            .lock()
+            .unwrap()
+            .get(&peer.id)
+            .is_some_and(|history| history.dampened);
+        let mut checks = vec![FilterCheck::new(
+            "flapping",
+            !dampened,
+            if dampened {
+                "flapping online/offline (FLAP_THRESHOLD)".to_string()
+            } else {
+                "not flapping".to_string()
+            },
+        )];
+
+        let online = peer.online.unwrap_or(false);
+        let offline_for = self
+            .offline_since
+            .lock()
+            .unwrap()
+            .get(&peer.id)
+            .map(|since| Utc::now().signed_duration_since(*since).num_seconds());
+        let online_check = match (online, self.config.offline_grace_seconds, offline_for) {
+            (true, _, _) => FilterCheck::new("online", true, "online".to_string()),
+            (false, Some(grace), Some(offline_for)) if offline_for < grace => FilterCheck::new(
+                "online",
+                true,
+                format!("offline for {}s, within OFFLINE_GRACE_SECONDS", offline_for),
+            ),
+            (false, _, _) => FilterCheck::new("online", false, "offline".to_string()),
+        };
+        checks.push(online_check);
+
+        checks.extend(self.filter_checks(peer));
+        checks
+    }
+
+    /// How each tag of the peer is parsed into services, mirroring
+    /// `extract_service_infos_from_peer`
+    pub fn tag_checks(&self, peer: &PeerStatus) -> Vec<TagCheck> {
+        let include_tags = self.config.include_tags.as_ref();
+        let mut checks = Vec::new();
+
+        for tag in peer.tags.iter().flatten() {
+            let clean_tag = tag.strip_prefix("tag:").unwrap_or(tag);
+            let check = match self.config.parse_service_info_from_tag(tag) {
+                Some(info) if include_tags.is_some_and(|tags| !tags.contains(&info.name)) => {
+                    TagCheck {
+                        detail: format!("service {} is not in INCLUDE_TAGS", info.name),
+                        ..TagCheck::from_service(tag, &info, false)
+                    }
+                }
+                Some(info) => TagCheck {
+                    detail: format!(
+                        "declares service {} on port {} ({})",
+                        info.name,
+                        info.port.unwrap_or(self.config.default_port),
+                        info.scheme
+                    ),
+                    ..TagCheck::from_service(tag, &info, true)
+                },
+                None if clean_tag.contains("--") => TagCheck::without_service(
+                    tag,
+                    "attribute tag (service--key-value), declares no service",
+                ),
+                None => TagCheck::without_service(
+                    tag,
+                    "not in the service-port-protocol format (the port is not a number)",
+                ),
+            };
+            checks.push(check);
+
+            if let Some(mapped) = self
+                .config
+                .tag_service_mapping
+                .as_ref()
+                .and_then(|mapping| mapping.get(clean_tag))
+            {
+                let included = include_tags.is_none_or(|tags| tags.contains(&mapped.name));
+                checks.push(TagCheck {
+                    detail: if included {
+                        format!("mapped to service {} by TAG_SERVICE_MAPPING", mapped.name)
+                    } else {
+                        format!(
+                            "mapped to service {} by TAG_SERVICE_MAPPING, which is not in INCLUDE_TAGS",
+                            mapped.name
+                        )
+                    },
+                    ..TagCheck::from_service(tag, mapped, included)
+                });
+            }
+        }
+
+        checks
+    }
+
+    /// The configured peer filters, apart from the online state
+    fn filter_exclusion(&self, peer: &PeerStatus) -> Option<String> {
+        self.filter_checks(peer)
+            .into_iter()
+            .find(|check| !check.passed)
+            .map(|check| check.detail)
+    }
+
+    fn filter_checks(&self, peer: &PeerStatus) -> Vec<FilterCheck> {
+        let mut checks = Vec::new();
+
+        // Skip exit nodes if configured
+        if self.config.exclude_exit_nodes {
+            checks.push(if peer.exit_node {
+                FilterCheck::new(
+                    "exit_node",
+                    false,
+                    "exit node (EXCLUDE_EXIT_NODES)".to_string(),
+                )
+            } else {
+                FilterCheck::new("exit_node", true, "not an exit node".to_string())
+            });
+        }
+
+        // Check if peer matches include/exclude filters
+        if let Some(include_tags) = &self.config.include_tags {
+            let matching: Vec<&String> = peer
+                .tags
+                .iter()
+                .flatten()
+                .filter(|peer_tag| {
+                    // Remove "tag:" prefix before comparison
+                    let clean_peer_tag = peer_tag.strip_prefix("tag:").unwrap_or(peer_tag);
+                    include_tags.iter().any(|tag| clean_peer_tag.contains(tag))
+                })
+                .collect();
+            checks.push(match (&peer.tags, matching.first()) {
+                // Peer has no tags but we require tags - exclude it
+                (None, _) => FilterCheck::new(
+                    "include_tags",
+                    false,
+                    "untagged, INCLUDE_TAGS is set".to_string(),
+                ),
+                (Some(_), None) => FilterCheck::new(
+                    "include_tags",
+                    false,
+                    "no tag matches INCLUDE_TAGS".to_string(),
+                ),
+                (Some(_), Some(tag)) => FilterCheck::new(
+                    "include_tags",
+                    true,
+                    format!("{} matches INCLUDE_TAGS", tag),
+                ),
+            });
+        }
+
+        if let Some(exclude_hostnames) = &self.config.exclude_hostnames {
+            let excluded = exclude_hostnames.contains(&peer.hostname);
+            checks.push(FilterCheck::new(
+                "exclude_hostnames",
+                !excluded,
+                if excluded {
+                    "hostname in EXCLUDE_HOSTNAMES".to_string()
+                } else {
+                    "hostname not in EXCLUDE_HOSTNAMES".to_string()
+                },
+            ));
+        }
+
+        // Drained for maintenance through the admin API
+        let disabled = self.disabled.read().unwrap().peers.contains(&peer.hostname);
+        checks.push(FilterCheck::new(
+            "disabled",
+            !disabled,
+            if disabled {
+                "disabled through the admin API".to_string()
+            } else {
+                "not disabled".to_string()
+            },
+        ));
+
+        // Check if peer is too inactive based on max_inactive_seconds
+        if let Some(max_inactive) = self.config.max_inactive_seconds {
+            let now = self.now();
+            let epoch = Utc.timestamp_opt(0, 0).unwrap();
+
+            // If last_write is epoch time (zero), treat as "never written"
+            let inactive_duration = now.signed_duration_since(peer.last_write);
+            checks.push(if peer.last_write == epoch {
+                // Exclude peers that have never written
+                FilterCheck::new(
+                    "max_inactive",
+                    false,
+                    "never active (MAX_INACTIVE_SECONDS)".to_string(),
+                )
+            } else if inactive_duration.num_seconds() > max_inactive {
+                FilterCheck::new(
+                    "max_inactive",
+                    false,
+                    format!(
+                        "inactive for {}s (MAX_INACTIVE_SECONDS)",
+                        inactive_duration.num_seconds()
+                    ),
+                )
+            } else {
+                FilterCheck::new(
+                    "max_inactive",
+                    true,
+                    format!("active {}s ago", inactive_duration.num_seconds()),
+                )
+            });
+        }
+
+        // Check if peer matches include_os filter
+        if let Some(include_os) = &self.config.include_os {
+            let included = include_os.contains(&peer.os);
+            checks.push(FilterCheck::new(
+                "include_os",
+                included,
+                format!(
+                    "OS {} {} INCLUDE_OS",
+                    peer.os,
+                    if included { "in" } else { "not in" }
+                ),
+            ));
+        }
+
+        if let Some(rules) = &self.config.posture_attributes {
+            let failed: Vec<&str> = rules
+                .iter()
+                .filter(|(key, value)| !posture_matches(peer, key, value))
+                .map(|(key, _)| key.as_str())
+                .collect();
+            checks.push(if failed.is_empty() {
+                FilterCheck::new(
+                    "posture",
+                    true,
+                    "posture attributes match POSTURE_ATTRIBUTES".to_string(),
+                )
+            } else {
+                FilterCheck::new(
+                    "posture",
+                    false,
+                    format!(
+                        "posture attributes {} don't match POSTURE_ATTRIBUTES",
+                        failed.join(", ")
+                    ),
+                )
+            });
+        }
+
+        // Exclude expired peers if configured
+        if self.config.exclude_expired {
+            let expired = peer.expired.unwrap_or(false);
+            checks.push(FilterCheck::new(
+                "expired",
+                !expired,
+                if expired {
+                    "key expired (EXCLUDE_EXPIRED)".to_string()
+                } else {
+                    "key not expired".to_string()
+                },
+            ));
+        }
+
+        if self.config.exclude_shared_nodes {
+            let shared = peer.sharee_node.unwrap_or(false);
+            checks.push(FilterCheck::new(
+                "shared_node",
+                !shared,
+                if shared {
+                    "shared into the tailnet (EXCLUDE_SHARED_NODES)".to_string()
+                } else {
+                    "not a shared node".to_string()
+                },
+            ));
+        }
+
+        // Relayed peers have no direct endpoint in CurAddr
+        if self.config.direct_connections_only {
+            let relayed = peer.cur_addr.is_empty() || !peer.peer_relay.is_empty();
+            checks.push(FilterCheck::new(
+                "direct_connection",
+                !relayed,
+                if relayed {
+                    "relayed connection (DIRECT_CONNECTIONS_ONLY)".to_string()
+                } else {
+                    format!("direct connection to {}", peer.cur_addr)
+                },
+            ));
+        }
+
+        checks
+    }
+
+    /// Record the peer's online/offline transitions and check whether it changed state more
+    /// than FLAP_THRESHOLD times within FLAP_WINDOW_SECONDS
+    fn is_flapping(&self, peer: &PeerStatus) -> bool {
+        let Some(threshold) = self.config.flap_threshold else {
+            return false;
+        };
+
+        let now = Utc::now();
+        let online = peer.online.unwrap_or(false);
+        let window = chrono::Duration::seconds(self.config.flap_window_seconds);
+
+        let mut flaps = self.flaps.lock().unwrap();
+        let history = flaps.entry(peer.id.clone()).or_insert_with(|| FlapHistory {
+            online,
+            transitions: VecDeque::new(),
+            dampened: false,
+        });
+        if history.online != online {
+            history.online = online;
+            history.transitions.push_back(now);
+        }
+        while history
+            .transitions
+            .front()
+            .is_some_and(|transition| now.signed_duration_since(*transition) > window)
+        {
+            history.transitions.pop_front();
+        }
+
+        let flapping = history.transitions.len() > threshold;
+        if flapping && !history.dampened {
+            warn!(
+                "Dampening peer {}: {} online/offline transitions within {}s",
+                peer.hostname,
+                history.transitions.len(),
+                self.config.flap_window_seconds
+            );
+            metrics().peer_dampenings.inc();
+        } else if !flapping && history.dampened {
+            info!("Peer {} stopped flapping", peer.hostname);
+        }
+        history.dampened = flapping;
+
+        let dampened = flaps.values().filter(|history| history.dampened).count();
+        metrics().dampened_peers.set(dampened as i64);
+        flapping
+    }
+
+    /// Whether a peer is online, or went offline less than OFFLINE_GRACE_SECONDS ago
+    fn online_or_within_grace(&self, peer: &PeerStatus) -> bool {
+        let mut offline_since = self.offline_since.lock().unwrap();
+        if peer.online.unwrap_or(false) {
+            offline_since.remove(&peer.id);
+            return true;
+        }
+
+        let Some(grace) = self.config.offline_grace_seconds else {
+            return false;
+        };
+        // Seeded from when tailscaled last saw the peer, so restarting the provider doesn't
+        // restart the grace period; peers never seen (zero time) start it now
+        let since = *offline_since.entry(peer.id.clone()).or_insert_with(|| {
+            let now = Utc::now();
+            if peer.last_seen > DateTime::UNIX_EPOCH {
+                peer.last_seen.min(now)
+            } else {
+                now
+            }
+        });
+        let within_grace = Utc::now().signed_duration_since(since).num_seconds() < grace;
+        if within_grace {
+            debug!(
+                "Keeping offline peer {} during its grace period",
+                peer.hostname
+            );
+        }
+        within_grace
+    }
+
+    /// Pick the peer's Tailscale addresses for the configured address family (falling back
+    /// to the other family, so single-stack peers still work) and format them as
+    /// "ip:port", with brackets for IPv6
+    fn peer_socket_addresses(&self, peer: &PeerStatus, port: u16) -> Option<Vec<String>> {
+        let ips: Vec<IpAddr> = peer
+            .tailscale_ips
+            .iter()
+            .filter_map(|ip| match ip.parse() {
+                Ok(ip) => Some(ip),
+                Err(_) => {
+                    warn!("Peer {} has invalid Tailscale IP {}", peer.hostname, ip);
+                    None
+                }
+            })
+            .collect();
+
+        if ips.is_empty() {
+            warn!("Peer {} has no Tailscale IPs", peer.hostname);
+            return None;
+        }
+
+        let selected: Vec<&IpAddr> = match self.config.address_family {
+            AddressFamily::Both => ips.iter().collect(),
+            family => {
+                let prefer_ipv6 = family == AddressFamily::Ipv6;
+                ips.iter()
+                    .find(|ip| ip.is_ipv6() == prefer_ipv6)
+                    .or_else(|| ips.first())
+                    .into_iter()
+                    .collect()
+            }
+        };
+
+        Some(
+            selected
+                .into_iter()
+                .map(|ip| SocketAddr::new(*ip, port).to_string())
+                .collect(),
+        )
+    }
+
+    /// Backend weight of a peer's service: the weight from its capacity hint (default 1),
+    /// scaled by the last-seen decay when enabled
+    fn server_weight(&self, peer: &PeerStatus, service_info: &ServiceInfo) -> i32 {
+        let hint_weight = self
+            .capacity_hint(peer, service_info)
+            .and_then(|hint| hint.weight)
+            .map(|weight| weight.clamp(1, i32::MAX as u32) as i32)
+            .unwrap_or(1);
+
+        hint_weight
+            .saturating_mul(self.decay_weight(peer))
+            .saturating_mul(self.latency_weight(peer))
+    }
+
+    /// Ping the peers concurrently and remember their round trips for filtering and weighting
+    #[instrument(skip_all, fields(peers = peers.len()))]
+    async fn measure_latencies(&self, peers: &[&PeerStatus]) {
+        let mut pings = JoinSet::new();
+        for peer in peers {
+            let Some(ip) = peer.tailscale_ips.first().cloned() else {
+                continue;
+            };
+            let client = self.tailscale_client.clone();
+            let id = peer.id.clone();
+            let hostname = peer.hostname.clone();
+            pings.spawn(async move {
+                match tokio::time::timeout(PING_TIMEOUT, client.ping(&ip)).await {
+                    Ok(Ok(result)) => Some((id, result.latency_seconds)),
+                    Ok(Err(e)) => {
+                        debug!("Failed to ping peer {}: {}", hostname, e);
+                        None
+                    }
+                    Err(_) => {
+                        debug!("Ping to peer {} timed out", hostname);
+                        None
+                    }
+                }
+            });
+        }
+
+        let mut latencies = HashMap::new();
+        while let Some(result) = pings.join_next().await {
+            if let Ok(Some((id, latency))) = result {
+                latencies.insert(id, latency);
+            }
+        }
+        *self.latencies.write().unwrap() = latencies;
+    }
+
+    /// Whether the peer answered the last ping within the threshold
+    fn within_latency(&self, peer: &PeerStatus, max_latency_ms: u64) -> bool {
+        self.latencies
+            .read()
+            .unwrap()
+            .get(&peer.id)
+            .is_some_and(|latency| latency * 1000.0 <= max_latency_ms as f64)
+    }
+
+    /// With LATENCY_WEIGHTING, the weight falls from MAX_SERVER_WEIGHT for the nearest peer
+    /// in proportion to the round trip; unmeasured peers get 1, as does everyone otherwise.
+    fn latency_weight(&self, peer: &PeerStatus) -> i32 {
+        if !self.config.latency_weighting {
+            return 1;
+        }
+
+        let latencies = self.latencies.read().unwrap();
+        let Some(latency) = latencies.get(&peer.id) else {
+            return 1;
+        };
+        let nearest = latencies.values().copied().fold(f64::INFINITY, f64::min);
+        if *latency <= 0.0 {
+            return MAX_SERVER_WEIGHT;
+        }
+        ((MAX_SERVER_WEIGHT as f64 * nearest / latency).round() as i32).clamp(1, MAX_SERVER_WEIGHT)
+    }
+
+    /// Current time for time-based checks, rounded down to TIME_QUANTUM_SECONDS in
+    /// deterministic mode so replicas refreshing moments apart agree
+    fn now(&self) -> DateTime<Utc> {
+        let now = Utc::now();
+        if !self.config.deterministic_output {
+            return now;
+        }
+        let quantum = self.config.time_quantum_seconds.max(1) as i64;
+        let seconds = now.timestamp() - now.timestamp().rem_euclid(quantum);
+        Utc.timestamp_opt(seconds, 0).single().unwrap_or(now)
+    }
+
+    /// With WEIGHT_DECAY_SECONDS set, the weight decays in WEIGHT_DECAY_STEPS steps from
+    /// MAX_SERVER_WEIGHT to 1 as the age of the peer's most recent handshake/last-seen
+    /// time approaches the window; otherwise every peer gets weight 1.
+    fn decay_weight(&self, peer: &PeerStatus) -> i32 {
+        let Some(window) = self
+            .config
+            .weight_decay_seconds
+            .filter(|window| *window > 0)
+        else {
+            return 1;
+        };
+
+        // Never-verified peers carry zero timestamps and end up with the lowest weight
+        let last_verified = peer.last_handshake.max(peer.last_seen);
+        let age = self
+            .now()
+            .signed_duration_since(last_verified)
+            .num_seconds()
+            .clamp(0, window);
+        let step = (window / WEIGHT_DECAY_STEPS).max(1);
+        let elapsed_steps = (age / step).min(WEIGHT_DECAY_STEPS);
+        let remaining = (WEIGHT_DECAY_STEPS - elapsed_steps) as f64 / WEIGHT_DECAY_STEPS as f64;
+        ((MAX_SERVER_WEIGHT as f64 * remaining).round() as i32).max(1)
+    }
+
+    /// Create HTTP service from Tailscale peer
+    fn create_http_service_from_peer(
+        &self,
+        peer: &PeerStatus,
+        service_info: &ServiceInfo,
+    ) -> Option<Service> {
+        let port = service_info.port.unwrap_or(self.config.default_port);
+        let weight = self.server_weight(peer, service_info);
+        let servers = self
+            .peer_socket_addresses(peer, port)?
+            .into_iter()
+            .map(|address| Server {
+                url: format!("{}://{}", service_info.scheme, address),
+                weight: Some(weight),
+            })
+            .collect();
+
+        Some(Service {
+            load_balancer: LoadBalancer {
+                servers,
+                health_check: self.resolve_health_check(peer, service_info),
+                servers_transport: self.servers_transport_for(service_info, port),
+            },
+        })
+    }
+
+    /// Health check of an HTTP service - HEALTH_CHECK_PATH, with the fields set by
+    /// "service--healthcheck-<field>-<value>" tag attributes (which can also add one).
+    /// A "service--healthcheck-false" (or "-true") tag attribute takes precedence over
+    /// HEALTH_CHECK_SKIP.
+    fn resolve_health_check(
+        &self,
+        peer: &PeerStatus,
+        service_info: &ServiceInfo,
+    ) -> Option<HealthCheck> {
+        let switch = self
+            .tag_attributes_for_service(peer, &service_info.name)
+            .filter(|attribute| attribute.key == "healthcheck")
+            .find_map(|attribute| match attribute.values.as_slice() {
+                [value] if value == "false" => Some(false),
+                [value] if value == "true" => Some(true),
+                _ => None,
             });
+        let enabled = switch.unwrap_or_else(|| {
+            self.config
+                .health_check_skip
+                .as_ref()
+                .is_none_or(|services| {
+                    !services
+                        .iter()
+                        .any(|service| service == "*" || *service == service_info.name)
+                })
+        });
+        if !enabled {
+            return None;
         }
 
-        // Check tag-service mapping for additional services
-        if let Some(mapping) = &self.config.tag_service_mapping {
-            if let Some(peer_tags) = &peer.tags {
-                for peer_tag in peer_tags {
-                    // Remove "tag:" prefix if present
-                    let clean_tag = peer_tag.strip_prefix("tag:").unwrap_or(peer_tag);
-                    if let Some(mapped_service) = mapping.get(clean_tag) {
-                        // Check if this service should be included
-                        if let Some(include_tags) = &self.config.include_tags {
-                            if include_tags.contains(&mapped_service.name) {
-                                service_infos.push(mapped_service.clone());
-                            }
-                        } else {
-                            service_infos.push(mapped_service.clone());
-                        }
-                    }
-                }
+        let mut health_check = self
+            .config
+            .health_check_path
+            .as_ref()
+            .map(|path| HealthCheck {
+                path: path.clone(),
+                interval: Some("30s".to_string()),
+                timeout: Some("5s".to_string()),
+                ..Default::default()
+            });
+
+        let mut patch = HealthCheckPatch::default();
+        for attribute in self
+            .tag_attributes_for_service(peer, &service_info.name)
+            .filter(|attribute| attribute.key == "healthcheck")
+        {
+            let Some((field, value)) = attribute.values.split_first() else {
+                continue;
+            };
+            if value.is_empty() && (field == "false" || field == "true") {
+                continue;
+            }
+            if let Err(e) = patch.set(&field.to_lowercase(), value.join("-")) {
+                warn!(
+                    "Peer {} has an invalid health check attribute for {}: {}",
+                    peer.hostname, service_info.name, e
+                );
             }
         }
+        patch.apply(&mut health_check);
+        health_check
+    }
 
-        service_infos
+    /// Name of the skip-verify serversTransport for https backends on well-known HTTPS
+    /// ports, which are usually served with certificates Traefik can't verify
+    fn servers_transport_for(&self, service_info: &ServiceInfo, port: u16) -> Option<String> {
+        (self.config.https_insecure_skip_verify
+            && service_info.scheme == "https"
+            && self.config.https_ports.contains(&port))
+        .then(|| self.config.decorate_name("insecure-transport"))
     }
 
-    /// Generate service name from service info
-    fn generate_service_name_from_info(
+    /// Create HTTP router for a peer
+    fn create_http_router_for_peer(
         &self,
         peer: &PeerStatus,
         service_info: &ServiceInfo,
-    ) -> String {
-        let hostname_safe = peer.hostname.to_lowercase().replace(['.', '_'], "-");
-        if service_info.name == "default" {
-            format!("tailscale-{}", hostname_safe)
-        } else {
-            format!("tailscale-{}-{}", hostname_safe, service_info.name)
+        service_name: &str,
+        tailnet: &str,
+    ) -> Option<Router> {
+        // Check if this service has a custom domain mapping
+        let custom_domain = self
+            .config
+            .service_domain_mapping
+            .as_ref()
+            .and_then(|domain_mapping| domain_mapping.get(&service_info.name));
+
+        let granted_rule = self
+            .service_grant(peer, service_info)
+            .and_then(|grant| grant.rule);
+
+        let mut rule = match (granted_rule, custom_domain) {
+            // A rule granted by the tailnet policy wins over everything else
+            (Some(rule), _) => rule,
+            // Use custom domain for this service
+            (None, Some(domain)) => format!("Host(`{}`)", domain),
+            // No custom domain, use default behavior
+            (None, None) => self.generate_default_host_rule(peer, service_info, tailnet)?,
+        };
+
+        if let Some(path_prefix) = &service_info.path_prefix {
+            rule = format!("{} && PathPrefix(`{}`)", rule, path_prefix);
         }
+
+        Some(Router {
+            entry_points: None,
+            rule,
+            service: service_name.to_string(),
+            middlewares: None,
+            priority: self.resolve_router_priority(peer, service_info),
+            tls: self
+                .resolve_tls_options(peer, service_info)
+                .map(|options| TlsConfig {
+                    options: Some(options),
+                    ..Default::default()
+                }),
+        })
     }
 
-    /// Generate router name from service info
-    fn generate_router_name_from_info(
+    /// Create the middlewares attached to a peer's HTTP router, in the order they apply
+    fn create_http_middlewares_for_peer(
         &self,
         peer: &PeerStatus,
         service_info: &ServiceInfo,
-    ) -> String {
-        let service_name = self.generate_service_name_from_info(peer, service_info);
-        format!("{}-router", service_name)
-    }
+        service_name: &str,
+    ) -> Vec<(String, Middleware)> {
+        let mut middlewares = Vec::new();
 
-    /// Check if peer should be included in Traefik configuration
-    fn should_include_peer(&self, peer: &PeerStatus) -> bool {
-        // Only include online peers
-        if !peer.online.unwrap_or(false) {
-            return false;
+        if let Some(rate_limit) = self.resolve_rate_limit(peer, service_info) {
+            middlewares.push((
+                format!("{}-ratelimit", service_name),
+                Middleware {
+                    rate_limit: Some(rate_limit),
+                    ..Default::default()
+                },
+            ));
         }
 
-        // Skip exit nodes if configured
-        if self.config.exclude_exit_nodes && peer.exit_node {
-            return false;
+        // A single compress middleware is shared by every router
+        if self.config.compress_responses {
+            middlewares.push((
+                self.config.decorate_name("compress"),
+                Middleware {
+                    compress: Some(CompressMiddleware {}),
+                    ..Default::default()
+                },
+            ));
         }
 
-        // Check if peer matches include/exclude filters
-        if let Some(include_tags) = &self.config.include_tags {
-            // Check if peer has any of the required tags
-            if let Some(peer_tags) = &peer.tags {
-                let has_matching_tag = include_tags.iter().any(|tag| {
-                    peer_tags.iter().any(|peer_tag| {
-                        // Remove "tag:" prefix before comparison
-                        let clean_peer_tag = peer_tag.strip_prefix("tag:").unwrap_or(peer_tag);
-                        clean_peer_tag.contains(tag)
-                    })
-                });
-                if !has_matching_tag {
-                    return false;
-                }
-            } else {
-                // Peer has no tags but we require tags - exclude it
-                return false;
-            }
+        if let Some(max_concurrent) = self
+            .capacity_hint(peer, service_info)
+            .and_then(|hint| hint.max_concurrent)
+        {
+            middlewares.push((
+                format!("{}-inflightreq", service_name),
+                Middleware {
+                    in_flight_req: Some(InFlightReqMiddleware {
+                        amount: max_concurrent,
+                    }),
+                    ..Default::default()
+                },
+            ));
         }
 
-        if let Some(exclude_hostnames) = &self.config.exclude_hostnames {
-            if exclude_hostnames.contains(&peer.hostname) {
-                return false;
-            }
+        // Strip the routed path prefix last, right before forwarding to the backend
+        if let Some(path_prefix) = &service_info.path_prefix
+            && self.should_strip_path_prefix(peer, service_info)
+        {
+            middlewares.push((
+                format!("{}-stripprefix", service_name),
+                Middleware {
+                    strip_prefix: Some(StripPrefixMiddleware {
+                        prefixes: vec![path_prefix.clone()],
+                    }),
+                    ..Default::default()
+                },
+            ));
         }
 
-        // Check if peer is too inactive based on max_inactive_seconds
-        if let Some(max_inactive) = self.config.max_inactive_seconds {
-            use chrono::{TimeZone, Utc};
-            let now = Utc::now();
-            let epoch = Utc.timestamp_opt(0, 0).unwrap();
+        middlewares
+    }
 
-            // If last_write is epoch time (zero), treat as "never written"
-            if peer.last_write == epoch {
-                return false; // Exclude peers that have never written
-            }
+    /// Whether a service's path prefix is stripped - a "service--stripprefix" (or
+    /// "service--stripprefix-false") tag attribute takes precedence over config
+    fn should_strip_path_prefix(&self, peer: &PeerStatus, service_info: &ServiceInfo) -> bool {
+        let from_tag = self
+            .tag_attributes_for_service(peer, &service_info.name)
+            .find(|attribute| attribute.key == "stripprefix")
+            .map(|attribute| {
+                attribute
+                    .values
+                    .first()
+                    .is_none_or(|value| value != "false")
+            });
 
-            let inactive_duration = now.signed_duration_since(peer.last_write);
-            if inactive_duration.num_seconds() > max_inactive {
-                return false;
-            }
-        }
+        from_tag.unwrap_or_else(|| {
+            self.config
+                .strip_path_prefix
+                .as_ref()
+                .is_some_and(|services| {
+                    services
+                        .iter()
+                        .any(|service| service == "*" || *service == service_info.name)
+                })
+        })
+    }
 
-        // Check if peer matches include_os filter
-        if let Some(include_os) = &self.config.include_os {
-            if !include_os.contains(&peer.os) {
-                return false;
-            }
-        }
+    /// Names of user-defined middlewares a peer references through "service--middleware-name"
+    /// tags, with bundles replaced by their middlewares
+    fn referenced_middlewares(&self, peer: &PeerStatus, service_info: &ServiceInfo) -> Vec<String> {
+        let granted = self
+            .service_grant(peer, service_info)
+            .map(|grant| grant.middlewares)
+            .unwrap_or_default();
 
-        // Exclude expired peers if configured
-        if self.config.exclude_expired {
-            if peer.expired.unwrap_or(false) {
-                return false;
+        let mut names = Vec::new();
+        for name in self
+            .tag_attributes_for_service(peer, &service_info.name)
+            .filter(|attribute| attribute.key == "middleware" && !attribute.values.is_empty())
+            .map(|attribute| attribute.values.join("-"))
+            .chain(granted)
+        {
+            let expanded = match self.middleware_bundles.get(&name) {
+                Some(members) => members.clone(),
+                None => {
+                    if !self.file_middlewares.contains_key(&name) {
+                        warn!(
+                            "Peer {} references unknown middleware {}",
+                            peer.hostname, name
+                        );
+                    }
+                    vec![name]
+                }
+            };
+            // A middleware already attached, e.g. by an overlapping bundle, applies once
+            for name in expanded {
+                if !names.contains(&name) {
+                    names.push(name);
+                }
             }
         }
-
-        true
+        names
     }
 
-
-    /// Create HTTP service from Tailscale peer
-    fn create_http_service_from_peer(
+    /// Resolve the router priority of a service - a "service--priority-10" tag attribute
+    /// takes precedence over the per-service and default priorities from config
+    fn resolve_router_priority(
         &self,
         peer: &PeerStatus,
         service_info: &ServiceInfo,
-    ) -> Option<Service> {
-        if peer.tailscale_ips.is_empty() {
-            warn!("Peer {} has no Tailscale IPs", peer.hostname);
-            return None;
+    ) -> Option<i32> {
+        let from_tag = self
+            .tag_attributes_for_service(peer, &service_info.name)
+            .filter(|attribute| attribute.key == "priority")
+            .find_map(|attribute| attribute.values.first()?.parse().ok());
+
+        from_tag
+            .or_else(|| {
+                self.config
+                    .router_priorities
+                    .as_ref()
+                    .and_then(|priorities| priorities.get(&service_info.name).copied())
+            })
+            .or(self.config.default_router_priority)
+    }
+
+    /// TLS option set of a service's router from a "service--tlsoptions-name" tag attribute
+    fn resolve_tls_options(&self, peer: &PeerStatus, service_info: &ServiceInfo) -> Option<String> {
+        let name = self
+            .tag_attributes_for_service(peer, &service_info.name)
+            .filter(|attribute| attribute.key == "tlsoptions" && !attribute.values.is_empty())
+            .map(|attribute| attribute.values.join("-"))
+            .next()?;
+        // Option sets of other providers ("name@file") aren't known here
+        if !self.tls_options.contains_key(&name) && !name.contains('@') {
+            warn!(
+                "Peer {} references unknown TLS options {}",
+                peer.hostname, name
+            );
         }
+        Some(name)
+    }
 
-        // Use the first Tailscale IP
-        let ip = &peer.tailscale_ips[0];
-        let port = service_info.port.unwrap_or(self.config.default_port);
+    /// Capacity hint a peer advertises for a service through CAPACITY_CAPABILITY
+    fn capacity_hint(&self, peer: &PeerStatus, service_info: &ServiceInfo) -> Option<CapacityHint> {
+        let capability = self.config.capacity_capability.as_ref()?;
+        capacity_hint(peer, capability, &service_info.name)
+    }
 
-        let server = Server {
-            url: format!("{}://{}:{}", service_info.scheme, ip, port),
-            weight: Some(1),
+    /// Services granted to a peer through SERVICE_CAPABILITY
+    fn granted_service_infos(&self, peer: &PeerStatus) -> Vec<ServiceInfo> {
+        let Some(capability) = &self.config.service_capability else {
+            return Vec::new();
         };
 
-        Some(Service {
-            load_balancer: LoadBalancer {
-                servers: vec![server],
-                health_check: self.config.health_check_path.as_ref().map(|path| {
-                    crate::traefik::HealthCheck {
-                        path: path.clone(),
-                        interval: Some("30s".to_string()),
-                        timeout: Some("5s".to_string()),
-                    }
-                }),
-            },
+        service_grants(peer, capability)
+            .iter()
+            .filter(|grant| {
+                self.config
+                    .include_tags
+                    .as_ref()
+                    .is_none_or(|include_tags| include_tags.contains(&grant.name))
+            })
+            .map(|grant| grant.service_info(&self.config))
+            .collect()
+    }
+
+    /// The grant a service came from, if any
+    fn service_grant(&self, peer: &PeerStatus, service_info: &ServiceInfo) -> Option<ServiceGrant> {
+        let capability = self.config.service_capability.as_ref()?;
+        service_grant(peer, capability, &service_info.name)
+    }
+
+    /// Resolve the rate limit for a service - tag attributes take precedence over config
+    fn resolve_rate_limit(
+        &self,
+        peer: &PeerStatus,
+        service_info: &ServiceInfo,
+    ) -> Option<RateLimitMiddleware> {
+        let from_tag = self
+            .tag_attributes_for_service(peer, &service_info.name)
+            .filter(|attribute| attribute.key == "ratelimit")
+            .find_map(|attribute| ProviderConfig::parse_rate_limit(&attribute.values));
+
+        from_tag.or_else(|| {
+            self.config
+                .rate_limits
+                .as_ref()
+                .and_then(|limits| limits.get(&service_info.name).cloned())
         })
     }
 
-    /// Create HTTP router for a peer
-    fn create_http_router_for_peer(
+    /// Iterate over the tag attributes a peer declares for the given service
+    fn tag_attributes_for_service<'a>(
+        &self,
+        peer: &'a PeerStatus,
+        service: &'a str,
+    ) -> impl Iterator<Item = TagAttribute> + 'a {
+        peer.tags
+            .iter()
+            .flatten()
+            .filter_map(|tag| TagAttribute::parse(tag))
+            .filter(move |attribute| attribute.service == service)
+    }
+
+    /// Generate default host rule - rendered from the configured template, or a
+    /// wildcard accepting all requests unless strict rules are enabled
+    fn generate_default_host_rule(
         &self,
         peer: &PeerStatus,
         service_info: &ServiceInfo,
-        service_name: &str,
-    ) -> Option<Router> {
-        // Check if this service has a custom domain mapping
-        let rule = if let Some(domain_mapping) = &self.config.service_domain_mapping {
-            if let Some(domain) = domain_mapping.get(&service_info.name) {
-                // Use custom domain for this service
-                format!("Host(`{}`)", domain)
-            } else {
-                // No custom domain, use default behavior
-                self.generate_default_host_rule(peer)
+        tailnet: &str,
+    ) -> Option<String> {
+        let magicdns = peer.dns_name.trim_end_matches('.');
+        let template = self
+            .config
+            .host_rule_template
+            .as_ref()
+            // A peer without a MagicDNS name can't fill the {magicdns} placeholder
+            .filter(|template| !(magicdns.is_empty() && template.contains("{magicdns}")));
+
+        match template {
+            Some(template) => Some(
+                template
+                    .replace("{hostname}", &Self::hostname_safe(peer))
+                    .replace("{service}", &service_info.name)
+                    .replace("{magicdns}", magicdns)
+                    .replace("{tailnet}", tailnet),
+            ),
+            None if self.config.strict_rules => {
+                Self::audit_strict_drop(
+                    peer,
+                    service_info,
+                    self.config.traefik_version.catch_all_http_rule(),
+                );
+                None
             }
-        } else {
-            // No domain mapping configured, use default behavior
-            self.generate_default_host_rule(peer)
-        };
+            None => Some(
+                self.config
+                    .traefik_version
+                    .catch_all_http_rule()
+                    .to_string(),
+            ),
+        }
+    }
 
-        Some(Router {
-            rule,
-            service: service_name.to_string(),
-            middlewares: None,
-            priority: None,
-            tls: None,
-        })
+    /// Report a service dropped because it would only get a catch-all rule
+    fn audit_strict_drop(peer: &PeerStatus, service_info: &ServiceInfo, fallback_rule: &str) {
+        warn!(
+            target: "audit",
+            "Dropped service {} of peer {}: no host mapping and strict rules forbid {}",
+            service_info.name, peer.hostname, fallback_rule
+        );
     }
 
-    /// Generate default host rule - wildcard to accept all requests
-    fn generate_default_host_rule(&self, _peer: &PeerStatus) -> String {
-        "HostRegexp(`.*`)".to_string()
+    /// Peer hostname normalized for use in names and DNS labels
+    fn hostname_safe(peer: &PeerStatus) -> String {
+        peer.hostname.to_lowercase().replace(['.', '_'], "-")
     }
 
     /// Create TCP service from Tailscale peer
@@ -379,23 +2599,19 @@ impl TraefikProvider {
         peer: &PeerStatus,
         service_info: &ServiceInfo,
     ) -> Option<TcpService> {
-        if peer.tailscale_ips.is_empty() {
-            warn!("Peer {} has no Tailscale IPs", peer.hostname);
-            return None;
-        }
-
-        let ip = &peer.tailscale_ips[0];
         let port = service_info.port.unwrap_or(self.config.default_port);
-
-        let server = TcpServer {
-            address: format!("{}:{}", ip, port),
-            weight: Some(1),
-        };
+        let weight = self.server_weight(peer, service_info);
+        let servers = self
+            .peer_socket_addresses(peer, port)?
+            .into_iter()
+            .map(|address| TcpServer {
+                address,
+                weight: Some(weight),
+            })
+            .collect();
 
         Some(TcpService {
-            load_balancer: TcpLoadBalancer {
-                servers: vec![server],
-            },
+            load_balancer: TcpLoadBalancer { servers },
         })
     }
 
@@ -407,23 +2623,37 @@ impl TraefikProvider {
         service_name: &str,
     ) -> Option<TcpRouter> {
         // Check if this service has a custom domain mapping for SNI
-        let rule = if let Some(domain_mapping) = &self.config.service_domain_mapping {
-            if let Some(domain) = domain_mapping.get(&service_info.name) {
-                // Use HostSNI with custom domain (for TLS-enabled TCP services)
-                format!("HostSNI(`{}`)", domain)
-            } else {
-                // No custom domain, accept all connections
-                "HostSNI(`*`)".to_string()
+        let custom_domain = self
+            .config
+            .service_domain_mapping
+            .as_ref()
+            .and_then(|domain_mapping| domain_mapping.get(&service_info.name));
+
+        let granted_rule = self
+            .service_grant(peer, service_info)
+            .and_then(|grant| grant.rule);
+
+        let rule = match (granted_rule, custom_domain) {
+            // A rule granted by the tailnet policy wins over everything else
+            (Some(rule), _) => rule,
+            // Use HostSNI with custom domain (for TLS-enabled TCP services)
+            (None, Some(domain)) => format!("HostSNI(`{}`)", domain),
+            (None, None) if self.config.strict_rules => {
+                Self::audit_strict_drop(peer, service_info, "HostSNI(`*`)");
+                return None;
             }
-        } else {
-            // No domain mapping, accept all connections
-            "HostSNI(`*`)".to_string()
+            // No custom domain, accept all connections
+            (None, None) => "HostSNI(`*`)".to_string(),
         };
 
         Some(TcpRouter {
+            entry_points: None,
             rule,
             service: service_name.to_string(),
-            tls: None,
+            // "service-port-tls" backends terminate TLS themselves
+            tls: (service_info.scheme == "tls").then_some(TcpTlsConfig {
+                passthrough: Some(true),
+            }),
         })
     }
 
@@ -433,23 +2663,19 @@ impl TraefikProvider {
         peer: &PeerStatus,
         service_info: &ServiceInfo,
     ) -> Option<UdpService> {
-        if peer.tailscale_ips.is_empty() {
-            warn!("Peer {} has no Tailscale IPs", peer.hostname);
-            return None;
-        }
-
-        let ip = &peer.tailscale_ips[0];
         let port = service_info.port.unwrap_or(self.config.default_port);
-
-        let server = UdpServer {
-            address: format!("{}:{}", ip, port),
-            weight: Some(1),
-        };
+        let weight = self.server_weight(peer, service_info);
+        let servers = self
+            .peer_socket_addresses(peer, port)?
+            .into_iter()
+            .map(|address| UdpServer {
+                address,
+                weight: Some(weight),
+            })
+            .collect();
 
         Some(UdpService {
-            load_balancer: UdpLoadBalancer {
-                servers: vec![server],
-            },
+            load_balancer: UdpLoadBalancer { servers },
         })
     }
 
@@ -462,6 +2688,7 @@ impl TraefikProvider {
     ) -> Option<UdpRouter> {
         // UDP routers are simple - just point to service
         Some(UdpRouter {
+            entry_points: None,
             service: service_name.to_string(),
         })
     }
@@ -471,6 +2698,641 @@ impl TraefikProvider {
         info!("Testing connection to Tailscale daemon");
         self.tailscale_client.test_connection().await?;
         info!("Successfully connected to Tailscale daemon");
+        for source in &self.sources {
+            if let Err(e) = source.provider.tailscale_client.test_connection().await {
+                warn!(
+                    "Failed to connect to Tailscale source {}: {}",
+                    source.name, e
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Clients of the further tailscaled instances of TAILSCALE_SOURCES, by source name
+    pub fn source_clients(&self) -> impl Iterator<Item = (&str, &TailscaleClient)> {
+        self.sources.iter().map(|source| {
+            (
+                source.name.as_str(),
+                source.provider.tailscale_client.as_ref(),
+            )
+        })
+    }
+
+    /// Tailscale identity behind an address, asked of the tailscaled whose tailnet has a
+    /// node with that address. Tailnets may share addresses, so the primary tailscaled
+    /// is asked first, and also when no tailnet knows the address.
+    pub async fn whois(&self, addr: &str) -> Result<WhoIsResponse, TailscaleError> {
+        if !self.sources.is_empty() {
+            let clients = std::iter::once(self.tailscale_client.as_ref())
+                .chain(self.source_clients().map(|(_, client)| client));
+            for client in clients {
+                match client.get_status().await {
+                    Ok(status) if has_address(&status, addr) => return client.whois(addr).await,
+                    Ok(_) => {}
+                    Err(e) => debug!("Can't look {} up in a tailnet: {}", addr, e),
+                }
+            }
+        }
+        self.tailscale_client.whois(addr).await
+    }
+}
+
+/// Whether a node of the tailnet, this one included, has the Tailscale IP `addr`
+fn has_address(status: &Status, addr: &str) -> bool {
+    status
+        .peers
+        .iter()
+        .flat_map(|peers| peers.values().flatten())
+        .chain(status.self_peer.as_ref())
+        .any(|node| node.tailscale_ips.iter().any(|ip| ip == addr))
+}
+
+/// Flatten bundles naming other bundles into the middlewares they attach, in order
+fn expand_bundles(
+    bundles: &BTreeMap<String, Vec<String>>,
+) -> Result<BTreeMap<String, Vec<String>>, String> {
+    fn expand(
+        bundles: &BTreeMap<String, Vec<String>>,
+        name: &str,
+        path: &mut Vec<String>,
+        expanded: &mut Vec<String>,
+    ) -> Result<(), String> {
+        if path.iter().any(|visited| visited == name) {
+            path.push(name.to_string());
+            return Err(format!("{} includes itself", path.join(" -> ")));
+        }
+        path.push(name.to_string());
+        for member in &bundles[name] {
+            if bundles.contains_key(member) {
+                expand(bundles, member, path, expanded)?;
+            } else if !expanded.contains(member) {
+                expanded.push(member.clone());
+            }
+        }
+        path.pop();
         Ok(())
     }
+
+    bundles
+        .keys()
+        .map(|name| {
+            let mut expanded = Vec::new();
+            expand(bundles, name, &mut Vec::new(), &mut expanded)?;
+            Ok((name.clone(), expanded))
+        })
+        .collect()
+}
+
+/// Whether a posture attribute of the peer, or else a capability of the same name in its
+/// CapMap, has the expected value. Capabilities without values count as "true".
+fn posture_matches(peer: &PeerStatus, key: &str, expected: &str) -> bool {
+    let attribute = peer
+        .posture_attributes
+        .as_ref()
+        .and_then(|attributes| attributes.get(key).cloned())
+        .or_else(|| {
+            let values = peer
+                .cap_map
+                .as_ref()?
+                .get(&NodeCapability(key.to_string()))?;
+            Some(
+                values
+                    .as_ref()
+                    .and_then(|values| values.first().cloned())
+                    .unwrap_or(serde_json::Value::Bool(true)),
+            )
+        });
+
+    attribute.is_some_and(|value| match value {
+        serde_json::Value::String(value) => value == expected,
+        value => serde_json::to_string(&value).is_ok_and(|value| value == expected),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// Go's zero time, which tailscaled reports for peers it never saw
+    const ZERO_TIME: &str = "0001-01-01T00:00:00Z";
+
+    fn provider(config: ProviderConfig) -> TraefikProvider {
+        TraefikProvider::new(config).expect("provider")
+    }
+
+    fn peer(hostname: &str, tags: &[&str]) -> PeerStatus {
+        serde_json::from_value(json!({
+            "ID": format!("n{}", hostname),
+            "PublicKey": format!("nodekey:{}", hostname),
+            "HostName": hostname,
+            "DNSName": format!("{}.tail1234.ts.net.", hostname),
+            "OS": "linux",
+            "UserID": 1,
+            "TailscaleIPs": ["100.64.0.1"],
+            "Tags": tags,
+            "CurAddr": "",
+            "Relay": "",
+            "RxBytes": 0,
+            "TxBytes": 0,
+            "Created": "2026-01-01T00:00:00Z",
+            "LastWrite": "2026-01-01T00:00:00Z",
+            "LastSeen": "2026-01-01T00:00:00Z",
+            "LastHandshake": "2026-01-01T00:00:00Z",
+            "Online": true,
+            "ExitNode": false,
+            "ExitNodeOption": false,
+            "Active": true,
+            "InNetworkMap": true,
+            "InMagicSock": true,
+            "InEngine": true
+        }))
+        .expect("peer status")
+    }
+
+    /// The peer as last seen (and handshaken with) this many seconds ago
+    fn seen_ago(mut peer: PeerStatus, seconds: i64) -> PeerStatus {
+        peer.last_seen = Utc::now() - chrono::Duration::seconds(seconds);
+        peer.last_handshake = peer.last_seen;
+        peer
+    }
+
+    #[test]
+    fn weight_decay_steps() {
+        let provider = provider(ProviderConfig {
+            weight_decay_seconds: Some(600),
+            ..Default::default()
+        });
+        let weight = |age| provider.decay_weight(&seen_ago(peer("nas", &[]), age));
+
+        assert_eq!(weight(0), MAX_SERVER_WEIGHT);
+        assert_eq!(weight(30), MAX_SERVER_WEIGHT);
+        // Ages within a step share its weight, so refreshes don't change it
+        assert_eq!(weight(65), 90);
+        assert_eq!(weight(115), 90);
+        assert_eq!(weight(330), 50);
+        assert_eq!(weight(590), 10);
+        assert_eq!(weight(600), 1);
+        assert_eq!(weight(86_400), 1);
+        // Never-verified peers carry zero timestamps
+        let mut never = peer("new", &[]);
+        never.last_seen = ZERO_TIME.parse().unwrap();
+        never.last_handshake = never.last_seen;
+        assert_eq!(provider.decay_weight(&never), 1);
+    }
+
+    #[test]
+    fn weight_decay_disabled() {
+        let provider = provider(ProviderConfig::default());
+        assert_eq!(provider.decay_weight(&seen_ago(peer("nas", &[]), 0)), 1);
+    }
+
+    fn offline(peer: PeerStatus) -> PeerStatus {
+        PeerStatus {
+            online: Some(false),
+            ..peer
+        }
+    }
+
+    #[test]
+    fn offline_grace_starts_at_last_seen() {
+        let provider = provider(ProviderConfig {
+            offline_grace_seconds: Some(300),
+            ..Default::default()
+        });
+
+        let recent = offline(seen_ago(peer("nas", &[]), 60));
+        assert!(provider.online_or_within_grace(&recent));
+        // Offline for longer than the grace period before the provider first saw it
+        let stale = offline(seen_ago(peer("printer", &[]), 600));
+        assert!(!provider.online_or_within_grace(&stale));
+        assert!(!provider.online_or_within_grace(&stale));
+
+        let mut never = offline(peer("new", &[]));
+        never.last_seen = ZERO_TIME.parse().unwrap();
+        assert!(provider.online_or_within_grace(&never));
+        let mut epoch = offline(peer("api", &[]));
+        epoch.last_seen = DateTime::UNIX_EPOCH;
+        assert!(provider.online_or_within_grace(&epoch));
+    }
+
+    #[test]
+    fn offline_grace_tracking() {
+        let graced = provider(ProviderConfig {
+            offline_grace_seconds: Some(300),
+            ..Default::default()
+        });
+        let online = seen_ago(peer("nas", &[]), 0);
+        assert!(graced.online_or_within_grace(&online));
+        assert!(graced.offline_since.lock().unwrap().is_empty());
+
+        // The first offline observation is kept, a later last seen doesn't move it
+        let gone = offline(seen_ago(peer("nas", &[]), 200));
+        assert!(graced.online_or_within_grace(&gone));
+        let since = graced.offline_since.lock().unwrap()[&gone.id];
+        assert!(graced.online_or_within_grace(&offline(seen_ago(peer("nas", &[]), 10))));
+        assert_eq!(graced.offline_since.lock().unwrap()[&gone.id], since);
+
+        // Coming back online ends the grace period
+        assert!(graced.online_or_within_grace(&online));
+        assert!(graced.offline_since.lock().unwrap().is_empty());
+
+        let without_grace = provider(ProviderConfig::default());
+        assert!(!without_grace.online_or_within_grace(&gone));
+    }
+
+    fn listener(proto: &str, port: u16, description: &str) -> HostinfoService {
+        HostinfoService {
+            proto: proto.to_string(),
+            port,
+            description: description.to_string(),
+        }
+    }
+
+    #[test]
+    fn hostinfo_listeners_are_opt_in() {
+        let provider = provider(ProviderConfig {
+            hostinfo_services: true,
+            hostinfo_services_allow: Some(vec!["nginx".to_string(), "5353".to_string()]),
+            ..Default::default()
+        });
+        let name = |service: &HostinfoService| {
+            provider
+                .hostinfo_service_info(service)
+                .map(|info| info.name)
+        };
+
+        assert_eq!(
+            name(&listener("tcp", 80, "nginx")).as_deref(),
+            Some("nginx-80")
+        );
+        assert_eq!(name(&listener("tcp", 22, "sshd")), None);
+        assert_eq!(name(&listener("tcp", 5432, "postgres")), None);
+        assert_eq!(name(&listener("peerapi4", 41641, "nginx")), None);
+        let dns = provider
+            .hostinfo_service_info(&listener("udp", 5353, "mDNS Responder"))
+            .expect("allowed by port");
+        assert_eq!(dns.name, "mdns-responder-5353");
+        assert_eq!(dns.protocol, Protocol::Udp);
+    }
+
+    #[test]
+    fn hostinfo_listeners_from_include_tags() {
+        let included = provider(ProviderConfig {
+            hostinfo_services: true,
+            include_tags: Some(vec!["caddy".to_string()]),
+            ..Default::default()
+        });
+        let caddy = listener("tcp", 443, "caddy");
+        let sshd = listener("tcp", 22, "sshd");
+        assert!(included.hostinfo_service_info(&caddy).is_some());
+        assert!(included.hostinfo_service_info(&sshd).is_none());
+
+        // Neither list set: nothing is published
+        let unlisted = provider(ProviderConfig {
+            hostinfo_services: true,
+            ..Default::default()
+        });
+        assert!(unlisted.hostinfo_service_info(&caddy).is_none());
+    }
+
+    #[test]
+    fn hostinfo_cached_per_node_key() {
+        let provider = provider(ProviderConfig {
+            hostinfo_services: true,
+            hostinfo_services_allow: Some(vec!["nginx".to_string()]),
+            ..Default::default()
+        });
+        let nas = peer("nas", &[]);
+        provider.hostinfo.lock().unwrap().insert(
+            nas.public_key.clone(),
+            (
+                Instant::now(),
+                vec![listener("tcp", 22, "sshd"), listener("tcp", 80, "nginx")],
+            ),
+        );
+
+        let infos = provider.hostinfo_service_infos(&nas);
+        assert_eq!(infos.len(), 1);
+        assert_eq!(infos[0].port, Some(80));
+        assert!(
+            provider
+                .hostinfo_service_infos(&peer("printer", &[]))
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn disabling_applies_to_every_source() {
+        let provider = provider(ProviderConfig {
+            tailscale_sources: vec![
+                ("corp".to_string(), "tcp://127.0.0.1:41112".to_string()),
+                ("lab".to_string(), "tcp://127.0.0.1:41113".to_string()),
+            ],
+            ..Default::default()
+        });
+        let nas = seen_ago(peer("nas", &[]), 0);
+        let printer = seen_ago(peer("printer", &[]), 0);
+        let providers: Vec<&TraefikProvider> = [&provider]
+            .into_iter()
+            .chain(provider.sources.iter().map(|source| &source.provider))
+            .collect();
+        assert_eq!(providers.len(), 3);
+
+        assert!(provider.set_peer_disabled("nas", true));
+        for each in &providers {
+            assert!(!each.should_include_peer(&nas));
+            assert!(each.should_include_peer(&printer));
+        }
+
+        provider.set_disabled(vec!["printer".to_string()], vec!["web*".to_string()]);
+        for each in &providers {
+            assert!(each.should_include_peer(&nas));
+            assert!(!each.should_include_peer(&printer));
+            assert_eq!(each.disabled().1, ["web*"]);
+        }
+
+        assert!(provider.set_peer_disabled("printer", false));
+        assert!(providers[2].should_include_peer(&printer));
+    }
+
+    #[test]
+    fn sources_share_loaded_files() {
+        let dir = std::env::temp_dir().join(format!("ts-provider-sources-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let write = |name: &str, content: &str| {
+            let path = dir.join(name);
+            std::fs::write(&path, content).unwrap();
+            Some(path.to_string_lossy().into_owned())
+        };
+        let provider = provider(ProviderConfig {
+            tailscale_sources: vec![("lab".to_string(), "tcp://127.0.0.1:41113".to_string())],
+            middlewares_file: write(
+                "middlewares.json",
+                r#"{"auth": {"basicAuth": {"users": ["u:p"]}}, "secure": ["auth"]}"#,
+            ),
+            tls_options_file: write("tls.json", r#"{"modern": {"minVersion": "VersionTLS13"}}"#),
+            #[cfg(feature = "scripting")]
+            route_script: write("route.rhai", "fn route(peer, service, router) { true }"),
+            ..Default::default()
+        });
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let source = &provider.sources[0].provider;
+        assert_eq!(source.config.name_prefix, "tailscale-lab");
+        assert!(Arc::ptr_eq(
+            &provider.file_middlewares,
+            &source.file_middlewares
+        ));
+        assert!(Arc::ptr_eq(
+            &provider.middleware_bundles,
+            &source.middleware_bundles
+        ));
+        assert!(Arc::ptr_eq(&provider.tls_options, &source.tls_options));
+        #[cfg(feature = "scripting")]
+        assert!(Arc::ptr_eq(
+            provider.route_script.as_ref().unwrap(),
+            source.route_script.as_ref().unwrap()
+        ));
+    }
+
+    /// A tailscaled LocalAPI serving one peer at `ip`, whose whois answers with `login`
+    async fn mock_tailscaled(ip: &'static str, login: &'static str) -> String {
+        use axum::{Json, Router, routing::get};
+
+        let mut node = serde_json::to_value(peer("laptop", &[])).unwrap();
+        node["TailscaleIPs"] = json!([ip]);
+        let status = json!({
+            "Version": "1.80.0",
+            "TUN": true,
+            "BackendState": "Running",
+            "AuthURL": "",
+            "TailscaleIPs": [],
+            "Self": null,
+            "Health": [],
+            "MagicDNSSuffix": "tail1234.ts.net",
+            "CurrentTailnet": null,
+            "CertDomains": null,
+            "Peer": {"nodekey:laptop": node},
+            "User": null,
+            "ClientVersion": null
+        });
+        let whois = json!({
+            "Node": {"ID": 1, "StableID": "nlaptop", "Name": "laptop", "User": 1, "Addresses": [ip]},
+            "UserProfile": {"ID": 1, "LoginName": login, "DisplayName": login, "ProfilePicURL": null}
+        });
+        let app = Router::new()
+            .route(
+                "/localapi/v0/status",
+                get(move || async move { Json(status) }),
+            )
+            .route(
+                "/localapi/v0/whois",
+                get(move || async move { Json(whois) }),
+            );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        format!("tcp://{}", address)
+    }
+
+    #[tokio::test]
+    async fn whois_asks_the_tailnet_of_the_caller() {
+        let primary = mock_tailscaled("100.64.0.1", "alice@corp.example").await;
+        let lab = mock_tailscaled("100.100.0.7", "bob@lab.example").await;
+        let provider = provider(ProviderConfig {
+            tailscale_socket_path: Some(primary),
+            tailscale_sources: vec![("lab".to_string(), lab)],
+            ..Default::default()
+        });
+        let login = |addr: &'static str| {
+            let provider = &provider;
+            async move {
+                let whois = provider.whois(addr).await.expect("whois");
+                whois.user_profile.login_name
+            }
+        };
+
+        assert_eq!(login("100.64.0.1").await, "alice@corp.example");
+        assert_eq!(login("100.100.0.7").await, "bob@lab.example");
+        // Addresses no tailnet knows are left to the primary tailscaled
+        assert_eq!(login("100.127.0.9").await, "alice@corp.example");
+    }
+
+    #[test]
+    fn colliding_peers_get_distinct_names() {
+        let provider = provider(ProviderConfig {
+            name_suffix: Some("v2".to_string()),
+            ..Default::default()
+        });
+        let tags = ["tag:app-8080", "tag:app--path-api", "tag:app--stripprefix"];
+        let dotted = peer("web.local", &tags);
+        let underscored = peer("web_local", &tags);
+
+        let claimed = |peer: &PeerStatus| {
+            let infos = provider.extract_service_infos_from_peer(peer);
+            provider.claimed_names(peer, None, &infos)
+        };
+        // Every generated key collides once NAME_PREFIX and NAME_SUFFIX are added
+        assert_eq!(
+            claimed(&dotted),
+            [
+                "http.middlewares.tailscale-web-local-app-v2-stripprefix",
+                "http.routers.tailscale-web-local-app-router-v2",
+                "http.services.tailscale-web-local-app-v2",
+            ]
+        );
+        assert_eq!(claimed(&dotted), claimed(&underscored));
+
+        let targets = [&dotted, &underscored]
+            .into_iter()
+            .map(|peer| (peer, provider.extract_service_infos_from_peer(peer)))
+            .collect();
+        let named = provider.disambiguate_names(targets);
+        assert_eq!(named.len(), 2);
+        assert_eq!(named[0].2, None);
+        assert_eq!(named[1].2.as_deref(), Some("nweblo"));
+
+        let outputs: Vec<PeerOutput> = named
+            .iter()
+            .map(|(peer, infos, node_tag)| {
+                provider.build_peer_output(peer, infos, "", node_tag.as_deref())
+            })
+            .collect();
+        let keys = |output: &PeerOutput| -> BTreeSet<String> {
+            (output.http_routers.keys())
+                .chain(output.http_services.keys())
+                .chain(output.http_middlewares.keys())
+                .cloned()
+                .collect()
+        };
+        assert_eq!(keys(&outputs[0]).len(), 3);
+        assert!(keys(&outputs[0]).is_disjoint(&keys(&outputs[1])));
+        let tagged_router = &outputs[1].http_routers["tailscale-web-local-nweblo-app-router-v2"];
+        assert_eq!(
+            tagged_router.middlewares.as_deref(),
+            Some(&["tailscale-web-local-nweblo-app-v2-stripprefix".to_string()][..])
+        );
+    }
+
+    #[test]
+    fn path_attribute_routes_and_strips() {
+        let provider = provider(ProviderConfig::default());
+        let peer = peer(
+            "nas",
+            &[
+                "tag:api-3000-http",
+                "tag:api--path-api-v1",
+                "tag:api--stripprefix",
+            ],
+        );
+
+        let infos = provider.extract_service_infos_from_peer(&peer);
+        assert_eq!(infos.len(), 1);
+        assert_eq!(infos[0].path_prefix.as_deref(), Some("/api/v1"));
+
+        let output = provider.build_peer_output(&peer, &infos, "", None);
+        let router = &output.http_routers["tailscale-nas-api-router"];
+        assert!(router.rule.ends_with("&& PathPrefix(`/api/v1`)"));
+        assert_eq!(
+            router.middlewares.as_deref(),
+            Some(&["tailscale-nas-api-stripprefix".to_string()][..])
+        );
+        let strip = output.http_middlewares["tailscale-nas-api-stripprefix"]
+            .strip_prefix
+            .as_ref()
+            .expect("stripPrefix middleware");
+        assert_eq!(strip.prefixes, ["/api/v1"]);
+    }
+
+    #[test]
+    fn path_attribute_without_strip() {
+        let provider = provider(ProviderConfig::default());
+        let peer = peer("nas", &["tag:web-8080", "tag:web--path-app"]);
+
+        let infos = provider.extract_service_infos_from_peer(&peer);
+        let output = provider.build_peer_output(&peer, &infos, "", None);
+        let router = &output.http_routers["tailscale-nas-web-router"];
+        assert!(router.rule.ends_with("&& PathPrefix(`/app`)"));
+        assert!(output.http_middlewares.is_empty());
+    }
+
+    #[cfg(feature = "api")]
+    #[test]
+    fn api_data_source_rejects_local_api_options() {
+        let api = |config: ProviderConfig| ProviderConfig {
+            data_source: DataSource::Api,
+            tailscale_api_key: Some(crate::config::Secret::new("tskey-api-test".to_string())),
+            ..config
+        };
+        assert!(TraefikProvider::new(api(ProviderConfig::default())).is_ok());
+
+        let combinations = [
+            (
+                "MAX_LATENCY_MS",
+                ProviderConfig {
+                    max_latency_ms: Some(50),
+                    ..Default::default()
+                },
+            ),
+            (
+                "LATENCY_WEIGHTING",
+                ProviderConfig {
+                    latency_weighting: true,
+                    ..Default::default()
+                },
+            ),
+            (
+                "HOSTINFO_SERVICES",
+                ProviderConfig {
+                    hostinfo_services: true,
+                    ..Default::default()
+                },
+            ),
+            (
+                "SERVE_DISCOVERY",
+                ProviderConfig {
+                    serve_discovery: true,
+                    ..Default::default()
+                },
+            ),
+            (
+                "SERVE_HTTPS",
+                ProviderConfig {
+                    serve_https: true,
+                    ..Default::default()
+                },
+            ),
+            (
+                "LISTEN_TAILNET_ONLY",
+                ProviderConfig {
+                    listen_tailnet_only: true,
+                    ..Default::default()
+                },
+            ),
+            (
+                "ADMIN_USERS",
+                ProviderConfig {
+                    admin_users: Some(vec!["alice@example.com".to_string()]),
+                    ..Default::default()
+                },
+            ),
+            (
+                "ADMIN_TAGS",
+                ProviderConfig {
+                    admin_tags: Some(vec!["tag:ops".to_string()]),
+                    ..Default::default()
+                },
+            ),
+        ];
+        for (option, config) in combinations {
+            let error = TraefikProvider::new(api(config))
+                .err()
+                .unwrap_or_else(|| panic!("{} accepted with the API data source", option))
+                .to_string();
+            assert!(error.contains(option), "{}", error);
+            assert!(error.contains("TAILSCALE_DATA_SOURCE=api"), "{}", error);
+        }
+    }
 }
@@ -0,0 +1,446 @@
+use crate::config::{Protocol, ServiceInfo};
+use crate::tailscale::PeerStatus;
+use crate::traefik::{Router, TcpRouter};
+use rhai::{AST, Array, Dynamic, Engine, Map, Scope};
+use tracing::{debug, info, warn};
+
+/// Name and arity of the function ROUTE_SCRIPT has to define
+const ENTRY_POINT: &str = "route";
+const ENTRY_POINT_PARAMS: usize = 3;
+
+/// Operations a single call may run before it is aborted, against runaway loops
+const MAX_OPERATIONS: u64 = 100_000;
+
+/// User script deciding on the router of every discovered service.
+///
+/// `fn route(peer, service, router)` is called with maps of the peer (id, hostname,
+/// dns_name, os, tags, ips, online), the parsed service (name, protocol, port, scheme,
+/// path_prefix) and the generated router (rule, middlewares, priority; the ones the
+/// protocol has). It returns `()` or `true` to keep the router, `false` to drop the
+/// service, or a map whose rule, middlewares and priority replace the generated ones.
+pub struct RouteScript {
+    path: String,
+    engine: Engine,
+    ast: AST,
+}
+
+/// What a script call decided
+enum Decision {
+    Keep,
+    Veto,
+    Patch(Map),
+}
+
+impl RouteScript {
+    pub fn load(path: &str) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let source = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read ROUTE_SCRIPT {}: {}", path, e))?;
+
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        engine.on_print(|text| info!(target: "script", "{}", text));
+        engine.on_debug(|text, _, position| debug!(target: "script", "{} {}", position, text));
+
+        let ast = engine
+            .compile(&source)
+            .map_err(|e| format!("Invalid ROUTE_SCRIPT {}: {}", path, e))?;
+        if !ast
+            .iter_functions()
+            .any(|f| f.name == ENTRY_POINT && f.params.len() == ENTRY_POINT_PARAMS)
+        {
+            return Err(format!(
+                "ROUTE_SCRIPT {} does not define fn {}(peer, service, router)",
+                path, ENTRY_POINT
+            )
+            .into());
+        }
+
+        info!("Loaded route script from {}", path);
+        Ok(Self {
+            path: path.to_string(),
+            engine,
+            ast,
+        })
+    }
+
+    /// Run the script on an HTTP router; false when the service is vetoed
+    pub fn apply_http(
+        &self,
+        peer: &PeerStatus,
+        service_info: &ServiceInfo,
+        router: &mut Router,
+    ) -> bool {
+        let mut generated = Map::new();
+        generated.insert("rule".into(), router.rule.clone().into());
+        generated.insert(
+            "middlewares".into(),
+            strings(router.middlewares.iter().flatten()),
+        );
+        generated.insert(
+            "priority".into(),
+            router
+                .priority
+                .map(|priority| Dynamic::from_int(priority.into()))
+                .unwrap_or(Dynamic::UNIT),
+        );
+
+        let patch = match self.decide(peer, service_info, generated) {
+            Decision::Keep => return true,
+            Decision::Veto => return false,
+            Decision::Patch(patch) => patch,
+        };
+        let patched = (|| -> Result<(), String> {
+            if let Some(rule) = patch.get("rule") {
+                router.rule = string(rule, "rule")?;
+            }
+            if let Some(middlewares) = patch.get("middlewares") {
+                let middlewares = string_array(middlewares, "middlewares")?;
+                router.middlewares = Some(middlewares).filter(|names| !names.is_empty());
+            }
+            if let Some(priority) = patch.get("priority") {
+                router.priority = if priority.is_unit() {
+                    None
+                } else {
+                    let priority = priority
+                        .as_int()
+                        .map_err(|kind| format!("priority must be an integer, not {}", kind))?;
+                    Some(i32::try_from(priority).map_err(|_| "priority is out of range")?)
+                };
+            }
+            Ok(())
+        })();
+        self.report(peer, service_info, patched);
+        true
+    }
+
+    /// Run the script on a TCP router; false when the service is vetoed
+    pub fn apply_tcp(
+        &self,
+        peer: &PeerStatus,
+        service_info: &ServiceInfo,
+        router: &mut TcpRouter,
+    ) -> bool {
+        let mut generated = Map::new();
+        generated.insert("rule".into(), router.rule.clone().into());
+
+        match self.decide(peer, service_info, generated) {
+            Decision::Keep => true,
+            Decision::Veto => false,
+            Decision::Patch(patch) => {
+                if let Some(rule) = patch.get("rule") {
+                    let patched = string(rule, "rule").map(|rule| router.rule = rule);
+                    self.report(peer, service_info, patched);
+                }
+                true
+            }
+        }
+    }
+
+    /// Run the script on a UDP service, which has no rule; false when it is vetoed
+    pub fn apply_udp(&self, peer: &PeerStatus, service_info: &ServiceInfo) -> bool {
+        !matches!(self.decide(peer, service_info, Map::new()), Decision::Veto)
+    }
+
+    /// Call the script. Failures keep the generated router, a broken script shouldn't
+    /// take every route down.
+    fn decide(&self, peer: &PeerStatus, service_info: &ServiceInfo, router: Map) -> Decision {
+        let args = (peer_map(peer), service_map(service_info), router);
+        let result =
+            match self
+                .engine
+                .call_fn::<Dynamic>(&mut Scope::new(), &self.ast, ENTRY_POINT, args)
+            {
+                Ok(result) => result,
+                Err(e) => {
+                    warn!(
+                        "ROUTE_SCRIPT {} failed for service {} of peer {}: {}",
+                        self.path, service_info.name, peer.hostname, e
+                    );
+                    return Decision::Keep;
+                }
+            };
+
+        if result.is_unit() {
+            return Decision::Keep;
+        }
+        if let Ok(keep) = result.as_bool() {
+            if !keep {
+                info!(
+                    target: "audit",
+                    "Dropping service {} of peer {}: vetoed by ROUTE_SCRIPT",
+                    service_info.name,
+                    peer.hostname
+                );
+                return Decision::Veto;
+            }
+            return Decision::Keep;
+        }
+        let kind = result.type_name();
+        match result.try_cast::<Map>() {
+            Some(patch) => Decision::Patch(patch),
+            None => {
+                warn!(
+                    "ROUTE_SCRIPT {} returned {} for service {} of peer {}, expected (), a bool or a map",
+                    self.path, kind, service_info.name, peer.hostname
+                );
+                Decision::Keep
+            }
+        }
+    }
+
+    fn report(&self, peer: &PeerStatus, service_info: &ServiceInfo, patched: Result<(), String>) {
+        match patched {
+            Ok(()) => debug!(
+                "ROUTE_SCRIPT patched the router of service {} of peer {}",
+                service_info.name, peer.hostname
+            ),
+            Err(e) => warn!(
+                "ROUTE_SCRIPT {} returned an invalid router for service {} of peer {}: {}",
+                self.path, service_info.name, peer.hostname, e
+            ),
+        }
+    }
+}
+
+fn peer_map(peer: &PeerStatus) -> Map {
+    let mut map = Map::new();
+    map.insert("id".into(), peer.id.0.clone().into());
+    map.insert("hostname".into(), peer.hostname.clone().into());
+    map.insert(
+        "dns_name".into(),
+        peer.dns_name.trim_end_matches('.').to_string().into(),
+    );
+    map.insert("os".into(), peer.os.clone().into());
+    map.insert("tags".into(), strings(peer.tags.iter().flatten()));
+    map.insert("ips".into(), strings(&peer.tailscale_ips));
+    map.insert("online".into(), peer.online.unwrap_or(false).into());
+    map
+}
+
+fn service_map(service_info: &ServiceInfo) -> Map {
+    let protocol = match service_info.protocol {
+        Protocol::Http => "http",
+        Protocol::Tcp => "tcp",
+        Protocol::Udp => "udp",
+    };
+    let mut map = Map::new();
+    map.insert("name".into(), service_info.name.clone().into());
+    map.insert("protocol".into(), protocol.into());
+    map.insert(
+        "port".into(),
+        service_info
+            .port
+            .map(|port| Dynamic::from_int(port.into()))
+            .unwrap_or(Dynamic::UNIT),
+    );
+    map.insert("scheme".into(), service_info.scheme.clone().into());
+    map.insert(
+        "path_prefix".into(),
+        service_info
+            .path_prefix
+            .clone()
+            .map(Dynamic::from)
+            .unwrap_or(Dynamic::UNIT),
+    );
+    map
+}
+
+fn strings<'a>(values: impl IntoIterator<Item = &'a String>) -> Dynamic {
+    let array: Array = values.into_iter().cloned().map(Dynamic::from).collect();
+    array.into()
+}
+
+fn string(value: &Dynamic, field: &str) -> Result<String, String> {
+    value
+        .clone()
+        .into_string()
+        .map_err(|kind| format!("{} must be a string, not {}", field, kind))
+}
+
+fn string_array(value: &Dynamic, field: &str) -> Result<Vec<String>, String> {
+    let kind = value.type_name();
+    value
+        .clone()
+        .try_cast::<Array>()
+        .ok_or_else(|| format!("{} must be an array, not {}", field, kind))?
+        .iter()
+        .map(|item| string(item, field))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn script(name: &str, source: &str) -> Result<RouteScript, String> {
+        let path = std::env::temp_dir().join(format!(
+            "ts-provider-script-{}-{}.rhai",
+            std::process::id(),
+            name
+        ));
+        std::fs::write(&path, source).unwrap();
+        let script = RouteScript::load(&path.to_string_lossy()).map_err(|e| e.to_string());
+        std::fs::remove_file(&path).unwrap();
+        script
+    }
+
+    fn peer(hostname: &str, tags: &[&str]) -> PeerStatus {
+        serde_json::from_value(json!({
+            "ID": format!("n{}", hostname),
+            "PublicKey": format!("nodekey:{}", hostname),
+            "HostName": hostname,
+            "DNSName": format!("{}.tail1234.ts.net.", hostname),
+            "OS": "linux",
+            "UserID": 1,
+            "TailscaleIPs": ["100.64.0.1"],
+            "Tags": tags,
+            "CurAddr": "",
+            "Relay": "",
+            "RxBytes": 0,
+            "TxBytes": 0,
+            "Created": "2026-01-01T00:00:00Z",
+            "LastWrite": "2026-01-01T00:00:00Z",
+            "LastSeen": "2026-01-01T00:00:00Z",
+            "LastHandshake": "2026-01-01T00:00:00Z",
+            "Online": true,
+            "ExitNode": false,
+            "ExitNodeOption": false,
+            "Active": true,
+            "InNetworkMap": true,
+            "InMagicSock": true,
+            "InEngine": true
+        }))
+        .expect("peer status")
+    }
+
+    fn service(name: &str, protocol: Protocol) -> ServiceInfo {
+        ServiceInfo {
+            name: name.to_string(),
+            port: Some(8080),
+            protocol,
+            scheme: "http".to_string(),
+            path_prefix: Some("/api".to_string()),
+        }
+    }
+
+    fn router() -> Router {
+        serde_json::from_value(json!({
+            "rule": "Host(`web.example.com`)",
+            "service": "web",
+            "middlewares": ["compress"]
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn load_requires_the_entry_point() {
+        let error = script("no-entry", "fn other(peer) { true }").err().unwrap();
+        assert!(error.contains("does not define fn route"), "{}", error);
+        let error = script("two-params", "fn route(peer, service) { true }")
+            .err()
+            .unwrap();
+        assert!(error.contains("does not define fn route"), "{}", error);
+        let error = script("syntax", "fn route(peer, service, router) {")
+            .err()
+            .unwrap();
+        assert!(error.starts_with("Invalid ROUTE_SCRIPT"), "{}", error);
+    }
+
+    #[test]
+    fn vetoes_and_keeps_by_peer_and_service() {
+        let script = script(
+            "veto",
+            r#"
+            fn route(peer, service, router) {
+                if "tag:staging" in peer.tags { return false; }
+                if service.protocol == "udp" && service.port == 8080 { return false; }
+                if service.name == "db" { return (); }
+                true
+            }
+            "#,
+        )
+        .unwrap();
+        let production = peer("nas", &["tag:web-8080"]);
+        let staging = peer("dev", &["tag:staging", "tag:web-8080"]);
+
+        assert!(script.apply_http(&production, &service("web", Protocol::Http), &mut router()));
+        assert!(!script.apply_http(&staging, &service("web", Protocol::Http), &mut router()));
+        assert!(!script.apply_udp(&production, &service("dns", Protocol::Udp)));
+        let mut tcp: TcpRouter =
+            serde_json::from_value(json!({"rule": "HostSNI(`*`)", "service": "db"})).unwrap();
+        assert!(script.apply_tcp(&production, &service("db", Protocol::Tcp), &mut tcp));
+        assert_eq!(tcp.rule, "HostSNI(`*`)");
+    }
+
+    #[test]
+    fn patches_the_generated_router() {
+        let script = script(
+            "patch",
+            r#"
+            fn route(peer, service, router) {
+                if service.protocol == "tcp" {
+                    return #{ rule: router.rule + " && ClientIP(`100.64.0.0/10`)" };
+                }
+                let middlewares = router.middlewares;
+                middlewares.push("auth");
+                #{
+                    rule: router.rule + " && PathPrefix(`" + service.path_prefix + "`)",
+                    middlewares: middlewares,
+                    priority: peer.hostname.len() * 10
+                }
+            }
+            "#,
+        )
+        .unwrap();
+
+        let mut patched = router();
+        assert!(script.apply_http(
+            &peer("nas", &[]),
+            &service("web", Protocol::Http),
+            &mut patched
+        ));
+        assert_eq!(
+            patched.rule,
+            "Host(`web.example.com`) && PathPrefix(`/api`)"
+        );
+        assert_eq!(
+            patched.middlewares,
+            Some(vec!["compress".to_string(), "auth".to_string()])
+        );
+        assert_eq!(patched.priority, Some(30));
+
+        let mut tcp: TcpRouter =
+            serde_json::from_value(json!({"rule": "HostSNI(`*`)", "service": "db"})).unwrap();
+        assert!(script.apply_tcp(&peer("nas", &[]), &service("db", Protocol::Tcp), &mut tcp));
+        assert_eq!(tcp.rule, "HostSNI(`*`) && ClientIP(`100.64.0.0/10`)");
+    }
+
+    #[test]
+    fn failures_keep_the_generated_router() {
+        let script = script(
+            "failing",
+            r#"
+            fn route(peer, service, router) {
+                if service.name == "loop" { loop {} }
+                if service.name == "throw" { throw "broken"; }
+                if service.name == "number" { return 42; }
+                #{ middlewares: [], priority: "high" }
+            }
+            "#,
+        )
+        .unwrap();
+        let nas = peer("nas", &[]);
+
+        for name in ["loop", "throw", "number"] {
+            let mut kept = router();
+            assert!(script.apply_http(&nas, &service(name, Protocol::Http), &mut kept));
+            assert_eq!(kept.middlewares, router().middlewares, "{}", name);
+        }
+        // Fields before the invalid priority still apply
+        let mut patched = router();
+        assert!(script.apply_http(&nas, &service("web", Protocol::Http), &mut patched));
+        assert_eq!(patched.middlewares, None);
+        assert_eq!(patched.priority, None);
+    }
+}
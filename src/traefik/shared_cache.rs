@@ -0,0 +1,323 @@
+use crate::redis::{Connection, RedisClient, Value};
+use crate::traefik::DynamicConfig;
+use std::sync::Mutex;
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// Take the generation lock when it is free, or extend it when this replica holds it
+const LOCK_SCRIPT: &str = r#"
+local holder = redis.call('GET', KEYS[1])
+if holder == false or holder == ARGV[1] then
+    redis.call('SET', KEYS[1], ARGV[1], 'PX', ARGV[2])
+    return 1
+end
+return 0
+"#;
+
+/// Redis shared by the replicas of a horizontally scaled deployment. The replica holding
+/// the generation lock queries Tailscale and stores the configuration; the others serve
+/// the stored one, so all of them serve the same bytes.
+pub struct SharedCache {
+    client: RedisClient,
+    replica_id: String,
+    lock_key: String,
+    config_key: String,
+    /// Held this long without renewal before another replica takes over
+    lock_ttl: Duration,
+    /// Whether the lock was held at the last generation, for logging changes
+    holding: Mutex<Option<bool>>,
+}
+
+impl SharedCache {
+    pub fn new(client: RedisClient, prefix: &str, replica_id: String, lock_ttl: Duration) -> Self {
+        info!(
+            "Sharing the configuration through {} as {}",
+            client.describe(),
+            replica_id
+        );
+        Self {
+            client,
+            replica_id,
+            lock_key: format!("{}:lock", prefix),
+            config_key: format!("{}:config", prefix),
+            lock_ttl,
+            holding: Mutex::new(None),
+        }
+    }
+
+    /// Generate the configuration while holding the generation lock and store it for the
+    /// other replicas, or load the one stored by the holder. When Redis is unreachable,
+    /// or before the holder stored anything, it is generated here without storing it.
+    pub async fn generate_or_load<F, Fut>(
+        &self,
+        generate: F,
+    ) -> Result<DynamicConfig, Box<dyn std::error::Error + Send + Sync>>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<DynamicConfig, Box<dyn std::error::Error + Send + Sync>>>,
+    {
+        let mut connection = match self.client.connect().await {
+            Ok(connection) => connection,
+            Err(e) => {
+                warn!(
+                    "Shared cache unavailable, generating the configuration here: {}",
+                    e
+                );
+                return generate().await;
+            }
+        };
+
+        match self.lock(&mut connection).await {
+            Ok(true) => {
+                self.log_holding(true);
+                let config = generate().await?;
+                if let Err(e) = self.store(&mut connection, &config).await {
+                    warn!(
+                        "Failed to store the configuration in the shared cache: {}",
+                        e
+                    );
+                }
+                Ok(config)
+            }
+            Ok(false) => {
+                self.log_holding(false);
+                match self.load(&mut connection).await {
+                    Ok(Some(config)) => Ok(config),
+                    Ok(None) => generate().await,
+                    Err(e) => {
+                        warn!(
+                            "Failed to load the shared configuration, generating it here: {}",
+                            e
+                        );
+                        generate().await
+                    }
+                }
+            }
+            Err(e) => {
+                warn!(
+                    "Shared cache unavailable, generating the configuration here: {}",
+                    e
+                );
+                generate().await
+            }
+        }
+    }
+
+    async fn lock(&self, connection: &mut Connection) -> Result<bool, String> {
+        let ttl = self.lock_ttl.as_millis().to_string();
+        let reply = connection
+            .command(&[
+                "EVAL",
+                LOCK_SCRIPT,
+                "1",
+                &self.lock_key,
+                &self.replica_id,
+                &ttl,
+            ])
+            .await?;
+        Ok(reply == Value::Int(1))
+    }
+
+    async fn store(
+        &self,
+        connection: &mut Connection,
+        config: &DynamicConfig,
+    ) -> Result<(), String> {
+        let json = serde_json::to_string(config).map_err(|e| e.to_string())?;
+        connection
+            .command(&["SET", &self.config_key, &json])
+            .await?;
+        Ok(())
+    }
+
+    async fn load(&self, connection: &mut Connection) -> Result<Option<DynamicConfig>, String> {
+        match connection
+            .command(&["GET", &self.config_key])
+            .await?
+            .into_string()
+        {
+            Some(json) => serde_json::from_str(&json)
+                .map(Some)
+                .map_err(|e| format!("Invalid shared configuration: {}", e)),
+            None => Ok(None),
+        }
+    }
+
+    fn log_holding(&self, holding: bool) {
+        let mut last = self.holding.lock().unwrap();
+        if *last != Some(holding) {
+            if holding {
+                info!("Holding the generation lock, generating the configuration");
+            } else {
+                info!("Another replica holds the generation lock, serving its configuration");
+            }
+            *last = Some(holding);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Instant;
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufStream};
+    use tokio::net::{TcpListener, TcpStream};
+
+    /// Keys of the mock Redis, with their expiry
+    type Keys = Arc<Mutex<HashMap<String, (String, Option<Instant>)>>>;
+
+    async fn read_command(stream: &mut BufStream<TcpStream>) -> Option<Vec<String>> {
+        let mut line = String::new();
+        stream.read_line(&mut line).await.ok()?;
+        let count: usize = line.trim().strip_prefix('*')?.parse().ok()?;
+        let mut args = Vec::with_capacity(count);
+        for _ in 0..count {
+            line.clear();
+            stream.read_line(&mut line).await.ok()?;
+            let length: usize = line.trim().strip_prefix('$')?.parse().ok()?;
+            let mut data = vec![0; length + 2];
+            stream.read_exact(&mut data).await.ok()?;
+            data.truncate(length);
+            args.push(String::from_utf8(data).ok()?);
+        }
+        Some(args)
+    }
+
+    /// Redis answering GET, SET and the EVAL of LOCK_SCRIPT
+    async fn serve(keys: Keys, stream: TcpStream) {
+        let mut stream = BufStream::new(stream);
+        while let Some(args) = read_command(&mut stream).await {
+            let reply = {
+                let mut keys = keys.lock().unwrap();
+                keys.retain(|_, (_, expires)| expires.is_none_or(|at| at > Instant::now()));
+                match args[0].as_str() {
+                    "EVAL" if args[1] == LOCK_SCRIPT => {
+                        let (key, replica, ttl) = (&args[3], &args[4], &args[5]);
+                        match keys.get(key) {
+                            Some((holder, _)) if holder != replica => ":0\r\n".to_string(),
+                            _ => {
+                                let ttl = Duration::from_millis(ttl.parse().unwrap());
+                                let expires = Some(Instant::now() + ttl);
+                                keys.insert(key.clone(), (replica.clone(), expires));
+                                ":1\r\n".to_string()
+                            }
+                        }
+                    }
+                    "SET" => {
+                        keys.insert(args[1].clone(), (args[2].clone(), None));
+                        "+OK\r\n".to_string()
+                    }
+                    "GET" => match keys.get(&args[1]) {
+                        Some((value, _)) => format!("${}\r\n{}\r\n", value.len(), value),
+                        None => "$-1\r\n".to_string(),
+                    },
+                    command => panic!("unexpected command {}", command),
+                }
+            };
+            if stream.write_all(reply.as_bytes()).await.is_err() || stream.flush().await.is_err() {
+                return;
+            }
+        }
+    }
+
+    async fn mock_redis() -> (String, Keys) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("redis://{}", listener.local_addr().unwrap());
+        let keys = Keys::default();
+        let served = keys.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(serve(served.clone(), stream));
+            }
+        });
+        (url, keys)
+    }
+
+    fn cache(url: &str, replica_id: &str, lock_ttl: Duration) -> SharedCache {
+        let client = RedisClient::new(url, None, None, None).unwrap();
+        SharedCache::new(client, "tailscale", replica_id.to_string(), lock_ttl)
+    }
+
+    fn config(url: &str) -> DynamicConfig {
+        serde_json::from_value(json!({
+            "http": {"services": {"web": {"loadBalancer": {"servers": [{"url": url}]}}}}
+        }))
+        .unwrap()
+    }
+
+    /// Generate `config(url)`, counting the generations
+    async fn generate(cache: &SharedCache, url: &str, generations: &AtomicUsize) -> DynamicConfig {
+        cache
+            .generate_or_load(|| async {
+                generations.fetch_add(1, Ordering::SeqCst);
+                Ok(config(url))
+            })
+            .await
+            .unwrap()
+    }
+
+    fn servers(config: &DynamicConfig) -> String {
+        let http = config.http.as_ref().unwrap();
+        http.services["web"].load_balancer.servers[0].url.clone()
+    }
+
+    #[tokio::test]
+    async fn followers_serve_the_configuration_of_the_lock_holder() {
+        let (url, keys) = mock_redis().await;
+        let first = cache(&url, "a", Duration::from_secs(30));
+        let second = cache(&url, "b", Duration::from_secs(30));
+        let generations = AtomicUsize::new(0);
+
+        let generated = generate(&first, "http://100.64.0.1:80", &generations).await;
+        let loaded = generate(&second, "http://100.64.0.2:80", &generations).await;
+
+        assert_eq!(generations.load(Ordering::SeqCst), 1);
+        assert_eq!(servers(&loaded), servers(&generated));
+        assert_eq!(keys.lock().unwrap()["tailscale:lock"].0, "a");
+        // The holder keeps generating and renews the lock
+        generate(&first, "http://100.64.0.3:80", &generations).await;
+        let loaded = generate(&second, "http://100.64.0.2:80", &generations).await;
+        assert_eq!(servers(&loaded), "http://100.64.0.3:80");
+        assert_eq!(generations.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn another_replica_takes_over_an_expired_lock() {
+        let (url, keys) = mock_redis().await;
+        let first = cache(&url, "a", Duration::from_millis(50));
+        let second = cache(&url, "b", Duration::from_millis(50));
+        let generations = AtomicUsize::new(0);
+        generate(&first, "http://100.64.0.1:80", &generations).await;
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let generated = generate(&second, "http://100.64.0.2:80", &generations).await;
+
+        assert_eq!(servers(&generated), "http://100.64.0.2:80");
+        assert_eq!(keys.lock().unwrap()["tailscale:lock"].0, "b");
+        let loaded = generate(&first, "http://100.64.0.1:80", &generations).await;
+        assert_eq!(servers(&loaded), "http://100.64.0.2:80");
+    }
+
+    #[tokio::test]
+    async fn generates_locally_without_a_stored_configuration_or_redis() {
+        let (url, keys) = mock_redis().await;
+        keys.lock()
+            .unwrap()
+            .insert("tailscale:lock".to_string(), ("other".to_string(), None));
+        let follower = cache(&url, "b", Duration::from_secs(30));
+        let generations = AtomicUsize::new(0);
+        let generated = generate(&follower, "http://100.64.0.2:80", &generations).await;
+        assert_eq!(servers(&generated), "http://100.64.0.2:80");
+        // Only the lock holder stores its configuration
+        assert!(!keys.lock().unwrap().contains_key("tailscale:config"));
+
+        let unreachable = cache("redis://127.0.0.1:9", "c", Duration::from_secs(30));
+        let generated = generate(&unreachable, "http://100.64.0.3:80", &generations).await;
+        assert_eq!(servers(&generated), "http://100.64.0.3:80");
+        assert_eq!(generations.load(Ordering::SeqCst), 2);
+    }
+}
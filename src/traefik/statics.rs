@@ -0,0 +1,135 @@
+use crate::config::Protocol;
+use crate::traefik::{
+    DynamicConfig, HttpConfig, LoadBalancer, Router, Server, Service, TcpConfig, TcpLoadBalancer,
+    TcpRouter, TcpServer, TcpService, UdpConfig, UdpLoadBalancer, UdpRouter, UdpServer, UdpService,
+};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+/// A backend outside the tailnet, declared in STATIC_SERVICES_FILE
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StaticService {
+    /// Backend URL ("http://host:port" for HTTP, "host:port" for TCP and UDP)
+    pub url: Option<String>,
+    /// Several backends, load balanced
+    #[serde(default)]
+    pub urls: Vec<String>,
+    /// http (default), tcp or udp
+    pub protocol: Option<String>,
+    /// Router rule; required for HTTP and TCP
+    pub rule: Option<String>,
+    #[serde(default)]
+    pub middlewares: Vec<String>,
+    #[serde(rename = "entryPoints")]
+    pub entry_points: Option<Vec<String>>,
+    pub priority: Option<i32>,
+}
+
+/// Build the routers and services of the static services, named "<name>" and
+/// "<name>-router"
+pub fn static_config(services: &BTreeMap<String, StaticService>) -> Result<DynamicConfig, String> {
+    let mut http = HttpConfig::default();
+    let mut tcp = TcpConfig::default();
+    let mut udp = UdpConfig::default();
+
+    for (name, service) in services {
+        let mut urls: Vec<String> = service.url.iter().cloned().collect();
+        urls.extend(service.urls.iter().cloned());
+        if urls.is_empty() {
+            return Err(format!("Static service {} has no url", name));
+        }
+        let router_name = format!("{}-router", name);
+        let protocol = Protocol::parse(service.protocol.as_deref().unwrap_or("http"));
+        let rule = match (&protocol, &service.rule) {
+            (Protocol::Udp, _) => String::new(),
+            (_, Some(rule)) => rule.clone(),
+            (_, None) => return Err(format!("Static service {} has no rule", name)),
+        };
+
+        match protocol {
+            Protocol::Http => {
+                http.services.insert(
+                    name.clone(),
+                    Service {
+                        load_balancer: LoadBalancer {
+                            servers: urls
+                                .into_iter()
+                                .map(|url| Server { url, weight: None })
+                                .collect(),
+                            health_check: None,
+                            servers_transport: None,
+                        },
+                    },
+                );
+                http.routers.insert(
+                    router_name,
+                    Router {
+                        entry_points: service.entry_points.clone(),
+                        rule,
+                        service: name.clone(),
+                        middlewares: (!service.middlewares.is_empty())
+                            .then(|| service.middlewares.clone()),
+                        priority: service.priority,
+                        tls: None,
+                    },
+                );
+            }
+            Protocol::Tcp => {
+                tcp.services.insert(
+                    name.clone(),
+                    TcpService {
+                        load_balancer: TcpLoadBalancer {
+                            servers: urls
+                                .into_iter()
+                                .map(|address| TcpServer {
+                                    address,
+                                    weight: None,
+                                })
+                                .collect(),
+                        },
+                    },
+                );
+                tcp.routers.insert(
+                    router_name,
+                    TcpRouter {
+                        entry_points: service.entry_points.clone(),
+                        rule,
+                        service: name.clone(),
+                        tls: None,
+                    },
+                );
+            }
+            Protocol::Udp => {
+                udp.services.insert(
+                    name.clone(),
+                    UdpService {
+                        load_balancer: UdpLoadBalancer {
+                            servers: urls
+                                .into_iter()
+                                .map(|address| UdpServer {
+                                    address,
+                                    weight: None,
+                                })
+                                .collect(),
+                        },
+                    },
+                );
+                udp.routers.insert(
+                    router_name,
+                    UdpRouter {
+                        entry_points: service.entry_points.clone(),
+                        service: name.clone(),
+                    },
+                );
+            }
+        }
+    }
+
+    Ok(DynamicConfig {
+        http: Some(http),
+        tcp: Some(tcp),
+        udp: Some(udp),
+        tls: None,
+    })
+}
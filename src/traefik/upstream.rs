@@ -0,0 +1,57 @@
+use crate::traefik::DynamicConfig;
+use http_body_util::{BodyExt, Full};
+use hyper::body::Bytes;
+use hyper_util::client::legacy::{Client, connect::HttpConnector};
+use hyper_util::rt::TokioExecutor;
+use std::time::Duration;
+use tracing::instrument;
+
+/// How long the upstream provider gets to answer
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Another Traefik HTTP provider whose configuration is merged into ours
+pub struct UpstreamProvider {
+    url: String,
+    client: Client<HttpConnector, Full<Bytes>>,
+}
+
+impl UpstreamProvider {
+    pub fn new(url: String) -> Self {
+        Self {
+            url,
+            client: Client::builder(TokioExecutor::new()).build(HttpConnector::new()),
+        }
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Fetch the current dynamic configuration of the upstream provider
+    #[instrument(name = "upstream_fetch", skip_all, fields(url = %self.url))]
+    pub async fn fetch(&self) -> Result<DynamicConfig, String> {
+        tokio::time::timeout(FETCH_TIMEOUT, self.fetch_inner())
+            .await
+            .unwrap_or_else(|_| Err("timed out".to_string()))
+    }
+
+    async fn fetch_inner(&self) -> Result<DynamicConfig, String> {
+        let uri: hyper::Uri = self
+            .url
+            .parse()
+            .map_err(|e| format!("invalid URL: {}", e))?;
+
+        let response = self.client.get(uri).await.map_err(|e| e.to_string())?;
+        if !response.status().is_success() {
+            return Err(format!("HTTP {}", response.status()));
+        }
+
+        let body = response
+            .into_body()
+            .collect()
+            .await
+            .map_err(|e| e.to_string())?
+            .to_bytes();
+        serde_json::from_slice(&body).map_err(|e| e.to_string())
+    }
+}
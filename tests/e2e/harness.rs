@@ -0,0 +1,145 @@
+use axum::Router;
+use std::future::Future;
+use std::process::{Child, Command};
+use std::time::Duration;
+use testcontainers::{ContainerAsync, GenericImage, ImageExt, runners::AsyncRunner};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+const TRAEFIK_IMAGE: &str = "traefik";
+const TRAEFIK_TAG: &str = "v3.1";
+const READY_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Reserve a free local port (released before use, good enough for tests)
+pub fn free_port() -> u16 {
+    std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+/// The provider binary, killed on drop
+pub struct Provider {
+    pub port: u16,
+    child: Child,
+}
+
+impl Provider {
+    pub fn start(tailscaled_port: u16, env: &[(&str, &str)]) -> Self {
+        let port = free_port();
+        let child = Command::new(env!("CARGO_BIN_EXE_traefik-tailscale-provider"))
+            .env(
+                "TAILSCALE_SOCKET_PATH",
+                format!("tcp://127.0.0.1:{}", tailscaled_port),
+            )
+            .env("SERVER_PORT", port.to_string())
+            .env("UPDATE_INTERVAL_SECONDS", "1")
+            .envs(env.iter().copied())
+            .spawn()
+            .expect("provider binary starts");
+
+        Self { port, child }
+    }
+}
+
+impl Drop for Provider {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Start Traefik polling the provider, with the given (name, port) entrypoints
+pub async fn start_traefik(
+    provider_port: u16,
+    entrypoints: &[(&str, u16)],
+) -> ContainerAsync<GenericImage> {
+    let mut args = vec![
+        format!(
+            "--providers.http.endpoint=http://127.0.0.1:{}/config",
+            provider_port
+        ),
+        "--providers.http.pollInterval=1s".to_string(),
+        "--log.level=DEBUG".to_string(),
+    ];
+    for (name, port) in entrypoints {
+        args.push(format!("--entrypoints.{}.address=127.0.0.1:{}", name, port));
+    }
+
+    GenericImage::new(TRAEFIK_IMAGE, TRAEFIK_TAG)
+        .with_network("host")
+        .with_cmd(args)
+        .start()
+        .await
+        .expect("traefik container starts")
+}
+
+/// Start an HTTP backend answering every request with `body`
+pub async fn start_http_backend(body: &'static str) -> u16 {
+    let app = Router::new().fallback(move || async move { body });
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    port
+}
+
+/// Start a TCP backend writing `greeting` to every connection
+pub async fn start_tcp_backend(greeting: &'static [u8]) -> u16 {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let _ = stream.write_all(greeting).await;
+            });
+        }
+    });
+    port
+}
+
+/// GET a path through Traefik, returning the body of a 200 response
+pub async fn http_get(port: u16, path: &str) -> Option<String> {
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).await.ok()?;
+    let request = format!(
+        "GET {} HTTP/1.1\r\nHost: e2e.test\r\nConnection: close\r\n\r\n",
+        path
+    );
+    stream.write_all(request.as_bytes()).await.ok()?;
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.ok()?;
+    let (head, body) = response.split_once("\r\n\r\n")?;
+    head.starts_with("HTTP/1.1 200").then(|| body.to_string())
+}
+
+/// Read what a TCP service sends through Traefik right after connecting
+pub async fn tcp_read(port: u16) -> Option<String> {
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).await.ok()?;
+    let mut buffer = vec![0; 256];
+    let read = tokio::time::timeout(Duration::from_secs(2), stream.read(&mut buffer))
+        .await
+        .ok()?
+        .ok()?;
+    (read > 0).then(|| String::from_utf8_lossy(&buffer[..read]).to_string())
+}
+
+/// Retry until Traefik has loaded the configuration and the request succeeds
+pub async fn wait_for_response<F, Fut>(mut request: F) -> String
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Option<String>>,
+{
+    let deadline = tokio::time::Instant::now() + READY_TIMEOUT;
+    loop {
+        if let Some(response) = request().await {
+            return response;
+        }
+        assert!(
+            tokio::time::Instant::now() < deadline,
+            "Traefik did not route the request within {:?}",
+            READY_TIMEOUT
+        );
+        tokio::time::sleep(Duration::from_millis(500)).await;
+    }
+}
@@ -0,0 +1,81 @@
+//! End-to-end tests running a real Traefik against the provider.
+//!
+//! The provider binary is pointed at an in-process mock tailscaled whose peers
+//! resolve to stub backends on 127.0.0.1, and Traefik (started with testcontainers
+//! on the host network) polls the provider through `providers.http`.
+//!
+//! Opt-in, requires Docker with host networking (Linux):
+//!
+//!     cargo test --features e2e --test e2e
+
+mod harness;
+mod mock_tailscaled;
+
+use harness::{Provider, free_port, start_traefik, wait_for_response};
+use mock_tailscaled::MockPeer;
+
+#[tokio::test]
+async fn routes_http_requests_to_tagged_peer() {
+    let backend_port = harness::start_http_backend("hello from nas").await;
+    let tailscaled_port = mock_tailscaled::start(vec![MockPeer::new(
+        "nas",
+        "127.0.0.1",
+        &[&format!("tag:web-{}-http", backend_port)],
+    )])
+    .await;
+
+    let provider = Provider::start(tailscaled_port, &[]);
+    let web_port = free_port();
+    let _traefik = start_traefik(provider.port, &[("web", web_port)]).await;
+
+    let body = wait_for_response(|| harness::http_get(web_port, "/")).await;
+    assert_eq!(body, "hello from nas");
+}
+
+#[tokio::test]
+async fn routes_tcp_connections_to_tagged_peer() {
+    let backend_port = harness::start_tcp_backend(b"hello from db\n").await;
+    let tailscaled_port = mock_tailscaled::start(vec![MockPeer::new(
+        "db",
+        "127.0.0.1",
+        &[&format!("tag:db-{}-tcp", backend_port)],
+    )])
+    .await;
+
+    let provider = Provider::start(tailscaled_port, &[]);
+    let tcp_port = free_port();
+    let _traefik = start_traefik(provider.port, &[("tcp", tcp_port)]).await;
+
+    let greeting = wait_for_response(|| harness::tcp_read(tcp_port)).await;
+    assert_eq!(greeting, "hello from db\n");
+}
+
+#[tokio::test]
+async fn excludes_offline_peers() {
+    let online_port = harness::start_http_backend("online").await;
+    let offline_port = harness::start_http_backend("offline").await;
+    let tailscaled_port = mock_tailscaled::start(vec![
+        MockPeer::new(
+            "online",
+            "127.0.0.1",
+            &[&format!("tag:web-{}-http", online_port)],
+        ),
+        MockPeer::new(
+            "offline",
+            "127.0.0.1",
+            &[&format!("tag:web-{}-http", offline_port)],
+        )
+        .offline(),
+    ])
+    .await;
+
+    let provider = Provider::start(tailscaled_port, &[]);
+    let web_port = free_port();
+    let _traefik = start_traefik(provider.port, &[("web", web_port)]).await;
+
+    // Both routers would match every host, so only the online one may exist
+    for _ in 0..5 {
+        let body = wait_for_response(|| harness::http_get(web_port, "/")).await;
+        assert_eq!(body, "online");
+    }
+}
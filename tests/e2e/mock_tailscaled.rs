@@ -0,0 +1,97 @@
+use axum::{Json, Router, routing::get};
+use serde_json::{Value, json};
+
+/// A peer served by the mock tailscaled
+pub struct MockPeer {
+    hostname: String,
+    ip: String,
+    tags: Vec<String>,
+    online: bool,
+}
+
+impl MockPeer {
+    pub fn new(hostname: &str, ip: &str, tags: &[&str]) -> Self {
+        Self {
+            hostname: hostname.to_string(),
+            ip: ip.to_string(),
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            online: true,
+        }
+    }
+
+    pub fn offline(mut self) -> Self {
+        self.online = false;
+        self
+    }
+
+    fn to_json(&self, index: usize) -> Value {
+        json!({
+            "ID": format!("node{}", index),
+            "PublicKey": format!("nodekey:{:064x}", index),
+            "HostName": self.hostname,
+            "DNSName": format!("{}.tail1234.ts.net.", self.hostname),
+            "OS": "linux",
+            "UserID": 1,
+            "TailscaleIPs": [self.ip],
+            "AllowedIPs": [format!("{}/32", self.ip)],
+            "Tags": self.tags,
+            "Addrs": null,
+            "CurAddr": "",
+            "Relay": "fra",
+            "RxBytes": 0,
+            "TxBytes": 0,
+            "Created": "2024-01-01T00:00:00Z",
+            "LastWrite": "2024-01-01T00:00:00Z",
+            "LastSeen": "2024-01-01T00:00:00Z",
+            "LastHandshake": "2024-01-01T00:00:00Z",
+            "Online": self.online,
+            "ExitNode": false,
+            "ExitNodeOption": false,
+            "Active": true,
+            "PeerAPIURL": null,
+            "InNetworkMap": true,
+            "InMagicSock": true,
+            "InEngine": true,
+            "KeyExpiry": null,
+            "Expired": false
+        })
+    }
+}
+
+/// Start a mock tailscaled LocalAPI on a random local port and return the port
+pub async fn start(peers: Vec<MockPeer>) -> u16 {
+    let peer_map: serde_json::Map<String, Value> = peers
+        .iter()
+        .enumerate()
+        .map(|(index, peer)| (format!("nodekey:{:064x}", index), peer.to_json(index)))
+        .collect();
+
+    let status = json!({
+        "Version": "1.87.0",
+        "TUN": true,
+        "BackendState": "Running",
+        "AuthURL": "",
+        "TailscaleIPs": ["100.64.0.100"],
+        "Self": null,
+        "Health": [],
+        "MagicDNSSuffix": "tail1234.ts.net",
+        "CurrentTailnet": null,
+        "CertDomains": null,
+        "Peer": peer_map,
+        "User": null,
+        "ClientVersion": null
+    });
+
+    let app = Router::new().route(
+        "/localapi/v0/status",
+        get(move || {
+            let status = status.clone();
+            async move { Json(status) }
+        }),
+    );
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    port
+}
@@ -0,0 +1,125 @@
+//! Size and startup budgets of the edge build.
+//!
+//! Only meaningful for the optimized edge binary, so both checks are ignored by a
+//! plain `cargo test` and fail when run against any other build:
+//!
+//!     cargo test --profile edge --no-default-features --test edge -- --ignored
+
+#[allow(dead_code)]
+#[path = "../e2e/mock_tailscaled.rs"]
+mod mock_tailscaled;
+
+use mock_tailscaled::MockPeer;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+const BINARY: &str = env!("CARGO_BIN_EXE_traefik-tailscale-provider");
+
+/// Budget for the stripped edge binary (3.5 MiB on x86_64-linux)
+const MAX_BINARY_SIZE: u64 = 4 * 1024 * 1024;
+
+/// Budget from process start until the first configuration is served
+const MAX_STARTUP_TIME: Duration = Duration::from_secs(1);
+
+/// Fail unless this is the edge build the budgets are meant for
+fn assert_edge_build() {
+    let profile = Path::new(BINARY)
+        .parent()
+        .and_then(|dir| dir.file_name())
+        .and_then(|profile| profile.to_str());
+    assert_eq!(
+        profile,
+        Some("edge"),
+        "budgets only apply to `--profile edge` builds"
+    );
+    let default_features = [
+        cfg!(feature = "docs"),
+        cfg!(feature = "notify"),
+        cfg!(feature = "https"),
+    ];
+    assert!(
+        !default_features.contains(&true),
+        "budgets only apply to builds with --no-default-features"
+    );
+}
+
+#[test]
+#[ignore = "needs the edge build, run with --profile edge --no-default-features -- --ignored"]
+fn binary_size_within_budget() {
+    assert_edge_build();
+
+    let size = std::fs::metadata(BINARY).unwrap().len();
+    assert!(
+        size <= MAX_BINARY_SIZE,
+        "edge binary is {} bytes, budget is {} bytes",
+        size,
+        MAX_BINARY_SIZE
+    );
+}
+
+#[tokio::test]
+#[ignore = "needs the edge build, run with --profile edge --no-default-features -- --ignored"]
+async fn serves_config_within_startup_budget() {
+    assert_edge_build();
+
+    let tailscaled_port = mock_tailscaled::start(vec![MockPeer::new(
+        "nas",
+        "127.0.0.1",
+        &["tag:web-3000-http"],
+    )])
+    .await;
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+
+    let started = Instant::now();
+    let mut child = Command::new(BINARY)
+        .env(
+            "TAILSCALE_SOCKET_PATH",
+            format!("tcp://127.0.0.1:{}", tailscaled_port),
+        )
+        .env("SERVER_PORT", port.to_string())
+        .stdout(Stdio::null())
+        .spawn()
+        .expect("provider binary starts");
+
+    let mut served = false;
+    while started.elapsed() < MAX_STARTUP_TIME * 5 {
+        if config_served(port).await {
+            served = true;
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    let elapsed = started.elapsed();
+
+    let _ = child.kill();
+    let _ = child.wait();
+
+    assert!(served, "provider never served /config");
+    assert!(
+        elapsed <= MAX_STARTUP_TIME,
+        "first /config took {:?}, budget is {:?}",
+        elapsed,
+        MAX_STARTUP_TIME
+    );
+}
+
+async fn config_served(port: u16) -> bool {
+    let Ok(mut stream) = TcpStream::connect(("127.0.0.1", port)).await else {
+        return false;
+    };
+    let request = "GET /config HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n";
+    if stream.write_all(request.as_bytes()).await.is_err() {
+        return false;
+    }
+
+    let mut response = String::new();
+    let _ = stream.read_to_string(&mut response).await;
+    response.starts_with("HTTP/1.1 200")
+}